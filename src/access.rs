@@ -0,0 +1,96 @@
+//! Permissions of the providers to read and write parameters, so that e.g. a remote control
+//! can see everything but only change a few levels
+//!
+//! Safe mode limits the parameters that all providers may change at once, e.g. while the
+//! surface is used by volunteers.
+
+use tokio::sync::watch;
+use tracing::info;
+
+use crate::settings::{AccessSettings, SafeModeSettings};
+use crate::subscriptions::PathPattern;
+
+/// The parameters that a provider may read and write. Providers without restrictions have
+/// full access.
+#[derive(Debug, Clone, Default)]
+pub struct Access {
+    /// Patterns of the parameters that may be read, or None for all
+    read: Option<Vec<PathPattern>>,
+    /// Patterns of the parameters that may be written, or None for all
+    write: Option<Vec<PathPattern>>,
+}
+
+impl Access {
+    pub fn new(settings: &AccessSettings) -> Self {
+        let patterns = |paths: &Option<Vec<String>>| {
+            paths
+                .as_ref()
+                .map(|paths| paths.iter().map(|path| PathPattern::new(path)).collect())
+        };
+
+        Self {
+            read: patterns(&settings.read),
+            write: patterns(&settings.write),
+        }
+    }
+
+    /// Full access, or the access of the settings if there are any
+    pub fn from_settings(settings: Option<&AccessSettings>) -> Self {
+        settings.map(Self::new).unwrap_or_default()
+    }
+
+    /// Whether the values of a parameter may be sent to the provider
+    pub fn may_read(&self, path: &str) -> bool {
+        Self::allows(&self.read, path)
+    }
+
+    /// Whether the provider may change the value of a parameter
+    pub fn may_write(&self, path: &str) -> bool {
+        Self::allows(&self.write, path)
+    }
+
+    fn allows(patterns: &Option<Vec<PathPattern>>, path: &str) -> bool {
+        patterns
+            .as_ref()
+            .is_none_or(|patterns| patterns.iter().any(|pattern| pattern.matches(path)))
+    }
+}
+
+/// A switch that only allows a few parameters to be changed while it is on
+pub struct SafeMode {
+    enabled: watch::Sender<bool>,
+    /// Patterns of the parameters that may still be changed
+    paths: Vec<PathPattern>,
+}
+
+impl SafeMode {
+    pub fn new(settings: &SafeModeSettings, enabled: bool) -> Self {
+        Self {
+            enabled: watch::Sender::new(enabled || settings.enabled),
+            paths: settings.paths.iter().map(|path| PathPattern::new(path)).collect(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        *self.enabled.borrow()
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        let changed = self
+            .enabled
+            .send_if_modified(|current| std::mem::replace(current, enabled) != enabled);
+        if changed {
+            info!(enabled, "Safe mode switched");
+        }
+    }
+
+    /// A receiver that sees when safe mode is switched
+    pub fn watch(&self) -> watch::Receiver<bool> {
+        self.enabled.subscribe()
+    }
+
+    /// Whether a parameter may be changed, which all parameters may while safe mode is off
+    pub fn allows(&self, path: &str) -> bool {
+        !self.is_enabled() || self.paths.iter().any(|pattern| pattern.matches(path))
+    }
+}
@@ -0,0 +1,295 @@
+//! Validation of the whole configuration without connecting to the console or the
+//! controllers, for the `config check` command
+//!
+//! Unlike starting the bridge, which stops at the first invalid setting, all problems are
+//! collected so that they can be fixed at once.
+
+use std::collections::HashMap;
+
+use crate::data::{Fader, InternalButton, InternalFunction, PathType};
+use crate::midi::{MAX_XTOUCH_COLOUR, STRIP_COUNT};
+use crate::settings::{ControllerSettings, MidiDefinition, MidiFader, Settings};
+
+/// Highest MIDI channel, note and Control Change number
+const MAX_MIDI_CHANNEL: u8 = 15;
+const MAX_MIDI_DATA: u8 = 127;
+
+/// All problems of the configuration, empty if it is valid
+pub fn problems(config: &Settings) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let controllers = config.midi.iter().zip(&config.midi_definitions);
+    for (index, (midi, definition)) in controllers.enumerate() {
+        let name = format!("MIDI controller {} ({})", index + 1, midi.input);
+        check_definition(definition, &name, &mut problems);
+        check_controller(config, midi, definition, &name, &mut problems);
+    }
+
+    for label in &config.mute_all {
+        check_fader(label, "Mute all label", &mut problems);
+    }
+
+    for link in &config.links {
+        if !link.offsets.is_empty() && link.offsets.len() != link.paths.len() {
+            problems.push(format!(
+                "Link of {} needs one offset per path",
+                link.paths.join(", ")
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Print the problems of the configuration, and whether it is valid
+pub fn print(config: &Settings) -> bool {
+    let problems = problems(config);
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    match problems.len() {
+        0 => println!("Configuration is valid"),
+        1 => println!("Found 1 problem"),
+        count => println!("Found {} problems", count),
+    }
+    problems.is_empty()
+}
+
+fn check_definition(definition: &MidiDefinition, name: &str, problems: &mut Vec<String>) {
+    // The buttons of every note, to find notes that are used more than once
+    let mut notes: HashMap<u8, Vec<String>> = HashMap::new();
+
+    let strip_buttons = definition
+        .faders
+        .iter()
+        .enumerate()
+        .flat_map(|(index, fader)| {
+            let strip = format!("fader {}", index + 1);
+            fader
+                .buttons
+                .iter()
+                .map(move |button| (strip.clone(), button))
+        });
+    let buttons = definition
+        .buttons
+        .iter()
+        .map(|button| ("buttons".to_string(), button));
+
+    for (index, fader) in definition.faders.iter().enumerate() {
+        if fader.channel > MAX_MIDI_CHANNEL {
+            problems.push(format!(
+                "{}: MIDI definition fader {} is on channel {}, which is out of range",
+                name,
+                index + 1,
+                fader.channel
+            ));
+        }
+    }
+
+    for (place, button) in strip_buttons.chain(buttons) {
+        let button_name = match &button.description {
+            Some(description) => format!("{} '{}'", place, description),
+            None => format!("{} note {}", place, button.key),
+        };
+        if button.channel > MAX_MIDI_CHANNEL {
+            problems.push(format!(
+                "{}: MIDI definition {} is on channel {}, which is out of range",
+                name, button_name, button.channel
+            ));
+        }
+        if button.key > MAX_MIDI_DATA {
+            problems.push(format!(
+                "{}: MIDI definition {} has note {}, which is out of range",
+                name, button_name, button.key
+            ));
+        }
+        notes.entry(button.key).or_default().push(button_name);
+    }
+
+    let mut duplicates = notes
+        .into_iter()
+        .filter(|(_, names)| names.len() > 1)
+        .collect::<Vec<_>>();
+    duplicates.sort();
+    for (note, names) in duplicates {
+        problems.push(format!(
+            "{}: MIDI note {} is used by {}",
+            name,
+            note,
+            names.join(", ")
+        ));
+    }
+
+    let strip_count = definition.strip_count();
+    if strip_count == 0 {
+        problems.push(format!("{}: MIDI definition has no channel strips", name));
+    }
+    if definition.encoders.len() > strip_count {
+        problems.push(format!(
+            "{}: MIDI definition has {} encoders, but the surface has {} strips",
+            name,
+            definition.encoders.len(),
+            strip_count
+        ));
+    }
+
+    let encoder_ccs = definition
+        .encoders
+        .iter()
+        .flat_map(|encoder| [encoder.cc, encoder.ring_cc]);
+    for cc in encoder_ccs.chain(definition.expression_pedal) {
+        if cc > MAX_MIDI_DATA {
+            problems.push(format!(
+                "{}: MIDI definition Control Change {} is out of range",
+                name, cc
+            ));
+        }
+    }
+}
+
+fn check_controller(
+    config: &Settings,
+    midi: &ControllerSettings,
+    definition: &MidiDefinition,
+    name: &str,
+    problems: &mut Vec<String>,
+) {
+    let assignments = &midi.assignments;
+    let console_ids = config
+        .console
+        .iter()
+        .filter_map(|console| console.id.as_deref())
+        .collect::<Vec<_>>();
+
+    for bank in assignments.banks.iter() {
+        let bank_name = bank.name.as_deref().unwrap_or("(unnamed)");
+        for label in bank.faders.iter().chain(bank.trim_db.keys()) {
+            check_fader(
+                label,
+                &format!("{}: fader label in bank '{}'", name, bank_name),
+                problems,
+            );
+        }
+        if let Some(dca) = bank.filter.as_ref().and_then(|filter| filter.dca)
+            && Fader::new_from_label(&format!("DCA {}", dca)).is_err()
+        {
+            problems.push(format!(
+                "{}: bank '{}' filters on DCA {}, which does not exist",
+                name, bank_name, dca
+            ));
+        }
+        if let Some(console) = &bank.console
+            && !console_ids.contains(&console.as_str())
+        {
+            problems.push(format!(
+                "{}: bank '{}' refers to unknown console '{}'",
+                name, bank_name, console
+            ));
+        }
+    }
+
+    for (channel, label) in &assignments.fixed_faders {
+        check_fader(label, &format!("{}: fixed fader label", name), problems);
+        if (*channel as usize) < STRIP_COUNT || *channel > MAX_MIDI_CHANNEL as u32 {
+            problems.push(format!(
+                "{}: fixed fader on MIDI channel {} is out of range, it must be between {} and {}",
+                name, channel, STRIP_COUNT, MAX_MIDI_CHANNEL
+            ));
+        }
+    }
+
+    let fixed = assignments.fixed_buttons.iter().map(|b| (b, "button"));
+    let long_press = assignments
+        .long_press_buttons
+        .iter()
+        .map(|b| (b, "long-press button"));
+    for ((note, label), kind) in fixed.chain(long_press) {
+        let what = format!("{}: {} label '{}'", name, kind, label);
+        match InternalButton::new_from_label(label) {
+            Ok(InternalButton {
+                function: InternalFunction::Macro(macro_name),
+            }) if !config.macros.contains_key(&macro_name) => {
+                problems.push(format!(
+                    "{} refers to undefined macro '{}'",
+                    what, macro_name
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(format!("{} is invalid: {:#}", what, e)),
+        }
+        if *note > MAX_MIDI_DATA as u32 {
+            problems.push(format!(
+                "{} is on note {}, which is out of range",
+                what, note
+            ));
+        }
+    }
+
+    for description in &assignments.fader_buttons {
+        if PathType::for_strip_button(description).is_none() {
+            problems.push(format!(
+                "{}: fader button '{}' must be one of Mute and Solo",
+                name, description
+            ));
+        }
+        let mut strips = definition.faders.iter().take(definition.strip_count());
+        let has_button = |fader: &MidiFader| {
+            fader
+                .buttons
+                .iter()
+                .any(|button| button.description.as_deref() == Some(description.as_str()))
+        };
+        if !strips.all(has_button) {
+            problems.push(format!(
+                "{}: fader button '{}' is missing from strips of the MIDI definition",
+                name, description
+            ));
+        }
+    }
+
+    if let Some(label) = &assignments.expression_pedal {
+        check_fader(
+            label,
+            &format!("{}: expression pedal label", name),
+            problems,
+        );
+    }
+
+    // Automatic banks are only known once the console is connected
+    if let Some(follow) = &midi.follow_layer
+        && !assignments.banks.is_auto()
+    {
+        for (layer, bank) in &follow.banks {
+            if !assignments
+                .banks
+                .iter()
+                .any(|b| b.name.as_ref() == Some(bank))
+            {
+                problems.push(format!(
+                    "{}: bank '{}' for console layer {} does not exist",
+                    name, bank, layer
+                ));
+            }
+        }
+    }
+
+    if let Some(colour) = midi
+        .colours
+        .table
+        .iter()
+        .chain(std::iter::once(&midi.colours.fallback))
+        .find(|c| **c > MAX_XTOUCH_COLOUR)
+    {
+        problems.push(format!(
+            "{}: invalid X-Touch colour {} in colour table",
+            name, colour
+        ));
+    }
+}
+
+fn check_fader(label: &str, what: &str, problems: &mut Vec<String>) {
+    if let Err(e) = Fader::new_from_label(label) {
+        problems.push(format!("{} '{}' is invalid: {:#}", what, label, e));
+    }
+}
@@ -3,6 +3,7 @@
 use std::collections::HashMap;
 use std::error::Error;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow, bail};
@@ -13,17 +14,184 @@ use tokio::net::UdpSocket;
 use tokio::sync::{Mutex, RwLock};
 use tokio::time::timeout;
 
-use crate::orchestrator::{Interface, Value};
+use crate::definitions::NodeDefinition;
+use crate::mock_console::MockConsole;
+use crate::orchestrator::{Interface, LinkStatus, OscOperation, Value};
+use crate::wing_osc::WingOscConsole;
+use crate::x32::X32Console;
+
+/// Parameter that is requested to identify the console, and to check that it still answers
+const IDENTITY_PATH: &str = "/$syscfg/$cnscfg";
+/// How often the console is checked, how long it has to answer, and how many checks it may
+/// miss before it is considered offline
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_millis(500);
+const HEARTBEAT_MISSES: u32 = 3;
+
+/// Number of consecutive read errors after which the connection is considered lost
+const RECONNECT_AFTER_ERRORS: u32 = 5;
+/// Delay before the first reconnection attempt, doubled after every failed attempt
+const RECONNECT_MIN_DELAY: Duration = Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// How long the tasks of a connection may take to stop when it is closed
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Counts of node data requests sent and answered. The console answers requests in order,
+/// and ends each answer with a `RequestEnd`.
+#[derive(Default)]
+struct RequestCounter {
+    sent: AtomicU64,
+    ended: AtomicU64,
+    notify: tokio::sync::Notify,
+}
+
+impl RequestCounter {
+    /// Consider all requests sent so far as answered, e.g. after a reconnection
+    fn end_all(&self) {
+        self.ended.store(self.sent.load(Ordering::Acquire), Ordering::Release);
+        self.notify.notify_waiters();
+    }
+}
+
+/// Values that were requested together, which can be awaited until the console has answered
+/// all of them
+pub struct RequestBatch {
+    requests: Arc<RequestCounter>,
+    last: u64,
+}
+
+impl RequestBatch {
+    /// Wait until the console has answered all requests of the batch
+    pub async fn wait(&self) {
+        loop {
+            let notified = self.requests.notify.notified();
+            if self.requests.ended.load(Ordering::Acquire) >= self.last {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// The long-running tasks of a console connection, which hold its socket or connection
+#[derive(Default)]
+pub(crate) struct ConsoleTasks(std::sync::Mutex<Vec<tokio::task::JoinHandle<()>>>);
+
+impl ConsoleTasks {
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.0.lock().unwrap().push(tokio::spawn(task));
+    }
+
+    /// Abort the tasks, and wait until they have released what they hold. Tasks only stop
+    /// where they await, so those that do not stop in time are left behind.
+    pub async fn stop(&self) {
+        let tasks = std::mem::take(&mut *self.0.lock().unwrap());
+        for task in &tasks {
+            task.abort();
+        }
+        for task in tasks {
+            if timeout(CLOSE_TIMEOUT, task).await.is_err() {
+                warn!("Console connection task did not stop in time");
+            }
+        }
+    }
+}
+
+/// The console the orchestrator talks to
+pub enum ConsoleBackend {
+    Wing(Console),
+    WingOsc(WingOscConsole),
+    X32(X32Console),
+    /// An in-memory console, for development without a WING
+    Mock(MockConsole),
+}
+
+impl ConsoleBackend {
+    pub async fn request_value(&mut self, osc_addr: &str) -> Result<()> {
+        match self {
+            Self::Wing(console) => console.request_value(osc_addr).await,
+            Self::WingOsc(console) => console.request_value(osc_addr).await,
+            Self::X32(console) => console.request_value(osc_addr).await,
+            Self::Mock(console) => console.request_value(osc_addr).await,
+        }
+    }
+
+    /// Request several values. If the console reports when it has answered them, a batch to
+    /// await is returned.
+    pub async fn request_values(&mut self, osc_addrs: &[&str]) -> Result<Option<RequestBatch>> {
+        match self {
+            Self::Wing(console) => console.request_values(osc_addrs).await.map(Some),
+            _ => {
+                for osc_addr in osc_addrs {
+                    self.request_value(osc_addr).await?;
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// Check a value before it is written, converting it to the type of the parameter
+    pub fn validate(&self, osc_addr: &str, value: Value) -> Result<Value> {
+        match self {
+            Self::Wing(console) => console.validate(osc_addr, value),
+            _ => Ok(value),
+        }
+    }
+
+    pub async fn set_value(&mut self, osc_addr: &str, value: Value) -> Result<()> {
+        match self {
+            Self::Wing(console) => console.set_value(osc_addr, value).await,
+            Self::WingOsc(console) => console.set_value(osc_addr, value).await,
+            Self::X32(console) => console.set_value(osc_addr, value).await,
+            Self::Mock(console) => console.set_value(osc_addr, value).await,
+        }
+    }
+
+    pub async fn set_interface(&mut self, interface: Interface) {
+        match self {
+            Self::Wing(console) => console.set_interface(interface).await,
+            Self::WingOsc(console) => console.set_interface(interface).await,
+            Self::X32(console) => console.set_interface(interface).await,
+            Self::Mock(console) => console.set_interface(interface).await,
+        }
+    }
+
+    pub async fn set_meters(&mut self, meters: Vec<libwing::Meter>) -> Result<()> {
+        match self {
+            Self::Wing(console) => console.set_meters(meters).await,
+            Self::WingOsc(console) => console.set_meters(meters).await,
+            Self::X32(console) => console.set_meters(meters).await,
+            Self::Mock(console) => console.set_meters(meters).await,
+        }
+    }
+
+    /// Close the connection, stopping its tasks so that its socket or connection is released
+    /// once this returns
+    pub async fn close(self) {
+        match self {
+            Self::Wing(console) => console.tasks.stop().await,
+            Self::WingOsc(console) => console.tasks.stop().await,
+            Self::X32(console) => console.tasks.stop().await,
+            Self::Mock(console) => console.tasks.stop().await,
+        }
+    }
+}
 
 /// WING connection
 pub struct Console {
-    wing: WingConsole,
+    /// The current connection, replaced when reconnecting
+    wing: Arc<std::sync::Mutex<WingConsole>>,
     remote_addr: String,
 
     interface: Arc<Mutex<Option<Interface>>>,
 
     meter_task_spawned: bool,
     meters: Arc<Mutex<Vec<libwing::Meter>>>,
+    requests: Arc<RequestCounter>,
+    /// Parameter definitions by node ID, or None while a definition is requested
+    definitions: Arc<std::sync::Mutex<HashMap<i32, Option<NodeDefinition>>>>,
+    tasks: ConsoleTasks,
 }
 
 impl Console {
@@ -40,16 +208,19 @@ impl Console {
         debug!("Successfully connected to Wing console at {}", remote_addr);
 
         let mut console = Self {
-            wing,
+            wing: Arc::new(std::sync::Mutex::new(wing)),
             remote_addr: remote_addr.to_string(),
             interface: Mutex::new(None).into(),
             meter_task_spawned: false,
             meters: Arc::new(Mutex::new(vec![])),
+            requests: Arc::new(RequestCounter::default()),
+            definitions: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            tasks: ConsoleTasks::default(),
         };
 
         // Initialise NAME_TO_DEF map, otherwise it will happen during a request, which is not great.
         debug!("Initialising NAME_TO_DEF map...");
-        std::hint::black_box(WingConsole::name_to_id("/$syscfg/$cnscfg"));
+        std::hint::black_box(WingConsole::name_to_id(IDENTITY_PATH));
         debug!("Initialised  NAME_TO_DEF map.");
 
         console.spawn_recv_task();
@@ -64,7 +235,7 @@ impl Console {
         debug!("Attempting to identify console...");
 
         let result = interface
-            .get_value("/$syscfg/$cnscfg", true)
+            .get_value_for(OscOperation::Identify, IDENTITY_PATH, true)
             .await?;
 
         match result {
@@ -79,7 +250,8 @@ impl Console {
     /// This will panic if no meters have been requested, as the internal UDP socket
     /// might not have been set up.
     fn spawn_meter_task(&self) {
-        let mut wing = self.wing.clone();
+        let shared_wing = self.wing.clone();
+        let mut wing = self.wing();
         let interface = self.interface.clone();
         let meters = self.meters.clone();
 
@@ -89,17 +261,21 @@ impl Console {
             info!("Subscribing to meter updates...");
         });
 
-        tokio::spawn(async move {
+        self.tasks.spawn(async move {
             loop {
                 let meter = match wing.read_meters() {
                     Ok(m) => m,
                     Err(libwing::Error::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
-                        // Just a simple timeout, nothing to worry about
+                        // Just a simple timeout, nothing to worry about. The read blocks, so
+                        // the task can only be stopped here while the console is quiet.
+                        tokio::task::yield_now().await;
                         continue;
                     },
                     Err(e) => {
                         warn!("Error during meter reception: {:?}", e);
                         tokio::time::sleep(Duration::from_millis(10)).await;
+                        // Pick up the new connection, if the receiving task reconnected
+                        wing = shared_wing.lock().unwrap().clone();
                         continue;
                     }
                 };
@@ -130,12 +306,33 @@ impl Console {
     /// Spawn a background tokio task that listens for incoming OSC packets
     /// and updates the parameter cache.
     fn spawn_recv_task(&mut self) {
-        let mut wing = self.wing.clone();
+        let shared_wing = self.wing.clone();
+        let mut wing = self.wing();
+        let remote_addr = self.remote_addr.clone();
         let interface = self.interface.clone();
+        let meters = self.meters.clone();
+        let requests = self.requests.clone();
+        let definitions = self.definitions.clone();
+
+        self.tasks.spawn(async move {
+            let mut online = true;
+            let mut errors = 0;
 
-        tokio::spawn(async move {
             loop {
                 let wing_read = wing.read();
+
+                // A timeout only means that there was no traffic
+                let timed_out = matches!(&wing_read, Err(libwing::Error::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut);
+
+                if !timed_out && wing_read.is_ok() != online {
+                    online = wing_read.is_ok();
+
+                    if let Some(iface) = interface.lock().await.as_ref() {
+                        let status = if online { LinkStatus::Online } else { LinkStatus::Offline };
+                        iface.set_link_status(status).await;
+                    }
+                }
+
                 match wing_read {
                     Ok(data) => match data {
                         WingResponse::NodeData(id, data) => {
@@ -144,21 +341,96 @@ impl Console {
 
                             Console::process_node_data(interface.clone(), id, data).await;
                         }
-                        WingResponse::RequestEnd => {}
-                        WingResponse::NodeDef(_) => {}
+                        WingResponse::RequestEnd => {
+                            requests.ended.fetch_add(1, Ordering::AcqRel);
+                            requests.notify.notify_waiters();
+                        }
+                        WingResponse::NodeDef(def) => {
+                            let definition = NodeDefinition::from_wing(&def);
+                            trace!(node_id = def.id, ?definition, "Received node definition");
+                            definitions.lock().unwrap().insert(def.id, Some(definition));
+                        }
                     },
                     Err(libwing::Error::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
-                        // Just a simple timeout, nothing to worry about
+                        // Just a simple timeout, nothing to worry about. The read blocks, so
+                        // the task can only be stopped here while the console is quiet.
+                        tokio::task::yield_now().await;
                     }
                     Err(e) => {
                         warn!("Error during OSC reception: {:?}", e);
                         tokio::time::sleep(Duration::from_millis(10)).await;
+
+                        errors += 1;
+                        if errors >= RECONNECT_AFTER_ERRORS {
+                            Self::reconnect(&shared_wing, &remote_addr, &interface, &meters).await;
+                            // Requests sent to the old connection will never be answered
+                            requests.end_all();
+                            wing = shared_wing.lock().unwrap().clone();
+                            // The link is reported online again once the console answers,
+                            // starting with the identify request
+                        }
+                        continue;
                     }
                 }
+
+                errors = 0;
             }
         });
     }
 
+    /// Connect to the console again after the connection was lost, retrying with exponential
+    /// backoff until it succeeds. Meters are subscribed again, and the cache is cleared so
+    /// that providers fetch fresh values when the link comes back online.
+    async fn reconnect(
+        wing: &Arc<std::sync::Mutex<WingConsole>>,
+        remote_addr: &str,
+        interface: &Arc<Mutex<Option<Interface>>>,
+        meters: &Arc<Mutex<Vec<libwing::Meter>>>,
+    ) {
+        let mut delay = RECONNECT_MIN_DELAY;
+
+        loop {
+            tokio::time::sleep(delay).await;
+            info!(addr = remote_addr, ?delay, "Reconnecting to console...");
+
+            let addr = remote_addr.to_string();
+            match tokio::task::spawn_blocking(move || WingConsole::connect(Some(&addr))).await {
+                Ok(Ok(connection)) => {
+                    *wing.lock().unwrap() = connection;
+                    break;
+                }
+                Ok(Err(e)) => warn!("Failed to reconnect to console: {:?}", e),
+                Err(e) => error!("Reconnection task failed: {:?}", e),
+            }
+
+            delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+        }
+
+        event!(Level::INFO, addr = remote_addr, "Console reconnected");
+
+        {
+            let meters = meters.lock().await;
+            if !meters.is_empty() {
+                if let Err(e) = wing.lock().unwrap().request_meter(&meters) {
+                    error!("Failed to subscribe to meters again: {:?}", e);
+                }
+            }
+        }
+
+        let iface = interface.lock().await.clone();
+        if let Some(iface) = iface {
+            iface.clear_cache().await;
+            // The answer to the identify request is read by the receiving task, which must
+            // not wait for it
+            tokio::spawn(Self::go_online(iface));
+        }
+    }
+
+    /// A handle to the current connection
+    fn wing(&self) -> WingConsole {
+        self.wing.lock().unwrap().clone()
+    }
+
     /// Decode raw meter data into an array of meter values
     async fn process_meter_data(meters: Arc<Mutex<Vec<libwing::Meter>>>, data: Vec<i16>) -> Result<Vec<Vec<f32>>> {
         let meters = meters.lock().await;
@@ -206,13 +478,15 @@ impl Console {
             return;
         }
 
-        let node_addr = &node_defn[0].0;
+        let (node_addr, node_def) = &node_defn[0];
 
         let value;
 
         // Even though the data may contain multiple values/value types, we employ a certain priority.
         if data.has_float() {
             value = Value::Float(data.get_float());
+        } else if data.has_int() && NodeDefinition::is_switch(node_def) {
+            value = Value::Bool(data.get_int() != 0);
         } else if data.has_int() {
             value = Value::Int(data.get_int());
         } else if data.has_string() {
@@ -256,13 +530,110 @@ impl Console {
 
         trace!(node_id, "Requesting OSC value Node ID");
 
-        self.wing
-            .request_node_data(node_id)
-            .with_context(|| format!("Failed to request node data for ID {}", node_id))?;
+        Self::request_node(&self.wing, &self.requests, node_id)?;
 
         Ok(())
     }
 
+    /// Request the value of a node, returning a batch that completes when it is answered
+    fn request_node(
+        wing: &std::sync::Mutex<WingConsole>,
+        requests: &Arc<RequestCounter>,
+        node_id: i32,
+    ) -> Result<RequestBatch> {
+        let mut wing = wing.lock().unwrap().clone();
+        wing.request_node_data(node_id)
+            .with_context(|| format!("Failed to request node data for ID {}", node_id))?;
+
+        Ok(RequestBatch {
+            requests: requests.clone(),
+            last: requests.sent.fetch_add(1, Ordering::AcqRel) + 1,
+        })
+    }
+
+    /// Spawn a background task that regularly requests the console identity, to notice when
+    /// the console stops answering even though the connection is still open
+    fn spawn_heartbeat_task(&self, interface: Interface) {
+        let wing = self.wing.clone();
+        let requests = self.requests.clone();
+
+        self.tasks.spawn(async move {
+            let Some(node_id) = WingConsole::name_to_id(IDENTITY_PATH) else {
+                error!("No node ID for {}, console heartbeat disabled", IDENTITY_PATH);
+                return;
+            };
+
+            let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+            let mut missed = 0;
+
+            loop {
+                interval.tick().await;
+
+                let sent = tokio::time::Instant::now();
+                let answered = match Self::request_node(&wing, &requests, node_id) {
+                    Ok(batch) => timeout(HEARTBEAT_TIMEOUT, batch.wait()).await.is_ok(),
+                    Err(e) => {
+                        debug!("Failed to send heartbeat: {:?}", e);
+                        false
+                    }
+                };
+
+                if answered {
+                    if missed >= HEARTBEAT_MISSES {
+                        info!("Console answers again");
+                    }
+                    missed = 0;
+                    interface.set_link_status(LinkStatus::Online).await;
+                    interface.set_round_trip(sent.elapsed());
+                } else {
+                    missed += 1;
+                    debug!(missed, "Console heartbeat not answered");
+
+                    if missed == HEARTBEAT_MISSES {
+                        warn!("Console stopped answering");
+                        interface.set_link_status(LinkStatus::Offline).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Request several OSC values, returning a batch that can be awaited until the console
+    /// has sent all of them
+    pub async fn request_values(&mut self, osc_addrs: &[&str]) -> Result<RequestBatch> {
+        for osc_addr in osc_addrs {
+            self.request_value(osc_addr).await?;
+        }
+
+        Ok(RequestBatch {
+            requests: self.requests.clone(),
+            last: self.requests.sent.load(Ordering::Acquire),
+        })
+    }
+
+    /// Check a value against the definition of its parameter, converting and clamping it.
+    /// Definitions are requested from the console the first time a parameter is written, and
+    /// values are accepted as they are until it has answered.
+    pub fn validate(&self, osc_addr: &str, value: Value) -> Result<Value> {
+        let node_id = WingConsole::name_to_id(osc_addr)
+            .with_context(|| format!("Unknown parameter {}", osc_addr))?;
+
+        let mut definitions = self.definitions.lock().unwrap();
+        match definitions.get(&node_id) {
+            Some(Some(definition)) => definition
+                .validate(value)
+                .with_context(|| format!("Invalid value for {}", osc_addr)),
+            Some(None) => Ok(value),
+            None => {
+                definitions.insert(node_id, None);
+                if let Err(e) = self.wing().request_node_definition(node_id) {
+                    warn!("Failed to request definition of {}: {:?}", osc_addr, e);
+                }
+                Ok(value)
+            }
+        }
+    }
+
     /// Set an OSC value
     pub async fn set_value(&mut self, osc_addr: &str, value: Value) -> Result<()> {
         debug!(osc_addr, ?value, "Setting OSC value");
@@ -275,9 +646,10 @@ impl Console {
         })?;
 
         let result = match value {
-            Value::Float(f) => self.wing.set_float(node_id, f),
-            Value::Int(i) => self.wing.set_int(node_id, i),
-            Value::Str(s) => self.wing.set_string(node_id, &s),
+            Value::Float(f) => self.wing().set_float(node_id, f),
+            Value::Int(i) => self.wing().set_int(node_id, i),
+            Value::Str(s) => self.wing().set_string(node_id, &s),
+            Value::Bool(on) => self.wing().set_int(node_id, i32::from(on)),
         };
 
         result.with_context(|| format!("Failed to set node data for ID {}", node_id))
@@ -286,21 +658,33 @@ impl Console {
     pub async fn set_interface(&mut self, interface: Interface) {
         let cloned_interface_for_later = interface.clone();
 
+        self.spawn_heartbeat_task(interface.clone());
         self.interface.lock().await.replace(interface);
 
-        tokio::spawn(async move {
-                match Self::identify(&cloned_interface_for_later).await {
-                    Ok(id_string) => info!(id_string, "Console identified as"),
-                    Err(e) => error!("Failed to identify console: {:?}", e),
-                }
-        });
+        tokio::spawn(Self::go_online(cloned_interface_for_later));
+    }
+
+    /// Identify the console, and report the link as online if it answers
+    async fn go_online(interface: Interface) {
+        interface.set_link_status(LinkStatus::Connecting).await;
+
+        match Self::identify(&interface).await {
+            Ok(id_string) => {
+                info!(id_string, "Console identified as");
+                interface.set_link_status(LinkStatus::Online).await;
+            }
+            Err(e) => {
+                error!("Failed to identify console: {:?}", e);
+                interface.set_link_status(LinkStatus::Offline).await;
+            }
+        }
     }
 
     pub async fn set_meters(&mut self, meters: Vec<libwing::Meter>) -> Result<()> {
         {
             let mut guard = self.meters.lock().await;
             *guard = meters;
-            self.wing.request_meter(&*guard).with_context(|| "Failed to request meters")?;
+            self.wing.lock().unwrap().request_meter(&*guard).with_context(|| "Failed to request meters")?;
         }
 
         if self.meter_task_spawned == false {
@@ -312,7 +696,12 @@ impl Console {
     }
 }
 
-fn wing_get_meter_count(meter: &libwing::Meter) -> usize {
+/// Whether a parameter exists in the node tree of the WING
+pub fn path_exists(osc_addr: &str) -> bool {
+    WingConsole::name_to_id(osc_addr).is_some()
+}
+
+pub(crate) fn wing_get_meter_count(meter: &libwing::Meter) -> usize {
     use libwing::Meter;
 
     match meter {
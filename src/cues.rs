@@ -0,0 +1,126 @@
+//! A cue list, stepping through scene recalls, parameter sets and fades with GO and BACK
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tracing::{error, info};
+
+use crate::data::{Fader, SCENE_ACTION_PATH, SCENE_INDEX_PATH};
+use crate::fades::FadeEngine;
+use crate::orchestrator::{Interface, Value};
+use crate::settings::CueSettings;
+
+struct Cue {
+    name: String,
+    scene: Option<i32>,
+    sets: Vec<(String, Value)>,
+    /// Faders to fade, with their target level in dB and the fade time
+    fades: Vec<(Fader, f32, Duration)>,
+}
+
+pub struct CueEngine {
+    cues: Vec<Cue>,
+    /// Index of the cue that was executed last
+    current: std::sync::Mutex<Option<usize>>,
+    fades: Arc<FadeEngine>,
+}
+
+impl CueEngine {
+    pub fn new(settings: &[CueSettings], fades: Arc<FadeEngine>) -> Result<Self> {
+        let cues = settings
+            .iter()
+            .map(|cue| {
+                let cue_fades = cue
+                    .fades
+                    .iter()
+                    .map(|fade| {
+                        let fader = Fader::new_from_label(&fade.fader).with_context(|| {
+                            format!("Fader label '{}' in cue '{}' is invalid", fade.fader, cue.name)
+                        })?;
+                        Ok((fader, fade.level_db, Duration::from_secs_f32(fade.time_secs.max(0.0))))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+
+                Ok(Cue {
+                    name: cue.name.clone(),
+                    scene: cue.scene,
+                    sets: cue.set.iter().map(|s| (s.osc.clone(), s.value.clone())).collect(),
+                    fades: cue_fades,
+                })
+            })
+            .collect::<Result<Vec<Cue>>>()?;
+
+        Ok(Self {
+            cues,
+            current: std::sync::Mutex::new(None),
+            fades,
+        })
+    }
+
+    /// Whether any cues have been defined
+    pub fn is_empty(&self) -> bool {
+        self.cues.is_empty()
+    }
+
+    /// The name of the cue that was executed last, if any
+    pub fn current_name(&self) -> Option<String> {
+        let current = (*self.current.lock().unwrap())?;
+        self.cues.get(current).map(|cue| cue.name.clone())
+    }
+
+    /// Whether there is a cue after the current one
+    pub fn has_next(&self) -> bool {
+        let next = self.current.lock().unwrap().map_or(0, |c| c + 1);
+        next < self.cues.len()
+    }
+
+    /// Whether there is a cue before the current one
+    pub fn has_previous(&self) -> bool {
+        self.current.lock().unwrap().is_some_and(|c| c > 0)
+    }
+
+    /// Execute the next cue, returning its name
+    pub async fn go(&self, interface: &Interface) -> Option<String> {
+        let next = self.current.lock().unwrap().map_or(0, |c| c + 1);
+        self.execute(next, interface).await
+    }
+
+    /// Execute the cue before the current one again, returning its name
+    pub async fn back(&self, interface: &Interface) -> Option<String> {
+        let previous = self.current.lock().unwrap().and_then(|c| c.checked_sub(1))?;
+        self.execute(previous, interface).await
+    }
+
+    async fn execute(&self, index: usize, interface: &Interface) -> Option<String> {
+        let cue = self.cues.get(index)?;
+        *self.current.lock().unwrap() = Some(index);
+
+        info!(index, name = cue.name.as_str(), "Executing cue");
+
+        if let Some(scene) = cue.scene {
+            interface.set_value_broadcast(SCENE_INDEX_PATH, Value::Int(scene)).await;
+            interface
+                .set_value_broadcast(SCENE_ACTION_PATH, Value::Str("GO".to_string()))
+                .await;
+        }
+
+        if !cue.sets.is_empty() {
+            interface.set_values(cue.sets.clone()).await;
+        }
+
+        for (fader, db, duration) in &cue.fades {
+            let engine = self.fades.clone();
+            let interface = interface.clone();
+            let (fader, db, duration) = (fader.clone(), *db, *duration);
+
+            tokio::spawn(async move {
+                if let Err(e) = engine.run(&fader, db, duration, &interface).await {
+                    error!("Failed to fade {:?} in cue: {:?}", fader, e);
+                }
+            });
+        }
+
+        Some(cue.name.clone())
+    }
+}
@@ -1,9 +1,79 @@
 //! Common data types
 
+use std::time::Duration;
+
 use anyhow::{Result, bail};
 use tracing::debug;
 use regex::Regex;
 
+use crate::model::ConsoleModel;
+use crate::settings::EncoderMode;
+
+/// Centre frequencies of the bands of a 31-band graphic EQ
+pub const GEQ_BANDS: [&str; 31] = [
+    "20 Hz", "25 Hz", "31.5 Hz", "40 Hz", "50 Hz", "63 Hz", "80 Hz", "100 Hz", "125 Hz",
+    "160 Hz", "200 Hz", "250 Hz", "315 Hz", "400 Hz", "500 Hz", "630 Hz", "800 Hz", "1 kHz",
+    "1.25 kHz", "1.6 kHz", "2 kHz", "2.5 kHz", "3.15 kHz", "4 kHz", "5 kHz", "6.3 kHz",
+    "8 kHz", "10 kHz", "12.5 kHz", "16 kHz", "20 kHz",
+];
+
+/// Gain range of a graphic EQ band, in dB
+const GEQ_MIN_DB: f64 = -15.0;
+const GEQ_MAX_DB: f64 = 15.0;
+
+/// Show control parameters of the WING library: the index and name of the active scene,
+/// and the action that recalls scenes
+pub const SCENE_INDEX_PATH: &str = "/$ctl/lib/$actidx";
+pub const SCENE_NAME_PATH: &str = "/$ctl/lib/$active";
+pub const SCENE_ACTION_PATH: &str = "/$ctl/lib/$action";
+
+/// Whether any solo is active on the console. Setting it to 0 clears all solos.
+pub const SOLO_ACTIVE_PATH: &str = "/$stat/solo";
+
+/// Index of the strip selected on the console, counting channels, aux, buses, mains,
+/// matrices and DCAs in that order from 0
+pub const SELECTED_STRIP_PATH: &str = "/$ctl/$stat/selidx";
+
+/// Strip types in the order of the console's selection index, and how many there are
+const SELECTION_ORDER: [(&str, i32); 6] = [
+    ("Channel", 40),
+    ("Aux", 8),
+    ("Bus", 16),
+    ("Main", 4),
+    ("Matrix", 8),
+    ("DCA", 16),
+];
+
+/// Range of the strip panning, and of the low cut filter frequency in Hz
+pub const PAN_MIN: f32 = -100.0;
+pub const PAN_MAX: f32 = 100.0;
+pub const LOW_CUT_MIN_HZ: f32 = 20.0;
+pub const LOW_CUT_MAX_HZ: f32 = 2000.0;
+
+/// The parameters of the selected strip edited by the encoders in focus mode, in order
+pub const FOCUS_PARAMETERS: [PathType; 3] = [PathType::Gain, PathType::Panning, PathType::LowCut];
+
+/// The labels of all mixer strips with their type, in the order of the console's strip
+/// selection
+pub fn mixer_strip_labels() -> impl Iterator<Item = (&'static str, String)> {
+    SELECTION_ORDER
+        .into_iter()
+        .flat_map(|(kind, count)| (1..=count).map(move |n| (kind, format!("{} {}", kind, n))))
+}
+
+/// The phantom power switch of a console input, by its group and number
+pub fn phantom_power_path(group: &str, input: i32) -> String {
+    format!("/io/in/{}/{}/vph", group, input)
+}
+
+/// Level range of the WING faders, in dB
+pub const FADER_MIN_DB: f32 = -144.0;
+pub const FADER_MAX_DB: f32 = 10.0;
+
+/// Gain range of the WING preamps, in dB
+pub const PREAMP_MIN_DB: f32 = -3.0;
+pub const PREAMP_MAX_DB: f32 = 45.0;
+
 #[derive(Debug, Clone, PartialEq)]
 enum FaderType {
     Channel,
@@ -12,6 +82,12 @@ enum FaderType {
     Main,
     Matrix,
     DCA,
+    /// A band of a graphic EQ in an FX slot, by its index
+    GeqBand(usize),
+    /// The level of a monitor (solo bus) output
+    Monitor,
+    /// Any other node of the console with the parameters of a strip, by its raw OSC directory
+    Custom,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -22,6 +98,47 @@ pub enum PathType {
     ScribbleColour,
     ScribbleName,
     ScribbleLed,
+    /// Preamp gain
+    Gain,
+    /// Level of a send, by its 1-based bus number
+    SendLevel(u8),
+    /// Comma-separated tags, which include DCA and mute group assignments (e.g. `#D1`)
+    Tags,
+    /// Whether an odd strip is stereo-linked with the next one
+    Link,
+    /// Whether the strip is assigned to the first main bus
+    MainAssign,
+    /// The input group (e.g. `LCL` or `A`) and input number that feed the strip
+    SourceGroup,
+    SourceInput,
+    /// Frequency of the low cut (high-pass) filter
+    LowCut,
+    /// Whether the strip is soloed
+    Solo,
+}
+
+impl PathType {
+    /// A short name of the parameter, for the scribble strips
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            PathType::Gain => "Gain",
+            PathType::Panning => "Pan",
+            PathType::LowCut => "Low Cut",
+            PathType::Fader => "Level",
+            PathType::Mute => "Mute",
+            _ => "",
+        }
+    }
+
+    /// The on/off parameter toggled by a strip button, by its description in the MIDI
+    /// definition
+    pub fn for_strip_button(description: &str) -> Option<Self> {
+        match description.to_lowercase().as_str() {
+            "mute" => Some(PathType::Mute),
+            "solo" => Some(PathType::Solo),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -34,6 +151,16 @@ pub struct Fader {
 
 impl Fader {
     pub fn get_osc_path(&self, path_type: PathType) -> String {
+        if let FaderType::GeqBand(band) = self.fader_type {
+            // GEQ band gains are the numbered parameters of the FX slot
+            return format!("{}/{}", self.osc_directory, band + 1);
+        }
+
+        if self.fader_type == FaderType::Monitor {
+            // Monitors only have a level
+            return format!("{}/lvl", self.osc_directory);
+        }
+
         match path_type {
             PathType::Fader => format!("{}/fdr", self.osc_directory),
             PathType::Panning => format!("{}/pan", self.osc_directory),
@@ -41,10 +168,47 @@ impl Fader {
             PathType::ScribbleColour => format!("{}/$col", self.osc_directory),
             PathType::ScribbleName => format!("{}/$name", self.osc_directory),
             PathType::ScribbleLed => format!("{}led", self.osc_directory),
+            PathType::Gain => format!("{}/in/set/$g", self.osc_directory),
+            PathType::SendLevel(bus) => format!("{}/send/{}/lvl", self.osc_directory, bus),
+            PathType::Tags => format!("{}/tags", self.osc_directory),
+            PathType::Link => format!("{}/$link", self.osc_directory),
+            PathType::MainAssign => format!("{}/main/1/on", self.osc_directory),
+            PathType::SourceGroup => format!("{}/in/conn/grp", self.osc_directory),
+            PathType::SourceInput => format!("{}/in/conn/in", self.osc_directory),
+            PathType::LowCut => format!("{}/flt/lcf", self.osc_directory),
+            PathType::Solo => format!("{}/$solo", self.osc_directory),
         }
     }
 
     pub fn path_matches(&self, osc_path: &str) -> Option<PathType> {
+        if let FaderType::GeqBand(_) | FaderType::Monitor = self.fader_type {
+            return (osc_path == self.get_osc_path(PathType::Fader)).then_some(PathType::Fader);
+        }
+
+        let rest = osc_path.strip_prefix(self.osc_directory.as_str());
+        if rest == Some("/in/set/$g") {
+            return Some(PathType::Gain);
+        }
+        if rest == Some("/main/1/on") {
+            return Some(PathType::MainAssign);
+        }
+        if rest == Some("/in/conn/grp") {
+            return Some(PathType::SourceGroup);
+        }
+        if rest == Some("/in/conn/in") {
+            return Some(PathType::SourceInput);
+        }
+        if rest == Some("/flt/lcf") {
+            return Some(PathType::LowCut);
+        }
+        if let Some(bus) = rest
+            .and_then(|r| r.strip_prefix("/send/"))
+            .and_then(|r| r.strip_suffix("/lvl"))
+            .and_then(|bus| bus.parse().ok())
+        {
+            return Some(PathType::SendLevel(bus));
+        }
+
         let parts: Vec<&str> = osc_path.rsplitn(2, '/').collect();
 
         if parts.len() != 2 {
@@ -62,6 +226,9 @@ impl Fader {
             "$col" => Some(PathType::ScribbleColour),
             "$name" => Some(PathType::ScribbleName),
             "led" => Some(PathType::ScribbleLed),
+            "tags" => Some(PathType::Tags),
+            "$link" => Some(PathType::Link),
+            "$solo" => Some(PathType::Solo),
             _ => None,
         }
     }
@@ -95,7 +262,192 @@ impl Fader {
         &self.wing_meter
     }
 
+    /// The OSC node with the parameters of this fader, e.g. `/ch/1`
+    pub fn osc_directory(&self) -> &str {
+        &self.osc_directory
+    }
+
+    /// Whether this strip has a preamp gain, i.e. it is an input
+    pub fn has_gain(&self) -> bool {
+        matches!(self.fader_type, FaderType::Channel | FaderType::Aux)
+    }
+
+    /// The number of this DCA, if it is one
+    pub fn dca_number(&self) -> Option<u8> {
+        if self.fader_type != FaderType::DCA {
+            return None;
+        }
+
+        self.osc_directory.rsplit('/').next()?.parse().ok()
+    }
+
+    /// Whether this strip is assigned to a DCA, according to its tags
+    pub fn tags_include_dca(tags: &str, dca: u8) -> bool {
+        let tag = format!("#D{}", dca);
+        tags.split([',', ' ']).any(|t| t.trim() == tag)
+    }
+
+    /// The strip at an index of the console's strip selection
+    pub fn new_from_selection_index(index: i32) -> Option<Fader> {
+        let mut first = 0;
+        for (kind, count) in SELECTION_ORDER {
+            if index >= first && index < first + count {
+                return Self::new_from_label(&format!("{} {}", kind, index - first + 1)).ok();
+            }
+            first += count;
+        }
+
+        None
+    }
+
+    /// The index of this strip in the console's strip selection
+    pub fn selection_index(&self) -> Option<i32> {
+        let kind = match self.fader_type {
+            FaderType::Channel => "Channel",
+            FaderType::Aux => "Aux",
+            FaderType::Bus => "Bus",
+            FaderType::Main => "Main",
+            FaderType::Matrix => "Matrix",
+            FaderType::DCA => "DCA",
+            _ => return None,
+        };
+        let number: i32 = self.osc_directory.rsplit('/').next()?.parse().ok()?;
+
+        let mut first = 0;
+        for (order_kind, count) in SELECTION_ORDER {
+            if order_kind == kind {
+                return (number >= 1 && number <= count).then_some(first + number - 1);
+            }
+            first += count;
+        }
+
+        None
+    }
+
+    /// Whether a console model has this strip. Strips other than those of the mixer, such as
+    /// graphic EQ bands, are assumed to exist.
+    pub fn exists_on(&self, model: ConsoleModel) -> bool {
+        let kind = match self.fader_type {
+            FaderType::Channel => "Channel",
+            FaderType::Aux => "Aux",
+            FaderType::Bus => "Bus",
+            FaderType::Main => "Main",
+            FaderType::Matrix => "Matrix",
+            FaderType::DCA => "DCA",
+            _ => return true,
+        };
+
+        self.osc_directory
+            .rsplit('/')
+            .next()
+            .and_then(|number| number.parse::<i32>().ok())
+            .is_some_and(|number| number <= model.strip_count(kind))
+    }
+
+    /// Whether this strip has a low cut filter
+    pub fn has_low_cut(&self) -> bool {
+        matches!(self.fader_type, FaderType::Channel | FaderType::Aux)
+    }
+
+    /// Whether this strip can be panned
+    pub fn has_panning(&self) -> bool {
+        !matches!(self.fader_type, FaderType::DCA | FaderType::GeqBand(_) | FaderType::Monitor)
+    }
+
+    /// The even strip that this odd strip can be stereo-linked with
+    pub fn link_partner(&self) -> Option<Fader> {
+        let prefix = match self.fader_type {
+            FaderType::Channel => "Channel",
+            FaderType::Aux => "Aux",
+            _ => return None,
+        };
+
+        let index: u8 = self.osc_directory.rsplit('/').next()?.parse().ok()?;
+        if index % 2 == 0 {
+            return None;
+        }
+
+        let partner = Self::new_from_label(&format!("{} {}", prefix, index + 1)).ok()?;
+        Some(match self.console_id() {
+            Some(console_id) => partner.on_console(console_id),
+            None => partner,
+        })
+    }
+
+    /// The same strip on another console than the first one
+    pub fn on_console(mut self, console_id: &str) -> Self {
+        self.osc_directory = format!("@{}{}", console_id, self.osc_directory);
+        // Meters are only received from the first console
+        self.wing_meter = None;
+        self
+    }
+
+    /// The identifier of the console this strip is on, if it is not the first one
+    pub fn console_id(&self) -> Option<&str> {
+        let rest = self.osc_directory.strip_prefix('@')?;
+        rest.split('/').next()
+    }
+
+    /// Whether this strip has sends to the buses
+    pub fn has_sends(&self) -> bool {
+        matches!(self.fader_type, FaderType::Channel | FaderType::Aux)
+    }
+
+    /// Whether this strip can be assigned to the main buses
+    pub fn has_main_assign(&self) -> bool {
+        matches!(self.fader_type, FaderType::Channel | FaderType::Aux | FaderType::Bus)
+    }
+
+    /// A fixed name for faders that have no name on the console
+    pub fn label(&self) -> Option<String> {
+        match self.fader_type {
+            FaderType::GeqBand(band) => GEQ_BANDS.get(band).map(|b| b.to_string()),
+            FaderType::Monitor => Some("Monitor".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Convert a fader parameter value to a fader position between 0 and 1
+    pub fn value_to_position(&self, value: f64) -> f64 {
+        match self.fader_type {
+            FaderType::GeqBand(_) => {
+                ((value - GEQ_MIN_DB) / (GEQ_MAX_DB - GEQ_MIN_DB)).clamp(0.0, 1.0)
+            }
+            _ => Self::db_to_float(value),
+        }
+    }
+
+    /// Convert a fader position between 0 and 1 to a fader parameter value
+    pub fn position_to_value(&self, position: f64) -> f64 {
+        match self.fader_type {
+            FaderType::GeqBand(_) => GEQ_MIN_DB + position * (GEQ_MAX_DB - GEQ_MIN_DB),
+            _ => Self::float_to_db(position),
+        }
+    }
+
+    /// A band of the graphic EQ in an FX slot
+    pub fn new_geq_band(fx_slot: u8, band: usize) -> Self {
+        Self {
+            osc_directory: format!("/fx/{}", fx_slot),
+            fader_type: FaderType::GeqBand(band),
+            wing_meter: None,
+        }
+    }
+
     pub fn new_from_label(label: &str) -> Result<Self> {
+        // Strips on other consoles are prefixed with their ID: "@stream Channel 1"
+        if let Some(rest) = label.strip_prefix('@') {
+            let (console_id, label) = rest
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhow::anyhow!("Fader label missing after console: {}", rest))?;
+            return Ok(Self::new_from_label(label.trim())?.on_console(console_id));
+        }
+
+        // Raw OSC directories: "/ch/7", or nodes without a label such as "/fx/3"
+        if label.starts_with('/') {
+            return Self::new_from_directory(label);
+        }
+
         // Label has format: "Channel 1"/"Matrix 4"
         let re = Regex::new(r"^(\w+)\s*(\d+)?$").unwrap();
         if let Some(caps) = re.captures(label) {
@@ -109,9 +461,19 @@ impl Fader {
                 "main" | "lr" => FaderType::Main,
                 "matrix" | "mtx" => FaderType::Matrix,
                 "dca" => FaderType::DCA,
+                "monitor" | "mon" => FaderType::Monitor,
                 _ => bail!("Unknown fader type: {}", base),
             };
 
+            if fader_type == FaderType::Monitor {
+                // Monitor 1 is the default, e.g. for the headphones
+                return Ok(Self {
+                    osc_directory: format!("/cfg/mon/{}", index.as_deref().unwrap_or("1")),
+                    fader_type,
+                    wing_meter: None,
+                });
+            }
+
             if let Some(index) = index {
                 let osc_directory = match fader_type {
                     FaderType::Channel => format!("/ch/{}", index),
@@ -153,6 +515,47 @@ impl Fader {
             bail!("Invalid fader label format: {}", label);
         }
     }
+
+    /// The fader of a raw OSC directory. Directories of the regular strips are the same as
+    /// their labels, so that e.g. their meters work.
+    fn new_from_directory(directory: &str) -> Result<Self> {
+        let directory = directory.trim_end_matches('/');
+        if directory.is_empty() || directory.contains(char::is_whitespace) {
+            bail!("Invalid OSC directory: '{}'", directory);
+        }
+
+        let re = Regex::new(r"^/(ch|aux|bus|main|mtx|dca)/(\d+)$").unwrap();
+        if let Some(caps) = re.captures(directory) {
+            return Self::new_from_label(&format!("{} {}", &caps[1], &caps[2]));
+        }
+
+        Ok(Self {
+            osc_directory: directory.to_string(),
+            fader_type: FaderType::Custom,
+            wing_meter: None,
+        })
+    }
+
+    /// The labels of the faders in a range such as "Channel 1-8" or "@stream Bus 1..16", or
+    /// None if the label is not a range
+    pub fn expand_label_range(label: &str) -> Result<Option<Vec<String>>> {
+        let re = Regex::new(r"^(@\S+\s+)?([A-Za-z]+)\s*(\d+)\s*(?:-|\.\.)\s*(\d+)$").unwrap();
+        let Some(caps) = re.captures(label.trim()) else {
+            return Ok(None);
+        };
+
+        let console = caps.get(1).map_or("", |m| m.as_str());
+        let base = &caps[2];
+        let (first, last) = match (caps[3].parse::<u8>(), caps[4].parse::<u8>()) {
+            (Ok(first), Ok(last)) if first <= last => (first, last),
+            _ => bail!("Invalid fader range: {}", label),
+        };
+
+        let labels = (first..=last)
+            .map(|index| format!("{}{} {}", console, base, index))
+            .collect();
+        Ok(Some(labels))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -169,10 +572,142 @@ impl OscButton {
     }
 }
 
+/// The fader that a function acts on
+#[derive(Debug, Clone, PartialEq)]
+pub enum FaderTarget {
+    /// A visible strip, by its index
+    Strip(usize),
+    /// The fixed fader after the strips
+    Master,
+    /// A fader by its label, whether it is visible or not
+    Fader(Fader),
+    /// The strip that is currently touched
+    Touched,
+    /// The strip chosen with the Select buttons
+    Selected,
+    /// The touched strip, or the selected one if no strip is touched
+    Active,
+}
+
+impl FaderTarget {
+    pub fn new_from_label(label: &str) -> Result<Self> {
+        let lower = label.to_lowercase();
+        match lower.as_str() {
+            "master" => return Ok(FaderTarget::Master),
+            "touched" => return Ok(FaderTarget::Touched),
+            "selected" => return Ok(FaderTarget::Selected),
+            "" | "active" => return Ok(FaderTarget::Active),
+            _ => {}
+        }
+
+        if let Some(strip) = lower.strip_prefix("strip ") {
+            // Strips are numbered from 1 on the surface
+            let index: usize = strip
+                .trim()
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| anyhow::anyhow!("Invalid strip number: {}", label))?;
+            return Ok(FaderTarget::Strip(index - 1));
+        }
+
+        Ok(FaderTarget::Fader(Fader::new_from_label(label)?))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum InternalFunction {
     PreviousBank,
     NextBank,
+    /// Shift the visible strips of the current bank by one
+    PreviousChannel,
+    NextChannel,
+    /// Toggle an on/off OSC parameter between 0 and 1
+    Toggle(String),
+    /// Set an on/off OSC parameter to 1 while the button is held
+    Momentary(String),
+    /// Run a user-defined macro
+    Macro(String),
+    /// Control the bands of the graphic EQ in an FX slot with the faders
+    Geq(u8),
+    /// Switch what the channel strip encoders control
+    EncoderMode(EncoderMode),
+    /// Recall the next or previous scene of the show
+    NextScene,
+    PreviousScene,
+    /// Recall a scene by its index in the show
+    Scene(i32),
+    /// Set a delay time parameter, in ms, from the interval between presses
+    TapTempo(String),
+    /// Show the members of a DCA on the strips
+    SpillDca(u8),
+    /// Ignore all other input until the surface is unlocked with the same function
+    LockSurface,
+    /// Show a test pattern on the whole surface until the same function switches it off
+    VegasMode,
+    /// Show the phantom power of the visible strips, and allow switching it with a long
+    /// press of their encoders
+    PhantomMode,
+    /// Switch the phantom power of the source of a visible strip, by its index
+    TogglePhantom(usize),
+    /// Show the console's RTA on the strip meters, each strip showing a group of bands
+    RtaMode,
+    /// Mute or unmute all strips of the configured Mute All set
+    MuteAll,
+    UnmuteAll,
+    /// Clear all active solos
+    ClearSolo,
+    /// Raise (1) or lower (-1) the level of a fader by a small step
+    Nudge(FaderTarget, i32),
+    /// Set the level of a fader to exactly this value, in dB
+    Snap(FaderTarget, f32),
+    /// Move a fader gradually to a level in dB over some time
+    Fade(FaderTarget, f32, Duration),
+    /// Execute the next cue of the cue list, or the previous one again
+    CueGo,
+    CueBack,
+    /// Store the current parameters in a named snapshot, or restore them from it
+    SaveSnapshot(String),
+    RecallSnapshot(String),
+    /// Switch the A/B compare parameters between the stored and the changed values, or
+    /// forget the stored values
+    AbCompare,
+    AbReset,
+    /// Switch safe mode, where only the configured parameters can be changed
+    SafeMode,
+}
+
+impl InternalFunction {
+    /// The OSC parameter this function acts on, if any
+    pub fn osc_path(&self) -> Option<&str> {
+        match self {
+            InternalFunction::Toggle(path) | InternalFunction::Momentary(path) => Some(path),
+            InternalFunction::Scene(_) => Some(SCENE_INDEX_PATH),
+            InternalFunction::TapTempo(path) => Some(path),
+            InternalFunction::ClearSolo => Some(SOLO_ACTIVE_PATH),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a duration such as "5s", "1.5 s" or "500ms"
+fn parse_duration(text: &str) -> Result<Duration> {
+    let lower = text.to_lowercase();
+    let (number, scale) = if let Some(ms) = lower.strip_suffix("ms") {
+        (ms, 0.001)
+    } else {
+        (lower.strip_suffix('s').unwrap_or(&lower), 1.0)
+    };
+
+    let seconds: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid duration: {}", text))?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        bail!("Invalid duration: {}", text);
+    }
+
+    Ok(Duration::from_secs_f64(seconds * scale))
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -186,7 +721,138 @@ impl InternalButton {
         let function = match label.to_lowercase().as_str() {
             "previous bank" => InternalFunction::PreviousBank,
             "next bank" => InternalFunction::NextBank,
-            _ => bail!("Unknown internal button function: {}", label),
+            "previous channel" => InternalFunction::PreviousChannel,
+            "next channel" => InternalFunction::NextChannel,
+            "lock surface" => InternalFunction::LockSurface,
+            "vegas" | "vegas mode" => InternalFunction::VegasMode,
+            "phantom mode" => InternalFunction::PhantomMode,
+            "rta" | "rta mode" => InternalFunction::RtaMode,
+            "mute all" => InternalFunction::MuteAll,
+            "unmute all" => InternalFunction::UnmuteAll,
+            "clear solo" => InternalFunction::ClearSolo,
+            "a/b" | "ab compare" => InternalFunction::AbCompare,
+            "a/b reset" | "ab reset" => InternalFunction::AbReset,
+            "safe mode" => InternalFunction::SafeMode,
+            "go" | "cue go" => InternalFunction::CueGo,
+            "back" | "cue back" => InternalFunction::CueBack,
+            "next scene" => InternalFunction::NextScene,
+            "previous scene" => InternalFunction::PreviousScene,
+            _ => {
+                // Functions with an argument, e.g. "Mute Channel 1" or "Toggle /ch/1/mute"
+                let (name, argument) = label
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow::anyhow!("Unknown internal button function: {}", label))?;
+                let argument = argument.trim();
+
+                match name.to_lowercase().as_str() {
+                    "mute" => InternalFunction::Toggle(
+                        Fader::new_from_label(argument)?.get_osc_path(PathType::Mute),
+                    ),
+                    "toggle" => InternalFunction::Toggle(argument.to_string()),
+                    "momentary" => InternalFunction::Momentary(argument.to_string()),
+                    "macro" => InternalFunction::Macro(argument.to_string()),
+                    "nudge" => {
+                        // "Nudge <target> up/down", e.g. "Nudge Strip 1 up" or "Nudge Master -"
+                        let (target, direction) = argument
+                            .rsplit_once(' ')
+                            .ok_or_else(|| anyhow::anyhow!("Invalid nudge button: {}", label))?;
+                        let direction = match direction.to_lowercase().as_str() {
+                            "up" | "+" => 1,
+                            "down" | "-" => -1,
+                            _ => bail!("Unknown nudge direction: {}", direction),
+                        };
+                        InternalFunction::Nudge(FaderTarget::new_from_label(target.trim())?, direction)
+                    }
+                    "snap" => {
+                        // "Snap <target> unity/-inf", where the target defaults to the
+                        // touched or selected strip, e.g. "Snap unity" or "Snap Master -inf"
+                        let (target, level) = argument.rsplit_once(' ').unwrap_or(("", argument));
+                        let level = match level.to_lowercase().as_str() {
+                            "unity" | "0" => 0.0,
+                            "-inf" | "off" => FADER_MIN_DB,
+                            _ => bail!("Unknown snap level: {}", level),
+                        };
+                        InternalFunction::Snap(FaderTarget::new_from_label(target.trim())?, level)
+                    }
+                    "save" | "recall" => {
+                        // "Save Snapshot <name>" or "Recall Snapshot <name>"
+                        let snapshot = argument
+                            .split_once(' ')
+                            .filter(|(word, _)| word.eq_ignore_ascii_case("snapshot"))
+                            .map(|(_, snapshot)| snapshot.trim().to_string())
+                            .ok_or_else(|| anyhow::anyhow!("Invalid snapshot button: {}", label))?;
+
+                        if name.eq_ignore_ascii_case("save") {
+                            InternalFunction::SaveSnapshot(snapshot)
+                        } else {
+                            InternalFunction::RecallSnapshot(snapshot)
+                        }
+                    }
+                    "fade" => {
+                        // "Fade out <target> over 5s", "Fade in <target> over 2s" or
+                        // "Fade <target> to -10 over 500ms"
+                        let (fade, duration) = argument
+                            .rsplit_once(" over ")
+                            .ok_or_else(|| anyhow::anyhow!("Fade button needs a duration: {}", label))?;
+                        let duration = parse_duration(duration.trim())?;
+
+                        let lower = fade.to_lowercase();
+                        let (target, level) = if let Some(target) = lower.strip_prefix("out ") {
+                            (target, FADER_MIN_DB)
+                        } else if let Some(target) = lower.strip_prefix("in ") {
+                            (target, 0.0)
+                        } else {
+                            let (target, level) = lower
+                                .rsplit_once(" to ")
+                                .ok_or_else(|| anyhow::anyhow!("Invalid fade button: {}", label))?;
+                            let level = match level.trim() {
+                                "-inf" | "off" => FADER_MIN_DB,
+                                "unity" => 0.0,
+                                level => level
+                                    .trim_end_matches("db")
+                                    .trim()
+                                    .parse()
+                                    .map_err(|_| anyhow::anyhow!("Invalid fade level: {}", level))?,
+                            };
+                            (target, level)
+                        };
+
+                        InternalFunction::Fade(FaderTarget::new_from_label(target.trim())?, level, duration)
+                    }
+                    "tap" => {
+                        // "Tap Tempo <FX slot>"
+                        let slot: u8 = argument
+                            .split_once(' ')
+                            .filter(|(word, _)| word.eq_ignore_ascii_case("tempo"))
+                            .and_then(|(_, slot)| slot.trim().parse().ok())
+                            .ok_or_else(|| anyhow::anyhow!("Invalid tap tempo button: {}", label))?;
+                        InternalFunction::TapTempo(format!("/fx/{}/time", slot))
+                    }
+                    "spill" => InternalFunction::SpillDca(
+                        Fader::new_from_label(argument)?
+                            .dca_number()
+                            .ok_or_else(|| anyhow::anyhow!("Only DCAs can be spilled: {}", label))?,
+                    ),
+                    "scene" => InternalFunction::Scene(
+                        argument
+                            .parse()
+                            .map_err(|_| anyhow::anyhow!("Invalid scene index: {}", argument))?,
+                    ),
+                    "geq" => InternalFunction::Geq(
+                        argument
+                            .parse()
+                            .map_err(|_| anyhow::anyhow!("Invalid FX slot: {}", argument))?,
+                    ),
+                    "encoders" => InternalFunction::EncoderMode(match argument.to_lowercase().as_str() {
+                        "off" => EncoderMode::Off,
+                        "gain" => EncoderMode::Gain,
+                        "sends" => EncoderMode::Sends,
+                        "focus" => EncoderMode::Focus,
+                        _ => bail!("Unknown encoder mode: {}", argument),
+                    }),
+                    _ => bail!("Unknown internal button function: {}", label),
+                }
+            }
         };
 
         Ok(Self { function })
@@ -0,0 +1,158 @@
+//! Definitions of console parameters, as reported by the console, to validate values
+//! before they are written
+
+use anyhow::{Result, bail};
+use libwing::{NodeType, WingNodeDef};
+
+use crate::orchestrator::Value;
+
+/// The kind of values a parameter holds, and their limits
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueKind {
+    /// A node with children, which has no value itself
+    Node,
+    Float { min: f32, max: f32 },
+    Int { min: i32, max: i32 },
+    /// An integer that is either 0 or 1
+    Bool,
+    /// One of a list of strings
+    Enum(Vec<String>),
+    Str { max_len: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeDefinition {
+    pub kind: ValueKind,
+    pub unit: String,
+    pub read_only: bool,
+}
+
+impl NodeDefinition {
+    pub fn from_wing(def: &WingNodeDef) -> Self {
+        let kind = match def.node_type {
+            NodeType::Node => ValueKind::Node,
+            NodeType::LinearFloat | NodeType::LogarithmicFloat | NodeType::FaderLevel => {
+                ValueKind::Float {
+                    min: def.min_float,
+                    max: def.max_float,
+                }
+            }
+            NodeType::FloatEnum => {
+                let values = def.float_enum.iter().map(|item| item.item);
+                ValueKind::Float {
+                    min: values.clone().fold(f32::INFINITY, f32::min),
+                    max: values.fold(f32::NEG_INFINITY, f32::max),
+                }
+            }
+            NodeType::Integer if Self::is_switch(def) => ValueKind::Bool,
+            NodeType::Integer => ValueKind::Int {
+                min: def.min_int,
+                max: def.max_int,
+            },
+            NodeType::StringEnum => {
+                ValueKind::Enum(def.string_enum.iter().map(|item| item.item.clone()).collect())
+            }
+            NodeType::String => ValueKind::Str {
+                max_len: def.max_string_len as usize,
+            },
+        };
+
+        Self {
+            kind,
+            unit: format!("{:?}", def.unit),
+            read_only: def.read_only,
+        }
+    }
+
+    /// Whether a parameter is an on/off switch
+    pub fn is_switch(def: &WingNodeDef) -> bool {
+        matches!(def.node_type, NodeType::Integer) && def.min_int == 0 && def.max_int == 1
+    }
+
+    /// Check that a value can be written to this parameter, converting it to the parameter's
+    /// type and clamping it to its range
+    pub fn validate(&self, value: Value) -> Result<Value> {
+        if self.read_only {
+            bail!("Parameter is read-only");
+        }
+
+        Ok(match (&self.kind, value) {
+            (ValueKind::Node, _) => bail!("Parameter is a node without a value"),
+
+            (ValueKind::Float { min, max }, value) => {
+                let number = match value {
+                    Value::Float(f) => f,
+                    Value::Int(i) => i as f32,
+                    Value::Bool(on) => f32::from(u8::from(on)),
+                    Value::Str(s) => match s.trim().parse() {
+                        Ok(f) => f,
+                        Err(_) => bail!("Expected a number, got '{}'", s),
+                    },
+                };
+                // Definitions without a range have both limits at zero
+                Value::Float(if min < max { number.clamp(*min, *max) } else { number })
+            }
+
+            (ValueKind::Int { min, max }, value) => {
+                let number = match value {
+                    Value::Int(i) => i,
+                    Value::Float(f) => f.round() as i32,
+                    Value::Bool(on) => i32::from(on),
+                    Value::Str(s) => match s.trim().to_lowercase().as_str() {
+                        "on" | "true" | "yes" => 1,
+                        "off" | "false" | "no" => 0,
+                        other => match other.parse() {
+                            Ok(i) => i,
+                            Err(_) => bail!("Expected a whole number, got '{}'", s),
+                        },
+                    },
+                };
+                Value::Int(if min < max { number.clamp(*min, *max) } else { number })
+            }
+
+            (ValueKind::Bool, value) => Value::Bool(match value {
+                Value::Bool(on) => on,
+                Value::Int(i) => i != 0,
+                Value::Float(f) => f.round() != 0.0,
+                Value::Str(s) => match s.trim().to_lowercase().as_str() {
+                    "on" | "true" | "yes" | "1" => true,
+                    "off" | "false" | "no" | "0" => false,
+                    _ => bail!("Expected on or off, got '{}'", s),
+                },
+            }),
+
+            (ValueKind::Enum(items), Value::Str(s)) => {
+                match items.iter().find(|item| item.eq_ignore_ascii_case(s.trim())) {
+                    Some(item) => Value::Str(item.clone()),
+                    None => bail!("'{}' is not one of {}", s, items.join(", ")),
+                }
+            }
+            (ValueKind::Enum(items), Value::Bool(on)) => {
+                if items.len() < 2 {
+                    bail!("Option {} is out of range, there are {}", i32::from(on), items.len());
+                }
+                Value::Int(i32::from(on))
+            }
+            (ValueKind::Enum(items), Value::Int(i)) => {
+                if i < 0 || i as usize >= items.len() {
+                    bail!("Option {} is out of range, there are {}", i, items.len());
+                }
+                Value::Int(i)
+            }
+            (ValueKind::Enum(_), Value::Float(f)) => bail!("Expected one of the options, got {}", f),
+
+            (ValueKind::Str { max_len }, value) => {
+                let text = match value {
+                    Value::Str(s) => s,
+                    Value::Int(i) => i.to_string(),
+                    Value::Float(f) => f.to_string(),
+                    Value::Bool(on) => i32::from(on).to_string(),
+                };
+                match *max_len {
+                    0 => Value::Str(text),
+                    _ => Value::Str(text.chars().take(*max_len).collect()),
+                }
+            }
+        })
+    }
+}
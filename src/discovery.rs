@@ -0,0 +1,136 @@
+//! Discovery of WING consoles on the local network
+//!
+//! Consoles answer a `WING?` datagram on the discovery port with a comma-separated
+//! description of themselves: `WING,<ip>,<name>,<model>,<serial>,<firmware>`.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use tokio::net::UdpSocket;
+use tokio::time::{Instant, timeout_at};
+use tracing::{debug, info};
+
+const DISCOVERY_PORT: u16 = 2222;
+const DISCOVERY_REQUEST: &[u8] = b"WING?";
+/// How long to wait for consoles to answer
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A console that answered the discovery request
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredConsole {
+    pub ip: String,
+    pub name: String,
+    pub model: String,
+    pub serial: String,
+    pub firmware: String,
+}
+
+impl DiscoveredConsole {
+    fn parse(response: &str) -> Option<Self> {
+        let mut fields = response.trim_end_matches('\0').trim().split(',');
+        if fields.next()? != "WING" {
+            return None;
+        }
+
+        Some(Self {
+            ip: fields.next()?.to_string(),
+            name: fields.next()?.to_string(),
+            model: fields.next()?.to_string(),
+            serial: fields.next()?.to_string(),
+            firmware: fields.next().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+/// Broadcast a discovery request, and collect the consoles that answer in time
+pub async fn discover() -> Result<Vec<DiscoveredConsole>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .with_context(|| "Failed to bind console discovery socket")?;
+    socket.set_broadcast(true)?;
+    socket
+        .send_to(DISCOVERY_REQUEST, ("255.255.255.255", DISCOVERY_PORT))
+        .await
+        .with_context(|| "Failed to broadcast console discovery request")?;
+
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    let mut consoles = Vec::new();
+    let mut buf = [0u8; 512];
+
+    while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, addr) = received?;
+        let response = String::from_utf8_lossy(&buf[..len]);
+
+        match DiscoveredConsole::parse(&response) {
+            Some(console) if !consoles.contains(&console) => {
+                debug!(?console, "Console discovered");
+                consoles.push(console);
+            }
+            Some(_) => {}
+            None => debug!(%addr, response = %response, "Ignoring unknown discovery response"),
+        }
+    }
+
+    Ok(consoles)
+}
+
+/// Ask the console at an address to describe itself
+pub async fn identify(ip: &str) -> Result<DiscoveredConsole> {
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .await
+        .with_context(|| "Failed to bind console discovery socket")?;
+    socket
+        .send_to(DISCOVERY_REQUEST, (ip, DISCOVERY_PORT))
+        .await
+        .with_context(|| format!("Failed to send identify request to {}", ip))?;
+
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    let mut buf = [0u8; 512];
+
+    loop {
+        let (len, _) = timeout_at(deadline, socket.recv_from(&mut buf))
+            .await
+            .with_context(|| format!("Console at {} did not identify itself", ip))??;
+
+        if let Some(console) = DiscoveredConsole::parse(&String::from_utf8_lossy(&buf[..len])) {
+            debug!(?console, "Console identified");
+            return Ok(console);
+        }
+    }
+}
+
+/// Find the address of the only console on the network, or the one with this name
+pub async fn find_console(name: Option<&str>) -> Result<String> {
+    info!(name, "Searching for consoles on the network");
+
+    let consoles = discover().await?;
+    let candidates: Vec<&DiscoveredConsole> = consoles
+        .iter()
+        .filter(|c| name.is_none_or(|name| c.name.eq_ignore_ascii_case(name)))
+        .collect();
+
+    match candidates.as_slice() {
+        [console] => {
+            info!(ip = console.ip.as_str(), name = console.name.as_str(), "Console found");
+            Ok(console.ip.clone())
+        }
+        [] if consoles.is_empty() => bail!("No consoles found on the network"),
+        [] => bail!(
+            "No console named '{}' found, only {}",
+            name.unwrap_or_default(),
+            describe(&consoles)
+        ),
+        _ => bail!(
+            "Several consoles found, set console.ip or console.name to pick one of {}",
+            describe(&consoles)
+        ),
+    }
+}
+
+fn describe(consoles: &[DiscoveredConsole]) -> String {
+    consoles
+        .iter()
+        .map(|c| format!("'{}' ({})", c.name, c.ip))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
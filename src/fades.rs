@@ -0,0 +1,118 @@
+//! Timed fades, moving fader levels gradually to a target
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+use crate::data::{Fader, PathType};
+use crate::orchestrator::{Interface, Value};
+
+/// How often the level of a fading fader is updated
+const FADE_UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct FadeEngine {
+    /// The ID of the fade running on each fader parameter
+    running: std::sync::Mutex<HashMap<String, u64>>,
+    fade_counter: std::sync::atomic::AtomicU64,
+}
+
+impl FadeEngine {
+    pub fn new() -> Self {
+        Self {
+            running: std::sync::Mutex::new(HashMap::new()),
+            fade_counter: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+
+    /// Whether a fader is currently fading
+    pub fn is_fading(&self, fader: &Fader) -> bool {
+        self.running
+            .lock()
+            .unwrap()
+            .contains_key(&fader.get_osc_path(PathType::Fader))
+    }
+
+    /// Stop the fade of a fader where it is, returning whether there was one
+    pub fn cancel(&self, fader: &Fader) -> bool {
+        let osc_path = fader.get_osc_path(PathType::Fader);
+        let cancelled = self.running.lock().unwrap().remove(&osc_path).is_some();
+
+        if cancelled {
+            info!(osc = osc_path.as_str(), "Fade cancelled");
+        }
+
+        cancelled
+    }
+
+    /// Move a fader from its current level to a target level in dB over some time,
+    /// replacing any fade that is already running on it.
+    ///
+    /// The level moves evenly along the fader travel rather than in dB, like a hand would.
+    pub async fn run(
+        &self,
+        fader: &Fader,
+        target_db: f32,
+        duration: Duration,
+        interface: &Interface,
+    ) -> Result<()> {
+        let osc_path = fader.get_osc_path(PathType::Fader);
+
+        let start_db = match interface.get_value(&osc_path, false).await? {
+            Value::Float(db) => db,
+            other => bail!("Expected float value for fader, got {:?}", other),
+        };
+
+        let id = self
+            .fade_counter
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.running.lock().unwrap().insert(osc_path.clone(), id);
+
+        info!(osc = osc_path.as_str(), start_db, target_db, ?duration, "Starting fade");
+
+        let start_position = fader.value_to_position(start_db as f64);
+        let target_position = fader.value_to_position(target_db as f64);
+        let started = Instant::now();
+
+        let mut interval = tokio::time::interval(FADE_UPDATE_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            if self.running.lock().unwrap().get(&osc_path) != Some(&id) {
+                // Cancelled, or replaced by another fade
+                return Ok(());
+            }
+
+            let progress = if duration.is_zero() {
+                1.0
+            } else {
+                (started.elapsed().as_secs_f64() / duration.as_secs_f64()).min(1.0)
+            };
+
+            let db = if progress >= 1.0 {
+                // Land exactly on the target, e.g. -inf
+                target_db
+            } else {
+                let position = start_position + (target_position - start_position) * progress;
+                fader.position_to_value(position) as f32
+            };
+
+            interface.set_value_broadcast(&osc_path, Value::Float(db)).await;
+
+            if progress >= 1.0 {
+                break;
+            }
+        }
+
+        let mut running = self.running.lock().unwrap();
+        if running.get(&osc_path) == Some(&id) {
+            running.remove(&osc_path);
+        }
+
+        debug!(osc = osc_path.as_str(), "Fade finished");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+//! Secondary MIDI controllers whose messages are mapped directly to OSC parameters
+
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use midly::live::LiveEvent;
+use midly::{MidiMessage, PitchBend};
+use tokio::runtime::Handle;
+use tracing::{debug, info, trace, warn};
+
+use crate::orchestrator::{Interface, LinkStatus, Value, WriteProvider};
+use crate::settings::{GenericMidiMapping, GenericMidiMessage, GenericMidiSettings};
+use crate::transforms::Transforms;
+
+type SharedInterface = Arc<std::sync::Mutex<Option<Interface>>>;
+
+pub struct GenericController {
+    _input: MidiInputConnection<(Arc<Vec<GenericMidiMapping>>, SharedInterface, Handle)>,
+    output: Option<std::sync::Mutex<MidiOutputConnection>>,
+    mappings: Arc<Vec<GenericMidiMapping>>,
+    transforms: Transforms,
+    interface: SharedInterface,
+}
+
+impl GenericController {
+    pub fn new(settings: &GenericMidiSettings) -> Result<Self> {
+        let mappings = Arc::new(settings.mappings.clone());
+        let transforms = Transforms::new(&settings.transforms)?;
+        let interface: SharedInterface = Arc::new(std::sync::Mutex::new(None));
+
+        if let Some(mapping) = mappings.iter().find(|m| m.channel > 15 || m.number > 127) {
+            anyhow::bail!("Invalid MIDI channel or number in mapping for {}", mapping.osc);
+        }
+
+        let input = MidiInput::new("X-Touch Wing Generic IN")?;
+        let ports = input.ports();
+        let input_port = ports
+            .iter()
+            .find(|p| input.port_name(p).ok().as_deref() == Some(&settings.input))
+            .ok_or_else(|| anyhow!("MIDI input port '{}' not found", settings.input))?;
+
+        let input_connection = input
+            .connect(
+                input_port,
+                "xtouch-wing-generic-input",
+                generic_midi_callback,
+                (mappings.clone(), interface.clone(), Handle::current()),
+            )
+            .map_err(|e| anyhow!("MIDI input connect failed: {}", e))?;
+
+        let output_connection = match &settings.output {
+            Some(output_name) => {
+                let output = MidiOutput::new("X-Touch Wing Generic OUT")?;
+                let ports = output.ports();
+                let output_port = ports
+                    .iter()
+                    .find(|p| output.port_name(p).ok().as_deref() == Some(output_name))
+                    .ok_or_else(|| anyhow!("MIDI output port '{}' not found", output_name))?;
+
+                Some(
+                    output
+                        .connect(output_port, "xtouch-wing-generic-output")
+                        .map_err(|e| anyhow!("MIDI output connect failed: {}", e))?,
+                )
+            }
+            None => None,
+        };
+
+        info!(
+            input = settings.input,
+            output = settings.output,
+            mappings = mappings.len(),
+            "Generic MIDI controller connected"
+        );
+
+        Ok(Self {
+            _input: input_connection,
+            output: output_connection.map(std::sync::Mutex::new),
+            mappings,
+            transforms,
+            interface,
+        })
+    }
+
+    /// Send the MIDI message that shows a value on the controller
+    fn send_feedback(&self, mapping: &GenericMidiMapping, value: &Value) -> Result<()> {
+        let Some(output) = &self.output else {
+            return Ok(());
+        };
+
+        let value = match value {
+            Value::Int(v) => *v as f32,
+            Value::Float(v) => *v,
+            Value::Bool(on) => f32::from(u8::from(*on)),
+            Value::Str(_) => return Ok(()),
+        };
+
+        let position = if mapping.max == mapping.min {
+            0.0
+        } else {
+            ((value - mapping.min) / (mapping.max - mapping.min)).clamp(0.0, 1.0)
+        };
+        let data7 = ((position * 127.0).round() as u8).into();
+
+        let message = match mapping.message {
+            GenericMidiMessage::ControlChange => MidiMessage::Controller {
+                controller: mapping.number.into(),
+                value: data7,
+            },
+            GenericMidiMessage::Note => MidiMessage::NoteOn {
+                key: mapping.number.into(),
+                vel: data7,
+            },
+            GenericMidiMessage::PitchBend => MidiMessage::PitchBend {
+                bend: PitchBend::from_f64(position as f64 * 2.0 - 1.0),
+            },
+        };
+
+        let ev = LiveEvent::Midi {
+            channel: mapping.channel.into(),
+            message,
+        };
+
+        let mut buf = Vec::with_capacity(3);
+        ev.write(&mut buf).map_err(|e| anyhow!("MIDI write fail {}", e))?;
+
+        output
+            .lock()
+            .map_err(|e| anyhow!("Failed to lock MIDI output mutex: {:?}", e))?
+            .send(&buf)
+            .map_err(|e| anyhow!("MIDI send failed: {}", e))
+    }
+}
+
+impl WriteProvider for GenericController {
+    fn write(&self, addr: &str, value: Value) -> anyhow::Result<()> {
+        for mapping in self.mappings.iter().filter(|m| m.osc == addr) {
+            self.send_feedback(mapping, &value)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_meter_values(&self, _values: Vec<Vec<f32>>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        "MIDI".to_string()
+    }
+
+    fn write_link_status(&self, _status: LinkStatus) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_interface(&self, interface: Interface) {
+        let mappings = self.mappings.clone();
+        let transforms = self.transforms.clone();
+        let has_output = self.output.is_some();
+
+        self.interface.lock().unwrap().replace(interface.clone());
+
+        tokio::spawn(async move {
+            interface.set_transforms(transforms).await;
+
+            // Only the mapped parameters are shown
+            let paths: Vec<String> = mappings.iter().map(|mapping| mapping.osc.clone()).collect();
+            interface.subscribe(&paths).await;
+
+            if has_output {
+                // Request the current values, so that the controller shows them
+                for mapping in mappings.iter() {
+                    interface.request_value_notification(&mapping.osc, false).await;
+                }
+            }
+        });
+    }
+}
+
+/// Find the mappings for a MIDI message, along with its position in the 0..1 range
+fn match_message(
+    mappings: &[GenericMidiMapping],
+    channel: u8,
+    message: &MidiMessage,
+) -> Vec<(GenericMidiMapping, f32)> {
+    let (kind, number, position) = match message {
+        MidiMessage::Controller { controller, value } => (
+            GenericMidiMessage::ControlChange,
+            controller.as_int(),
+            value.as_int() as f32 / 127.0,
+        ),
+        MidiMessage::NoteOn { key, vel } => {
+            (GenericMidiMessage::Note, key.as_int(), vel.as_int() as f32 / 127.0)
+        }
+        MidiMessage::NoteOff { key, .. } => (GenericMidiMessage::Note, key.as_int(), 0.0),
+        MidiMessage::PitchBend { bend } => (
+            GenericMidiMessage::PitchBend,
+            0,
+            ((bend.as_f64() + 1.0) / 2.0) as f32,
+        ),
+        _ => return Vec::new(),
+    };
+
+    mappings
+        .iter()
+        .filter(|m| m.message == kind && m.channel == channel)
+        .filter(|m| kind == GenericMidiMessage::PitchBend || m.number == number)
+        .map(|m| (m.clone(), position))
+        .collect()
+}
+
+fn generic_midi_callback(
+    _timestamp_us: u64,
+    bytes: &[u8],
+    input: &mut (Arc<Vec<GenericMidiMapping>>, SharedInterface, Handle),
+) {
+    let (mappings, interface, handle) = input;
+
+    if let Some(interface) = interface.lock().unwrap().as_ref() {
+        interface.metrics().record_midi_message();
+    }
+
+    let Ok(LiveEvent::Midi { channel, message }) = LiveEvent::parse(bytes) else {
+        trace!(bytes, "Ignoring generic MIDI input");
+        return;
+    };
+
+    let matches = match_message(mappings, channel.as_int(), &message);
+    if matches.is_empty() {
+        debug!(?message, channel = channel.as_int(), "Unmapped generic MIDI input");
+        return;
+    }
+
+    let Some(interface) = interface.lock().unwrap().clone() else {
+        warn!("Generic MIDI input received before the interface was set");
+        return;
+    };
+
+    handle.spawn(async move {
+        for (mapping, position) in matches {
+            let scaled = mapping.min + position * (mapping.max - mapping.min);
+            let value = if mapping.integer {
+                Value::Int(scaled.round() as i32)
+            } else {
+                Value::Float(scaled)
+            };
+
+            interface.set_value(&mapping.osc, value).await;
+        }
+    });
+}
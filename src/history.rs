@@ -0,0 +1,186 @@
+//! A bounded log of the changes of parameter values and who made them, so that it can be
+//! found out afterwards what happened during a service
+//!
+//! The log is kept in memory, and also stored in a file unless that is switched off, so that
+//! it can be read with the `history` command after the bridge was stopped.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::orchestrator::Value;
+use crate::settings::HistorySettings;
+use crate::subscriptions::PathPattern;
+
+/// A change of the value of a parameter
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Milliseconds since the Unix epoch
+    pub time: u64,
+    /// Who made the change, e.g. the console or a controller
+    pub origin: String,
+    pub path: String,
+    /// The value before the change, if it was known
+    pub old: Option<Value>,
+    pub new: Value,
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let old = match &self.old {
+            Some(value) => format_value(value),
+            None => "?".to_string(),
+        };
+
+        write!(
+            f,
+            "{}  {:<16} {} {} -> {}",
+            format_time(self.time),
+            self.origin,
+            self.path,
+            old,
+            format_value(&self.new)
+        )
+    }
+}
+
+pub struct History {
+    entries: Mutex<VecDeque<HistoryEntry>>,
+    size: usize,
+    file: Option<PathBuf>,
+    /// Whether there are entries that are not stored in the file yet
+    unsaved: AtomicBool,
+    /// Held while the file is written, so that saves do not overlap
+    writing: Mutex<()>,
+}
+
+impl History {
+    /// Create the log, continuing the one stored in the file if there is one
+    pub fn new(settings: &HistorySettings) -> Self {
+        let file = settings.file.as_ref().map(PathBuf::from);
+
+        let mut entries = match &file {
+            Some(file) => Self::read(file).unwrap_or_else(|e| {
+                debug!("Starting a new history: {:?}", e);
+                VecDeque::new()
+            }),
+            None => VecDeque::new(),
+        };
+        while entries.len() > settings.size {
+            entries.pop_front();
+        }
+
+        Self {
+            entries: Mutex::new(entries),
+            size: settings.size,
+            file,
+            unsaved: AtomicBool::new(false),
+            writing: Mutex::new(()),
+        }
+    }
+
+    /// Read the entries stored in a history file
+    pub fn read(file: &Path) -> Result<VecDeque<HistoryEntry>> {
+        let contents = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read history file {}", file.display()))?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse history file {}", file.display()))
+    }
+
+    pub fn record(&self, origin: &str, path: &str, old: Option<Value>, new: Value) {
+        if self.size == 0 {
+            return;
+        }
+
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or_default();
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.size {
+            entries.pop_front();
+        }
+        entries.push_back(HistoryEntry {
+            time,
+            origin: origin.to_string(),
+            path: path.to_string(),
+            old,
+            new,
+        });
+        self.unsaved.store(true, Ordering::Relaxed);
+    }
+
+    /// Store the entries in the history file, if there is one and anything changed. This
+    /// blocks while the file is written.
+    pub fn save(&self) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let _writing = self.writing.lock().unwrap();
+        if !self.unsaved.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        // Copied, so that changes are not held up while they are converted
+        let entries = self.entries.lock().unwrap().clone();
+        let contents = serde_yaml::to_string(&entries)?;
+        std::fs::write(file, contents)
+            .with_context(|| format!("Failed to write history file {}", file.display()))?;
+
+        debug!(file = %file.display(), "History saved");
+        Ok(())
+    }
+}
+
+/// Print the stored history, optionally only of the parameters that match a pattern
+pub fn print(settings: &HistorySettings, pattern: Option<&str>) -> Result<()> {
+    let Some(file) = &settings.file else {
+        anyhow::bail!("Set history.file in the configuration to keep the history");
+    };
+
+    let pattern = pattern.map(PathPattern::new);
+    let entries = History::read(Path::new(file))?;
+    let mut found = false;
+
+    for entry in &entries {
+        if pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.matches(&entry.path))
+        {
+            println!("{}", entry);
+            found = true;
+        }
+    }
+
+    if !found {
+        println!("No changes recorded");
+    }
+    Ok(())
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Int(i) => i.to_string(),
+        Value::Float(f) => format!("{:.2}", f),
+        Value::Str(s) => format!("'{}'", s),
+        Value::Bool(on) => if *on { "on" } else { "off" }.to_string(),
+    }
+}
+
+/// A time in milliseconds since the Unix epoch as a local date and time
+fn format_time(millis: u64) -> String {
+    match chrono::DateTime::from_timestamp_millis(millis as i64) {
+        Some(time) => time
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string(),
+        None => millis.to_string(),
+    }
+}
@@ -0,0 +1,74 @@
+//! User-defined macros, executing ordered lists of OSC sets
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use tracing::{debug, info};
+
+use crate::orchestrator::{Interface, Value};
+use crate::settings::MacroStep;
+
+pub struct MacroEngine {
+    macros: HashMap<String, Vec<MacroStep>>,
+    /// Names of the macros that are currently being executed
+    running: std::sync::Mutex<HashSet<String>>,
+}
+
+impl MacroEngine {
+    pub fn new(macros: &HashMap<String, Vec<MacroStep>>) -> Self {
+        Self {
+            macros: macros.clone(),
+            running: std::sync::Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Whether a macro with this name has been defined
+    pub fn contains(&self, name: &str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    /// Whether a macro is currently being executed
+    pub fn is_running(&self, name: &str) -> bool {
+        self.running.lock().unwrap().contains(name)
+    }
+
+    /// Execute all steps of a macro in order, waiting for any delays in between.
+    ///
+    /// A macro cannot be started again while it is still running.
+    pub async fn run(&self, name: &str, interface: &Interface) -> Result<()> {
+        let steps = self
+            .macros
+            .get(name)
+            .ok_or_else(|| anyhow!("Unknown macro '{}'", name))?;
+
+        if !self.running.lock().unwrap().insert(name.to_string()) {
+            bail!("Macro '{}' is already running", name);
+        }
+
+        info!(name, steps = steps.len(), "Running macro");
+
+        // Steps without a delay between them are set at once
+        let mut batch = Vec::new();
+        for step in steps {
+            if step.delay_ms > 0 {
+                Self::set_batch(&mut batch, interface).await;
+                tokio::time::sleep(Duration::from_millis(step.delay_ms)).await;
+            }
+
+            debug!(macro_name = name, osc = step.osc.as_str(), value = ?step.value, "Macro step");
+            batch.push((step.osc.clone(), step.value.clone()));
+        }
+        Self::set_batch(&mut batch, interface).await;
+
+        self.running.lock().unwrap().remove(name);
+
+        Ok(())
+    }
+
+    async fn set_batch(batch: &mut Vec<(String, Value)>, interface: &Interface) {
+        if !batch.is_empty() {
+            interface.set_values(std::mem::take(batch)).await;
+        }
+    }
+}
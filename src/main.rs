@@ -6,23 +6,54 @@
 #![allow(unused_mut)]
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use env_logger::Env;
 use tracing::{debug, error, info, level_filters::LevelFilter, warn};
 use tracing_subscriber::EnvFilter;
 
+mod access;
+mod check;
 mod console;
+mod cues;
 mod data;
+mod definitions;
+mod discovery;
+mod fades;
+mod generic_midi;
+mod history;
+mod macros;
+mod metrics;
 mod midi;
+mod mock_console;
+mod model;
 mod mqtt;
+mod mqtt_topics;
 mod orchestrator;
+mod osc_server;
+mod profiles;
 mod settings;
+mod simulator;
+mod snapshots;
+mod subscriptions;
+mod surface_state;
+mod transforms;
 mod utils;
+mod wing_osc;
+mod wizard;
+mod x32;
 
 /// XTouch Wing - Command line options
+/// How often the state of the surfaces is stored, if it changed
+const SURFACE_STATE_SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Configuration file, instead of the first one found of ./config.yml,
+    /// $XDG_CONFIG_HOME/xtouch-wing/config.yml and /etc/xtouch-wing/config.yml
+    #[arg(short, long, value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+
     /// Activate debug mode
     #[arg(short, long)]
     debug: bool,
@@ -38,6 +69,59 @@ struct Cli {
     /// Enable vegas mode without faders (for testing)
     #[arg(long, default_value_t = false)]
     vegas_silent: bool,
+
+    /// Connect to a simulated X-Touch in the terminal instead of a real one
+    #[arg(long, default_value_t = false)]
+    simulate_surface: bool,
+
+    /// Use an in-memory console instead of a WING on the network
+    #[arg(long, default_value_t = false)]
+    mock_console: bool,
+
+    /// Flash the strip of a fader on the surfaces once connected, e.g. "Channel 5"
+    #[arg(long, value_name = "FADER")]
+    identify: Option<String>,
+
+    /// Store the configured snapshot parameters under a name, and exit
+    #[arg(long, value_name = "NAME")]
+    save_snapshot: Option<String>,
+
+    /// Restore a stored snapshot to the console, and exit
+    #[arg(long, value_name = "NAME")]
+    restore_snapshot: Option<String>,
+
+    /// Start in safe mode, where only the configured safe mode parameters can be changed
+    #[arg(long, default_value_t = false)]
+    safe_mode: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the WING consoles found on the local network
+    Discover,
+    /// Show the recorded changes of parameter values
+    History {
+        /// Only show the parameters that match a pattern, e.g. "/ch/*/mute"
+        path: Option<String>,
+    },
+    /// Work with the configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Validate the configuration and list all problems, without connecting to anything
+    Check,
+    /// Create a configuration file by answering a few questions
+    Init,
+    /// Print a JSON Schema of the configuration file, for editors
+    Schema,
 }
 
 #[tokio::main]
@@ -54,41 +138,616 @@ async fn main() -> Result<()> {
         .with_target(true)
         .init();
 
-    let config =
-        settings::Settings::new().with_context(|| "Failed to load configuration settings")?;
+    if let Some(Command::Discover) = cli.command {
+        let consoles = discovery::discover().await?;
+        if consoles.is_empty() {
+            println!("No consoles found");
+        }
+        for console in consoles {
+            println!(
+                "{}\t{}\t{} (serial {}, firmware {})",
+                console.ip, console.name, console.model, console.serial, console.firmware
+            );
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Config { command: ConfigCommand::Schema }) = &cli.command {
+        println!("{}", settings::json_schema()?);
+        return Ok(());
+    }
+
+    if let Some(Command::Config { command: ConfigCommand::Init }) = &cli.command {
+        let path = cli.config.clone().unwrap_or_else(|| "config.yml".into());
+        return wizard::run(&path).await;
+    }
+
+    let config_path = settings::find_config(cli.config.as_deref())?;
+    let mut config = settings::Settings::new(&config_path).with_context(|| {
+        format!("Failed to load configuration settings from {}", config_path.display())
+    })?;
+
+    if let Some(Command::History { path }) = &cli.command {
+        return history::print(&config.history, path.as_deref());
+    }
+
+    if let Some(Command::Config { command: ConfigCommand::Check }) = &cli.command {
+        if !check::print(&config) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
     if cli.debug {
         debug!("Debug mode is enabled");
     }
     info!("XTouch Wing started");
 
+    let mute_all_paths = config
+        .mute_all
+        .iter()
+        .map(|label| {
+            data::Fader::new_from_label(label)
+                .map(|fader| fader.get_osc_path(data::PathType::Mute))
+                .with_context(|| format!("Mute all label '{}' in your configuration is invalid", label))
+        })
+        .collect::<Result<Vec<String>>>()?;
+
+    let snapshots = std::sync::Arc::new(snapshots::SnapshotStore::new(&config.snapshots));
+
+    let history = history::History::new(&config.history);
+    let surface_states =
+        std::sync::Arc::new(surface_state::SurfaceStateStore::new(config.state_file.as_deref()));
+
+    let links = config
+        .links
+        .iter()
+        .map(|link| {
+            if !link.offsets.is_empty() && link.offsets.len() != link.paths.len() {
+                anyhow::bail!("Link of {} needs one offset per path", link.paths.join(", "));
+            }
+            let offsets = link.offsets.iter().copied().chain(std::iter::repeat(0.0));
+            Ok(orchestrator::ParameterLink {
+                members: link.paths.iter().cloned().zip(offsets).collect(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    check_console_routes(&mut config)?;
+    let console_ip = resolve_console_ip(&cli, &config.console[0]).await?;
+
+    if cli.save_snapshot.is_some() || cli.restore_snapshot.is_some() {
+        return run_snapshot_command(&cli, &config, &console_ip, &snapshots, mute_all_paths).await;
+    }
+
+    if config.midi.iter().any(|midi| midi.assignments.banks.is_auto()) {
+        build_automatic_banks(&cli, &mut config, &console_ip)
+            .await
+            .with_context(|| "Failed to build banks from the console")?;
+    }
+    adapt_banks_to_consoles(&cli, &mut config, &console_ip).await;
+
+    // Kept alive for as long as the application runs
+    let mut simulator = None;
+    if cli.simulate_surface {
+        let (sim, input, output) =
+            simulator::Simulator::start().with_context(|| "Failed to start surface simulator")?;
+        simulator = Some(sim);
+
+        // Only the first controller is simulated
+        config.midi.truncate(1);
+        for midi_settings in &mut config.midi {
+            midi_settings.input = input.clone();
+            midi_settings.output = output.clone();
+        }
+    }
+
+    let macros = std::sync::Arc::new(macros::MacroEngine::new(&config.macros));
+    let fades = std::sync::Arc::new(fades::FadeEngine::new());
+    let cues = std::sync::Arc::new(
+        cues::CueEngine::new(&config.cues, fades.clone())
+            .with_context(|| "Failed to load the cue list")?,
+    );
+
+    let mut controllers = Vec::new();
+    for (midi_settings, midi_definition) in config.midi.iter().zip(&config.midi_definitions) {
+        let midi = midi::Controller::new(
+            midi_settings,
+            midi_definition,
+            macros.clone(),
+            fades.clone(),
+            cues.clone(),
+            snapshots.clone(),
+            surface_states.clone(),
+        )
+            .with_context(|| format!("Failed to create MIDI controller '{}'", midi_settings.input))?;
+        midi.lock().await.clean_buttons().await;
+        controllers.push(midi);
+    }
+
+    if let Some(midi) = controllers.first() {
+        if cli.vegas {
+            warn!("{}", "Test run, Vegas mode");
+            midi.lock().await.vegas_mode(true).await?;
+        } else if cli.vegas_silent {
+            warn!("{}", "Test run, Vegas mode silent");
+            midi.lock().await.vegas_mode(false).await?;
+        }
+    }
+
+    // Connecting may take a while, so show some feedback on the surfaces first
+    for midi in &controllers {
+        midi.lock().await.show_splash(&console_ip).await;
+    }
+
     // OSC connection logic
-    let remote_addr = format!("{}:{}", config.console.ip, config.console.port);
-    let console = console::Console::new(&config.console.ip, cli.local_port)
+    let consoles = connect_consoles(&cli, &config, &console_ip).await?;
+
+    let mut configured_paths = mute_all_paths.clone();
+    for midi in &controllers {
+        configured_paths.extend(midi.lock().await.configured_paths());
+    }
+    check_configured_paths(&config, configured_paths);
+
+    // Kept to identify strips and to clean up the surfaces on exit
+    let surfaces = controllers.clone();
+
+    let mut providers = controllers
+        .into_iter()
+        .zip(&config.midi)
+        .map(|(midi, midi_settings)| {
+            (
+                Box::new(midi) as Box<dyn orchestrator::WriteProvider>,
+                access::Access::from_settings(midi_settings.access.as_ref()),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    for generic_settings in &config.generic_midi {
+        let generic = generic_midi::GenericController::new(generic_settings).with_context(|| {
+            format!("Failed to create generic MIDI controller '{}'", generic_settings.input)
+        })?;
+        providers.push((
+            Box::new(generic) as Box<dyn orchestrator::WriteProvider>,
+            access::Access::from_settings(generic_settings.access.as_ref()),
+        ));
+    }
+
+    if config.mqtt.enabled {
+        let strips = mqtt::configured_strips(&config);
+        let mqtt = mqtt::Mqtt::new(&config.mqtt, strips, surfaces.clone())
+            .with_context(|| "Failed to create MQTT client")?;
+        providers.push((
+            Box::new(mqtt) as Box<dyn orchestrator::WriteProvider>,
+            access::Access::from_settings(config.mqtt.access.as_ref()),
+        ));
+    }
+
+    if let Some(osc_settings) = &config.osc_server {
+        let server = osc_server::OscServer::new(osc_settings)
+            .await
+            .with_context(|| "Failed to start OSC server")?;
+        providers.push((
+            Box::new(server) as Box<dyn orchestrator::WriteProvider>,
+            access::Access::from_settings(osc_settings.access.as_ref()),
+        ));
+    }
+
+    let orchestrator =
+        orchestrator::Orchestrator::new(
+            consoles,
+            providers,
+            mute_all_paths,
+            links,
+            history,
+            (&config.timeouts).into(),
+            access::SafeMode::new(&config.safe_mode, cli.safe_mode),
+        )
+        .await;
+
+    if let Some(label) = &cli.identify {
+        let fader = data::Fader::new_from_label(label)
+            .with_context(|| format!("Invalid fader to identify: {}", label))?;
+
+        let mut found = false;
+        for midi in &surfaces {
+            match midi.lock().await.identify(&fader).await {
+                Ok(()) => found = true,
+                Err(e) => debug!("Not identifying on controller: {:?}", e),
+            }
+        }
+        if !found {
+            warn!(label = label.as_str(), "Fader to identify is not on any surface");
+        }
+    }
+
+    let saved_states = surface_states.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SURFACE_STATE_SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = saved_states.save() {
+                warn!("Failed to save surface state: {:?}", e);
+            }
+        }
+    });
+
+    shutdown_signal().await?;
+    info!("Shutting down");
+
+    for midi in &surfaces {
+        midi.lock().await.shut_down().await;
+    }
+    orchestrator.shut_down().await;
+    if let Err(e) = surface_states.save() {
+        warn!("Failed to save surface state: {:?}", e);
+    }
+
+    Ok(())
+}
+
+/// Wait until the bridge is asked to stop, with Ctrl+C or by the service manager
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate =
+            signal(SignalKind::terminate()).with_context(|| "Failed to listen for SIGTERM")?;
+
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result.with_context(|| "Failed to listen for SIGINT")?
+            }
+            _ = terminate.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c()
         .await
-        .with_context(|| "Failed to create OSC console connection")?;
+        .with_context(|| "Failed to listen for Ctrl+C")?;
+
+    Ok(())
+}
+
+/// Make sure that every additional console has a unique ID, and that banks only refer to
+/// consoles that exist. Banks on the first console are not routed.
+fn check_console_routes(config: &mut settings::Settings) -> Result<()> {
+    let Some((first, others)) = config.console.split_first() else {
+        anyhow::bail!("No console is configured");
+    };
+
+    let mut ids = std::collections::HashSet::new();
+    for console in others {
+        let id = console
+            .id
+            .as_deref()
+            .with_context(|| "Every console after the first one needs an id")?;
+        if !ids.insert(id) || first.id.as_deref() == Some(id) {
+            anyhow::bail!("Console id '{}' is used more than once", id);
+        }
+    }
+
+    let first_id = first.id.clone();
+    let ids: Vec<String> = ids.into_iter().map(str::to_string).collect();
+
+    for bank in config.midi.iter_mut().flat_map(|midi| midi.assignments.banks.iter_mut()) {
+        match &bank.console {
+            Some(id) if Some(id) == first_id.as_ref() => bank.console = None,
+            Some(id) if !ids.contains(id) => {
+                anyhow::bail!("Bank {:?} refers to unknown console '{}'", bank.name, id)
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace the automatic banks with one bank per strip type of the first console, with the
+/// strips that have a name, split into banks of the surface size
+async fn build_automatic_banks(
+    cli: &Cli,
+    config: &mut settings::Settings,
+    first_ip: &str,
+) -> Result<()> {
+    let consoles = connect_consoles(cli, config, first_ip).await?;
+    let orchestrator =
+        orchestrator::Orchestrator::new(
+            consoles,
+            Vec::new(),
+            Vec::new(),
+            Vec::new(),
+            history::History::new(&config.history),
+            (&config.timeouts).into(),
+            access::SafeMode::new(&config.safe_mode, cli.safe_mode),
+        )
+        .await;
+    let interface = orchestrator.external_interface();
+
+    let strips: Vec<(&str, String, String)> = data::mixer_strip_labels()
+        .filter_map(|(kind, label)| {
+            let fader = data::Fader::new_from_label(&label).ok()?;
+            Some((kind, label, fader.get_osc_path(data::PathType::ScribbleName)))
+        })
+        .collect();
+    let paths: Vec<String> = strips.iter().map(|(_, _, path)| path.clone()).collect();
+    // Strips that the console does not have never answer
+    if let Err(e) = interface.request_values(&paths, true).await {
+        debug!("Not all strip names received: {:?}", e);
+    }
+
+    let mut banks: Vec<settings::FaderBank> = Vec::new();
+    for (kind, label, path) in strips {
+        match interface.get_cached_value(&path).await {
+            Some(orchestrator::Value::Str(name)) if !name.trim().is_empty() => {}
+            _ => continue,
+        }
+        match banks.last_mut() {
+            Some(bank) if bank.name.as_deref() == Some(kind) => bank.faders.push(label),
+            _ => banks.push(settings::FaderBank {
+                name: Some(kind.to_string()),
+                faders: vec![label],
+                meter: Default::default(),
+                trim_db: Default::default(),
+                console: None,
+                filter: None,
+            }),
+        }
+    }
+    orchestrator.shut_down().await;
+
+    let mut expanded = Vec::new();
+    for bank in banks {
+        expanded.extend(bank.split(midi::STRIP_COUNT));
+    }
+    if expanded.is_empty() {
+        anyhow::bail!("None of the strips on the console have a name");
+    }
+    info!(banks = expanded.len(), "Banks built from the strips on the console");
+
+    for midi in &mut config.midi {
+        if midi.assignments.banks.is_auto() {
+            midi.assignments.banks = settings::Banks::List(expanded.clone());
+        }
+    }
+
+    Ok(())
+}
+
+/// Identify the model of each console, and leave the strips that it does not have out of the
+/// banks routed to it
+async fn adapt_banks_to_consoles(cli: &Cli, config: &mut settings::Settings, first_ip: &str) {
+    let mut models = std::collections::HashMap::new();
+    for (index, settings) in config.console.iter().enumerate() {
+        let ip = match index {
+            0 => Some(first_ip),
+            _ => settings.ip.as_deref(),
+        };
+        let info = identify_console(cli, settings, ip).await;
+
+        info!(
+            id = settings.id.as_deref(),
+            model = %info.model,
+            firmware = info.firmware.as_deref(),
+            "Console identified"
+        );
+        if info.firmware_untested() {
+            warn!(
+                firmware = info.firmware.as_deref(),
+                "Console firmware is older than expected, some parameters may not exist"
+            );
+        }
+
+        // Banks on the first console have no console ID after routing
+        let id = if index == 0 { None } else { settings.id.clone() };
+        models.insert(id, info.model);
+    }
+
+    for bank in config.midi.iter_mut().flat_map(|midi| midi.assignments.banks.iter_mut()) {
+        bank.faders.retain(|label| {
+            let Ok(fader) = data::Fader::new_from_label(label) else {
+                return true;
+            };
+            let console = fader.console_id().map(str::to_string).or_else(|| bank.console.clone());
+            let Some(model) = models.get(&console) else {
+                return true;
+            };
+
+            if fader.exists_on(*model) {
+                true
+            } else {
+                warn!(
+                    label = label.as_str(),
+                    bank = ?bank.name,
+                    %model,
+                    "Console does not have this strip, leaving it out"
+                );
+                false
+            }
+        });
+    }
+}
+
+/// Identify a console by asking it, assuming a full-size WING if it does not answer
+async fn identify_console(
+    cli: &Cli,
+    settings: &settings::ConsoleSettings,
+    ip: Option<&str>,
+) -> model::ConsoleInfo {
+    if settings.console_type == settings::ConsoleType::X32 {
+        return model::ConsoleInfo::new(model::ConsoleModel::X32);
+    }
+
+    let ip = match ip {
+        Some(ip) if !cli.mock_console => ip,
+        _ => return model::ConsoleInfo::new(model::ConsoleModel::Wing),
+    };
+
+    match discovery::identify(ip).await {
+        Ok(console) => model::ConsoleInfo::from_discovery(&console),
+        Err(e) => {
+            warn!("Failed to identify the console, assuming a WING: {:?}", e);
+            model::ConsoleInfo::new(model::ConsoleModel::Wing)
+        }
+    }
+}
+
+/// Warn about all parameters referred to by the configuration that do not exist on the
+/// consoles they are routed to, at once
+fn check_configured_paths(config: &settings::Settings, mut paths: Vec<String>) {
+    paths.extend(config.generic_midi.iter().flat_map(|g| g.mappings.iter().map(|m| m.osc.clone())));
+    paths.extend(config.macros.values().flatten().map(|step| step.osc.clone()));
+    paths.extend(config.cues.iter().flat_map(|cue| cue.set.iter().map(|s| s.osc.clone())));
+    paths.extend(config.snapshots.paths.iter().filter(|path| !path.contains('*')).cloned());
+    paths.extend(config.snapshots.ab_compare.iter().cloned());
+
+    let is_wing = |console: &settings::ConsoleSettings| {
+        console.console_type != settings::ConsoleType::X32
+    };
+
+    let mut invalid: Vec<String> = paths
+        .into_iter()
+        .filter(|osc_addr| {
+            let (console, path) = match osc_addr.strip_prefix('@') {
+                Some(rest) => {
+                    let (id, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+                    (config.console.iter().find(|c| c.id.as_deref() == Some(id)), path)
+                }
+                None => (config.console.first(), osc_addr.as_str()),
+            };
+
+            match console {
+                // Other consoles have a different parameter tree
+                Some(console) => is_wing(console) && !console::path_exists(path),
+                None => true,
+            }
+        })
+        .collect();
+
+    invalid.sort();
+    invalid.dedup();
 
-    let mut midi = midi::Controller::new(&config.midi, &config.midi_definition)
-        .with_context(|| "Failed to create MIDI controller")?;
-    midi.lock().await.clean_buttons().await;
+    if !invalid.is_empty() {
+        warn!(
+            "{} parameters in your configuration do not exist on the console: {}",
+            invalid.len(),
+            invalid.join(", ")
+        );
+    }
+}
+
+/// The address of a console, discovering a WING if it is not configured
+async fn resolve_console_ip(cli: &Cli, settings: &settings::ConsoleSettings) -> Result<String> {
+    Ok(match &settings.ip {
+        _ if cli.mock_console => "mock".to_string(),
+        Some(ip) => ip.clone(),
+        None if settings.console_type == settings::ConsoleType::X32 => {
+            anyhow::bail!("Set console.ip in your configuration, only WING consoles can be discovered")
+        }
+        None => discovery::find_console(settings.name.as_deref())
+            .await
+            .with_context(|| "Failed to discover a console, set console.ip in your configuration")?,
+    })
+}
+
+/// Connect to all configured consoles, returning them with their IDs
+async fn connect_consoles(
+    cli: &Cli,
+    config: &settings::Settings,
+    first_ip: &str,
+) -> Result<Vec<(String, console::ConsoleBackend)>> {
+    let mut consoles = Vec::new();
+
+    for (index, settings) in config.console.iter().enumerate() {
+        let console_ip = match index {
+            0 => first_ip.to_string(),
+            _ => resolve_console_ip(cli, settings).await?,
+        };
+        // Each console needs its own local port
+        let local_port = cli.local_port + index as u16;
+
+        let console = create_console(cli, settings, &console_ip, local_port)
+            .await
+            .with_context(|| format!("Failed to connect to console {}", index + 1))?;
+        consoles.push((settings.id.clone().unwrap_or_default(), console));
+    }
 
-    // let mut mqtt = mqtt::Mqtt::new(&config.mqtt.host, config.mqtt.port)
-    //     .await
-    //     .with_context(|| "Failed to create MQTT client")?;
+    Ok(consoles)
+}
 
-    if cli.vegas {
-        warn!("{}", "Test run, Vegas mode");
-        midi.lock().await.vegas_mode(true).await?;
-    } else if cli.vegas_silent {
-        warn!("{}", "Test run, Vegas mode silent");
-        midi.lock().await.vegas_mode(false).await?;
+/// Connect to a configured console, or create a mock console if requested
+async fn create_console(
+    cli: &Cli,
+    settings: &settings::ConsoleSettings,
+    console_ip: &str,
+    local_port: u16,
+) -> Result<console::ConsoleBackend> {
+    if cli.mock_console {
+        return Ok(console::ConsoleBackend::Mock(mock_console::MockConsole::new()));
     }
 
-    let mut midi_arc = std::sync::Arc::new(Box::new(midi) as Box<dyn orchestrator::WriteProvider>);
+    Ok(match settings.console_type {
+        settings::ConsoleType::Wing => {
+            let console = console::Console::new(console_ip, local_port)
+                .await
+                .with_context(|| "Failed to create OSC console connection")?;
+            console::ConsoleBackend::Wing(console)
+        }
+        settings::ConsoleType::WingOsc => {
+            let console = wing_osc::WingOscConsole::new(console_ip, settings.port, local_port)
+                .await
+                .with_context(|| "Failed to create Wing OSC connection")?;
+            console::ConsoleBackend::WingOsc(console)
+        }
+        settings::ConsoleType::X32 => {
+            let console = x32::X32Console::new(console_ip, settings.port, local_port)
+                .await
+                .with_context(|| "Failed to create X32 console connection")?;
+            console::ConsoleBackend::X32(console)
+        }
+    })
+}
+
+/// Save or restore a snapshot with the console only, without any surfaces
+async fn run_snapshot_command(
+    cli: &Cli,
+    config: &settings::Settings,
+    console_ip: &str,
+    snapshots: &snapshots::SnapshotStore,
+    mute_all_paths: Vec<String>,
+) -> Result<()> {
+    let consoles = connect_consoles(cli, config, console_ip).await?;
+    let orchestrator =
+        orchestrator::Orchestrator::new(
+            consoles,
+            Vec::new(),
+            mute_all_paths,
+            Vec::new(),
+            history::History::new(&config.history),
+            (&config.timeouts).into(),
+            access::SafeMode::new(&config.safe_mode, cli.safe_mode),
+        )
+        .await;
+    let interface = orchestrator.external_interface();
 
-    let mut orchestrator = orchestrator::Orchestrator::new(console, vec![midi_arc]).await;
+    if let Some(name) = &cli.save_snapshot {
+        if config.snapshots.paths.is_empty() {
+            anyhow::bail!("Snapshots can only be saved from the command line if their paths are configured");
+        }
+
+        let count = snapshots.capture(name, &interface).await?;
+        info!(name, count, "Snapshot saved");
+    }
+
+    if let Some(name) = &cli.restore_snapshot {
+        let count = snapshots.restore(name, &interface).await?;
+        info!(name, count, "Snapshot restored");
+    }
 
-    std::future::pending::<()>().await;
+    // The restored values are all written before the orchestrator stops
+    orchestrator.shut_down().await;
 
-    unreachable!()
+    Ok(())
 }
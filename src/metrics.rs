@@ -0,0 +1,159 @@
+//! Counters and gauges of the bridge at runtime, for anything that shows them, such as a
+//! metrics endpoint or a status topic
+//!
+//! Counters only ever increase. Rates are calculated from two snapshots taken some time apart.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+/// Registry of the metrics of an orchestrator and the providers attached to it
+pub struct Metrics {
+    started: Instant,
+    /// Number of values in the parameter cache
+    cache_size: AtomicUsize,
+    /// Number of values received from each interface, by interface ID
+    values: Mutex<HashMap<usize, u64>>,
+    /// Round-trip time to the first console in microseconds, or 0 if unknown
+    round_trip_us: AtomicU64,
+    /// Number of values requested from the consoles
+    requests: AtomicU64,
+    /// Number of requests that the consoles never answered
+    timeouts: AtomicU64,
+    /// Number of MIDI messages received from all controllers
+    midi_messages: AtomicU64,
+}
+
+/// The metrics at one point in time
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSnapshot {
+    #[serde(skip)]
+    taken: Instant,
+    pub uptime: Duration,
+    pub cache_size: usize,
+    /// Values received from each interface, by the name of the interface
+    pub values: BTreeMap<String, u64>,
+    pub round_trip: Option<Duration>,
+    pub requests: u64,
+    pub timeouts: u64,
+    pub midi_messages: u64,
+}
+
+/// Rates of the counters between two snapshots
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsRates {
+    /// Values per second received from each interface, by the name of the interface
+    pub values_per_second: BTreeMap<String, f64>,
+    pub midi_messages_per_second: f64,
+    /// Fraction of the requests that were not answered, between 0 and 1
+    pub timeout_rate: f64,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            cache_size: AtomicUsize::new(0),
+            values: Mutex::new(HashMap::new()),
+            round_trip_us: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            midi_messages: AtomicU64::new(0),
+        }
+    }
+
+    pub fn set_cache_size(&self, size: usize) {
+        self.cache_size.store(size, Ordering::Relaxed);
+    }
+
+    pub fn record_values(&self, interface_id: usize, count: usize) {
+        *self.values.lock().unwrap().entry(interface_id).or_default() += count as u64;
+    }
+
+    pub fn set_round_trip(&self, round_trip: Duration) {
+        let micros = round_trip.as_micros().clamp(1, u64::MAX as u128) as u64;
+        self.round_trip_us.store(micros, Ordering::Relaxed);
+    }
+
+    pub fn record_requests(&self, count: usize) {
+        self.requests.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_midi_message(&self) {
+        self.midi_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let values = self
+            .values
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, count)| (interface_name(*id), *count))
+            .collect();
+
+        MetricsSnapshot {
+            taken: Instant::now(),
+            uptime: self.started.elapsed(),
+            cache_size: self.cache_size.load(Ordering::Relaxed),
+            values,
+            round_trip: match self.round_trip_us.load(Ordering::Relaxed) {
+                0 => None,
+                micros => Some(Duration::from_micros(micros)),
+            },
+            requests: self.requests.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            midi_messages: self.midi_messages.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// The rates of the counters since an earlier snapshot
+    pub fn rates_since(&self, earlier: &MetricsSnapshot) -> MetricsRates {
+        let seconds = self
+            .taken
+            .duration_since(earlier.taken)
+            .as_secs_f64()
+            .max(f64::EPSILON);
+
+        let values_per_second = self
+            .values
+            .iter()
+            .map(|(name, count)| {
+                let before = earlier.values.get(name).copied().unwrap_or_default();
+                (name.clone(), (count - before) as f64 / seconds)
+            })
+            .collect();
+
+        let requests = self.requests - earlier.requests;
+        let timeouts = self.timeouts - earlier.timeouts;
+
+        MetricsRates {
+            values_per_second,
+            midi_messages_per_second: (self.midi_messages - earlier.midi_messages) as f64 / seconds,
+            timeout_rate: if requests == 0 {
+                0.0
+            } else {
+                (timeouts as f64 / requests as f64).min(1.0)
+            },
+        }
+    }
+}
+
+/// A readable name for an interface ID
+fn interface_name(id: usize) -> String {
+    match id {
+        0 => "console".to_string(),
+        usize::MAX => "external".to_string(),
+        id => format!("provider {}", id),
+    }
+}
@@ -2,9 +2,10 @@
 
 use core::f32;
 use std::cell::{Cell, Ref, RefCell};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Weak};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, anyhow};
 use clap::error;
@@ -13,16 +14,32 @@ use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
 use midly::PitchBend;
 use midly::io::Write;
 use midly::live::LiveEvent;
+use serde::Serialize;
 use tokio::runtime::Handle;
 use tokio::sync::Mutex;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
 use tracing_subscriber::field::debug;
 
-use crate::data::{Fader, InternalButton, InternalFunction, PathType};
-use crate::orchestrator::{Interface, Value, WriteProvider};
-use crate::settings::{ControllerSettings, MidiDefinition};
-use crate::utils::try_arc_new_cyclic;
-
-const ASCII_TO_7SEGMENT: [Option<u8>; 128] = [
+use crate::data::{
+    FADER_MAX_DB, FADER_MIN_DB, FOCUS_PARAMETERS, Fader, FaderTarget, GEQ_BANDS, InternalButton,
+    InternalFunction, LOW_CUT_MAX_HZ, LOW_CUT_MIN_HZ, PAN_MAX, PAN_MIN, PREAMP_MAX_DB,
+    PREAMP_MIN_DB, PathType, SCENE_ACTION_PATH, SCENE_INDEX_PATH, SCENE_NAME_PATH,
+    SELECTED_STRIP_PATH, SOLO_ACTIVE_PATH, phantom_power_path,
+};
+use crate::cues::CueEngine;
+use crate::fades::FadeEngine;
+use crate::macros::MacroEngine;
+use crate::metrics::Metrics;
+use crate::orchestrator::{Interface, LinkStatus, Value, WriteProvider};
+use crate::snapshots::{AbState, SnapshotStore};
+use crate::surface_state::{SurfaceState, SurfaceStateStore};
+use crate::settings::{
+    BankFilter, ButtonCombo, ControllerSettings, EncoderAcceleration, EncoderMode, FaderBank,
+    FaderDeadband, MeterSource, MidiDefinition,
+};
+use crate::utils::{transliterate, try_arc_new_cyclic};
+
+pub(crate) const ASCII_TO_7SEGMENT: [Option<u8>; 128] = [
     None, None, None, None, None, None, None, None, None, None, None, None, None, None,
     None, None, None, None, None, None, None, None, None, None, None, None, None, None,
     None, None, None, None,
@@ -124,23 +141,315 @@ const ASCII_TO_7SEGMENT: [Option<u8>; 128] = [
     Some(0),  // DEL
 ];
 
-const WING_TO_XTOUCH_COLOR: [u8; 13] = [
-    0, 7, 6, 4, 7, 2, 2, 3, 3, 1, 1, 5, 5
+/// Most channel strips a surface can have, with the master fader on the MIDI channel after them
+pub(crate) const STRIP_COUNT: usize = 8;
+
+/// How often blinking LEDs are toggled
+const BLINK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often long scribble strip names are scrolled by one character
+const MARQUEE_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Number of characters in each row of a scribble strip
+const SCRIBBLE_ROW_LEN: usize = 7;
+
+/// Range of the console's meter values, in dB
+const METER_RANGE_DB: f32 = 128.0;
+/// Gain reduction shown by a full meter, in dB
+const GAIN_REDUCTION_RANGE_DB: f32 = 24.0;
+
+/// Parameter trees that the surface may show, whichever strips are visible, on any console
+const SUBSCRIBED_TREES: [&str; 11] = [
+    "/ch/**", "/aux/**", "/bus/**", "/main/**", "/mtx/**", "/dca/**", "/fx/**", "/cfg/mon/**",
+    "/io/in/**", "/$ctl/**", "/$stat/**",
 ];
 
+/// Number of bands of the console's RTA, spaced by 1/12 octave from its lowest frequency
+const RTA_BANDS: usize = 120;
+const RTA_LOWEST_HZ: f32 = 20.0;
+
+/// Strips that can be members of a DCA
+const DCA_MEMBER_CANDIDATES: [(&str, u8); 2] = [("Channel", 40), ("Aux", 8)];
+
+/// How often tap tempo LEDs are updated, and the longest interval between two taps
+const TEMPO_LED_INTERVAL: Duration = Duration::from_millis(20);
+/// Time between the frames of the Vegas mode test pattern
+const VEGAS_FRAME_INTERVAL: Duration = Duration::from_millis(1000 / 30);
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to wait at most for queued MIDI messages to be sent before exiting
+const OUTPUT_FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+const OUTPUT_FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Number of taps averaged to calculate the tempo
+const TAP_HISTORY: usize = 4;
+
+/// How often the MIDI ports are checked for a disconnected controller
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often the surface checks whether it should go to sleep
+const SLEEP_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often, and for how long, the bank number flashes after a bank change
+const BANK_FLASH_INTERVAL: Duration = Duration::from_millis(150);
+const BANK_FLASH_COUNT: usize = 3;
+
+/// How often, and for how long, a strip flashes when it is identified
+const IDENTIFY_FLASH_INTERVAL: Duration = Duration::from_millis(250);
+const IDENTIFY_FLASH_COUNT: usize = 6;
+
+/// How long a fader value stays on the scribble strip after the fader is released
+const VALUE_DISPLAY_DURATION: Duration = Duration::from_secs(1);
+
+/// The strip button that switches phantom power with a long press, in phantom mode
+const PHANTOM_BUTTON: &str = "Encoder Push";
+
+/// Level change of the nudge functions when it is not configured, in dB
+const DEFAULT_NUDGE_STEP_DB: f32 = 0.5;
+
+/// The highest 14-bit fader position
+const PITCH_BEND_MAX: u16 = 0x3FFF;
+
+/// Delay between the requests that warm up the cache with the parameters of all banks, so
+/// that the console is not flooded
+const HYDRATION_REQUEST_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Number of LED positions on an encoder ring
+const RING_POSITIONS: f32 = 11.0;
+/// Encoder ring mode that lights all LEDs up to the position
+const RING_MODE_FILL: u8 = 0x20;
+
+/// The highest X-Touch scribble colour index (white)
+pub(crate) const MAX_XTOUCH_COLOUR: u8 = 7;
+
+/// The state of a button LED
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LedState {
+    Off,
+    On,
+    Blink,
+    /// Flash briefly once per period
+    Pulse(Duration),
+}
+
+impl From<bool> for LedState {
+    fn from(lit: bool) -> Self {
+        if lit { LedState::On } else { LedState::Off }
+    }
+}
+
+/// A temporary set of strips that replaces the current bank, such as GEQ bands
+#[derive(Debug)]
+struct StripOverlay {
+    name: String,
+    faders: Vec<Fader>,
+    /// The function that opened this overlay
+    function: InternalFunction,
+    /// The strip window offset of the bank, restored when the overlay is closed
+    saved_window_offset: usize,
+}
+
+/// The part of the banks that a surface shows. Every surface keeps its own window, so that
+/// surfaces can sit on different banks while sharing the values of the orchestrator.
+#[derive(Debug, Default)]
+struct BankWindow {
+    bank: usize,
+    /// Index of the first visible strip within the current bank
+    offset: usize,
+    overlay: Option<StripOverlay>,
+}
+
+/// What to do in response to a button press or release
+#[derive(Debug)]
+enum ButtonAction {
+    /// Execute a function
+    Press(InternalFunction),
+    /// Release a held function
+    Release(InternalFunction),
+    /// Execute and immediately release a function, after a short press
+    Tap(InternalFunction),
+    /// Execute a function if the button with the given press ID is still held after the
+    /// long-press duration
+    ArmLongPress(u64, InternalFunction),
+    Nothing,
+}
+
+impl ButtonAction {
+    /// Whether this action is allowed while the surface is locked
+    fn unlocks(&self) -> bool {
+        match self {
+            ButtonAction::Press(function)
+            | ButtonAction::Tap(function)
+            | ButtonAction::ArmLongPress(_, function) => *function == InternalFunction::LockSurface,
+            _ => false,
+        }
+    }
+}
+
+/// Whether a surface is connected and what it shows, for status reports
+#[derive(Debug, Clone, Serialize)]
+pub struct SurfaceStatus {
+    /// The name of the MIDI input
+    pub name: String,
+    pub connected: bool,
+    /// The number of the visible bank, starting from 1, and its name
+    pub bank: usize,
+    pub bank_name: Option<String>,
+    pub locked: bool,
+}
+
 /// Simple controller owning a MIDI input and output handle.
 pub struct Controller {
     pub input: Arc<std::sync::Mutex<MidiInputConnection<(Weak<Mutex<Controller>>, Handle)>>>,
     pub output: Arc<std::sync::Mutex<MidiOutputConnection>>,
-
+    /// Outgoing MIDI messages, sent to the output by a background task
+    output_queue: UnboundedSender<Vec<u8>>,
+    /// Set by the output task when sending fails, which usually means the device is gone
+    output_failed: Arc<std::sync::atomic::AtomicBool>,
+    /// Number of queued MIDI messages that were not sent yet
+    output_pending: Arc<std::sync::atomic::AtomicUsize>,
+    /// Names of the MIDI ports, used to reconnect after the device is unplugged
+    input_name: String,
+    output_name: String,
+    /// Whether the MIDI ports are connected, as last seen by the watchdog
+    ports_connected: bool,
+
+    /// A handle to this controller, for use by background tasks
+    this: Weak<Mutex<Controller>>,
+    /// The tokio runtime, for spawning tasks from the MIDI callback thread
+    runtime: Handle,
     interface: Arc<Mutex<Option<Interface>>>,
-
-    current_bank: usize,
+    /// Metrics of the orchestrator, known once the interface is set
+    metrics: Option<Arc<Metrics>>,
+    macros: Arc<MacroEngine>,
+    fades: Arc<FadeEngine>,
+    cues: Arc<CueEngine>,
+    snapshots: Arc<SnapshotStore>,
+    /// Where the bank, encoder mode and lock are kept across restarts
+    surface_states: Arc<SurfaceStateStore>,
+
+    window: BankWindow,
     banks: Vec<Vec<Fader>>,
+    /// The strips of the current bank, without the partners of stereo-linked strips and the
+    /// strips that do not match the filter of the bank
+    resolved_bank: Vec<Fader>,
     bank_names: Vec<Option<String>>,
+    bank_filters: Vec<Option<BankFilter>>,
+    bank_meter_sources: Vec<MeterSource>,
     buttons: HashMap<u32, InternalButton>,
+    long_press_buttons: HashMap<u32, InternalFunction>,
+    long_press_duration: Duration,
+    /// Buttons with a long-press function that are currently held, with a unique press ID
+    pressed_buttons: HashMap<u32, (u64, Instant)>,
+    press_counter: u64,
+    /// Number of channel strips on this surface, which is the width of the bank window
+    strip_count: usize,
+    /// Touch sensor notes, mapped to the index of their strip
+    touch_notes: HashMap<u32, usize>,
+    /// The notes of the buttons of each strip, by their description in the MIDI definition
+    strip_buttons: Vec<HashMap<String, u32>>,
+    /// Strip buttons that toggle a parameter of their strip, by their description
+    fader_buttons: Vec<(String, PathType)>,
+    /// Expression pedal CC number and the fader it controls
+    expression_pedal: Option<(u8, Fader)>,
+    /// All notes that are currently held down
+    held_notes: HashSet<u32>,
+    /// Strip buttons that toggle the main assignment, and whether each visible strip is
+    /// assigned to the main bus
+    main_assign_combo: Option<ButtonCombo>,
+    strip_main_assigned: Vec<Option<bool>>,
+    /// Whether phantom power is shown and switched, the phantom power parameter of the
+    /// source of each visible strip, and its state
+    phantom_mode: bool,
+    /// Whether the strip meters show the console's RTA instead of the strip levels
+    rta_mode: bool,
+    strip_phantom_paths: Vec<Option<String>>,
+    strip_phantom: Vec<Option<bool>>,
+    /// Level change of the nudge functions, in dB
+    nudge_step_db: f32,
+    /// Level offsets of the faders of each bank on the surface, by their fader parameter, in dB
+    bank_trims: Vec<HashMap<String, f32>>,
+    /// Fader updates too small to send, and the last level and 14-bit position of each
+    /// fader by MIDI channel, whether it was sent or received
+    fader_deadband: Option<FaderDeadband>,
+    fader_positions: std::sync::Mutex<HashMap<usize, (f32, u16)>>,
+    /// The console parameter with the selected layer, and the bank index for each layer
+    layer_follow: Option<(String, HashMap<i32, usize>)>,
+    /// Whether the selected strip follows, and changes, the selection on the console
+    follow_selection: bool,
 
     cached_colours: [u8; 8],
+    /// X-Touch colours for each WING colour index, and for unknown indices
+    colour_table: Vec<u8>,
+    fallback_colour: u8,
+    /// Whether each visible strip is muted
+    strip_mutes: Vec<bool>,
+    /// Meter threshold for signal-present indication, and whether each strip is above it
+    signal_present_db: Option<f32>,
+    signal_present: Vec<bool>,
+    /// The name of each visible strip
+    strip_names: Vec<String>,
+    /// Whether long names are scrolled, and the current scroll position
+    marquee: bool,
+    marquee_step: usize,
+    /// Whether each visible strip is currently touched
+    strip_touched: Vec<bool>,
+    /// Whether each visible strip is showing its value instead of its name, and a counter
+    /// that is increased on each value update
+    showing_value: Vec<bool>,
+    value_display_generation: Vec<u64>,
+    /// Whether blinking LEDs are currently lit
+    blink_phase: bool,
+    /// A status message that takes priority over the bank name on the main display
+    status_message: Option<String>,
+    /// Whether the console link was lost, and the surface must be re-hydrated once it is back
+    link_lost: bool,
+    /// Increased on each bank change, to cancel any previous flashing of the bank number
+    bank_flash_generation: u64,
+    /// Time of the last MIDI or OSC activity, and whether the surface is sleeping
+    last_activity: Instant,
+    asleep: bool,
+    /// Inactivity period before sleeping, and whether to park the faders while asleep
+    sleep_timeout: Option<Duration>,
+    park_faders_on_sleep: bool,
+    /// Whether to park the faders when the bridge exits
+    park_faders_on_exit: bool,
+    /// Whether all input except the lock function is ignored
+    locked: bool,
+    /// Whether the surface shows the Vegas mode test pattern instead of the strips
+    vegas: bool,
+    /// A strip to identify once the interface is set
+    pending_identify: Option<Fader>,
+    /// The last LED states, scribble colours and scribble rows (by display offset) that
+    /// were sent, so that unchanged ones are not sent again
+    sent_leds: std::sync::Mutex<HashMap<u32, bool>>,
+    sent_colours: std::sync::Mutex<Option<[u8; STRIP_COUNT]>>,
+    sent_lcd_rows: std::sync::Mutex<HashMap<u8, Vec<u8>>>,
+    /// What the encoders control, the strip of each encoder CC and the ring CC of each strip
+    encoder_mode: EncoderMode,
+    encoder_ccs: HashMap<u8, usize>,
+    encoder_ring_ccs: Vec<u8>,
+    /// Gain change per encoder click, and the highest gain that can be set, in dB
+    gain_step_db: f32,
+    max_gain_db: f32,
+    /// Send level change per encoder click, as a fraction of the fader travel
+    send_step: f32,
+    /// Change per encoder click of the panning and ratio of the low cut frequency in focus mode
+    pan_step: f32,
+    low_cut_ratio: f32,
+    /// Encoder acceleration, and the time each encoder was last turned
+    encoder_acceleration: Option<EncoderAcceleration>,
+    last_encoder_turn: Vec<Option<Instant>>,
+    /// The strip chosen with the Select buttons, and the names of the buses its sends go to
+    selected_fader: Option<Fader>,
+    send_names: Vec<String>,
+    /// Name of the active scene, and whether it replaces the bank name on the main display
+    scene_name: Option<String>,
+    scene_on_main_display: bool,
+    /// Faders that do not change with the bank, by MIDI channel
+    fixed_faders: HashMap<usize, Fader>,
+    /// Recent tap times of each tap tempo parameter, and the time tempo pulses start from
+    taps: HashMap<String, Vec<Instant>>,
+    tempo_epoch: Instant,
 }
 
 impl Controller {
@@ -148,61 +457,80 @@ impl Controller {
     pub fn new(
         midi_settings: &ControllerSettings,
         midi_definition: &MidiDefinition,
+        macros: Arc<MacroEngine>,
+        fades: Arc<FadeEngine>,
+        cues: Arc<CueEngine>,
+        snapshots: Arc<SnapshotStore>,
+        surface_states: Arc<SurfaceStateStore>,
     ) -> Result<Arc<Mutex<Self>>> {
-        try_arc_new_cyclic(|weak| {
-            let input_name = &midi_settings.input;
-            let output_name = &midi_settings.output;
+        let (output_queue, output_queue_rx) = unbounded_channel();
+        let output_pending = Arc::new(std::sync::atomic::AtomicUsize::new(0));
 
-            let input = MidiInput::new("X-Touch Wing IN")?;
-            let output = MidiOutput::new("X-Touch Wing OUT")?;
+        let output_failed = Arc::new(std::sync::atomic::AtomicBool::new(false));
 
-            let ports = input.ports();
-            let input_port = ports
-                .iter()
-                .find(|p| input.port_name(p).ok().as_deref() == Some(&input_name))
-                .ok_or_else(|| anyhow::anyhow!("MIDI input port '{}' not found", input_name))?;
-
-            let ports = output.ports();
-            let output_port = ports
-                .iter()
-                .find(|p| output.port_name(p).ok().as_deref() == Some(&output_name))
-                .ok_or_else(|| anyhow::anyhow!("MIDI output port '{}' not found", output_name))?;
-
-            // Wrap connect errors into anyhow so we don't require the backend error
-            // types to be `Sync` for the `?` operator.
-            let input_connection = input
-                .connect(
-                    input_port,
-                    "xtouch-wing-input",
-                    midi_callback,
-                    (weak.clone(), Handle::current()),
-                )
-                .map_err(|e| anyhow!("MIDI input connect failed: {}", e))?;
-
-            let output_connection = output
-                .connect(output_port, "xtouch-wing-output")
-                .map_err(|e| anyhow!("MIDI output connect failed: {}", e))?;
-
-            info!(
-                "MIDI input '{}' and output '{}' connected",
-                input_name, output_name
-            );
+        let controller = try_arc_new_cyclic(|weak| {
+            let (input_connection, output_connection) =
+                Self::connect(&midi_settings.input, &midi_settings.output, weak.clone())?;
 
             let mut banks = Vec::new();
-            for bank in &midi_settings.assignments.banks {
+            for bank in midi_settings.assignments.banks.iter() {
                 let faders = bank
                     .faders
                     .iter()
                     .map(|label| {
-                        Fader::new_from_label(label).with_context(|| {
+                        let fader = Fader::new_from_label(label).with_context(|| {
                             format!("Fader label '{}' in your configuration is invalid", label)
-                        })
+                        })?;
+                        Ok(Self::route_fader(bank, label, fader))
                     })
                     .collect::<Result<Vec<Fader>>>()?;
 
                 banks.push(faders);
             }
 
+            let bank_trims = midi_settings
+                .assignments
+                .banks
+                .iter()
+                .map(|bank| {
+                    bank.trim_db
+                        .iter()
+                        .map(|(label, trim)| {
+                            let fader = Fader::new_from_label(label).with_context(|| {
+                                format!(
+                                    "Trimmed fader label '{}' in your configuration is invalid",
+                                    label
+                                )
+                            })?;
+                            let fader = Self::route_fader(bank, label, fader);
+                            Ok((fader.get_osc_path(PathType::Fader), *trim))
+                        })
+                        .collect::<Result<HashMap<String, f32>>>()
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let layer_follow = match &midi_settings.follow_layer {
+                Some(follow) => {
+                    let layer_banks = follow
+                        .banks
+                        .iter()
+                        .map(|(layer, name)| {
+                            let bank = midi_settings
+                                .assignments
+                                .banks
+                                .iter()
+                                .position(|b| b.name.as_deref() == Some(name.as_str()))
+                                .ok_or_else(|| {
+                                    anyhow!("Bank '{}' for console layer {} does not exist", name, layer)
+                                })?;
+                            Ok((*layer, bank))
+                        })
+                        .collect::<Result<HashMap<i32, usize>>>()?;
+                    Some((follow.osc.clone(), layer_banks))
+                }
+                None => None,
+            };
+
             let buttons = midi_settings
                 .assignments
                 .fixed_buttons
@@ -216,11 +544,155 @@ impl Controller {
                 })
                 .collect::<Result<HashMap<u32, InternalButton>>>()?;
 
+            let long_press_buttons = midi_settings
+                .assignments
+                .long_press_buttons
+                .iter()
+                .map(|(index, label)| {
+                    let button = InternalButton::new_from_label(label).with_context(|| {
+                        format!("Long-press button label '{}' in your configuration is invalid", label)
+                    })?;
+
+                    Ok((*index, button.function))
+                })
+                .collect::<Result<HashMap<u32, InternalFunction>>>()?;
+
+            let unknown_macro = buttons
+                .values()
+                .map(|b| &b.function)
+                .chain(long_press_buttons.values())
+                .find_map(|function| match function {
+                    InternalFunction::Macro(name) if !macros.contains(name) => Some(name),
+                    _ => None,
+                });
+
+            if let Some(name) = unknown_macro {
+                anyhow::bail!("Macro '{}' is assigned to a button, but it is not defined", name);
+            }
+
+            let colours = &midi_settings.colours;
+            if let Some(colour) = colours
+                .table
+                .iter()
+                .chain(std::iter::once(&colours.fallback))
+                .find(|c| **c > MAX_XTOUCH_COLOUR)
+            {
+                anyhow::bail!("Invalid X-Touch colour {} in colour table", colour);
+            }
+
+            let mut fixed_faders = midi_settings
+                .assignments
+                .fixed_faders
+                .iter()
+                .map(|(channel, label)| {
+                    let fader = Fader::new_from_label(label).with_context(|| {
+                        format!("Fixed fader label '{}' in your configuration is invalid", label)
+                    })?;
+                    Ok((*channel as usize, fader))
+                })
+                .collect::<Result<HashMap<usize, Fader>>>()?;
+
+            if let Some((channel, _)) = fixed_faders.iter().find(|(c, _)| **c < STRIP_COUNT) {
+                anyhow::bail!("Fixed fader on MIDI channel {} overlaps the strips", channel);
+            }
+
+            if midi_settings.monitor_on_master {
+                // The master fader is on the channel after the strips
+                fixed_faders.insert(STRIP_COUNT, Fader::new_from_label("Monitor")?);
+            }
+
+            let strip_count = midi_definition.strip_count();
+            if strip_count == 0 {
+                anyhow::bail!("The MIDI definition has no channel strips");
+            }
+
+            let touch_notes = midi_definition
+                .faders
+                .iter()
+                .take(strip_count)
+                .enumerate()
+                .flat_map(|(index, fader)| {
+                    fader
+                        .buttons
+                        .iter()
+                        .filter(|b| b.description.as_deref() == Some("Touch"))
+                        .map(move |b| (b.key as u32, index))
+                })
+                .collect::<HashMap<u32, usize>>();
+
+            let strip_buttons = midi_definition
+                .faders
+                .iter()
+                .take(strip_count)
+                .map(|fader| {
+                    fader
+                        .buttons
+                        .iter()
+                        .filter_map(|b| Some((b.description.clone()?, b.key as u32)))
+                        .collect::<HashMap<String, u32>>()
+                })
+                .collect::<Vec<_>>();
+
+            let fader_buttons = midi_settings
+                .assignments
+                .fader_buttons
+                .iter()
+                .map(|description| {
+                    let path_type = PathType::for_strip_button(description).ok_or_else(|| {
+                        anyhow!("Fader button '{}' must be one of Mute and Solo", description)
+                    })?;
+                    Ok((description.clone(), path_type))
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            // Continue where the surface was left, as long as the banks still exist
+            let restored = surface_states.get(&midi_settings.input);
+            let window = match &restored {
+                Some(state) if state.bank < banks.len() => BankWindow {
+                    bank: state.bank,
+                    offset: if state.offset < banks[state.bank].len() { state.offset } else { 0 },
+                    overlay: None,
+                },
+                _ => BankWindow::default(),
+            };
+
+            let expression_pedal = match (
+                midi_definition.expression_pedal,
+                &midi_settings.assignments.expression_pedal,
+            ) {
+                (Some(cc), Some(label)) => {
+                    let fader = Fader::new_from_label(label).with_context(|| {
+                        format!("Expression pedal label '{}' in your configuration is invalid", label)
+                    })?;
+                    Some((cc, fader))
+                }
+                (None, Some(label)) => {
+                    warn!("Expression pedal assigned to '{}', but the MIDI definition has no pedal", label);
+                    None
+                }
+                _ => None,
+            };
+
             Ok(Mutex::new(Self {
                 input: Arc::new(std::sync::Mutex::new(input_connection)),
                 output: Arc::new(std::sync::Mutex::new(output_connection)),
+                output_queue,
+                output_failed: output_failed.clone(),
+                output_pending: output_pending.clone(),
+                input_name: midi_settings.input.clone(),
+                output_name: midi_settings.output.clone(),
+                ports_connected: true,
+                this: weak.clone(),
+                runtime: Handle::current(),
                 interface: Arc::new(Mutex::new(None)),
-                current_bank: 0,
+                metrics: None,
+                macros,
+                fades,
+                cues,
+                snapshots,
+                surface_states,
+                resolved_bank: banks.get(window.bank).cloned().unwrap_or_default(),
+                window,
                 banks: banks,
                 bank_names: midi_settings
                     .assignments
@@ -228,373 +700,2873 @@ impl Controller {
                     .iter()
                     .map(|b| b.name.clone())
                     .collect(),
+                bank_meter_sources: midi_settings
+                    .assignments
+                    .banks
+                    .iter()
+                    .map(|b| b.meter)
+                    .collect(),
+                bank_filters: midi_settings
+                    .assignments
+                    .banks
+                    .iter()
+                    .map(|b| b.filter.clone())
+                    .collect(),
                 buttons: buttons,
+                long_press_buttons,
+                long_press_duration: Duration::from_millis(midi_settings.assignments.long_press_ms),
+                pressed_buttons: HashMap::new(),
+                press_counter: 0,
+                strip_count,
+                touch_notes,
+                strip_buttons,
+                fader_buttons,
+                expression_pedal,
+                held_notes: HashSet::new(),
+                main_assign_combo: midi_settings.main_assign_combo.clone(),
+                strip_main_assigned: vec![None; strip_count],
+                phantom_mode: false,
+                rta_mode: false,
+                strip_phantom_paths: vec![None; strip_count],
+                strip_phantom: vec![None; strip_count],
+                nudge_step_db: midi_settings.nudge_step_db.unwrap_or(DEFAULT_NUDGE_STEP_DB),
+                bank_trims,
+                fader_deadband: midi_settings.fader_deadband.clone(),
+                fader_positions: Default::default(),
+                layer_follow,
+                follow_selection: midi_settings.follow_selection,
                 cached_colours: [7; _],
+                colour_table: midi_settings.colours.table.clone(),
+                fallback_colour: midi_settings.colours.fallback,
+                strip_mutes: vec![false; strip_count],
+                signal_present_db: midi_settings.signal_present_db,
+                signal_present: vec![false; strip_count],
+                strip_names: vec![String::new(); strip_count],
+                marquee: midi_settings.scribble_marquee,
+                marquee_step: 0,
+                strip_touched: vec![false; strip_count],
+                showing_value: vec![false; strip_count],
+                value_display_generation: vec![0; strip_count],
+                blink_phase: false,
+                status_message: None,
+                link_lost: false,
+                bank_flash_generation: 0,
+                last_activity: Instant::now(),
+                asleep: false,
+                sleep_timeout: midi_settings
+                    .sleep
+                    .as_ref()
+                    .map(|s| Duration::from_secs(s.timeout_secs)),
+                park_faders_on_sleep: midi_settings.sleep.as_ref().is_some_and(|s| s.park_faders),
+                park_faders_on_exit: midi_settings.park_faders_on_exit,
+                locked: restored.as_ref().is_some_and(|state| state.locked),
+                vegas: false,
+                pending_identify: None,
+                sent_leds: Default::default(),
+                sent_colours: Default::default(),
+                sent_lcd_rows: Default::default(),
+                encoder_mode: restored
+                    .as_ref()
+                    .map_or(midi_settings.encoders.mode, |state| state.encoder_mode),
+                encoder_ccs: midi_definition
+                    .encoders
+                    .iter()
+                    .enumerate()
+                    .take(strip_count)
+                    .map(|(index, encoder)| (encoder.cc, index))
+                    .collect(),
+                encoder_ring_ccs: midi_definition
+                    .encoders
+                    .iter()
+                    .take(strip_count)
+                    .map(|encoder| encoder.ring_cc)
+                    .collect(),
+                gain_step_db: midi_settings.encoders.gain_step_db,
+                max_gain_db: midi_settings.encoders.max_gain_db.min(PREAMP_MAX_DB),
+                send_step: midi_settings.encoders.send_step,
+                pan_step: midi_settings.encoders.pan_step,
+                low_cut_ratio: midi_settings.encoders.low_cut_ratio,
+                encoder_acceleration: midi_settings.encoders.acceleration.clone(),
+                last_encoder_turn: vec![None; strip_count],
+                selected_fader: None,
+                send_names: vec![String::new(); strip_count],
+                scene_name: None,
+                scene_on_main_display: midi_settings.scene_on_main_display,
+                fixed_faders,
+                taps: HashMap::new(),
+                tempo_epoch: Instant::now(),
             }))
-        })
+        })?;
+
+        let output = controller.try_lock()?.output.clone();
+        Self::spawn_output_task(
+            output,
+            output_queue_rx,
+            midi_settings.max_messages_per_sec,
+            output_failed,
+            output_pending,
+        );
+        Self::spawn_watchdog_task(Arc::downgrade(&controller));
+
+        Self::spawn_blink_task(Arc::downgrade(&controller));
+        let has_tap_tempo = controller
+            .try_lock()?
+            .buttons
+            .values()
+            .any(|b| matches!(b.function, InternalFunction::TapTempo(_)));
+        if has_tap_tempo {
+            Self::spawn_tempo_task(Arc::downgrade(&controller));
+        }
+        if midi_settings.scribble_marquee {
+            Self::spawn_marquee_task(Arc::downgrade(&controller));
+        }
+        if midi_settings.sleep.is_some() {
+            Self::spawn_sleep_task(Arc::downgrade(&controller));
+        }
+
+        Ok(controller)
     }
 
-    #[instrument(name = "midi_set_fader", level = Level::DEBUG, skip(self, fader, value))]
-    pub async fn process_fader_input(
-        &mut self,
-        fader_index: usize,
-        fader: &Fader,
-        path: PathType,
-        value: &Value,
-    ) -> Result<()> {
-        match path {
-            PathType::Fader => {
-                if let Value::Float(db) = value {
-                    let midi_value: f64 = Fader::db_to_float((*db) as f64);
+    /// The parameters of the faders of all banks, without duplicates
+    fn all_bank_paths(&self) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self
+            .banks
+            .iter()
+            .flatten()
+            .chain(self.fixed_faders.values())
+            .flat_map(|fader| {
+                let mut path_types = vec![PathType::Fader];
+                if fader.label().is_none() {
+                    path_types.extend([PathType::Mute, PathType::ScribbleName, PathType::ScribbleColour]);
+                }
+                if fader.link_partner().is_some() {
+                    path_types.push(PathType::Link);
+                }
 
-                    debug!(fader_index, db = ?db, val = ?midi_value, "Setting fader value");
+                path_types.into_iter().map(|path_type| fader.get_osc_path(path_type))
+            })
+            .filter(|path| seen.insert(path.clone()))
+            .collect()
+    }
 
-                    let ev = LiveEvent::Midi {
-                        channel: (fader_index as u8).into(),
-                        message: midly::MidiMessage::PitchBend {
-                            // TODO: Handle 1.0 max value
-                            bend: PitchBend::from_f64(midi_value * 2.0 - 1.0),
-                        },
-                    };
+    /// All parameters that the configuration of this controller refers to
+    pub fn configured_paths(&self) -> Vec<String> {
+        let mut paths = self.all_bank_paths();
+        paths.extend(self.function_paths());
+        paths
+    }
 
-                    let mut buf = Vec::with_capacity(3);
-                    ev.write(&mut buf)
-                        .map_err(|e| anyhow!("MIDI write fail {}", e))?;
-                    // synchronous context: use blocking_lock to acquire the Tokio mutex
-                    self.send_midi(&buf)?;
-                } else {
-                    warn!("Expected float value for fader, got {:?}", value);
-                }
-            }
-            PathType::ScribbleColour => {
-                if let Value::Int(colour_index) = value {
-                    debug!(fader_index, scribble_colour = colour_index, "Setting fader scribble colour");
-                    let wing_color = WING_TO_XTOUCH_COLOR
-                        .get(*colour_index as usize)
-                        .copied()
-                        .unwrap_or(7);
+    /// The parameters that buttons and console layers act on
+    fn function_paths(&self) -> Vec<String> {
+        let mut paths = Vec::new();
 
-                    self.cached_colours[fader_index] = wing_color;
-                    self.send_colours().await;
-                } else {
-                    warn!("Expected int value for scribble colour, got {:?}", value);
-                }
-            }
-            PathType::ScribbleName => {
-                if let Value::Str(name) = value {
-                    debug!(fader_index, scribble_name = name.as_str(), "Setting fader scribble name");
-                    self.set_lcd_text(name, fader_index as u8).await;
-                } else {
-                    warn!("Expected string value for scribble name, got {:?}", value);
-                }
+        for function in self.buttons.values().map(|b| &b.function).chain(self.long_press_buttons.values()) {
+            if let Some(osc_path) = function.osc_path() {
+                paths.push(osc_path.to_string());
             }
-            _ => {}
         }
 
-        Ok(())
+        if let Some((layer_path, _)) = &self.layer_follow {
+            paths.push(layer_path.clone());
+        }
+
+        paths
     }
 
-    pub async fn process_osc_input(&mut self, osc_addr: &str, value: &Value) -> Result<()> {
-        let faders = &self
+    /// Parameter trees of the faders in all banks, as custom faders may show directories outside
+    /// of the subscribed trees
+    fn fader_trees(&self) -> Vec<String> {
+        let mut trees: Vec<String> = self
             .banks
-            .get(self.current_bank)
-            .ok_or_else(|| anyhow::anyhow!("Current bank {} not found", self.current_bank))?;
-
-        let faders = (*faders).clone();
-
-        for (index, fader) in faders.iter().enumerate() {
-            if let Some(path_type) = fader.path_matches(osc_addr) {
-                self.process_fader_input(index, fader, path_type, value).await?;
-            }
-        }
+            .iter()
+            .flatten()
+            .map(|fader| format!("{}/**", fader.osc_directory()))
+            .collect();
+        trees.sort();
+        trees.dedup();
 
-        Ok(())
+        trees
     }
 
-    async fn refresh_bank(&self) -> Result<()> {
-        info!("Hydrating bank {} buttons & faders", self.current_bank);
+    /// Refresh the button LEDs whenever safe mode is switched, also from elsewhere
+    fn spawn_safe_mode_watch(&self, interface: &Interface) {
+        let mut safe_mode = interface.watch_safe_mode();
+        let this = self.this.clone();
 
-        let faders = self
-            .banks
-            .get(self.current_bank)
-            .ok_or_else(|| anyhow::anyhow!("Bank {} not on list", self.current_bank))?;
+        self.runtime.spawn(async move {
+            while safe_mode.changed().await.is_ok() {
+                let Some(controller) = this.upgrade() else {
+                    return;
+                };
+                controller.lock().await.refresh_all_button_leds().await;
+            }
+        });
+    }
 
-        let interface_guard = self
-                .interface
-                .lock()
-                .await;
-        let interface = interface_guard
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("Interface not set"))?;
+    /// Request the parameters of the faders of all banks in the background, so that bank
+    /// changes are shown from the cache right away
+    fn spawn_hydration(&self) {
+        let paths = self.all_bank_paths();
+        let interface = self.interface.clone();
 
-        for (index, fader) in faders.iter().enumerate() {
-            let osc_path = fader.get_osc_path(PathType::Fader);
+        self.runtime.spawn(async move {
+            let Some(interface) = interface.lock().await.clone() else {
+                return;
+            };
 
-            let value = interface
-                .request_value_notification_checked(&osc_path, false)
-                .await;
+            let mut requested = 0;
+            for path in &paths {
+                if interface.get_cached_value(path).await.is_some() {
+                    continue;
+                }
 
-            if let Err(e) = value {
-                warn!(
-                    "OSC value for {} not found during bank refresh: {}",
-                    osc_path, e
-                );
+                interface.ensure_value(path, false).await;
+                requested += 1;
+                tokio::time::sleep(HYDRATION_REQUEST_INTERVAL).await;
             }
 
-            interface
-                .request_value_notification(&fader.get_osc_path(PathType::ScribbleColour), false)
-                .await;
+            info!(parameters = paths.len(), requested, "Parameters of all banks hydrated");
+        });
+    }
 
-            interface
-                .request_value_notification(&fader.get_osc_path(PathType::ScribbleName), false)
-                .await;
+    /// Move a fader to the console of its bank, unless its label names a console itself
+    fn route_fader(bank: &FaderBank, label: &str, fader: Fader) -> Fader {
+        match &bank.console {
+            Some(console_id) if !label.starts_with('@') => fader.on_console(console_id),
+            _ => fader,
         }
+    }
 
-        drop(interface_guard);
+    /// Open the MIDI input and output ports with the given names
+    fn connect(
+        input_name: &str,
+        output_name: &str,
+        this: Weak<Mutex<Controller>>,
+    ) -> Result<(
+        MidiInputConnection<(Weak<Mutex<Controller>>, Handle)>,
+        MidiOutputConnection,
+    )> {
+        let input = MidiInput::new("X-Touch Wing IN")?;
+        let output = MidiOutput::new("X-Touch Wing OUT")?;
+
+        let ports = input.ports();
+        let input_port = ports
+            .iter()
+            .find(|p| input.port_name(p).ok().as_deref() == Some(input_name))
+            .ok_or_else(|| anyhow::anyhow!("MIDI input port '{}' not found", input_name))?;
 
-        self.refresh_all_button_leds().await;
+        let ports = output.ports();
+        let output_port = ports
+            .iter()
+            .find(|p| output.port_name(p).ok().as_deref() == Some(output_name))
+            .ok_or_else(|| anyhow::anyhow!("MIDI output port '{}' not found", output_name))?;
+
+        // Wrap connect errors into anyhow so we don't require the backend error
+        // types to be `Sync` for the `?` operator.
+        let input_connection = input
+            .connect(
+                input_port,
+                "xtouch-wing-input",
+                midi_callback,
+                (this, Handle::current()),
+            )
+            .map_err(|e| anyhow!("MIDI input connect failed: {}", e))?;
 
-        self.write_text_to_main_display(
-            self.bank_names
-                .get(self.current_bank)
-                .and_then(|name| name.as_deref())
-                .unwrap_or(""),
-        ).await;
+        let output_connection = output
+            .connect(output_port, "xtouch-wing-output")
+            .map_err(|e| anyhow!("MIDI output connect failed: {}", e))?;
 
-        self.request_meters().await;
+        info!(
+            "MIDI input '{}' and output '{}' connected",
+            input_name, output_name
+        );
 
-        Ok(())
+        Ok((input_connection, output_connection))
     }
 
-    async fn get_function_button_lit(&self, function: &InternalFunction) -> Result<bool> {
-        let mut result: anyhow::Result<_>;
-
-        match function {
-            InternalFunction::NextBank => {
-                result = Ok(self.current_bank + 1 < self.banks.len());
-            },
-            InternalFunction::PreviousBank => {
-                result = Ok(self.current_bank > 0);
-            },
+    /// Whether a MIDI output port with the given name currently exists
+    fn output_port_exists(output_name: &str) -> bool {
+        match MidiOutput::new("X-Touch Wing Watchdog") {
+            Ok(output) => output
+                .ports()
+                .iter()
+                .any(|p| output.port_name(p).ok().as_deref() == Some(output_name)),
+            Err(e) => {
+                warn!("Failed to scan MIDI ports: {}", e);
+                true
+            }
         }
-
-        result.with_context(|| format!("While checking function LED {:?}", function))
     }
 
-    async fn refresh_button_led(&self, button: u32) {
-        if let Some(internal_button) = self.buttons.get(&button) {
-            let lit = self.get_function_button_lit(&internal_button.function).await;
+    /// Spawn the background task that detects an unplugged controller and reconnects to it
+    /// when it comes back
+    fn spawn_watchdog_task(this: Weak<Mutex<Controller>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(WATCHDOG_INTERVAL);
+            let mut connected = true;
 
-            if let Err(e) = lit {
-                warn!("Failed to get button LED state for button {}: {}", button, e);
-                return;
-            }
+            loop {
+                interval.tick().await;
 
-            let lit = lit.unwrap();
+                let Some(controller) = this.upgrade() else {
+                    break;
+                };
 
-            let midi_value = if lit { 127 } else { 0 };
+                let (input_name, output_name, output_failed) = {
+                    let controller = controller.lock().await;
+                    (
+                        controller.input_name.clone(),
+                        controller.output_name.clone(),
+                        controller.output_failed.clone(),
+                    )
+                };
 
-            let ev = LiveEvent::Midi {
-                channel: 0.into(),
-                message: midly::MidiMessage::NoteOn {
-                    key: (button as u8).into(),
-                    vel: midi_value.into(),
-                },
-            };
+                let present = Self::output_port_exists(&output_name);
+                let failed = output_failed.load(std::sync::atomic::Ordering::Relaxed);
 
-            let mut buf = Vec::with_capacity(3);
-            ev.write(&mut buf)
-                .map_err(|e| anyhow!("MIDI write fail {}", e))
-                .unwrap();
-            if let Err(e) = self.send_midi(&buf) {
-                warn!("Failed to send MIDI for button {}: {}", button, e);
+                if connected && (!present || failed) {
+                    warn!(port = output_name, "MIDI controller disconnected");
+                    connected = false;
+                    controller.lock().await.ports_connected = false;
+                }
+
+                if connected || !present {
+                    continue;
+                }
+
+                match Self::connect(&input_name, &output_name, this.clone()) {
+                    Ok((input_connection, output_connection)) => {
+                        // The controller is not locked here, as dropping the old input
+                        // connection waits for its callback to finish
+                        let (input, output) = {
+                            let controller = controller.lock().await;
+                            (controller.input.clone(), controller.output.clone())
+                        };
+                        *input.lock().unwrap() = input_connection;
+                        *output.lock().unwrap() = output_connection;
+                        output_failed.store(false, std::sync::atomic::Ordering::Relaxed);
+                        connected = true;
+
+                        let mut controller = controller.lock().await;
+                        controller.ports_connected = true;
+                        if let Err(e) = controller.restore_surface().await {
+                            error!("Failed to restore surface after reconnecting: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        debug!("MIDI controller not reconnected yet: {}", e);
+                    }
+                }
             }
-        } else {
-            // ...
-        }
+        });
     }
 
-    async fn refresh_all_button_leds(&self) {
-        // TODO: Cache LED status and don't update if not necessary
-        for button in self.buttons.keys() {
-            self.refresh_button_led(*button).await;
-        }
+    /// Forget what has been sent to the surface and send everything again, e.g. after the
+    /// controller has been power-cycled
+    async fn restore_surface(&mut self) -> Result<()> {
+        self.fader_positions.lock().unwrap().clear();
+        self.sent_leds.lock().unwrap().clear();
+        self.sent_lcd_rows.lock().unwrap().clear();
+        *self.sent_colours.lock().unwrap() = None;
+
+        self.clean_buttons().await;
+        self.send_colours().await;
+        self.refresh_bank().await
     }
 
-    /// Clear all button LEDs (set to 0).
-    pub async fn clean_buttons(&self) {
-        for note in 0..115 {
-            let ev = LiveEvent::Midi {
-                channel: 0.into(),
+    /// Spawn the background task that drives all blinking LEDs
+    fn spawn_blink_task(this: Weak<Mutex<Controller>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(BLINK_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let Some(controller) = this.upgrade() else {
+                    break;
+                };
+                let mut controller = controller.lock().await;
+
+                if controller.asleep {
+                    continue;
+                }
+
+                controller.blink_phase = !controller.blink_phase;
+                controller.refresh_blinking_leds().await;
+            }
+        });
+    }
+
+    /// Spawn the background task that shows the Vegas mode test pattern until it is switched
+    /// off
+    fn spawn_vegas_task(this: Weak<Mutex<Controller>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(VEGAS_FRAME_INTERVAL);
+            let mut clk = 0;
+
+            loop {
+                interval.tick().await;
+
+                let Some(controller) = this.upgrade() else {
+                    break;
+                };
+                let controller = controller.lock().await;
+
+                if !controller.vegas {
+                    break;
+                }
+                if let Err(e) = controller.send_vegas_frame(clk, true) {
+                    warn!("Failed to send Vegas mode frame: {}", e);
+                }
+                clk += 1;
+            }
+        });
+    }
+
+    /// Spawn the background task that pulses tap tempo LEDs
+    fn spawn_tempo_task(this: Weak<Mutex<Controller>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TEMPO_LED_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let Some(controller) = this.upgrade() else {
+                    break;
+                };
+                let controller = controller.lock().await;
+
+                if controller.asleep {
+                    continue;
+                }
+
+                let buttons = controller
+                    .buttons
+                    .iter()
+                    .filter(|(_, b)| matches!(b.function, InternalFunction::TapTempo(_)))
+                    .map(|(note, _)| *note)
+                    .collect::<Vec<u32>>();
+
+                for button in buttons {
+                    controller
+                        .refresh_button_led_if(button, |state| matches!(state, LedState::Pulse(_)))
+                        .await;
+                }
+            }
+        });
+    }
+
+    /// Spawn the background task that scrolls names which do not fit on the scribble strips
+    fn spawn_marquee_task(this: Weak<Mutex<Controller>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(MARQUEE_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let Some(controller) = this.upgrade() else {
+                    break;
+                };
+                let mut controller = controller.lock().await;
+
+                if controller.asleep {
+                    continue;
+                }
+
+                controller.marquee_step = controller.marquee_step.wrapping_add(1);
+
+                for index in 0..controller.strip_count {
+                    if controller.strip_names[index].chars().count() > SCRIBBLE_ROW_LEN * 2
+                        && !controller.showing_value[index]
+                    {
+                        controller.show_strip_name(index).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Spawn the background task that puts the surface to sleep when it is not used
+    fn spawn_sleep_task(this: Weak<Mutex<Controller>>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SLEEP_CHECK_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let Some(controller) = this.upgrade() else {
+                    break;
+                };
+                let mut controller = controller.lock().await;
+
+                let idle = controller
+                    .sleep_timeout
+                    .is_some_and(|timeout| controller.last_activity.elapsed() >= timeout);
+
+                if idle && !controller.asleep {
+                    controller.sleep().await;
+                }
+            }
+        });
+    }
+
+    /// Turn off all LEDs and displays, until the surface is used again
+    async fn sleep(&mut self) {
+        info!("Surface going to sleep");
+
+        self.asleep = true;
+        self.blank(self.park_faders_on_sleep).await;
+        self.request_meters().await;
+    }
+
+    /// Leave the surface blank before the bridge exits, and wait until everything was sent
+    /// to it
+    pub async fn shut_down(&mut self) {
+        info!(input = self.input_name.as_str(), "Shutting down surface");
+
+        self.asleep = true;
+        self.request_meters().await;
+        self.blank(self.park_faders_on_exit).await;
+        self.flush_output().await;
+    }
+
+    /// Turn off all LEDs and displays, and optionally move all faders to the bottom
+    async fn blank(&mut self, park_faders: bool) {
+        self.signal_present.fill(false);
+
+        self.clean_buttons().await;
+
+        for index in 0..self.strip_count {
+            self.set_lcd_rows("", "", index as u8).await;
+        }
+
+        self.write_colours([0; STRIP_COUNT]);
+
+        self.write_assignment_display("").await;
+        self.write_text_to_main_display("").await;
+
+        if park_faders {
+            self.fader_positions.lock().unwrap().clear();
+
+            // The master fader is on the channel after the strips
+            for channel in 0..=STRIP_COUNT as u8 {
+                let ev = LiveEvent::Midi {
+                    channel: channel.into(),
+                    message: midly::MidiMessage::PitchBend {
+                        bend: PitchBend::from_f64(-1.0),
+                    },
+                };
+
+                let mut buf = Vec::with_capacity(3);
+                ev.write(&mut buf).unwrap();
+                if let Err(e) = self.send_midi(&buf) {
+                    warn!("Failed to park fader {}: {}", channel, e);
+                }
+            }
+        }
+    }
+
+    /// Restore the surface after sleeping, replaying the cached state
+    async fn wake(&mut self) -> Result<()> {
+        if !self.asleep {
+            return Ok(());
+        }
+
+        info!("Surface waking up");
+
+        self.asleep = false;
+        self.last_activity = Instant::now();
+
+        self.send_colours().await;
+        self.refresh_bank().await
+    }
+
+    #[instrument(name = "midi_set_fader", level = Level::DEBUG, skip(self, fader, value))]
+    /// Move a motorised fader to the position of a value
+    fn send_fader_position(&self, fader_index: usize, fader: &Fader, value: &Value) -> Result<()> {
+        if let Value::Float(db) = value {
+            let db = *db + self.fader_trim(fader_index, fader);
+            let midi_value: f64 = fader.value_to_position(db as f64);
+            let counts = (midi_value.clamp(0.0, 1.0) * PITCH_BEND_MAX as f64).round() as u16;
+
+            if self.within_deadband(fader_index, db, counts) {
+                trace!(fader_index, db, counts, "Fader update within deadband");
+                return Ok(());
+            }
+            self.fader_positions.lock().unwrap().insert(fader_index, (db, counts));
+
+            debug!(fader_index, db = ?db, val = ?midi_value, "Setting fader value");
+
+            let ev = LiveEvent::Midi {
+                channel: (fader_index as u8).into(),
+                message: midly::MidiMessage::PitchBend {
+                    // TODO: Handle 1.0 max value
+                    bend: PitchBend::from_f64(midi_value * 2.0 - 1.0),
+                },
+            };
+
+            let mut buf = Vec::with_capacity(3);
+            ev.write(&mut buf)
+                .map_err(|e| anyhow!("MIDI write fail {}", e))?;
+            self.send_midi(&buf)?;
+        } else {
+            warn!("Expected float value for fader, got {:?}", value);
+        }
+
+        Ok(())
+    }
+
+    pub async fn process_fader_input(
+        &mut self,
+        fader_index: usize,
+        fader: &Fader,
+        path: PathType,
+        value: &Value,
+    ) -> Result<()> {
+        match path {
+            PathType::Fader => {
+                self.send_fader_position(fader_index, fader, value)?;
+            }
+            PathType::ScribbleColour => {
+                if let Value::Int(colour_index) = value {
+                    debug!(fader_index, scribble_colour = colour_index, "Setting fader scribble colour");
+                    let wing_color = usize::try_from(*colour_index)
+                        .ok()
+                        .and_then(|index| self.colour_table.get(index))
+                        .copied()
+                        .unwrap_or(self.fallback_colour);
+
+                    self.cached_colours[fader_index] = wing_color;
+                    self.send_colours().await;
+                } else {
+                    warn!("Expected int value for scribble colour, got {:?}", value);
+                }
+            }
+            PathType::Mute => {
+                if let Some(mute) = value.as_bool() {
+                    debug!(fader_index, mute, "Setting fader mute");
+
+                    self.strip_mutes[fader_index] = mute;
+                    self.send_colours().await;
+                    self.send_fader_button_leds(fader_index, &PathType::Mute, mute);
+                } else {
+                    warn!("Expected int value for mute, got {:?}", value);
+                }
+            }
+            PathType::Solo => {
+                if let Some(solo) = value.as_bool() {
+                    debug!(fader_index, solo, "Setting fader solo");
+                    self.send_fader_button_leds(fader_index, &PathType::Solo, solo);
+                } else {
+                    warn!("Expected int value for solo, got {:?}", value);
+                }
+            }
+            PathType::ScribbleName => {
+                if let Value::Str(name) = value {
+                    debug!(fader_index, scribble_name = name.as_str(), "Setting fader scribble name");
+                    self.strip_names[fader_index] = name.clone();
+                    if !self.showing_value[fader_index] {
+                        self.show_strip_name(fader_index).await;
+                    }
+                } else {
+                    warn!("Expected string value for scribble name, got {:?}", value);
+                }
+            }
+            PathType::MainAssign => {
+                if let Some(on) = value.as_bool() {
+                    debug!(fader_index, on, "Setting strip main assignment");
+
+                    self.strip_main_assigned[fader_index] = Some(on);
+                    if !self.showing_value[fader_index] {
+                        self.show_strip_name(fader_index).await;
+                    }
+                } else {
+                    warn!("Expected int value for main assignment, got {:?}", value);
+                }
+            }
+            PathType::SourceGroup | PathType::SourceInput => {
+                if self.phantom_mode {
+                    // The phantom power switch moves with the source
+                    self.resolve_phantom_source(fader_index).await;
+                    if !self.showing_value[fader_index] {
+                        self.show_strip_name(fader_index).await;
+                    }
+                }
+            }
+            PathType::Gain => {
+                if self.encoder_mode == EncoderMode::Gain {
+                    if let Value::Float(gain) = value {
+                        let position = (gain - PREAMP_MIN_DB) / (PREAMP_MAX_DB - PREAMP_MIN_DB);
+                        self.set_encoder_ring(fader_index, Some(position));
+                    } else {
+                        warn!("Expected float value for gain, got {:?}", value);
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub async fn process_osc_input(&mut self, osc_addr: &str, value: &Value) -> Result<()> {
+        self.last_activity = Instant::now();
+
+        if self.asleep {
+            // The state is replayed from the cache on wake-up
+            return Ok(());
+        }
+
+        let faders = self.visible_faders().to_vec();
+
+        for (index, fader) in faders.iter().enumerate() {
+            if let Some(path_type) = fader.path_matches(osc_addr) {
+                self.process_fader_input(index, fader, path_type, value).await?;
+            }
+        }
+
+        for (channel, fader) in &self.fixed_faders {
+            if fader.path_matches(osc_addr) == Some(PathType::Fader) {
+                self.send_fader_position(*channel, fader, value)?;
+            }
+        }
+
+        let is_bank_condition = self.bank_condition_paths().iter().any(|path| path == osc_addr);
+        if is_bank_condition && self.resolve_bank().await {
+            self.refresh_bank().await?;
+        }
+
+        if self.encoder_mode == EncoderMode::Sends {
+            self.process_send_input(osc_addr, value).await;
+        }
+
+        if self.encoder_mode == EncoderMode::Focus {
+            self.process_focus_input(osc_addr, value);
+        }
+
+        if self.follow_selection && osc_addr == SELECTED_STRIP_PATH {
+            let selected = match value {
+                Value::Int(index) => Fader::new_from_selection_index(*index),
+                _ => None,
+            };
+
+            if selected.is_some() && selected != self.selected_fader {
+                debug!(?selected, "Strip selected on the console");
+                self.selected_fader = selected;
+                self.refresh_select_leds();
+                self.refresh_encoders().await?;
+            }
+        }
+
+        if self.phantom_mode {
+            self.process_phantom_input(osc_addr, value).await;
+        }
+
+        if let Some((layer_path, layer_banks)) = &self.layer_follow {
+            if osc_addr == layer_path {
+                let bank = match value {
+                    Value::Int(layer) => layer_banks.get(layer).copied(),
+                    _ => None,
+                };
+
+                if let Some(bank) = bank.filter(|b| *b != self.window.bank || self.window.overlay.is_some()) {
+                    info!(?value, bank, "Following the console layer");
+                    self.window.bank = bank;
+                    self.window.overlay = None;
+                    self.window.offset = 0;
+                    self.refresh_bank().await?;
+                }
+            }
+        }
+
+        if osc_addr == SCENE_NAME_PATH {
+            if let Value::Str(name) = value {
+                debug!(scene = name.as_str(), "Active scene changed");
+                self.scene_name = Some(name.clone());
+                self.update_main_display().await;
+            }
+        }
+
+        self.refresh_button_leds_for_path(osc_addr).await;
+
+        Ok(())
+    }
+
+    /// Show the send levels and destinations of the selected strip on the encoders
+    async fn process_send_input(&mut self, osc_addr: &str, value: &Value) {
+        if let Some(PathType::SendLevel(bus)) =
+            self.selected_fader.as_ref().and_then(|f| f.path_matches(osc_addr))
+        {
+            if let (Value::Float(level), Some(index)) = (value, (bus as usize).checked_sub(1)) {
+                let position = Fader::db_to_float(*level as f64) as f32;
+                self.set_encoder_ring(index, Some(position));
+            }
+            return;
+        }
+
+        let bus = osc_addr
+            .strip_prefix("/bus/")
+            .and_then(|r| r.strip_suffix("/$name"))
+            .and_then(|bus| bus.parse::<usize>().ok())
+            .filter(|bus| (1..=self.strip_count).contains(bus));
+
+        if let (Some(bus), Value::Str(name)) = (bus, value) {
+            let index = bus - 1;
+            self.send_names[index] = name.clone();
+
+            if index < self.visible_faders().len() && !self.showing_value[index] {
+                self.show_strip_name(index).await;
+            }
+        }
+    }
+
+    /// Show the phantom power state of the strips fed by an input
+    async fn process_phantom_input(&mut self, osc_addr: &str, value: &Value) {
+        for index in 0..self.strip_count {
+            if self.strip_phantom_paths[index].as_deref() != Some(osc_addr) {
+                continue;
+            }
+
+            if let Some(on) = value.as_bool() {
+                self.strip_phantom[index] = Some(on);
+                if !self.showing_value[index] {
+                    self.show_strip_name(index).await;
+                }
+            } else {
+                warn!("Expected int value for phantom power, got {:?}", value);
+            }
+        }
+    }
+
+    /// Query the console for the input that feeds the strip at an index, and request the
+    /// state of its phantom power
+    async fn resolve_phantom_source(&mut self, index: usize) {
+        self.strip_phantom_paths[index] = None;
+        self.strip_phantom[index] = None;
+
+        let Some(fader) = self.visible_faders().get(index).filter(|f| f.has_gain()).cloned() else {
+            return;
+        };
+        let Ok(interface) = self.get_interface().await else {
+            return;
+        };
+
+        let source_paths =
+            [fader.get_osc_path(PathType::SourceGroup), fader.get_osc_path(PathType::SourceInput)];
+        let source = interface.get_values(&source_paths, false).await;
+
+        let path = match source.as_deref() {
+            Ok([Value::Str(group), Value::Int(input)]) => phantom_power_path(group, *input),
+            source => {
+                debug!(?fader, ?source, "Source of strip not resolved");
+                return;
+            }
+        };
+
+        debug!(?fader, path = path.as_str(), "Strip source resolved");
+        self.strip_phantom_paths[index] = Some(path.clone());
+        interface.request_value_notification(&path, false).await;
+    }
+
+    /// Switch the phantom power of the source of the strip at an index
+    async fn toggle_phantom(&mut self, index: usize) -> Result<()> {
+        let Some(path) = self.strip_phantom_paths.get(index).cloned().flatten() else {
+            debug!(index, "Strip has no source with phantom power");
+            return Ok(());
+        };
+
+        let interface = self.get_interface().await?;
+        let value = interface.get_value(&path, false).await?;
+        let Some(on) = value.as_bool() else {
+            anyhow::bail!("Expected on/off value for phantom power, got {:?}", value);
+        };
+
+        info!(index, path = path.as_str(), on = !on, "Switching phantom power");
+        interface.set_value(&path, Value::Bool(!on)).await;
+
+        // Strips with the same source show the new state too
+        self.process_phantom_input(&path, &Value::Bool(!on)).await;
+
+        Ok(())
+    }
+
+    /// Show the focus parameters of the selected strip on the encoder rings
+    fn process_focus_input(&self, osc_addr: &str, value: &Value) {
+        let Some(path_type) = self.selected_fader.as_ref().and_then(|f| f.path_matches(osc_addr)) else {
+            return;
+        };
+        let Some(index) = FOCUS_PARAMETERS.iter().position(|p| *p == path_type) else {
+            return;
+        };
+
+        if let Value::Float(value) = value {
+            self.set_encoder_ring(index, Some(focus_position(&path_type, *value)));
+        }
+    }
+
+    /// Query the console for the strips that are assigned to a DCA
+    async fn dca_members(&self, dca: u8) -> Result<Vec<Fader>> {
+        let interface = self.get_interface().await?;
+
+        let candidates = DCA_MEMBER_CANDIDATES
+            .iter()
+            .flat_map(|(kind, count)| (1..=*count).map(move |i| format!("{} {}", kind, i)))
+            .map(|label| Fader::new_from_label(&label))
+            .collect::<Result<Vec<Fader>>>()?;
+
+        let tags_paths: Vec<String> =
+            candidates.iter().map(|fader| fader.get_osc_path(PathType::Tags)).collect();
+        if let Err(e) = interface.request_values(&tags_paths, false).await {
+            debug!("Not all tags of the DCA member candidates arrived: {:?}", e);
+        }
+
+        // Strips whose tags are not available are left out
+        let mut members = Vec::new();
+        for (fader, tags_path) in candidates.into_iter().zip(&tags_paths) {
+            match interface.get_cached_value(tags_path).await {
+                Some(Value::Str(tags)) if Fader::tags_include_dca(&tags, dca) => members.push(fader),
+                Some(_) => {}
+                None => debug!("Tags of {:?} not available", fader),
+            }
+        }
+
+        debug!(dca, members = members.len(), "DCA members found");
+
+        Ok(members)
+    }
+
+    /// Select the strip at an index, e.g. to control its sends with the encoders. Selecting
+    /// a DCA spills its members onto the strips, or returns from the spill.
+    async fn select_strip(&mut self, index: usize) -> Result<()> {
+        let Some(fader) = self.visible_faders().get(index).cloned() else {
+            return Ok(());
+        };
+
+        if let Some(dca) = fader.dca_number() {
+            return self.do_function(InternalFunction::SpillDca(dca)).await;
+        }
+
+        debug!(index, ?fader, "Strip selected");
+
+        if let Some(selection_index) = fader.selection_index().filter(|_| self.follow_selection) {
+            self.get_interface()
+                .await?
+                .set_value(SELECTED_STRIP_PATH, Value::Int(selection_index))
+                .await;
+        }
+
+        self.selected_fader = Some(fader);
+
+        self.refresh_select_leds();
+        self.refresh_encoders().await
+    }
+
+    /// Light the Select button of the selected strip, if it is visible
+    fn refresh_select_leds(&self) {
+        let faders = self.visible_faders();
+
+        for index in 0..self.strip_count {
+            if let Some(note) = self.strip_button_note(index, "Select") {
+                let selected = faders.get(index).is_some_and(|f| Some(f) == self.selected_fader.as_ref());
+                self.send_button_led(note, selected);
+            }
+        }
+    }
+
+    /// The strip whose main assignment combo is completed by pressing this note
+    fn main_assign_combo_strip(&self, note: u32) -> Option<usize> {
+        let combo = self.main_assign_combo.as_ref()?;
+
+        (0..self.strip_count).find(|index| {
+            self.strip_button_note(*index, &combo.press) == Some(note)
+                && self
+                    .strip_button_note(*index, &combo.hold)
+                    .is_some_and(|hold| self.held_notes.contains(&hold))
+        })
+    }
+
+    /// Assign the strip at an index to the main bus, or unassign it if it is assigned
+    async fn toggle_main_assign(&mut self, index: usize) -> Result<()> {
+        let Some(fader) = self.visible_faders().get(index).cloned() else {
+            return Ok(());
+        };
+        if !fader.has_main_assign() {
+            debug!(?fader, "Strip cannot be assigned to the main bus");
+            return Ok(());
+        }
+
+        let interface = self.get_interface().await?;
+        let osc_path = fader.get_osc_path(PathType::MainAssign);
+
+        let value = interface.get_value(&osc_path, false).await?;
+        let Some(assigned) = value.as_bool() else {
+            anyhow::bail!("Expected on/off value for main assignment, got {:?}", value);
+        };
+
+        info!(?fader, assigned = !assigned, "Toggling main assignment");
+        interface.set_value(&osc_path, Value::Bool(!assigned)).await;
+
+        self.strip_main_assigned[index] = Some(!assigned);
+        if !self.showing_value[index] {
+            self.show_strip_name(index).await;
+        }
+
+        Ok(())
+    }
+
+    /// Toggle an on/off parameter of a visible strip, such as its mute
+    async fn toggle_strip_parameter(&mut self, index: usize, path_type: PathType) -> Result<()> {
+        let Some(fader) = self.visible_faders().get(index).cloned() else {
+            return Ok(());
+        };
+        if fader.label().is_some() {
+            debug!(?fader, ?path_type, "Strip has no such parameter");
+            return Ok(());
+        }
+
+        let interface = self.get_interface().await?;
+        let osc_path = fader.get_osc_path(path_type.clone());
+
+        let value = interface.get_value(&osc_path, false).await?;
+        let Some(on) = value.as_bool() else {
+            anyhow::bail!("Expected on/off value for {}, got {:?}", osc_path, value);
+        };
+
+        info!(?fader, ?path_type, on = !on, "Toggling strip parameter");
+        interface.set_value(&osc_path, Value::Bool(!on)).await;
+        self.send_fader_button_leds(index, &path_type, !on);
+
+        Ok(())
+    }
+
+    /// The strip and parameter of a fader button note, unless it is assigned to a function
+    fn fader_button(&self, note: u32) -> Option<(usize, PathType)> {
+        if self.buttons.contains_key(&note) || self.long_press_buttons.contains_key(&note) {
+            return None;
+        }
+
+        (0..self.strip_count).find_map(|index| {
+            self.fader_buttons
+                .iter()
+                .find(|(description, _)| self.strip_button_note(index, description) == Some(note))
+                .map(|(_, path_type)| (index, path_type.clone()))
+        })
+    }
+
+    /// Light the fader buttons of a strip that show a parameter
+    fn send_fader_button_leds(&self, index: usize, path_type: &PathType, on: bool) {
+        for (description, _) in self.fader_buttons.iter().filter(|(_, p)| p == path_type) {
+            if let Some(note) = self.strip_button_note(index, description) {
+                self.send_button_led(note, on);
+            }
+        }
+    }
+
+    /// The strip whose Select button has this note, unless it is assigned to a function
+    fn select_button_strip(&self, note: u32) -> Option<usize> {
+        if self.buttons.contains_key(&note) || self.long_press_buttons.contains_key(&note) {
+            return None;
+        }
+
+        (0..self.strip_count).find(|index| self.strip_button_note(*index, "Select") == Some(note))
+    }
+
+    /// All faders of the current bank, or of the overlay if one is open
+    fn current_faders(&self) -> &[Fader] {
+        if let Some(overlay) = &self.window.overlay {
+            return &overlay.faders;
+        }
+
+        &self.resolved_bank
+    }
+
+    /// Update the strips of the current bank from the cached stereo link flags and the
+    /// parameters that the bank is filtered on, returning whether they have changed
+    async fn resolve_bank(&mut self) -> bool {
+        let Some(bank) = self.banks.get(self.window.bank) else {
+            return false;
+        };
+        let Ok(interface) = self.get_interface().await else {
+            return false;
+        };
+        let filter = self.bank_filters.get(self.window.bank).cloned().flatten();
+
+        if filter.is_some()
+            && let Err(e) = interface.request_values(&self.bank_condition_paths(), false).await
+        {
+            debug!("Not all filtered parameters of the bank arrived: {:?}", e);
+        }
+
+        let mut hidden = Vec::new();
+        for fader in bank {
+            if let Some(partner) = fader.link_partner() {
+                let link = interface.get_cached_value(&fader.get_osc_path(PathType::Link)).await;
+                if link.and_then(|v| v.as_bool()) == Some(true) {
+                    hidden.push(partner);
+                }
+            }
+        }
+
+        let mut resolved = Vec::new();
+        for fader in bank.iter().filter(|f| !hidden.contains(f)) {
+            if let Some(filter) = &filter
+                && !Self::matches_filter(&interface, fader, filter).await
+            {
+                continue;
+            }
+            resolved.push(fader.clone());
+        }
+        if resolved == self.resolved_bank {
+            return false;
+        }
+
+        debug!(
+            bank = self.window.bank,
+            hidden = hidden.len(),
+            strips = resolved.len(),
+            "Bank strips resolved"
+        );
+        self.resolved_bank = resolved;
+        true
+    }
+
+    /// Whether a strip meets the conditions of a bank filter, by its cached parameters
+    async fn matches_filter(interface: &Interface, fader: &Fader, filter: &BankFilter) -> bool {
+        if let Some(dca) = filter.dca {
+            match interface.get_cached_value(&fader.get_osc_path(PathType::Tags)).await {
+                Some(Value::Str(tags)) if Fader::tags_include_dca(&tags, dca) => {}
+                _ => return false,
+            }
+        }
+
+        if filter.unmuted {
+            let mute = interface.get_cached_value(&fader.get_osc_path(PathType::Mute)).await;
+            if mute.and_then(|v| v.as_bool()) != Some(false) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// The parameters that decide which strips of the current bank are shown
+    fn bank_condition_paths(&self) -> Vec<String> {
+        let filter = self.bank_filters.get(self.window.bank).cloned().flatten();
+
+        let mut paths = Vec::new();
+        for fader in self.banks.get(self.window.bank).into_iter().flatten() {
+            if fader.link_partner().is_some() {
+                paths.push(fader.get_osc_path(PathType::Link));
+            }
+            if let Some(filter) = &filter {
+                if filter.dca.is_some() {
+                    paths.push(fader.get_osc_path(PathType::Tags));
+                }
+                if filter.unmuted {
+                    paths.push(fader.get_osc_path(PathType::Mute));
+                }
+            }
+        }
+
+        paths
+    }
+
+    /// The faders of the current bank that are currently visible on the surface
+    fn visible_faders(&self) -> &[Fader] {
+        let faders = self.current_faders();
+
+        let start = self.window.offset.min(faders.len());
+        let end = (start + self.strip_count).min(faders.len());
+
+        &faders[start..end]
+    }
+
+    /// Replace the current bank with a temporary set of strips
+    fn open_overlay(&mut self, name: String, faders: Vec<Fader>, function: InternalFunction) {
+        let saved_window_offset = match self.window.overlay.take() {
+            Some(overlay) => overlay.saved_window_offset,
+            None => self.window.offset,
+        };
+
+        info!(name, strips = faders.len(), "Opening strip overlay");
+
+        self.window.overlay = Some(StripOverlay {
+            name,
+            faders,
+            function,
+            saved_window_offset,
+        });
+        self.window.offset = 0;
+    }
+
+    /// Return to the current bank from an overlay
+    fn close_overlay(&mut self) {
+        if let Some(overlay) = self.window.overlay.take() {
+            self.window.offset = overlay.saved_window_offset;
+        }
+    }
+
+    /// How many strips the Channel Left/Right buttons move the visible window by
+    fn channel_step(&self) -> usize {
+        // Overlays are paged through
+        if self.window.overlay.is_some() { self.strip_count } else { 1 }
+    }
+
+    /// Get a handle to the orchestrator interface, if it has been set
+    async fn get_interface(&self) -> Result<Interface> {
+        self.interface
+            .lock()
+            .await
+            .clone()
+            .ok_or_else(|| anyhow!("Interface not set"))
+    }
+
+    async fn refresh_bank(&mut self) -> Result<()> {
+        info!(
+            "Hydrating bank {} buttons & faders from strip {}",
+            self.window.bank, self.window.offset
+        );
+
+        if self.window.bank >= self.banks.len() {
+            anyhow::bail!("Bank {} not on list", self.window.bank);
+        }
+        self.remember_state();
+
+        self.resolve_bank().await;
+
+        // Strip states are re-populated by the notifications below
+        self.strip_mutes.fill(false);
+        self.strip_names.fill(String::new());
+        self.strip_main_assigned.fill(None);
+        self.strip_phantom_paths.fill(None);
+        self.strip_phantom.fill(None);
+        self.showing_value.fill(false);
+        for index in 0..self.strip_count {
+            for (_, path_type) in &self.fader_buttons {
+                self.send_fader_button_leds(index, path_type, false);
+            }
+        }
+
+        let faders = self.visible_faders();
+        let mut labels = Vec::new();
+
+        let interface_guard = self
+                .interface
+                .lock()
+                .await;
+        let interface = interface_guard
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Interface not set"))?;
+
+        // Request the whole bank at once, so that the values below are already in the cache
+        let bank_paths: Vec<String> = faders
+            .iter()
+            .flat_map(|fader| match fader.label() {
+                Some(_) => vec![fader.get_osc_path(PathType::Fader)],
+                None => [PathType::Fader, PathType::ScribbleColour, PathType::ScribbleName, PathType::Mute]
+                    .into_iter()
+                    .map(|path_type| fader.get_osc_path(path_type))
+                    .collect(),
+            })
+            .collect();
+        if let Err(e) = interface.request_values(&bank_paths, false).await {
+            warn!("Not all values of the bank arrived: {:?}", e);
+        }
+
+        for (index, fader) in faders.iter().enumerate() {
+            let osc_path = fader.get_osc_path(PathType::Fader);
+
+            let value = interface
+                .request_value_notification_checked(&osc_path, false)
+                .await;
+
+            if let Err(e) = value {
+                warn!(
+                    "OSC value for {} not found during bank refresh: {}",
+                    osc_path, e
+                );
+            }
+
+            if let Some(label) = fader.label() {
+                // This fader has no scribble parameters on the console
+                labels.push((index, label));
+                continue;
+            }
+
+            interface
+                .request_value_notification(&fader.get_osc_path(PathType::ScribbleColour), false)
+                .await;
+
+            interface
+                .request_value_notification(&fader.get_osc_path(PathType::ScribbleName), false)
+                .await;
+
+            interface
+                .request_value_notification(&fader.get_osc_path(PathType::Mute), false)
+                .await;
+
+            if self.main_assign_combo.is_some() && fader.has_main_assign() {
+                interface
+                    .request_value_notification(&fader.get_osc_path(PathType::MainAssign), false)
+                    .await;
+            }
+
+            if self.fader_buttons.iter().any(|(_, path_type)| *path_type == PathType::Solo) {
+                interface
+                    .request_value_notification(&fader.get_osc_path(PathType::Solo), false)
+                    .await;
+            }
+        }
+
+        for button in self.buttons.values() {
+            if let Some(osc_path) = button.function.osc_path() {
+                interface.request_value_notification(osc_path, false).await;
+            }
+        }
+
+        let has_mute_all = self
+            .buttons
+            .values()
+            .any(|b| matches!(b.function, InternalFunction::MuteAll | InternalFunction::UnmuteAll));
+        if has_mute_all {
+            for osc_path in interface.mute_all_paths() {
+                interface.request_value_notification(osc_path, false).await;
+            }
+        }
+
+        if self.scene_on_main_display {
+            interface.request_value_notification(SCENE_NAME_PATH, false).await;
+        }
+
+        if let Some((layer_path, _)) = &self.layer_follow {
+            interface.ensure_value(layer_path, false).await;
+        }
+
+        if self.follow_selection {
+            interface.ensure_value(SELECTED_STRIP_PATH, false).await;
+        }
+
+        for fader in self.fixed_faders.values() {
+            interface
+                .request_value_notification(&fader.get_osc_path(PathType::Fader), false)
+                .await;
+        }
+
+        // Changes to the link flags and the filtered parameters re-resolve the bank
+        for osc_path in self.bank_condition_paths() {
+            interface.request_value_notification(&osc_path, false).await;
+        }
+
+        drop(interface_guard);
+
+        let fader_count = faders.len();
+        if self.phantom_mode {
+            for index in 0..fader_count {
+                self.resolve_phantom_source(index).await;
+            }
+        }
+
+        // Strips without a fader would otherwise keep their previous contents
+        for index in fader_count..self.strip_count {
+            self.cached_colours[index] = 0;
+            self.set_lcd_rows("", "", index as u8).await;
+        }
+
+        if !labels.is_empty() || fader_count < self.strip_count {
+            for (index, label) in labels {
+                self.cached_colours[index] = 7;
+                self.strip_names[index] = label;
+                self.show_strip_name(index).await;
+            }
+            self.send_colours().await;
+        }
+
+        self.refresh_all_button_leds().await;
+        self.refresh_select_leds();
+        self.refresh_encoders().await?;
+
+        self.update_main_display().await;
+        self.flash_bank_number();
+
+        self.request_meters().await;
+
+        Ok(())
+    }
+
+    /// Record the state of the surface, so that it is restored after a restart
+    /// Whether the surface is connected and what it shows
+    pub fn status(&self) -> SurfaceStatus {
+        SurfaceStatus {
+            name: self.input_name.clone(),
+            connected: self.ports_connected,
+            bank: self.window.bank + 1,
+            bank_name: self.bank_names.get(self.window.bank).cloned().flatten(),
+            locked: self.locked,
+        }
+    }
+
+    fn remember_state(&self) {
+        let state = SurfaceState {
+            bank: self.window.bank,
+            offset: self.window.offset,
+            encoder_mode: self.encoder_mode,
+            locked: self.locked,
+        };
+        self.surface_states.update(&self.input_name, state);
+    }
+
+    async fn get_function_button_led(&self, function: &InternalFunction) -> Result<LedState> {
+        let mut result: anyhow::Result<LedState>;
+
+        match function {
+            InternalFunction::NextBank => {
+                result = Ok((self.window.bank + 1 < self.banks.len()).into());
+            },
+            InternalFunction::PreviousBank => {
+                result = Ok((self.window.bank > 0).into());
+            },
+            InternalFunction::NextChannel => {
+                let len = self.current_faders().len();
+                result = Ok((self.window.offset + self.strip_count < len).into());
+            },
+            InternalFunction::PreviousChannel => {
+                result = Ok((self.window.offset > 0).into());
+            },
+            InternalFunction::Toggle(path) | InternalFunction::Momentary(path) => {
+                let value = self.get_interface().await?.get_cached_value(path).await;
+                result = Ok((value.and_then(|v| v.as_bool()) == Some(true)).into());
+            },
+            InternalFunction::Macro(name) => {
+                result = Ok(if self.macros.is_running(name) {
+                    LedState::Blink
+                } else {
+                    LedState::Off
+                });
+            },
+            InternalFunction::Geq(_) | InternalFunction::SpillDca(_) => {
+                let open = self.window.overlay.as_ref().is_some_and(|o| o.function == *function);
+                result = Ok(open.into());
+            },
+            InternalFunction::EncoderMode(mode) => {
+                result = Ok((self.encoder_mode == *mode).into());
+            },
+            InternalFunction::NextScene | InternalFunction::PreviousScene => {
+                result = Ok(LedState::Off);
+            },
+            InternalFunction::Scene(index) => {
+                let value = self.get_interface().await?.get_cached_value(SCENE_INDEX_PATH).await;
+                result = Ok((value == Some(Value::Int(*index))).into());
+            },
+            InternalFunction::TapTempo(path) => {
+                let value = self.get_interface().await?.get_cached_value(path).await;
+                result = Ok(match value {
+                    Some(Value::Float(ms)) if ms > 0.0 => {
+                        LedState::Pulse(Duration::from_secs_f32(ms / 1000.0))
+                    }
+                    _ => LedState::Off,
+                });
+            },
+            InternalFunction::LockSurface => {
+                result = Ok(self.locked.into());
+            },
+            InternalFunction::VegasMode => {
+                result = Ok(self.vegas.into());
+            },
+            InternalFunction::PhantomMode => {
+                result = Ok(self.phantom_mode.into());
+            },
+            InternalFunction::RtaMode => {
+                result = Ok(self.rta_mode.into());
+            },
+            InternalFunction::TogglePhantom(_) => {
+                result = Ok(LedState::Off);
+            },
+            InternalFunction::Nudge(_, _) | InternalFunction::Snap(_, _) => {
+                result = Ok(LedState::Off);
+            },
+            InternalFunction::CueGo => {
+                result = Ok(self.cues.has_next().into());
+            },
+            InternalFunction::SaveSnapshot(_) | InternalFunction::RecallSnapshot(_) => {
+                result = Ok(LedState::Off);
+            },
+            InternalFunction::AbCompare => {
+                // Blinks while the changes are live, and is lit while the stored values are
+                result = Ok(match self.snapshots.ab_state() {
+                    AbState::Off => LedState::Off,
+                    AbState::ShowingA => LedState::On,
+                    AbState::ShowingB => LedState::Blink,
+                });
+            },
+            InternalFunction::AbReset => {
+                result = Ok(LedState::Off);
+            },
+            InternalFunction::SafeMode => {
+                result = Ok(self.get_interface().await?.safe_mode().into());
+            },
+            InternalFunction::CueBack => {
+                result = Ok(self.cues.has_previous().into());
+            },
+            InternalFunction::Fade(target, _, _) => {
+                let fading = self
+                    .resolve_fader_target(target)
+                    .is_some_and(|fader| self.fades.is_fading(&fader));
+                result = Ok(if fading { LedState::Blink } else { LedState::Off });
+            },
+            InternalFunction::ClearSolo => {
+                let value = self.get_interface().await?.get_cached_value(SOLO_ACTIVE_PATH).await;
+                result = Ok((value.and_then(|v| v.as_bool()) == Some(true)).into());
+            },
+            InternalFunction::MuteAll | InternalFunction::UnmuteAll => {
+                // Lit while everything is muted
+                let interface = self.get_interface().await?;
+                let mut all_muted = !interface.mute_all_paths().is_empty();
+                for path in interface.mute_all_paths() {
+                    let value = interface.get_cached_value(path).await;
+                    all_muted &= value.and_then(|v| v.as_bool()) == Some(true);
+                }
+                result = Ok(all_muted.into());
+            },
+        }
+
+        result.with_context(|| format!("While checking function LED {:?}", function))
+    }
+
+    async fn refresh_button_led(&self, button: u32) {
+        self.refresh_button_led_if(button, |_| true).await;
+    }
+
+    /// Refresh a button LED, if its current state satisfies the given filter
+    async fn refresh_button_led_if(&self, button: u32, filter: impl Fn(LedState) -> bool) {
+        if let Some(internal_button) = self.buttons.get(&button) {
+            let state = self.get_function_button_led(&internal_button.function).await;
+
+            if let Err(e) = state {
+                warn!("Failed to get button LED state for button {}: {}", button, e);
+                return;
+            }
+
+            let state = state.unwrap();
+
+            if !filter(state) {
+                return;
+            }
+
+            let lit = match state {
+                LedState::Off => false,
+                LedState::On => true,
+                LedState::Blink => self.blink_phase,
+                LedState::Pulse(period) => {
+                    let period = period.as_millis().max(1);
+                    self.tempo_epoch.elapsed().as_millis() % period < period / 4
+                }
+            };
+
+            self.send_button_led(button, lit);
+        } else {
+            // ...
+        }
+    }
+
+    /// Toggle the LEDs of all buttons that are currently blinking
+    async fn refresh_blinking_leds(&self) {
+        for button in self.buttons.keys() {
+            self.refresh_button_led_if(*button, |state| state == LedState::Blink)
+                .await;
+        }
+    }
+
+    /// Turn a button LED on or off, unless it is already in that state
+    fn send_button_led(&self, button: u32, lit: bool) {
+        if self.sent_leds.lock().unwrap().insert(button, lit) == Some(lit) {
+            return;
+        }
+
+        let midi_value = if lit { 127 } else { 0 };
+
+        let ev = LiveEvent::Midi {
+            channel: 0.into(),
+            message: midly::MidiMessage::NoteOn {
+                key: (button as u8).into(),
+                vel: midi_value.into(),
+            },
+        };
+
+        let mut buf = Vec::with_capacity(3);
+        ev.write(&mut buf)
+            .map_err(|e| anyhow!("MIDI write fail {}", e))
+            .unwrap();
+        if let Err(e) = self.send_midi(&buf) {
+            warn!("Failed to send MIDI for button {}: {}", button, e);
+        }
+    }
+
+    /// Refresh the LEDs of all buttons whose function depends on an OSC parameter
+    async fn refresh_button_leds_for_path(&self, osc_addr: &str) {
+        let is_mute_all_path = match self.get_interface().await {
+            Ok(interface) => interface.mute_all_paths().iter().any(|p| p == osc_addr),
+            Err(_) => false,
+        };
+
+        let buttons = self
+            .buttons
+            .iter()
+            .filter(|(_, button)| {
+                button.function.osc_path() == Some(osc_addr)
+                    || (is_mute_all_path
+                        && matches!(
+                            button.function,
+                            InternalFunction::MuteAll | InternalFunction::UnmuteAll
+                        ))
+            })
+            .map(|(note, _)| *note)
+            .collect::<Vec<u32>>();
+
+        for button in buttons {
+            self.refresh_button_led(button).await;
+        }
+    }
+
+    async fn refresh_all_button_leds(&self) {
+        for button in self.buttons.keys() {
+            self.refresh_button_led(*button).await;
+        }
+    }
+
+    /// Clear all button LEDs (set to 0).
+    pub async fn clean_buttons(&self) {
+        for note in 0..115 {
+            let ev = LiveEvent::Midi {
+                channel: 0.into(),
                 message: midly::MidiMessage::NoteOn {
                     key: (note as u8).into(),
                     vel: 0.into(),
                 },
             };
 
-            let mut buf = Vec::with_capacity(3);
-            ev.write(&mut buf).unwrap();
-            if let Err(e) = self.send_midi(&buf) {
-                warn!("Failed to clear button {}: {}", note, e);
+            let mut buf = Vec::with_capacity(3);
+            ev.write(&mut buf).unwrap();
+            if let Err(e) = self.send_midi(&buf) {
+                warn!("Failed to clear button {}: {}", note, e);
+            }
+
+            self.sent_leds.lock().unwrap().insert(note, false);
+        }
+    }
+
+    /// Send the current colours, as stored in the cache, to the controller. This does not
+    /// update or request OSC values.
+    ///
+    /// Muted strips are shown with inverted colours.
+    async fn send_colours(&self) {
+        let mut c = self.cached_colours;
+        for (colour, muted) in c.iter_mut().zip(&self.strip_mutes) {
+            if *muted {
+                *colour |= 0x40;
+            }
+        }
+
+        self.write_colours(c);
+    }
+
+    /// Send scribble strip colours to the controller, unless they are already shown
+    fn write_colours(&self, c: [u8; STRIP_COUNT]) {
+        if self.sent_colours.lock().unwrap().replace(c) == Some(c) {
+            return;
+        }
+
+        let sysex = [
+            0xF0, 0x00, 0x00, 0x66, 0x14, 0x72,
+            c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7],
+            0xF7,
+        ];
+
+        if let Err(e) = self.send_midi(&sysex) {
+            warn!("Failed to send colour sysex: {}", e);
+        }
+    }
+
+    /// The name of a strip, with markers for its state
+    fn strip_display_name(&self, index: usize) -> String {
+        let mut name = self.strip_names[index].clone();
+
+        if self.strip_main_assigned[index] == Some(true) {
+            name.insert(0, '*');
+        }
+
+        name
+    }
+
+    /// Show the name of a strip on its scribble, scrolling it if it is too long
+    async fn show_strip_name(&self, index: usize) {
+        if let Some(parameter) = FOCUS_PARAMETERS.get(index).filter(|_| self.encoder_mode == EncoderMode::Focus) {
+            // The bottom row shows what this strip's encoder edits
+            let (row1, _) = split_scribble_text(&transliterate(&self.strip_display_name(index)));
+            self.set_lcd_rows(&row1, parameter.short_name(), index as u8).await;
+            return;
+        }
+
+        if self.encoder_mode == EncoderMode::Sends {
+            // The bottom row shows where the send of this strip's encoder goes
+            let (row1, _) = split_scribble_text(&transliterate(&self.strip_display_name(index)));
+            self.set_lcd_rows(&row1, &self.send_names[index], index as u8).await;
+            return;
+        }
+
+        if self.rta_mode {
+            // The bottom row shows the lowest frequency of the bands on this strip's meter
+            let (row1, _) = split_scribble_text(&transliterate(&self.strip_display_name(index)));
+            self.set_lcd_rows(&row1, &rta_group_label(index), index as u8).await;
+            return;
+        }
+
+        if self.phantom_mode {
+            let (row1, _) = split_scribble_text(&transliterate(&self.strip_display_name(index)));
+            let phantom = match self.strip_phantom[index] {
+                Some(true) => "+48V",
+                Some(false) => "48V off",
+                None => "",
+            };
+            self.set_lcd_rows(&row1, phantom, index as u8).await;
+            return;
+        }
+
+        let name = transliterate(&self.strip_display_name(index));
+        let len = name.chars().count();
+
+        if !self.marquee || len <= SCRIBBLE_ROW_LEN * 2 {
+            self.set_lcd_text(&name, index as u8).await;
+            return;
+        }
+
+        // Scroll through the name, followed by a gap before it repeats
+        const GAP: &str = "   ";
+        let start = self.marquee_step % (len + GAP.len());
+        let window: Vec<char> = name
+            .chars()
+            .chain(GAP.chars())
+            .cycle()
+            .skip(start)
+            .take(SCRIBBLE_ROW_LEN * 2)
+            .collect();
+
+        let row1: String = window[..SCRIBBLE_ROW_LEN].iter().collect();
+        let row2: String = window[SCRIBBLE_ROW_LEN..].iter().collect();
+
+        self.set_lcd_rows(&row1, &row2, index as u8).await;
+    }
+
+    async fn set_lcd_text(&self, text: &str, disp: u8) {
+        // Transliterate first, so that the text is split at the right length
+        let (row1_str, row2_str) = split_scribble_text(&transliterate(text));
+
+        self.set_lcd_rows(&row1_str, &row2_str, disp).await;
+    }
+
+    /// Temporarily show a fader value in dB on the bottom row of its scribble strip
+    async fn show_fader_value(&mut self, index: usize, db: f32) {
+        self.value_display_generation[index] += 1;
+        self.showing_value[index] = true;
+
+        let (row1, _) = split_scribble_text(&transliterate(&self.strip_display_name(index)));
+        self.set_lcd_rows(&row1, &format_db(db), index as u8).await;
+
+        self.schedule_value_revert(index);
+    }
+
+    /// Show the name of a strip again after it has shown its value for a while, unless the
+    /// fader is still touched or has moved since.
+    fn schedule_value_revert(&self, index: usize) {
+        let this = self.this.clone();
+        let generation = self.value_display_generation[index];
+
+        self.runtime.spawn(async move {
+            tokio::time::sleep(VALUE_DISPLAY_DURATION).await;
+
+            let Some(controller) = this.upgrade() else {
+                return;
+            };
+            let mut controller = controller.lock().await;
+
+            if controller.showing_value[index]
+                && !controller.strip_touched[index]
+                && controller.value_display_generation[index] == generation
+            {
+                controller.showing_value[index] = false;
+                controller.show_strip_name(index).await;
+            }
+        });
+    }
+
+    /// Record a fader being touched or released. Returns false if the note does not belong
+    /// to a touch sensor.
+    fn register_touch(&mut self, note: u32, touched: bool) -> bool {
+        let Some(&index) = self.touch_notes.get(&note) else {
+            return false;
+        };
+
+        trace!(index, touched, "Fader touch");
+
+        if index < self.strip_count {
+            self.strip_touched[index] = touched;
+
+            // Grabbing a fader stops its fade
+            if let Some(fader) = self.visible_faders().get(index).filter(|_| touched) {
+                self.fades.cancel(fader);
+            }
+
+            if !touched {
+                self.schedule_value_revert(index);
+            }
+        }
+
+        true
+    }
+
+    /// Show the application version and the console being connected to across the
+    /// scribble strips, until the first bank is hydrated
+    pub async fn show_splash(&mut self, console_addr: &str) {
+        let title = format!("X-Touch Wing v{}", env!("CARGO_PKG_VERSION"));
+        let status = format!("Connecting to {}...", console_addr);
+
+        let row1: Vec<char> = title.chars().collect();
+        let row2: Vec<char> = status.chars().collect();
+
+        for index in 0..self.strip_count {
+            let chunk = |row: &[char]| -> String {
+                row.iter().skip(index * SCRIBBLE_ROW_LEN).take(SCRIBBLE_ROW_LEN).collect()
+            };
+            self.set_lcd_rows(&chunk(&row1), &chunk(&row2), index as u8).await;
+        }
+
+        self.cached_colours = [7; _];
+        self.send_colours().await;
+
+        self.write_text_to_main_display(&format!("v{}", env!("CARGO_PKG_VERSION"))).await;
+    }
+
+    /// Write the two rows of a scribble strip display
+    async fn set_lcd_rows(&self, row1_str: &str, row2_str: &str, disp: u8) {
+        const MAX_LEN: u8 = SCRIBBLE_ROW_LEN as u8;
+        const NUM_DISPLAYS: u8 = STRIP_COUNT as u8;
+
+        if disp >= NUM_DISPLAYS {
+            warn!("Invalid display index {:?}", disp);
+            return;
+        }
+
+        fn pad(s: &str, max_len: usize) -> Vec<u8> {
+            let mut bytes = transliterate(s).into_bytes();
+            bytes.truncate(max_len);
+            while bytes.len() < max_len {
+                bytes.push(b' ');
+            }
+            bytes
+        }
+
+        let row1 = pad(row1_str, MAX_LEN as usize);
+        let row2 = pad(row2_str, MAX_LEN as usize);
+        let offset1 = disp.wrapping_mul(MAX_LEN);
+        let offset2 = offset1.wrapping_add(NUM_DISPLAYS.wrapping_mul(MAX_LEN));
+
+        for (row_index, offset, row) in [(1, offset1, row1), (2, offset2, row2)] {
+            if self.sent_lcd_rows.lock().unwrap().get(&offset) == Some(&row) {
+                continue;
+            }
+
+            let mut sysex: Vec<u8> = [0xF0, 0x00, 0x00, 0x66, 0x14, 0x12, offset].to_vec();
+            sysex.extend_from_slice(&row);
+            sysex.push(0xF7);
+
+            if let Err(e) = self.send_midi(&sysex) {
+                warn!("Failed to write to display {} row{}: {}", disp, row_index, e);
+            }
+
+            self.sent_lcd_rows.lock().unwrap().insert(offset, row);
+        }
+    }
+
+    async fn do_function(&mut self, function: InternalFunction) -> Result<()> {
+        let mut result;
+
+        match &function {
+            InternalFunction::NextBank => {
+                self.window.bank = (self.window.bank + 1) % self.banks.len();
+                self.window.overlay = None;
+                self.window.offset = 0;
+                result = self.refresh_bank().await;
+            }
+            InternalFunction::PreviousBank => {
+                if self.window.bank == 0 {
+                    self.window.bank = self.banks.len() - 1;
+                } else {
+                    self.window.bank -= 1;
+                }
+                self.window.overlay = None;
+                self.window.offset = 0;
+                result = self.refresh_bank().await;
+            }
+            InternalFunction::NextChannel => {
+                let len = self.current_faders().len();
+
+                if self.window.offset + self.strip_count < len {
+                    self.window.offset += self.channel_step();
+                    result = self.refresh_bank().await;
+                } else {
+                    result = Ok(());
+                }
+            }
+            InternalFunction::PreviousChannel => {
+                if self.window.offset > 0 {
+                    self.window.offset = self.window.offset.saturating_sub(self.channel_step());
+                    result = self.refresh_bank().await;
+                } else {
+                    result = Ok(());
+                }
+            }
+            InternalFunction::Toggle(path) => {
+                let interface = self.get_interface().await?;
+
+                result = match interface.get_value(path, false).await {
+                    Ok(value) => match value.as_bool() {
+                        Some(on) => {
+                            interface.set_value(path, Value::Bool(!on)).await;
+                            self.refresh_button_leds_for_path(path).await;
+                            Ok(())
+                        }
+                        None => Err(anyhow!("Cannot toggle non-switch value {:?}", value)),
+                    },
+                    Err(e) => Err(e),
+                };
+            }
+            InternalFunction::Momentary(path) => {
+                let interface = self.get_interface().await?;
+                interface.set_value(path, Value::Bool(true)).await;
+                self.refresh_button_leds_for_path(path).await;
+                result = Ok(());
+            }
+            InternalFunction::Macro(name) => {
+                self.spawn_macro(name.clone()).await?;
+                result = Ok(());
+            }
+            InternalFunction::NextScene | InternalFunction::PreviousScene => {
+                let action = if function == InternalFunction::NextScene { "GONEXT" } else { "GOPREV" };
+                let interface = self.get_interface().await?;
+                interface.set_value(SCENE_ACTION_PATH, Value::Str(action.to_string())).await;
+                result = Ok(());
+            }
+            InternalFunction::TapTempo(path) => {
+                result = self.tap_tempo(path).await;
+            }
+            InternalFunction::Scene(index) => {
+                let interface = self.get_interface().await?;
+                interface.set_value(SCENE_INDEX_PATH, Value::Int(*index)).await;
+                interface.set_value(SCENE_ACTION_PATH, Value::Str("GO".to_string())).await;
+                self.refresh_button_leds_for_path(SCENE_INDEX_PATH).await;
+                result = Ok(());
+            }
+            InternalFunction::Geq(slot) => {
+                if self.window.overlay.as_ref().is_some_and(|o| o.function == function) {
+                    self.close_overlay();
+                } else {
+                    let faders = (0..GEQ_BANDS.len())
+                        .map(|band| Fader::new_geq_band(*slot, band))
+                        .collect();
+                    self.open_overlay(format!("GEQ FX{}", slot), faders, function.clone());
+                }
+                result = self.refresh_bank().await;
+            }
+            InternalFunction::SpillDca(dca) => {
+                if self.window.overlay.as_ref().is_some_and(|o| o.function == function) {
+                    self.close_overlay();
+                } else {
+                    let dca_fader = Fader::new_from_label(&format!("DCA {}", dca))?;
+                    let members = self.dca_members(*dca).await?;
+
+                    // The DCA itself stays on the first strip, so that it can be closed again
+                    let faders = std::iter::once(dca_fader).chain(members).collect();
+                    self.open_overlay(format!("DCA{} SPILL", dca), faders, function.clone());
+                }
+                result = self.refresh_bank().await;
+            }
+            InternalFunction::EncoderMode(mode) => {
+                info!(?mode, "Encoder mode changed");
+                self.encoder_mode = *mode;
+                self.remember_state();
+                self.send_names.fill(String::new());
+
+                self.refresh_all_button_leds().await;
+                result = self.refresh_encoders().await;
+
+                // The bottom rows show the send destinations in some modes only
+                for index in 0..self.visible_faders().len() {
+                    if !self.showing_value[index] {
+                        self.show_strip_name(index).await;
+                    }
+                }
+            }
+            InternalFunction::LockSurface => {
+                self.locked = !self.locked;
+                info!(locked = self.locked, "Surface lock toggled");
+                self.remember_state();
+
+                self.update_main_display().await;
+                self.refresh_all_button_leds().await;
+                result = Ok(());
+            }
+            InternalFunction::VegasMode => {
+                self.vegas = !self.vegas;
+                info!(vegas = self.vegas, "Vegas mode toggled");
+
+                result = if self.vegas {
+                    Self::spawn_vegas_task(self.this.clone());
+                    self.send_vegas_message()
+                } else {
+                    // The test pattern replaced everything on the surface
+                    self.restore_surface().await
+                };
+            }
+            InternalFunction::PhantomMode => {
+                self.phantom_mode = !self.phantom_mode;
+                info!(phantom_mode = self.phantom_mode, "Phantom power mode toggled");
+
+                self.refresh_all_button_leds().await;
+                // The bottom rows show the phantom power in this mode
+                result = self.refresh_bank().await;
+            }
+            InternalFunction::TogglePhantom(index) => {
+                result = self.toggle_phantom(*index).await;
+            }
+            InternalFunction::RtaMode => {
+                self.rta_mode = !self.rta_mode;
+                info!(rta_mode = self.rta_mode, "RTA mode toggled");
+
+                // The console's RTA shows the selected strip when it follows the selection
+                if let Some(selection_index) = self
+                    .selected_fader
+                    .as_ref()
+                    .and_then(|f| f.selection_index())
+                    .filter(|_| self.rta_mode)
+                {
+                    self.get_interface()
+                        .await?
+                        .set_value(SELECTED_STRIP_PATH, Value::Int(selection_index))
+                        .await;
+                }
+
+                self.refresh_all_button_leds().await;
+                // The meters and the bottom rows of the scribbles change in this mode
+                result = self.refresh_bank().await;
+            }
+            InternalFunction::MuteAll | InternalFunction::UnmuteAll => {
+                let interface = self.get_interface().await?;
+                interface.mute_all(function == InternalFunction::MuteAll).await;
+                result = Ok(());
+            }
+            InternalFunction::Nudge(target, direction) => {
+                result = self.nudge(target, *direction).await;
+            }
+            InternalFunction::Fade(target, db, duration) => {
+                match self.resolve_fader_target(target) {
+                    // Pressing the button again stops the fade
+                    Some(fader) if self.fades.is_fading(&fader) => {
+                        self.fades.cancel(&fader);
+                    }
+                    Some(fader) => self.spawn_fade(fader, *db, *duration).await?,
+                    None => debug!(?target, "No fader to fade"),
+                }
+                self.refresh_all_button_leds().await;
+                result = Ok(());
+            }
+            InternalFunction::SaveSnapshot(name) => {
+                let interface = self.get_interface().await?;
+                result = self.snapshots.capture(name, &interface).await.map(|_| ());
+            }
+            InternalFunction::RecallSnapshot(name) => {
+                let interface = self.get_interface().await?;
+                result = self.snapshots.restore(name, &interface).await.map(|_| ());
+            }
+            InternalFunction::AbCompare => {
+                let interface = self.get_interface().await?;
+                result = self.snapshots.ab_toggle(&interface).await.map(|_| ());
+                self.refresh_all_button_leds().await;
+            }
+            InternalFunction::AbReset => {
+                self.snapshots.ab_reset();
+                self.refresh_all_button_leds().await;
+                result = Ok(());
+            }
+            InternalFunction::SafeMode => {
+                // The LEDs of all surfaces are refreshed when safe mode is switched
+                let interface = self.get_interface().await?;
+                interface.set_safe_mode(!interface.safe_mode());
+                result = Ok(());
+            }
+            InternalFunction::CueGo | InternalFunction::CueBack => {
+                let interface = self.get_interface().await?;
+                let name = if function == InternalFunction::CueGo {
+                    self.cues.go(&interface).await
+                } else {
+                    self.cues.back(&interface).await
+                };
+
+                if name.is_none() {
+                    debug!(?function, "No cue to execute");
+                }
+
+                self.update_main_display().await;
+                self.refresh_all_button_leds().await;
+                result = Ok(());
+            }
+            InternalFunction::Snap(target, db) => {
+                result = match self.resolve_fader_target(target) {
+                    Some(fader) => {
+                        info!(?fader, db, "Snapping fader");
+                        self.set_fader_level(&fader, *db).await
+                    }
+                    None => {
+                        debug!(?target, "No fader to snap");
+                        Ok(())
+                    }
+                };
+            }
+            InternalFunction::ClearSolo => {
+                info!("Clearing all solos");
+                let interface = self.get_interface().await?;
+                interface.set_value(SOLO_ACTIVE_PATH, Value::Bool(false)).await;
+                self.refresh_button_leds_for_path(SOLO_ACTIVE_PATH).await;
+                result = Ok(());
             }
         }
+
+        result.with_context(|| format!("While executing function {:?}", function))
     }
 
-    /// Send the current colours, as stored in the cache, to the controller. This does not
-    /// update or request OSC values.
-    async fn send_colours(&self) {
-        let c = &self.cached_colours;
+    /// Set the LED rings of the encoders for the current mode, requesting the values they
+    /// show
+    async fn refresh_encoders(&self) -> Result<()> {
+        let faders = self.visible_faders().to_vec();
+        let interface = self.get_interface().await?;
+
+        for index in 0..self.encoder_ring_ccs.len() {
+            match (self.encoder_mode, faders.get(index)) {
+                (EncoderMode::Gain, Some(fader)) if fader.has_gain() => {
+                    interface
+                        .request_value_notification(&fader.get_osc_path(PathType::Gain), false)
+                        .await;
+                }
+                (EncoderMode::Sends, _) => {
+                    let bus = index as u8 + 1;
+                    let bus_name = format!("/bus/{}/$name", bus);
+                    interface.request_value_notification(&bus_name, false).await;
+
+                    match self.selected_fader.as_ref().filter(|f| f.has_sends()) {
+                        Some(selected) => {
+                            let level = selected.get_osc_path(PathType::SendLevel(bus));
+                            interface.request_value_notification(&level, false).await;
+                        }
+                        None => self.set_encoder_ring(index, None),
+                    }
+                }
+                (EncoderMode::Focus, _) => {
+                    let parameter = FOCUS_PARAMETERS
+                        .get(index)
+                        .zip(self.selected_fader.as_ref())
+                        .filter(|(parameter, selected)| focus_parameter_exists(parameter, selected));
+
+                    match parameter {
+                        Some((parameter, selected)) => {
+                            let path = selected.get_osc_path(parameter.clone());
+                            interface.request_value_notification(&path, false).await;
+                        }
+                        None => self.set_encoder_ring(index, None),
+                    }
+                }
+                _ => self.set_encoder_ring(index, None),
+            }
+        }
 
-        let sysex = [
-            0xF0, 0x00, 0x00, 0x66, 0x14, 0x72,
-            c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7],
-            0xF7,
-        ];
+        Ok(())
+    }
 
-        if let Err(e) = self.send_midi(&sysex) {
-            warn!("Failed to send colour sysex: {}", e);
+    /// Show a position between 0 and 1 on the LED ring of an encoder, or turn it off
+    fn set_encoder_ring(&self, index: usize, position: Option<f32>) {
+        let Some(&ring_cc) = self.encoder_ring_ccs.get(index) else {
+            return;
+        };
+
+        let value = match position {
+            // Position 0 turns the ring off, so the lowest value still lights one LED
+            Some(position) => {
+                RING_MODE_FILL | (1.0 + position.clamp(0.0, 1.0) * (RING_POSITIONS - 1.0)).round() as u8
+            }
+            None => 0,
+        };
+
+        let ev = LiveEvent::Midi {
+            channel: 0.into(),
+            message: midly::MidiMessage::Controller {
+                controller: ring_cc.into(),
+                value: value.into(),
+            },
+        };
+
+        let mut buf = Vec::with_capacity(3);
+        ev.write(&mut buf).unwrap();
+        if let Err(e) = self.send_midi(&buf) {
+            warn!("Failed to set encoder ring {}: {}", index, e);
         }
     }
 
-    async fn set_lcd_text(&self, text: &str, disp: u8) {
-        const MAX_LEN: u8 = 7;
-        const NUM_DISPLAYS: u8 = 8;
+    /// Convert encoder clicks to a number of steps, which is larger when the encoder is
+    /// turned quickly and acceleration is enabled
+    fn accelerate_encoder(&mut self, index: usize, clicks: i32) -> f32 {
+        let now = Instant::now();
+        let last_turn = self.last_encoder_turn[index].replace(now);
 
-        if disp >= NUM_DISPLAYS {
-            warn!("Invalid display index {:?}", disp);
-            return;
-        }
+        let Some(acceleration) = &self.encoder_acceleration else {
+            return clicks as f32;
+        };
 
-        let (row1_str, row2_str) = if text.contains(' ') && text.chars().count() <= (MAX_LEN as usize) * 2 {
-            let mut parts = text.splitn(2, ' ');
-            (
-                parts.next().unwrap_or("").to_string(),
-                parts.next().unwrap_or("").to_string(),
-            )
-        } else {
-            let mut it = text.chars();
-            let a: String = it.by_ref().take(MAX_LEN as usize).collect();
-            let b: String = it.take(MAX_LEN as usize).collect();
-            (a, b)
+        let window = Duration::from_millis(acceleration.window_ms);
+        let multiplier = match last_turn.map(|t| now - t) {
+            Some(elapsed) if elapsed < window => (window.as_secs_f32()
+                / elapsed.as_secs_f32().max(0.001))
+            .clamp(1.0, acceleration.max_multiplier.max(1.0)),
+            _ => 1.0,
         };
 
-        fn pad(s: &str, max_len: usize) -> Vec<u8> {
-            let mut bytes = s.bytes().collect::<Vec<u8>>();
-            while bytes.len() < max_len {
-                bytes.push(b' ');
+        trace!(index, clicks, multiplier, "Encoder acceleration");
+        clicks as f32 * multiplier
+    }
+
+    /// Handle an encoder being turned by a number of clicks, negative when turned to the left
+    async fn process_encoder(&mut self, index: usize, clicks: i32) -> Result<()> {
+        let Some(fader) = self.visible_faders().get(index).cloned() else {
+            return Ok(());
+        };
+
+        let steps = self.accelerate_encoder(index, clicks);
+
+        match self.encoder_mode {
+            EncoderMode::Off => {}
+            EncoderMode::Gain => {
+                if !fader.has_gain() {
+                    return Ok(());
+                }
+
+                let osc_path = fader.get_osc_path(PathType::Gain);
+                let interface = self.get_interface().await?;
+
+                let current = match interface.get_value(&osc_path, false).await? {
+                    Value::Float(gain) => gain,
+                    other => anyhow::bail!("Expected float value for gain, got {:?}", other),
+                };
+                let gain = (current + steps * self.gain_step_db)
+                    .clamp(PREAMP_MIN_DB, self.max_gain_db);
+
+                debug!(index, current, gain, "Setting gain from encoder");
+                interface.set_value(&osc_path, Value::Float(gain)).await;
+
+                let position = (gain - PREAMP_MIN_DB) / (PREAMP_MAX_DB - PREAMP_MIN_DB);
+                self.set_encoder_ring(index, Some(position));
+                self.show_fader_value(index, gain).await;
+            }
+            EncoderMode::Focus => {
+                let Some((parameter, selected)) = FOCUS_PARAMETERS
+                    .get(index)
+                    .zip(self.selected_fader.clone())
+                    .filter(|(parameter, selected)| focus_parameter_exists(parameter, selected))
+                else {
+                    return Ok(());
+                };
+
+                let osc_path = selected.get_osc_path(parameter.clone());
+                let interface = self.get_interface().await?;
+
+                let current = match interface.get_value(&osc_path, false).await? {
+                    Value::Float(value) => value,
+                    other => anyhow::bail!("Expected float value for {:?}, got {:?}", parameter, other),
+                };
+
+                let value = match parameter {
+                    PathType::Gain => (current + steps * self.gain_step_db).clamp(PREAMP_MIN_DB, self.max_gain_db),
+                    PathType::Panning => (current + steps * self.pan_step).clamp(PAN_MIN, PAN_MAX),
+                    // Frequencies move by a ratio, so that each click sounds alike
+                    _ => (current * self.low_cut_ratio.powf(steps)).clamp(LOW_CUT_MIN_HZ, LOW_CUT_MAX_HZ),
+                };
+
+                debug!(index, ?parameter, current, value, "Setting focus parameter from encoder");
+                interface.set_value(&osc_path, Value::Float(value)).await;
+
+                self.set_encoder_ring(index, Some(focus_position(parameter, value)));
+            }
+            EncoderMode::Sends => {
+                let Some(selected) = self.selected_fader.clone().filter(|f| f.has_sends()) else {
+                    return Ok(());
+                };
+
+                let osc_path = selected.get_osc_path(PathType::SendLevel(index as u8 + 1));
+                let interface = self.get_interface().await?;
+
+                let current = match interface.get_value(&osc_path, false).await? {
+                    Value::Float(level) => level,
+                    other => anyhow::bail!("Expected float value for send level, got {:?}", other),
+                };
+
+                // Sends move along the same curve as the faders
+                let position =
+                    (Fader::db_to_float(current as f64) + (steps * self.send_step) as f64).clamp(0.0, 1.0);
+                let level = (Fader::float_to_db(position) as f32).max(-144.0);
+
+                debug!(index, current, level, "Setting send level from encoder");
+                interface.set_value(&osc_path, Value::Float(level)).await;
+
+                self.set_encoder_ring(index, Some(position as f32));
+                self.show_fader_value(index, level).await;
             }
-            bytes
         }
 
-        let row1 = pad(&row1_str, MAX_LEN as usize);
-        let row2 = pad(&row2_str, MAX_LEN as usize);
-        let offset1 = disp.wrapping_mul(MAX_LEN);
-        let offset2 = offset1.wrapping_add(NUM_DISPLAYS.wrapping_mul(MAX_LEN));
+        Ok(())
+    }
+
+    /// The fader that a function acts on, if there is one
+    fn resolve_fader_target(&self, target: &FaderTarget) -> Option<Fader> {
+        match target {
+            FaderTarget::Strip(index) => self.visible_faders().get(*index).cloned(),
+            FaderTarget::Master => self.fixed_faders.get(&STRIP_COUNT).cloned(),
+            FaderTarget::Fader(fader) => Some(fader.clone()),
+            FaderTarget::Touched => self
+                .strip_touched
+                .iter()
+                .position(|touched| *touched)
+                .and_then(|index| self.visible_faders().get(index).cloned()),
+            FaderTarget::Selected => self.selected_fader.clone(),
+            FaderTarget::Active => self
+                .resolve_fader_target(&FaderTarget::Touched)
+                .or_else(|| self.resolve_fader_target(&FaderTarget::Selected)),
+        }
+    }
+
+    /// Whether a fader update is too close to the last known fader position to be sent
+    fn within_deadband(&self, fader_index: usize, db: f32, counts: u16) -> bool {
+        let Some(deadband) = &self.fader_deadband else {
+            return false;
+        };
+        let Some((last_db, last_counts)) = self.fader_positions.lock().unwrap().get(&fader_index).copied()
+        else {
+            return false;
+        };
+
+        // Levels are far apart near -inf even when the positions are close, and vice versa
+        (db - last_db).abs() < deadband.db || counts.abs_diff(last_counts) < deadband.counts
+    }
 
-        let mut sysex1: Vec<u8> = [0xF0, 0x00, 0x00, 0x66, 0x14, 0x12, offset1].to_vec();
-        sysex1.extend_from_slice(&row1);
-        sysex1.push(0xF7);
+    /// The level offset of the fader at an index on this surface, in dB. Only the strips of
+    /// the current bank are trimmed, by the trims of that bank.
+    fn fader_trim(&self, fader_index: usize, fader: &Fader) -> f32 {
+        if fader_index >= self.strip_count || self.window.overlay.is_some() {
+            return 0.0;
+        }
 
-        let mut sysex2: Vec<u8> = [0xF0, 0x00, 0x00, 0x66, 0x14, 0x12, offset2].to_vec();
-        sysex2.extend_from_slice(&row2);
-        sysex2.push(0xF7);
+        self.bank_trims
+            .get(self.window.bank)
+            .and_then(|trims| trims.get(&fader.get_osc_path(PathType::Fader)))
+            .copied()
+            .unwrap_or(0.0)
+    }
 
-        if let Err(e) = self.send_midi(&sysex1) {
-            warn!("Failed to write to display {} row1: {}", disp, e);
+    /// Convert a level shown by the fader at an index on this surface to the level on the
+    /// console
+    fn untrim_level(&self, fader_index: usize, fader: &Fader, db: f32) -> f32 {
+        let trim = self.fader_trim(fader_index, fader);
+        if trim == 0.0 {
+            return db;
         }
 
-        if let Err(e) = self.send_midi(&sysex2) {
-            warn!("Failed to write to display {} row2: {}", disp, e);
+        // The bottom of the fader stays at -inf
+        if db <= FADER_MIN_DB {
+            return FADER_MIN_DB;
         }
+
+        (db - trim).clamp(FADER_MIN_DB, FADER_MAX_DB)
     }
 
-    async fn do_function(&mut self, function: InternalFunction) -> Result<()> {
-        let mut result;
+    /// Set the level of a fader on the console, and move it on this surface
+    async fn set_fader_level(&mut self, fader: &Fader, db: f32) -> Result<()> {
+        let osc_path = fader.get_osc_path(PathType::Fader);
+        self.get_interface().await?.set_value(&osc_path, Value::Float(db)).await;
 
-        match function {
-            InternalFunction::NextBank => {
-                self.current_bank = (self.current_bank + 1) % self.banks.len();
-                result = self.refresh_bank().await;
+        // The new value is not sent back to us, so move our own faders too
+        self.process_osc_input(&osc_path, &Value::Float(db)).await
+    }
+
+    /// Raise or lower the level of a fader by the nudge step
+    async fn nudge(&mut self, target: &FaderTarget, direction: i32) -> Result<()> {
+        let Some(fader) = self.resolve_fader_target(target) else {
+            debug!(?target, "No fader to nudge");
+            return Ok(());
+        };
+
+        let osc_path = fader.get_osc_path(PathType::Fader);
+        let interface = self.get_interface().await?;
+
+        let current = match interface.get_value(&osc_path, false).await? {
+            Value::Float(db) => db,
+            other => anyhow::bail!("Expected float value for fader, got {:?}", other),
+        };
+        let db = (current + direction as f32 * self.nudge_step_db).clamp(FADER_MIN_DB, FADER_MAX_DB);
+
+        debug!(?fader, current, db, "Nudging fader");
+        self.set_fader_level(&fader, db).await
+    }
+
+    /// Record a tap, and set the parameter to the average interval of the recent taps
+    async fn tap_tempo(&mut self, path: &str) -> Result<()> {
+        let now = Instant::now();
+        let taps = self.taps.entry(path.to_string()).or_default();
+
+        // A long pause starts a new measurement
+        if taps.last().is_some_and(|last| now - *last > TAP_TIMEOUT) {
+            taps.clear();
+        }
+        taps.push(now);
+        if taps.len() > TAP_HISTORY {
+            taps.remove(0);
+        }
+
+        if taps.len() < 2 {
+            return Ok(());
+        }
+
+        let interval = (*taps.last().unwrap() - taps[0]) / (taps.len() as u32 - 1);
+        let ms = interval.as_secs_f32() * 1000.0;
+        debug!(path, ms, bpm = 60_000.0 / ms, "Tap tempo");
+
+        self.get_interface().await?.set_value(path, Value::Float(ms)).await;
+        // Restart the pulses on this tap
+        self.tempo_epoch = now;
+
+        Ok(())
+    }
+
+    /// Run a macro in the background. Its buttons blink until it finishes.
+    async fn spawn_macro(&self, name: String) -> Result<()> {
+        let interface = self.get_interface().await?;
+        let engine = self.macros.clone();
+        let this = self.this.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = engine.run(&name, &interface).await {
+                error!("Failed to run macro '{}': {:?}", name, e);
             }
-            InternalFunction::PreviousBank => {
-                if self.current_bank == 0 {
-                    self.current_bank = self.banks.len() - 1;
+
+            if let Some(controller) = this.upgrade() {
+                controller.lock().await.refresh_all_button_leds().await;
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn spawn_fade(&self, fader: Fader, db: f32, duration: Duration) -> Result<()> {
+        let interface = self.get_interface().await?;
+        let engine = self.fades.clone();
+        let this = self.this.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = engine.run(&fader, db, duration, &interface).await {
+                error!("Failed to fade {:?}: {:?}", fader, e);
+            }
+
+            if let Some(controller) = this.upgrade() {
+                controller.lock().await.refresh_all_button_leds().await;
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Handle the release of a button that is bound to a function
+    /// Execute a function as if its button had been tapped, for functions that are triggered
+    /// remotely instead of on the surface
+    pub async fn trigger_function(&mut self, function: InternalFunction) -> Result<()> {
+        self.do_function(function.clone()).await?;
+        self.release_function(function).await
+    }
+
+    async fn release_function(&mut self, function: InternalFunction) -> Result<()> {
+        if let InternalFunction::Momentary(path) = &function {
+            let interface = self.get_interface().await?;
+            interface.set_value(path, Value::Bool(false)).await;
+            self.refresh_button_leds_for_path(path).await;
+        }
+
+        Ok(())
+    }
+
+    /// Record a button press or release, and decide which function should be executed
+    fn register_button(&mut self, note: u32, pressed: bool) -> ButtonAction {
+        let short = self.buttons.get(&note).map(|b| b.function.clone());
+
+        // Phantom power is only switched with a long press, to avoid accidents
+        let phantom_strip = (0..self.strip_count)
+            .filter(|_| self.phantom_mode)
+            .find(|index| self.strip_button_note(*index, PHANTOM_BUTTON) == Some(note));
+
+        let long = match (self.long_press_buttons.get(&note), phantom_strip) {
+            (_, Some(index)) => InternalFunction::TogglePhantom(index),
+            (Some(function), None) => function.clone(),
+            (None, None) => {
+                return match (pressed, short) {
+                    (true, Some(function)) => ButtonAction::Press(function),
+                    (false, Some(function)) => ButtonAction::Release(function),
+                    _ => ButtonAction::Nothing,
+                };
+            }
+        };
+
+        if pressed {
+            // The short-press function is deferred until release
+            self.press_counter += 1;
+            self.pressed_buttons.insert(note, (self.press_counter, Instant::now()));
+
+            ButtonAction::ArmLongPress(self.press_counter, long)
+        } else {
+            match self.pressed_buttons.remove(&note) {
+                Some((_, pressed_at)) => {
+                    trace!(note, held_ms = pressed_at.elapsed().as_millis() as u64, "Short press");
+                    short.map_or(ButtonAction::Nothing, ButtonAction::Tap)
+                }
+                // The long-press function has already fired
+                None => ButtonAction::Nothing,
+            }
+        }
+    }
+
+    /// Execute the function(s) that correspond to a button event
+    async fn dispatch_button_action(controller: Arc<Mutex<Controller>>, note: u32, action: ButtonAction) {
+        let result = match action {
+            ButtonAction::Press(function) => controller.lock().await.do_function(function).await,
+            ButtonAction::Release(function) => {
+                controller.lock().await.release_function(function).await
+            }
+            ButtonAction::Tap(function) => {
+                let mut controller = controller.lock().await;
+
+                match controller.do_function(function.clone()).await {
+                    Ok(()) => controller.release_function(function).await,
+                    Err(e) => Err(e),
+                }
+            }
+            ButtonAction::ArmLongPress(press_id, function) => {
+                let duration = controller.lock().await.long_press_duration;
+                tokio::time::sleep(duration).await;
+
+                let mut controller = controller.lock().await;
+                let still_held =
+                    controller.pressed_buttons.get(&note).map(|(id, _)| *id) == Some(press_id);
+
+                if still_held {
+                    controller.pressed_buttons.remove(&note);
+                    debug!(note, "Long press");
+                    controller.do_function(function).await
                 } else {
-                    self.current_bank -= 1;
+                    Ok(())
                 }
-                result = self.refresh_bank().await;
             }
+            ButtonAction::Nothing => Ok(()),
+        };
+
+        if let Err(e) = result {
+            error!("Failed to execute function for button {}: {:?}", note, e);
         }
+    }
 
-        result.with_context(|| format!("While executing function {:?}", function))
+    /// Show the status message on the main display if there is one, or the name of the
+    /// current bank otherwise
+    async fn update_main_display(&self) {
+        let cue_name = self.cues.current_name();
+
+        let text = match (&self.status_message, &self.window.overlay) {
+            _ if self.locked => "LOCKED",
+            (Some(status), _) => status.as_str(),
+            (None, Some(overlay)) => overlay.name.as_str(),
+            (None, None) if cue_name.is_some() => cue_name.as_deref().unwrap_or(""),
+            (None, None) if self.scene_on_main_display => {
+                self.scene_name.as_deref().unwrap_or("")
+            }
+            (None, None) => self
+                .bank_names
+                .get(self.window.bank)
+                .and_then(|name| name.as_deref())
+                .unwrap_or(""),
+        };
+
+        self.write_text_to_main_display(text).await;
     }
 
-    async fn write_text_to_main_display(&self, text: &str) {
-        let display_cc = (64..=75).rev().collect::<Vec<u8>>();
+    /// Set or clear the status message shown on the main display
+    async fn set_status_message(&mut self, status: Option<String>) {
+        if self.status_message != status {
+            self.status_message = status;
+            self.update_main_display().await;
+        }
+    }
+
+    /// Show the current bank number on the Assignment display, flashing it a few times
+    fn flash_bank_number(&mut self) {
+        self.bank_flash_generation += 1;
+        let generation = self.bank_flash_generation;
+        let this = self.this.clone();
+
+        self.runtime.spawn(async move {
+            for step in 0..=BANK_FLASH_COUNT * 2 {
+                if step > 0 {
+                    tokio::time::sleep(BANK_FLASH_INTERVAL).await;
+                }
+
+                let Some(controller) = this.upgrade() else {
+                    return;
+                };
+                let controller = controller.lock().await;
+
+                if controller.bank_flash_generation != generation {
+                    return;
+                }
+
+                // Blank on odd steps, ending with the number shown
+                let text = if step % 2 == 0 {
+                    format!("{:2}", (controller.window.bank + 1) % 100)
+                } else {
+                    String::new()
+                };
+
+                controller.write_assignment_display(&text).await;
+            }
+        });
+    }
+
+    /// Show the strip of a fader and flash its scribble and buttons for a few seconds, so that
+    /// it can be found on the surface. On the console, the strip is only selected by writing its
+    /// index to `/$ctl/$stat/selidx`, as the WING has no identify node for strips. Before the
+    /// interface is set, the strip is identified once it is.
+    pub async fn identify(&mut self, fader: &Fader) -> Result<()> {
+        let Some((bank, position)) = self
+            .banks
+            .iter()
+            .enumerate()
+            .find_map(|(bank, faders)| Some((bank, faders.iter().position(|f| f == fader)?)))
+        else {
+            anyhow::bail!("{:?} is not on any bank of this surface", fader);
+        };
+
+        if self.interface.lock().await.is_none() {
+            debug!(?fader, "Identifying strip once the interface is set");
+            self.pending_identify = Some(fader.clone());
+            return Ok(());
+        }
+
+        info!(bank, position, ?fader, "Identifying strip");
+
+        self.window.bank = bank;
+        self.window.overlay = None;
+        if position < self.window.offset || position >= self.window.offset + self.strip_count {
+            self.window.offset = position - position % self.strip_count;
+        }
+        let window_offset = self.window.offset;
+        let index = position - window_offset;
+        self.refresh_bank().await?;
+
+        if let Some(selection_index) = fader.selection_index() {
+            self.get_interface()
+                .await?
+                .set_value(SELECTED_STRIP_PATH, Value::Int(selection_index))
+                .await;
+        }
+
+        let this = self.this.clone();
+
+        self.runtime.spawn(async move {
+            for step in 0..IDENTIFY_FLASH_COUNT * 2 {
+                let Some(controller) = this.upgrade() else {
+                    return;
+                };
+                let controller = controller.lock().await;
 
-        let text = text.chars().take(display_cc.len()).collect::<String>();
+                // Stop once the strip has moved away
+                if controller.window.bank != bank || controller.window.offset != window_offset {
+                    return;
+                }
+
+                let lit = step % 2 == 0;
+                let mut colours = controller.cached_colours;
+                colours[index] = if lit { 7 | 0x40 } else { 0 };
+                controller.write_colours(colours);
+                controller
+                    .set_lcd_rows(if lit { "IDENTIFY" } else { "" }, "", index as u8)
+                    .await;
+                for note in controller.strip_buttons[index].values() {
+                    controller.send_button_led(*note, lit);
+                }
+
+                drop(controller);
+                tokio::time::sleep(IDENTIFY_FLASH_INTERVAL).await;
+            }
+
+            let Some(controller) = this.upgrade() else {
+                return;
+            };
+            let mut controller = controller.lock().await;
+
+            if controller.window.bank == bank && controller.window.offset == window_offset {
+                if let Err(e) = controller.refresh_bank().await {
+                    warn!("Failed to restore strip after identifying it: {:?}", e);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Write up to two characters to the Assignment display, left of the main display
+    async fn write_assignment_display(&self, text: &str) {
+        let mut chars = text.chars();
 
-        // An offset to discard the first two characters because they are too far away on
-        // the display
-        let mut text_offset = 2;
-        if text.len() > display_cc.len() - 2 {
-            text_offset = 0;
+        // The Assignment display digits are CCs 75 and 74, from left to right
+        for cc in [75, 74] {
+            self.write_segment(cc, chars.next().unwrap_or(' '));
         }
+    }
+
+    async fn write_text_to_main_display(&self, text: &str) {
+        // The main display digits are CCs 73 to 64, from left to right
+        let display_cc = (64..=73).rev().collect::<Vec<u8>>();
+
+        let text = text.chars().take(display_cc.len()).collect::<Vec<char>>();
 
         // We iterate over the entire display to clear any digits that may have been left
         // from before
         for (i, cc) in display_cc.iter().enumerate() {
-            let index = i.checked_sub(text_offset);
-            let ch = match index {
-                Some(idx) => text.chars().nth(idx).unwrap_or(' '),
-                None => ' ',
-            };
-            let midi_value = ASCII_TO_7SEGMENT
-                .get(ch as usize)
-                .and_then(|v| *v);
+            self.write_segment(*cc, text.get(i).copied().unwrap_or(' '));
+        }
+    }
 
-            if let Some(midi_value) = midi_value {
-                let ev = LiveEvent::Midi {
-                    channel: 0.into(),
-                    message: midly::MidiMessage::Controller {
-                        controller: display_cc[i].into(),
-                        value: midi_value.into(),
-                    },
-                };
+    /// Write a single character to a digit of the 7-segment display
+    fn write_segment(&self, cc: u8, ch: char) {
+        let midi_value = ASCII_TO_7SEGMENT
+            .get(ch as usize)
+            .and_then(|v| *v);
+
+        if let Some(midi_value) = midi_value {
+            let ev = LiveEvent::Midi {
+                channel: 0.into(),
+                message: midly::MidiMessage::Controller {
+                    controller: cc.into(),
+                    value: midi_value.into(),
+                },
+            };
 
-                let mut buf = Vec::with_capacity(3);
-                ev.write(&mut buf).unwrap();
-                if let Err(e) = self.send_midi(&buf) {
-                    warn!("Failed to write to main display: {}", e);
-                }
+            let mut buf = Vec::with_capacity(3);
+            ev.write(&mut buf).unwrap();
+            if let Err(e) = self.send_midi(&buf) {
+                warn!("Failed to write to 7-segment display: {}", e);
             }
         }
     }
 
+    /// Queue a MIDI message to be sent to the controller
     fn send_midi(&self, data: &[u8]) -> Result<()> {
         trace!(?data, "MIDI output");
 
-        match self.output.lock() {
-            Ok(mut conn) => conn.send(data).map_err(|e| anyhow!("MIDI send failed: {}", e)),
-            Err(e) => Err(anyhow!("Failed to lock MIDI output mutex: {:?}", e)),
-        }
+        self.output_pending.fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+        self.output_queue.send(data.to_vec()).map_err(|_| {
+            self.output_pending.fetch_sub(1, std::sync::atomic::Ordering::AcqRel);
+            anyhow!("MIDI output queue closed")
+        })
     }
 
-    async fn request_meters(&self) {
-        let bank = match self.banks.get(self.current_bank) {
-            Some(b) => b,
-            None => {
-                error!("Current bank {} not found when requesting meters", self.current_bank);
-                return;
+    /// Wait until all queued MIDI messages were sent, for a short while at most
+    async fn flush_output(&self) {
+        let flushed = async {
+            while self.output_pending.load(std::sync::atomic::Ordering::Acquire) > 0 {
+                tokio::time::sleep(OUTPUT_FLUSH_POLL_INTERVAL).await;
             }
         };
 
-        let meters = bank
-            .iter()
-            .filter_map(|fader| {
-                fader.get_meter().clone()
-            })
-            .collect::<Vec<_>>();
+        if tokio::time::timeout(OUTPUT_FLUSH_TIMEOUT, flushed).await.is_err() {
+            warn!("Not all MIDI messages were sent to the surface");
+        }
+    }
+
+    /// Spawn the background task that sends queued MIDI messages to the controller.
+    ///
+    /// Messages queued while the previous ones were being sent are sent as a batch, where
+    /// only the last of several messages to the same target (e.g. the same LED) is kept.
+    fn spawn_output_task(
+        output: Arc<std::sync::Mutex<MidiOutputConnection>>,
+        mut queue: UnboundedReceiver<Vec<u8>>,
+        max_messages_per_sec: Option<u32>,
+        failed: Arc<std::sync::atomic::AtomicBool>,
+        pending: Arc<std::sync::atomic::AtomicUsize>,
+    ) {
+        let interval = max_messages_per_sec
+            .filter(|rate| *rate > 0)
+            .map(|rate| Duration::from_secs(1) / rate);
+
+        tokio::spawn(async move {
+            let mut batch = Vec::new();
+
+            while let Some(message) = queue.recv().await {
+                batch.push(message);
+                while let Ok(message) = queue.try_recv() {
+                    batch.push(message);
+                }
+
+                let batch_len = batch.len();
+                let messages = coalesce_midi(std::mem::take(&mut batch));
+                trace!(queued = batch_len, sent = messages.len(), "Flushing MIDI output");
+
+                for message in messages {
+                    let result = match output.lock() {
+                        Ok(mut conn) => {
+                            conn.send(&message).map_err(|e| anyhow!("MIDI send failed: {}", e))
+                        }
+                        Err(e) => Err(anyhow!("Failed to lock MIDI output mutex: {:?}", e)),
+                    };
+
+                    if let Err(e) = result {
+                        // Avoid flooding the log while the device is unplugged
+                        if !failed.swap(true, std::sync::atomic::Ordering::Relaxed) {
+                            warn!("Failed to send MIDI message {:?}: {}", message, e);
+                        }
+                    }
+
+                    if let Some(interval) = interval {
+                        tokio::time::sleep(interval).await;
+                    }
+                }
+
+                pending.fetch_sub(batch_len, std::sync::atomic::Ordering::AcqRel);
+            }
+        });
+    }
+
+    /// Subscribe to the meters of the visible strips, releasing those of any strips that are
+    /// no longer visible. No meters are needed while asleep.
+    async fn request_meters(&self) {
+        let meters = match self.asleep {
+            true => vec![],
+            false if self.rta_mode => vec![libwing::Meter::Rta],
+            false => self
+                .visible_faders()
+                .iter()
+                .filter_map(|fader| {
+                    fader.get_meter().clone()
+                })
+                .collect::<Vec<_>>(),
+        };
 
         let interface = self.interface.lock().await;
 
@@ -609,191 +3581,271 @@ impl Controller {
         }
     }
 
-    async fn send_meters(&self, values: Vec<Vec<f32>>) {
-        // TODO: Handle non-existent meters!!!
-        for (chan, channel_values) in values.iter().enumerate() {
-            if chan >= 8 {
-                warn!("Ignoring meter channel {} (only 0-7 supported)", chan);
-                continue;
-            }
+    /// The note of a strip button, by its description in the MIDI definition
+    fn strip_button_note(&self, index: usize, description: &str) -> Option<u32> {
+        self.strip_buttons.get(index)?.get(description).copied()
+    }
 
-            let level = channel_values.get(0).copied().unwrap_or(0.0);
-            let level = level.clamp(0.0, 1.0);
-            // Power scaling
-            let level = level.powf(4.0);
+    async fn send_meters(&mut self, values: Vec<Vec<f32>>) {
+        if self.asleep {
+            return;
+        }
 
-            let channel_offset: u8 = (level * 15.0) as u8;
+        let source = self
+            .bank_meter_sources
+            .get(self.window.bank)
+            .copied()
+            .unwrap_or_default();
+
+        // Index of the value within each meter, which contains input, output, gate and
+        // dynamics values
+        let value_index = match source {
+            MeterSource::Level => 0,
+            MeterSource::Gate => 5,
+            MeterSource::GainReduction => 7,
+        };
 
-            let ev = LiveEvent::Midi {
-                channel: 0.into(),
-                message: midly::MidiMessage::ChannelAftertouch {
-                    vel: (chan as u8 * 16 + channel_offset).into(),
-                },
+        if self.rta_mode {
+            if let Some(bands) = values.first() {
+                self.send_rta_meters(bands);
+            }
+            return;
+        }
+
+        // Only the visible strips that have a meter are subscribed to, in the order of the
+        // strips, so strips without one are skipped
+        let metered_strips: Vec<usize> = self
+            .visible_faders()
+            .iter()
+            .enumerate()
+            .filter(|(_, fader)| fader.get_meter().is_some())
+            .map(|(index, _)| index)
+            .collect();
+
+        for (chan, channel_values) in metered_strips.into_iter().zip(&values) {
+            let level = match source {
+                MeterSource::Level => {
+                    let level = channel_values.get(value_index).copied().unwrap_or(0.0);
+                    let level = level.clamp(0.0, 1.0);
+                    // Power scaling
+                    level.powf(4.0)
+                }
+                MeterSource::Gate | MeterSource::GainReduction => {
+                    // Gain values are 1.0 when no reduction takes place
+                    let gain = channel_values.get(value_index).copied().unwrap_or(1.0);
+                    let reduction_db = (1.0 - gain) * METER_RANGE_DB;
+                    (reduction_db / GAIN_REDUCTION_RANGE_DB).clamp(0.0, 1.0)
+                }
             };
 
-            let mut buf = Vec::with_capacity(3);
-            ev.write(&mut buf)
-                .map_err(|e| anyhow!("MIDI write fail {}", e))
-                .unwrap();
-            if let Err(e) = self.send_midi(&buf) {
-                warn!("Failed to send MIDI for meter channel {}: {}", chan, e);
+            self.write_strip_meter(chan, level);
+
+            if let Some(threshold) = self.signal_present_db {
+                let level = channel_values.get(0).copied().unwrap_or(0.0);
+                let present = (level - 1.0) * METER_RANGE_DB > threshold;
+
+                if present != self.signal_present[chan] {
+                    self.signal_present[chan] = present;
+
+                    if let Some(note) = self.strip_button_note(chan, "Rec") {
+                        self.send_button_led(note, present);
+                    }
+                }
             }
         }
     }
 
+    /// Show the RTA bands on the strip meters, each strip showing the loudest band of its
+    /// group
+    fn send_rta_meters(&self, bands: &[f32]) {
+        let group_size = RTA_BANDS / STRIP_COUNT;
+
+        for (chan, group) in bands.chunks(group_size).take(STRIP_COUNT).enumerate() {
+            let level = group.iter().copied().fold(0.0, f32::max).clamp(0.0, 1.0);
+            self.write_strip_meter(chan, level.powf(4.0));
+        }
+    }
+
+    /// Set the meter of a strip to a level from 0 to 1
+    fn write_strip_meter(&self, chan: usize, level: f32) {
+        let channel_offset: u8 = (level * 15.0) as u8;
+
+        let ev = LiveEvent::Midi {
+            channel: 0.into(),
+            message: midly::MidiMessage::ChannelAftertouch {
+                vel: (chan as u8 * 16 + channel_offset).into(),
+            },
+        };
+
+        let mut buf = Vec::with_capacity(3);
+        ev.write(&mut buf)
+            .map_err(|e| anyhow!("MIDI write fail {}", e))
+            .unwrap();
+        if let Err(e) = self.send_midi(&buf) {
+            warn!("Failed to send MIDI for meter channel {}: {}", chan, e);
+        }
+    }
+
     /// Runs a never-ending Vegas mode test pattern.
     pub async fn vegas_mode(&mut self, faders: bool) -> Result<()> {
-        let mut clk = 0;
+        self.send_vegas_message()?;
 
-        {
-            let max_len = 56 * 2;
-            let message = b"Hello this is a test message from kongr45gpen!          Hello this is a test message from kongr45gpen!";
-
-            // Text display
-            let mut sysex: Vec<u8> = [
-                0xF0, 0x00, 0x00, 0x66, 0x14, 0x12, 0x00, // Header
-            ]
-            .to_vec();
-            sysex.extend_from_slice(&message[..max_len.min(message.len())]);
-            sysex.push(0xF7);
-            self.send_midi(&sysex)?;
+        let mut clk = 0;
+        loop {
+            tokio::time::sleep(VEGAS_FRAME_INTERVAL).await;
+            self.send_vegas_frame(clk, faders)?;
+            clk += 1;
         }
+    }
 
-        loop {
-            tokio::time::sleep(tokio::time::Duration::from_millis(1000 / 30)).await;
+    /// Show the test message of Vegas mode on the displays
+    fn send_vegas_message(&self) -> Result<()> {
+        let max_len = 56 * 2;
+        let message = b"Hello this is a test message from kongr45gpen!          Hello this is a test message from kongr45gpen!";
+
+        // Text display
+        let mut sysex: Vec<u8> = [
+            0xF0, 0x00, 0x00, 0x66, 0x14, 0x12, 0x00, // Header
+        ]
+        .to_vec();
+        sysex.extend_from_slice(&message[..max_len.min(message.len())]);
+        sysex.push(0xF7);
+        self.send_midi(&sysex)?;
+        Ok(())
+    }
 
-            let mut buf = Vec::new();
+    /// Send one frame of the Vegas mode test pattern, where `clk` is the frame number
+    fn send_vegas_frame(&self, clk: i32, faders: bool) -> Result<()> {
+        let mut buf = Vec::new();
 
-            // Pitch bends channels 0 - 8
-            for channel in 0..9 {
-                let value =
-                    f32::sin(clk as f32 * 0.2 + channel as f32 / 9.0 * 2.0 * f32::consts::PI);
+        // Pitch bends channels 0 - 8
+        for channel in 0..9 {
+            let value =
+                f32::sin(clk as f32 * 0.2 + channel as f32 / 9.0 * 2.0 * f32::consts::PI);
 
-                let ev = LiveEvent::Midi {
-                    channel: channel.into(),
-                    message: midly::MidiMessage::PitchBend {
-                        bend: PitchBend::from_f32(value),
-                    },
-                };
+            let ev = LiveEvent::Midi {
+                channel: channel.into(),
+                message: midly::MidiMessage::PitchBend {
+                    bend: PitchBend::from_f32(value),
+                },
+            };
 
-                ev.write(&mut buf).unwrap();
-                if faders {
-                    self.send_midi(&buf)?;
-                }
-                buf.clear();
+            ev.write(&mut buf).unwrap();
+            if faders {
+                self.send_midi(&buf)?;
             }
+            buf.clear();
+        }
 
-            // Notes 0-101 channel 0
-            // keys = 0..102 and 113 and 114 and 115
-            let keys = (0..102).chain(113..116);
-            for key in keys {
-                let vel = f32::sin(clk as f32 * 0.3 + key as f32 * 199.352);
+        // Notes 0-101 channel 0
+        // keys = 0..102 and 113 and 114 and 115
+        let keys = (0..102).chain(113..116);
+        for key in keys {
+            let vel = f32::sin(clk as f32 * 0.3 + key as f32 * 199.352);
 
-                let vel = if vel > 0.2 { 127 } else { 0 };
+            let vel = if vel > 0.2 { 127 } else { 0 };
 
-                let ev = LiveEvent::Midi {
-                    channel: 0.into(),
-                    message: midly::MidiMessage::NoteOn {
-                        key: key.into(),
-                        vel: vel.into(),
-                    },
-                };
+            let ev = LiveEvent::Midi {
+                channel: 0.into(),
+                message: midly::MidiMessage::NoteOn {
+                    key: key.into(),
+                    vel: vel.into(),
+                },
+            };
 
-                ev.write(&mut buf).unwrap();
-                self.send_midi(&buf)?;
-                buf.clear();
-            }
+            ev.write(&mut buf).unwrap();
+            self.send_midi(&buf)?;
+            buf.clear();
+        }
 
-            // Meters
-            // Notes 0-120 channel 1
-            for chan in 0..8 {
-                let level = f32::sin(-clk as f32 * 0.3 + chan as f32 / 9.0 * 2.0 * f32::consts::PI);
-                // Map from -1..1 to 0..1
-                let level = (level + 1.0) / 2.0;
+        // Meters
+        // Notes 0-120 channel 1
+        for chan in 0..8 {
+            let level = f32::sin(-clk as f32 * 0.3 + chan as f32 / 9.0 * 2.0 * f32::consts::PI);
+            // Map from -1..1 to 0..1
+            let level = (level + 1.0) / 2.0;
 
-                let channel_offset: u8 = (level * 15.0) as u8;
+            let channel_offset: u8 = (level * 15.0) as u8;
 
-                let ev = LiveEvent::Midi {
-                    channel: 0.into(),
-                    message: midly::MidiMessage::ChannelAftertouch {
-                        // key: (chan * 16 + channel_offset).into(),
-                        vel: (chan * 16 + channel_offset).into(),
-                    },
-                };
+            let ev = LiveEvent::Midi {
+                channel: 0.into(),
+                message: midly::MidiMessage::ChannelAftertouch {
+                    // key: (chan * 16 + channel_offset).into(),
+                    vel: (chan * 16 + channel_offset).into(),
+                },
+            };
 
-                ev.write(&mut buf).unwrap();
-                self.send_midi(&buf)?;
-                buf.clear();
-            }
+            ev.write(&mut buf).unwrap();
+            self.send_midi(&buf)?;
+            buf.clear();
+        }
 
-            // Encoders
-            // CC 48-55, 56-63
-            for encoder in 0..8 {
-                let value =
-                    f32::sin(-clk as f32 * 0.02 + encoder as f32 * 0.02 * 2.0 * f32::consts::PI);
-                // Map from -1..1 to 0..127
-                let value = ((value + 1.0) / 2.0 * 127.0) as u8;
+        // Encoders
+        // CC 48-55, 56-63
+        for encoder in 0..8 {
+            let value =
+                f32::sin(-clk as f32 * 0.02 + encoder as f32 * 0.02 * 2.0 * f32::consts::PI);
+            // Map from -1..1 to 0..127
+            let value = ((value + 1.0) / 2.0 * 127.0) as u8;
 
-                let ev = LiveEvent::Midi {
-                    channel: 0.into(),
-                    message: midly::MidiMessage::Controller {
-                        controller: (48 + encoder).into(),
-                        value: value.into(),
-                    },
-                };
+            let ev = LiveEvent::Midi {
+                channel: 0.into(),
+                message: midly::MidiMessage::Controller {
+                    controller: (48 + encoder).into(),
+                    value: value.into(),
+                },
+            };
 
-                ev.write(&mut buf).unwrap();
-                self.send_midi(&buf)?;
-                buf.clear();
-            }
+            ev.write(&mut buf).unwrap();
+            self.send_midi(&buf)?;
+            buf.clear();
+        }
 
-            {
-                let colours = (0..8)
-                    .map(|i| {
-                        let c =
-                            f32::sin(-clk as f32 * 0.1 + i as f32 * 0.2 * 2.0 * f32::consts::PI);
-                        ((c + 1.0) / 2.0 * 7.0) as u8
-                    })
-                    .collect::<Vec<u8>>();
-
-                let sysex = [
-                    0xF0, 0x00, 0x00, 0x66, 0x14, 0x72, // Header
-                    colours[0], colours[1], colours[2], colours[3], colours[4], colours[5],
-                    colours[6], colours[7], // Colours
-                    0xF7,
-                ];
-
-                // Scribble Strip Colours (sysex)
-                self.send_midi(&sysex)?;
-            }
-
-            // 7-segment display
-            // CC 96-107, 112-123
-            // Actual CC 64-76
-            // From right to left
-            for cc in 64..76 {
-                let value = f32::sin(-clk as f32 * 0.02 + cc as f32 * 0.01 * 2.0 * f32::consts::PI);
-                // Map from -1..1 to 0..127
-                let value = ((value + 1.0) / 2.0 * 127.0) as u8;
-
-                // The display seems to be following a custom ASCII code
-                // starting from letters + symbols + numbers, duplicated wrt the comma display
-                let ev = LiveEvent::Midi {
-                    channel: 0.into(),
-                    message: midly::MidiMessage::Controller {
-                        controller: cc.into(),
-                        value: value.into(),
-                    },
-                };
+        {
+            let colours = (0..8)
+                .map(|i| {
+                    let c =
+                        f32::sin(-clk as f32 * 0.1 + i as f32 * 0.2 * 2.0 * f32::consts::PI);
+                    ((c + 1.0) / 2.0 * 7.0) as u8
+                })
+                .collect::<Vec<u8>>();
 
-                ev.write(&mut buf).unwrap();
-                self.send_midi(&buf)?;
-                buf.clear();
-            }
+            let sysex = [
+                0xF0, 0x00, 0x00, 0x66, 0x14, 0x72, // Header
+                colours[0], colours[1], colours[2], colours[3], colours[4], colours[5],
+                colours[6], colours[7], // Colours
+                0xF7,
+            ];
 
-            clk += 1;
+            // Scribble Strip Colours (sysex)
+            self.send_midi(&sysex)?;
+        }
+
+        // 7-segment display
+        // CC 96-107, 112-123
+        // Actual CC 64-76
+        // From right to left
+        for cc in 64..76 {
+            let value = f32::sin(-clk as f32 * 0.02 + cc as f32 * 0.01 * 2.0 * f32::consts::PI);
+            // Map from -1..1 to 0..127
+            let value = ((value + 1.0) / 2.0 * 127.0) as u8;
+
+            // The display seems to be following a custom ASCII code
+            // starting from letters + symbols + numbers, duplicated wrt the comma display
+            let ev = LiveEvent::Midi {
+                channel: 0.into(),
+                message: midly::MidiMessage::Controller {
+                    controller: cc.into(),
+                    value: value.into(),
+                },
+            };
+
+            ev.write(&mut buf).unwrap();
+            self.send_midi(&buf)?;
+            buf.clear();
         }
 
+
         Ok(())
     }
 }
@@ -820,13 +3872,31 @@ impl WriteProvider for Arc<Mutex<Controller>> {
         let controller = self.clone();
 
         tokio::task::spawn(async move {
-            let controller = controller.lock().await;
+            let mut controller = controller.lock().await;
 
+            let mut patterns: Vec<String> = SUBSCRIBED_TREES
+                .iter()
+                .flat_map(|tree| [tree.to_string(), format!("@*{}", tree)])
+                .collect();
+            patterns.extend(controller.function_paths());
+            patterns.extend(controller.fader_trees());
+            interface.subscribe(&patterns).await;
+
+            controller.metrics = Some(interface.metrics());
+            controller.spawn_safe_mode_watch(&interface);
             controller.interface.lock().await.replace(interface);
 
             if let Err(e) = controller.refresh_bank().await {
                 error!("Failed to refresh bank on interface set: {}", e);
             }
+
+            if let Some(fader) = controller.pending_identify.take()
+                && let Err(e) = controller.identify(&fader).await
+            {
+                warn!("Failed to identify strip: {:?}", e);
+            }
+
+            controller.spawn_hydration();
         });
     }
 
@@ -834,13 +3904,47 @@ impl WriteProvider for Arc<Mutex<Controller>> {
         let controller = self.clone();
 
         tokio::task::spawn(async move {
-            let controller = controller.lock().await;
+            let mut controller = controller.lock().await;
 
             controller.send_meters(values).await;
         });
 
         Ok(())
     }
+
+    fn name(&self) -> String {
+        "X-Touch".to_string()
+    }
+
+    fn write_link_status(&self, status: LinkStatus) -> anyhow::Result<()> {
+        let controller = self.clone();
+
+        tokio::task::spawn(async move {
+            let message = match status {
+                LinkStatus::Online => None,
+                LinkStatus::Connecting => Some("CONN".to_string()),
+                LinkStatus::Offline => Some("OFFLINE".to_string()),
+            };
+
+            let mut controller = controller.lock().await;
+            controller.set_status_message(message).await;
+
+            match status {
+                LinkStatus::Offline => controller.link_lost = true,
+                LinkStatus::Online if controller.link_lost => {
+                    controller.link_lost = false;
+                    info!("Console link is back, re-hydrating surface");
+                    if let Err(e) = controller.refresh_bank().await {
+                        error!("Failed to refresh bank after reconnecting: {:?}", e);
+                    }
+                    controller.spawn_hydration();
+                }
+                _ => {}
+            }
+        });
+
+        Ok(())
+    }
 }
 
 fn midi_callback(_timestamp_us: u64, bytes: &[u8], input: &mut (Weak<Mutex<Controller>>, Handle)) {
@@ -862,18 +3966,60 @@ fn midi_callback(_timestamp_us: u64, bytes: &[u8], input: &mut (Weak<Mutex<Contr
 
     let mut controller_lock = controller.blocking_lock();
 
+    controller_lock.last_activity = Instant::now();
+    if let Some(metrics) = &controller_lock.metrics {
+        metrics.record_midi_message();
+    }
+
+    if controller_lock.asleep {
+        // Any input only wakes the surface up
+        let controller_for_spawn = controller.clone();
+        handle.spawn(async move {
+            if let Err(e) = controller_for_spawn.lock().await.wake().await {
+                error!("Failed to wake up surface: {}", e);
+            }
+        });
+        return;
+    }
+
     match event {
         Ok(LiveEvent::Midi { channel, message }) => {
             match message {
                 midly::MidiMessage::PitchBend { bend } => {
                     let fader_index = channel.as_int() as usize;
-                    let faders = &controller_lock
-                        .banks
-                        .get(controller_lock.current_bank)
-                        .expect("Current bank not found");
 
-                    if let Some(fader) = faders.get(fader_index) {
-                        let db_value = Fader::float_to_db((bend.as_f64() + 1.0) / 2.0) as f32;
+                    let is_strip = fader_index < STRIP_COUNT;
+                    let fader = if is_strip {
+                        controller_lock.visible_faders().get(fader_index).cloned()
+                    } else {
+                        controller_lock.fixed_faders.get(&fader_index).cloned()
+                    };
+
+                    if controller_lock.locked {
+                        // Move the fader back to where it was
+                        if let Some(fader) = &fader {
+                            let osc_addr = fader.get_osc_path(PathType::Fader);
+                            let interface = controller_lock.interface.clone();
+
+                            handle.spawn(async move {
+                                if let Some(iface) = interface.lock().await.as_ref() {
+                                    iface.request_value_notification(&osc_addr, false).await;
+                                }
+                            });
+                        }
+                        return;
+                    }
+
+                    if let Some(fader) = &fader {
+                        let db_value = fader.position_to_value((bend.as_f64() + 1.0) / 2.0) as f32;
+                        let console_db = controller_lock.untrim_level(fader_index, fader, db_value);
+
+                        let counts = bend.0.as_int();
+                        controller_lock
+                            .fader_positions
+                            .lock()
+                            .unwrap()
+                            .insert(fader_index, (db_value, counts));
 
                         let osc_addr = fader.get_osc_path(PathType::Fader);
                         let interface = controller_lock.interface.clone();
@@ -884,10 +4030,21 @@ fn midi_callback(_timestamp_us: u64, bytes: &[u8], input: &mut (Weak<Mutex<Contr
                                 .await
                                 .as_ref()
                                 .unwrap()
-                                .set_value(&osc_addr, Value::Float(db_value))
+                                .set_value(&osc_addr, Value::Float(console_db))
                                 .await;
                         });
 
+                        if is_strip {
+                            let controller_for_spawn = controller.clone();
+                            handle.spawn(async move {
+                                controller_for_spawn
+                                    .lock()
+                                    .await
+                                    .show_fader_value(fader_index, db_value)
+                                    .await;
+                            });
+                        }
+
                         // Emit the message back as midi so that the console doesn't complain
                         if let Err(e) = controller_lock.send_midi(bytes) {
                             warn!("Failed to echo MIDI message: {}", e);
@@ -899,35 +4056,129 @@ fn midi_callback(_timestamp_us: u64, bytes: &[u8], input: &mut (Weak<Mutex<Contr
                 midly::MidiMessage::NoteOn { key, vel } => {
                     let note = key.as_int() as u32;
 
-                    if vel.as_int() == 0 {
-                        // Button released
+                    let pressed = match vel.as_int() {
+                        0 => false,
+                        127 => true,
+                        _ => {
+                            warn!("I am not prepared to handle MIDI input velocities such as {} for note {}", vel.as_int(), key.as_int());
+                            return;
+                        }
+                    };
+
+                    if controller_lock.register_touch(note, pressed) {
+                        return;
+                    }
+
+                    if pressed {
+                        controller_lock.held_notes.insert(note);
+                    } else {
+                        controller_lock.held_notes.remove(&note);
+                    }
+
+                    if let Some(index) = controller_lock
+                        .main_assign_combo_strip(note)
+                        .filter(|_| pressed && !controller_lock.locked)
+                    {
+                        let controller_for_spawn = controller.clone();
+                        handle.spawn(async move {
+                            let mut controller = controller_for_spawn.lock().await;
+                            if let Err(e) = controller.toggle_main_assign(index).await {
+                                error!("Failed to toggle main assignment of strip {}: {:?}", index, e);
+                            }
+                        });
                         return;
-                    } else if vel.as_int() != 127 {
-                        warn!("I am not prepared to handle MIDI input velocities such as {} for note {}", vel.as_int(), key.as_int());
+                    }
+
+                    // Timestamps are recorded here, so that presses are tracked in order
+                    let action = controller_lock.register_button(note, pressed);
+
+                    if controller_lock.locked && !action.unlocks() {
+                        trace!(note, "Ignoring button while the surface is locked");
                         return;
                     }
 
-                    let maybe_function = controller_lock
-                        .buttons
-                        .get(&note)
-                        .map(|b| b.function.clone());
+                    let select_strip = controller_lock.select_button_strip(note);
+                    let fader_button = controller_lock.fader_button(note);
 
                     drop(controller_lock);
 
-                    if let Some(function) = maybe_function {
+                    if let ButtonAction::Nothing = action {
+                        if let Some(index) = select_strip {
+                            if pressed {
+                                let controller_for_spawn = controller.clone();
+                                handle.spawn(async move {
+                                    let mut controller = controller_for_spawn.lock().await;
+                                    if let Err(e) = controller.select_strip(index).await {
+                                        error!("Failed to select strip {}: {:?}", index, e);
+                                    }
+                                });
+                            }
+                        } else if let Some((index, path_type)) = fader_button.filter(|_| pressed) {
+                            let controller_for_spawn = controller.clone();
+                            handle.spawn(async move {
+                                let mut controller = controller_for_spawn.lock().await;
+                                let result = controller.toggle_strip_parameter(index, path_type).await;
+                                if let Err(e) = result {
+                                    error!("Failed to toggle strip {} parameter: {:?}", index, e);
+                                }
+                            });
+                        } else if pressed {
+                            debug!("Unassigned Note On for key {}", note);
+                        }
+                    } else {
                         let controller_for_spawn = controller.clone();
+                        handle.spawn(Controller::dispatch_button_action(
+                            controller_for_spawn,
+                            note,
+                            action,
+                        ));
+                    }
+                    return;
+                }
+                midly::MidiMessage::Controller { controller: _, value: _ } if controller_lock.locked => {
+                    trace!("Ignoring control change while the surface is locked");
+                }
+                midly::MidiMessage::Controller { controller: cc, value }
+                    if controller_lock.encoder_ccs.contains_key(&cc.as_int()) =>
+                {
+                    let index = controller_lock.encoder_ccs[&cc.as_int()];
+                    // Clicks to the right are 1-63, and to the left 65-127
+                    let clicks = match value.as_int() {
+                        v @ 1..=63 => v as i32,
+                        v @ 65..=127 => -(v as i32 - 64),
+                        _ => 0,
+                    };
+
+                    let controller_for_spawn = controller.clone();
+                    handle.spawn(async move {
+                        let mut controller = controller_for_spawn.lock().await;
+                        if let Err(e) = controller.process_encoder(index, clicks).await {
+                            error!("Failed to process encoder {}: {:?}", index, e);
+                        }
+                    });
+                }
+                midly::MidiMessage::Controller { controller: cc, value } => {
+                    let pedal_addr = controller_lock
+                        .expression_pedal
+                        .as_ref()
+                        .filter(|(pedal_cc, _)| *pedal_cc == cc.as_int())
+                        .map(|(_, fader)| {
+                            let position = value.as_int() as f64 / 127.0;
+                            (fader.get_osc_path(PathType::Fader), fader.position_to_value(position))
+                        });
+
+                    if let Some((osc_addr, db_value)) = pedal_addr {
+                        let db_value = db_value as f32;
+                        let interface = controller_lock.interface.clone();
+
                         handle.spawn(async move {
-                            if let Err(e) = controller_for_spawn.lock().await.do_function(function.clone()).await {
-                                error!(
-                                    "Failed to execute button function {:?}: {}",
-                                    function, e
-                                );
+                            if let Some(iface) = interface.lock().await.as_ref() {
+                                iface.set_value(&osc_addr, Value::Float(db_value)).await;
                             }
                         });
                     } else {
-                        debug!("Unassigned Note On for key {}", note);
+                        debug!("Unassigned Control Change {} = {}", cc.as_int(), value.as_int());
                     }
-                    return;
                 }
                 other => {
                     warn!("Unhandled MIDI message: {:?}", other);
@@ -942,3 +4193,97 @@ fn midi_callback(_timestamp_us: u64, bytes: &[u8], input: &mut (Weak<Mutex<Contr
         }
     }
 }
+
+/// Whether a strip has a parameter that is edited in focus mode
+fn focus_parameter_exists(parameter: &PathType, fader: &Fader) -> bool {
+    match parameter {
+        PathType::Gain => fader.has_gain(),
+        PathType::Panning => fader.has_panning(),
+        PathType::LowCut => fader.has_low_cut(),
+        _ => false,
+    }
+}
+
+/// The encoder ring position between 0 and 1 of a focus parameter value
+fn focus_position(parameter: &PathType, value: f32) -> f32 {
+    match parameter {
+        PathType::Gain => (value - PREAMP_MIN_DB) / (PREAMP_MAX_DB - PREAMP_MIN_DB),
+        PathType::Panning => (value - PAN_MIN) / (PAN_MAX - PAN_MIN),
+        PathType::LowCut => (value / LOW_CUT_MIN_HZ).ln() / (LOW_CUT_MAX_HZ / LOW_CUT_MIN_HZ).ln(),
+        _ => 0.0,
+    }
+}
+
+/// Split a name into the two rows of a scribble strip, breaking at a space if it fits
+fn split_scribble_text(text: &str) -> (String, String) {
+    if text.contains(' ') && text.chars().count() <= SCRIBBLE_ROW_LEN * 2 {
+        let mut parts = text.splitn(2, ' ');
+        (
+            parts.next().unwrap_or("").to_string(),
+            parts.next().unwrap_or("").to_string(),
+        )
+    } else {
+        let mut it = text.chars();
+        let a: String = it.by_ref().take(SCRIBBLE_ROW_LEN).collect();
+        let b: String = it.take(SCRIBBLE_ROW_LEN).collect();
+        (a, b)
+    }
+}
+
+/// The target of a MIDI message that later messages to the same target override, such as
+/// a button LED or a scribble strip row. Messages without one are never dropped.
+fn midi_target(message: &[u8]) -> Option<&[u8]> {
+    match message {
+        // Note On (LEDs) and Control Change (displays, encoder rings), by note/CC number
+        [0x90..=0x9F | 0xB0..=0xBF, _, ..] => Some(&message[..2]),
+        // Pitch Bend (faders), by channel
+        [0xE0..=0xEF, ..] => Some(&message[..1]),
+        // Scribble strip text, by display offset
+        [0xF0, 0x00, 0x00, 0x66, 0x14, 0x12, _, ..] => Some(&message[..7]),
+        // Scribble strip colours
+        [0xF0, 0x00, 0x00, 0x66, 0x14, 0x72, ..] => Some(&message[..6]),
+        _ => None,
+    }
+}
+
+/// Drop the messages of a batch that are overridden by a later message to the same target
+fn coalesce_midi(batch: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+    let mut last_index = HashMap::new();
+    for (index, message) in batch.iter().enumerate() {
+        if let Some(target) = midi_target(message) {
+            last_index.insert(target.to_vec(), index);
+        }
+    }
+
+    batch
+        .iter()
+        .enumerate()
+        .filter(|(index, message)| {
+            midi_target(message).is_none_or(|target| last_index.get(target) == Some(index))
+        })
+        .map(|(_, message)| message.clone())
+        .collect()
+}
+
+/// Format a fader level to fit in a scribble strip row
+fn format_db(db: f32) -> String {
+    if db <= -144.0 {
+        "-oo dB".to_string()
+    } else if db.abs() >= 100.0 {
+        format!("{:+.0}dB", db)
+    } else {
+        format!("{:+.1}dB", db)
+    }
+}
+
+/// The lowest frequency of the RTA bands shown on the meter of a strip, e.g. "640Hz"
+fn rta_group_label(index: usize) -> String {
+    let band = index * (RTA_BANDS / STRIP_COUNT);
+    let hz = RTA_LOWEST_HZ * 2f32.powf(band as f32 / 12.0);
+
+    if hz >= 1000.0 {
+        format!("{:.1}kHz", hz / 1000.0)
+    } else {
+        format!("{:.0}Hz", hz)
+    }
+}
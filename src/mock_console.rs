@@ -0,0 +1,159 @@
+//! An in-memory stand-in for the WING, so that the bridge can be run without a console on
+//! the network.
+//!
+//! Values that are set are stored and echoed back like the console does, values that were
+//! never set get a plausible default, and meters move on their own.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use tracing::{debug, info};
+
+use crate::console::{ConsoleTasks, wing_get_meter_count};
+use crate::orchestrator::{Interface, LinkStatus, Value};
+
+/// Name reported when the mock console is identified
+const MOCK_CONSOLE_NAME: &str = "Mock WING";
+/// How often fake meter values are sent
+const METER_INTERVAL: Duration = Duration::from_millis(50);
+/// Delay before answering, so that requests are not answered synchronously
+const RESPONSE_DELAY: Duration = Duration::from_millis(2);
+
+pub struct MockConsole {
+    values: Arc<Mutex<HashMap<String, Value>>>,
+    interface: Arc<Mutex<Option<Interface>>>,
+
+    meter_task_spawned: bool,
+    meters: Arc<Mutex<Vec<libwing::Meter>>>,
+    pub(crate) tasks: ConsoleTasks,
+}
+
+impl MockConsole {
+    pub fn new() -> Self {
+        info!("Using a mock console, no WING will be contacted");
+
+        Self {
+            values: Arc::new(Mutex::new(HashMap::new())),
+            interface: Arc::new(Mutex::new(None)),
+            meter_task_spawned: false,
+            meters: Arc::new(Mutex::new(vec![])),
+            tasks: ConsoleTasks::default(),
+        }
+    }
+
+    /// A plausible value for a parameter that was never set, based on its last path component
+    fn default_value(osc_addr: &str) -> Value {
+        let name = osc_addr.rsplit('/').next().unwrap_or_default();
+
+        match name {
+            "$cnscfg" => Value::Str(MOCK_CONSOLE_NAME.to_string()),
+            "name" | "grp" | "$name" => Value::Str(String::new()),
+            "mute" | "on" | "$link" | "vph" | "solo" | "$solo" => Value::Bool(false),
+            "in" | "col" | "selidx" => Value::Int(0),
+            _ => Value::Float(0.0),
+        }
+    }
+
+    /// Send a value to the orchestrator as if it came from the console
+    fn respond(&self, osc_addr: &str, value: Value) {
+        let interface = self.interface.clone();
+        let osc_addr = osc_addr.to_string();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(RESPONSE_DELAY).await;
+
+            if let Some(iface) = interface.lock().await.as_ref() {
+                iface.set_value(&osc_addr, value).await;
+            }
+        });
+    }
+
+    pub async fn request_value(&mut self, osc_addr: &str) -> Result<()> {
+        debug!(osc_addr, "Mock console value requested");
+
+        let value = self
+            .values
+            .lock()
+            .await
+            .entry(osc_addr.to_string())
+            .or_insert_with(|| Self::default_value(osc_addr))
+            .clone();
+        self.respond(osc_addr, value);
+
+        Ok(())
+    }
+
+    pub async fn set_value(&mut self, osc_addr: &str, value: Value) -> Result<()> {
+        debug!(osc_addr, ?value, "Mock console value set");
+
+        self.values.lock().await.insert(osc_addr.to_string(), value.clone());
+        self.respond(osc_addr, value);
+
+        Ok(())
+    }
+
+    pub async fn set_interface(&mut self, interface: Interface) {
+        self.interface.lock().await.replace(interface.clone());
+
+        tokio::spawn(async move {
+            interface.set_link_status(LinkStatus::Connecting).await;
+            tokio::time::sleep(RESPONSE_DELAY).await;
+            interface.set_link_status(LinkStatus::Online).await;
+        });
+    }
+
+    pub async fn set_meters(&mut self, meters: Vec<libwing::Meter>) -> Result<()> {
+        *self.meters.lock().await = meters;
+
+        if !self.meter_task_spawned {
+            self.spawn_meter_task();
+            self.meter_task_spawned = true;
+        }
+
+        Ok(())
+    }
+
+    /// Spawn a background task that sends slowly moving meter values for all subscribed
+    /// meters
+    fn spawn_meter_task(&self) {
+        let interface = self.interface.clone();
+        let meters = self.meters.clone();
+        let started = Instant::now();
+
+        self.tasks.spawn(async move {
+            let mut interval = tokio::time::interval(METER_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let time = started.elapsed().as_secs_f32();
+                let values: Vec<Vec<f32>> = meters
+                    .lock()
+                    .await
+                    .iter()
+                    .enumerate()
+                    .map(|(index, meter)| {
+                        (0..wing_get_meter_count(meter))
+                            .map(|value| {
+                                let phase = index as f32 * 0.7 + value as f32 * 0.3;
+                                0.55 + 0.35 * (time * 1.3 + phase).sin() * (time * 0.4 + phase).cos()
+                            })
+                            .collect()
+                    })
+                    .collect();
+
+                if values.is_empty() {
+                    continue;
+                }
+
+                if let Some(iface) = interface.lock().await.as_ref() {
+                    iface.set_meters(values).await;
+                }
+            }
+        });
+    }
+}
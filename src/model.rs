@@ -0,0 +1,117 @@
+//! Console models and firmware versions, and the features that differ between them
+//!
+//! Banks can name strips that a console does not have, e.g. a bank made for a WING used
+//! with an X32. Such strips are left out of the banks once the console is identified.
+
+use std::fmt;
+
+use crate::discovery::DiscoveredConsole;
+
+/// The oldest firmware that the WING parameter paths have been checked against
+const TESTED_FIRMWARE: (u32, u32) = (3, 0);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsoleModel {
+    Wing,
+    WingRack,
+    WingCompact,
+    X32,
+}
+
+impl ConsoleModel {
+    /// The model from the model field of the console's identify response
+    pub fn from_identity(model: &str) -> Self {
+        let model = model.to_lowercase();
+
+        if model.contains("rack") {
+            Self::WingRack
+        } else if model.contains("compact") {
+            Self::WingCompact
+        } else {
+            Self::Wing
+        }
+    }
+
+    /// How many strips of each type the console has, in the order of the strip selection
+    pub fn strip_counts(&self) -> [(&'static str, i32); 6] {
+        match self {
+            // All WING models share the same mixing engine
+            Self::Wing | Self::WingRack | Self::WingCompact => [
+                ("Channel", 40),
+                ("Aux", 8),
+                ("Bus", 16),
+                ("Main", 4),
+                ("Matrix", 8),
+                ("DCA", 16),
+            ],
+            // The X32 main LR and mono buses are mapped to Main 1 and 2
+            Self::X32 => [
+                ("Channel", 32),
+                ("Aux", 8),
+                ("Bus", 16),
+                ("Main", 2),
+                ("Matrix", 6),
+                ("DCA", 8),
+            ],
+        }
+    }
+
+    /// How many strips of a type the console has
+    pub fn strip_count(&self, kind: &str) -> i32 {
+        self.strip_counts()
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map_or(0, |(_, count)| *count)
+    }
+
+    /// Whether the console has the WING parameter tree and meter IDs
+    pub fn is_wing(&self) -> bool {
+        *self != Self::X32
+    }
+}
+
+impl fmt::Display for ConsoleModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Wing => "WING",
+            Self::WingRack => "WING Rack",
+            Self::WingCompact => "WING Compact",
+            Self::X32 => "X32",
+        })
+    }
+}
+
+/// What is known about a connected console
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleInfo {
+    pub model: ConsoleModel,
+    pub firmware: Option<String>,
+}
+
+impl ConsoleInfo {
+    pub fn new(model: ConsoleModel) -> Self {
+        Self { model, firmware: None }
+    }
+
+    pub fn from_discovery(console: &DiscoveredConsole) -> Self {
+        Self {
+            model: ConsoleModel::from_identity(&console.model),
+            firmware: Some(console.firmware.clone()).filter(|f| !f.is_empty()),
+        }
+    }
+
+    /// The major and minor firmware version, if known
+    pub fn firmware_version(&self) -> Option<(u32, u32)> {
+        let mut parts = self.firmware.as_deref()?.trim_start_matches(['v', 'V']).split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+
+        Some((major, minor))
+    }
+
+    /// Whether the firmware is known to be older than the one the WING paths were checked
+    /// against, so that some parameters may not exist
+    pub fn firmware_untested(&self) -> bool {
+        self.model.is_wing() && self.firmware_version().is_some_and(|v| v < TESTED_FIRMWARE)
+    }
+}
@@ -1,103 +1,764 @@
+//! Publishes the parameters of the console to an MQTT broker, and writes the values that
+//! clients publish back to the console
+//!
+//! Values are published retained to `<prefix>/state/<OSC path>`, e.g.
+//! `xtouchwing/state/ch/1/fdr`, and a value published to `<prefix>/set/<OSC path>` is written
+//! to that parameter. Payloads are the JSON values, e.g. `-10.5`, `true` or `"Vocals"`, or
+//! plain text such as `on`, which is converted to the type of the parameter. Commands that
+//! fail are answered on `<prefix>/status/set`. Parameters can have custom topics instead, see
+//! [`crate::mqtt_topics`].
+//!
+//! Bigger actions are published to `<prefix>/action` as the label of a button function, e.g.
+//! `Scene 3`, `Macro Intro`, `Cue Go`, `Next Bank`, `Lock Surface` or `Vegas`. They run on the
+//! first surface, or on another one with its number in the topic, e.g. `xtouchwing/action/2`.
+//! `Identify <fader>`, e.g. `Identify Channel 5`, flashes the strip of a fader on the surfaces
+//! that have it, like the `--identify` option. Actions that fail are answered on
+//! `<prefix>/status/action`.
+//!
+//! A status document is published retained to `<prefix>/status` periodically, with the
+//! connection to the console, the state of the surfaces, the size of the parameter cache and
+//! the number of errors, so that the bridge can be monitored remotely.
+//!
+//! With Home Assistant discovery, the level, mute and name of every strip of the banks are
+//! announced as entities of one device. They are announced again whenever Home Assistant
+//! starts, and are unavailable while `<prefix>/availability` is `offline`, which the broker
+//! publishes when the bridge disconnects.
+//!
+//! The meters of selected strips are published to `<prefix>/meter/<OSC node>`, e.g.
+//! `xtouchwing/meter/ch/1`, as the peak level in dB since the last time, at the configured
+//! interval instead of the rate of the console.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
-use tracing::{debug, error};
-use rumqttc::{AsyncClient, MqttOptions};
-use tokio::{task, time};
+use anyhow::{Context, Result, bail};
+use rumqttc::{
+    AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, Publish, QoS, Transport,
+};
+use serde_json::json;
+use tokio::sync::Mutex;
+use tokio::time;
+use tracing::{debug, info, warn};
+
+use crate::data::{Fader, InternalButton, PathType};
+use crate::midi::{Controller, SurfaceStatus};
+use crate::mqtt_topics::TopicMapping;
+use crate::orchestrator::{Interface, LinkStatus, Value, WriteProvider};
+use crate::settings::{MqttFaderUnit, MqttSettings, MqttTlsSettings, Settings};
+
+type SharedInterface = Arc<std::sync::Mutex<Option<Interface>>>;
+type Surfaces = Arc<Vec<Arc<Mutex<Controller>>>>;
+
+const KEEP_ALIVE: Duration = Duration::from_secs(5);
+/// Requests to the broker that can be queued before publishing fails
+const QUEUE_SIZE: usize = 1000;
+/// Largest message, enough for the discovery of all strips of a console
+const MAX_PACKET_SIZE: usize = 1024 * 1024;
+/// How long to wait before connecting again after the connection failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
 
-pub struct Mqtt {}
+/// Topic prefix of Home Assistant's MQTT discovery
+const DISCOVERY_PREFIX: &str = "homeassistant";
+/// Payloads of the availability topic
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+/// Range of the fader levels in dB
+const LEVEL_MIN_DB: f32 = -144.0;
+const LEVEL_MAX_DB: f32 = 10.0;
+/// Range of the meter levels, which the console sends from 0 to 1
+const METER_RANGE_DB: f32 = 128.0;
+
+pub struct Mqtt {
+    client: AsyncClient,
+    prefix: String,
+    paths: Vec<String>,
+    /// The parameters of the entities announced to Home Assistant
+    announced_paths: Vec<String>,
+    fader_unit: MqttFaderUnit,
+    /// Custom topics of parameters
+    topics: Arc<Vec<TopicMapping>>,
+    /// The meters that are published, and their peak levels since they were last published
+    meters: Vec<libwing::Meter>,
+    meter_peaks: Arc<std::sync::Mutex<Vec<Option<f32>>>>,
+    /// The last state of the connection to the console, for the status document
+    link_status: Arc<std::sync::Mutex<LinkStatus>>,
+    interface: SharedInterface,
+}
 
 impl Mqtt {
-    pub async fn new(remote_host: &str, remote_port: u16) -> anyhow::Result<Self> {
-        let mut mqttoptions = MqttOptions::new("xtouch-wing-client", remote_host, remote_port);
-        mqttoptions.set_keep_alive(Duration::from_secs(5));
-
-        let (mut client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
-
-        task::spawn(async move {
-            let payload = r#"{
-                "dev": {
-                    "ids": "xtouch_wing_001",
-                    "name": "XTouch Wing",
-                    "mf": "kongr45gpen",
-                    "mdl": "X-Touch Wing",
-                    "sw": "1.0"
-                },
-                 "origin": {
-                    "name":"xtouch-wing",
-                    "sw": "1.0",
-                    "url": "https://github.com/kongr45gpen/xtouch-wing"
-                },
-                "cmps": {
-                    "main_volume": {
-                        "p": "number",
-                        "device_class": "sound_pressure",
-                        "unit_of_measurement": "%",
-                        "min": 0,
-                        "max": 100,
-                        "unique_id": "xtw01_main_vol",
-                        "name": "Volume",
-                        "icon": "mdi:volume-high",
-                        "value_template": "{{ value_json.main_volume }}"
-                    }
-                },
-                "command_topic": "xtouchwing/command",
-                "state_topic": "xtouchwing/state",
-                "qos": 2
-            }"#;
-
-            let result = client
-                .publish(
-                    "homeassistant/device/xtouchwing/config",
-                    rumqttc::QoS::AtLeastOnce,
-                    true,
-                    payload,
-                )
-                .await;
-
-            if let Err(e) = result {
-                error!("Failed to publish MQTT config: {:?}", e);
+    /// Create the client, which connects to the broker in the background and keeps
+    /// reconnecting if the connection fails. The strips are announced to Home Assistant if
+    /// discovery is enabled, and actions run on the surfaces.
+    pub fn new(
+        settings: &MqttSettings,
+        strips: Vec<(String, Fader)>,
+        surfaces: Vec<Arc<Mutex<Controller>>>,
+    ) -> Result<Self> {
+        let mut options = MqttOptions::new(
+            settings.client_id.as_str(),
+            settings.host.as_str(),
+            settings.port,
+        );
+        options.set_keep_alive(KEEP_ALIVE);
+        options.set_max_packet_size(MAX_PACKET_SIZE, MAX_PACKET_SIZE);
+        options.set_last_will(LastWill::new(
+            availability_topic(&settings.topic_prefix),
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+
+        match (&settings.username, &settings.password) {
+            (Some(username), password) => {
+                options.set_credentials(username, password.as_deref().unwrap_or_default());
+            }
+            (None, Some(_)) => anyhow::bail!("An MQTT password needs a username"),
+            (None, None) => {}
+        }
+        if let Some(tls) = &settings.tls {
+            options.set_transport(tls_transport(tls)?);
+        }
+
+        let topics = settings
+            .topics
+            .iter()
+            .map(TopicMapping::new)
+            .collect::<Result<Vec<_>>>()
+            .context("Invalid MQTT topic mapping")?;
+        let topics = Arc::new(topics);
+
+        let mut meters = Vec::new();
+        let mut meter_topics = Vec::new();
+        for label in &settings.meters {
+            let fader = Fader::new_from_label(label)
+                .with_context(|| format!("Invalid MQTT meter label {}", label))?;
+            let Some(meter) = fader.get_meter() else {
+                anyhow::bail!("{} has no meter to publish to MQTT", label);
+            };
+            meters.push(meter.clone());
+            meter_topics.push(format!(
+                "{}/meter{}",
+                settings.topic_prefix,
+                fader.osc_directory()
+            ));
+        }
+        let meter_peaks = Arc::new(std::sync::Mutex::new(vec![None; meters.len()]));
+        let link_status = Arc::new(std::sync::Mutex::new(LinkStatus::Connecting));
+        let surfaces = Arc::new(surfaces);
+        let failed_commands = Arc::new(AtomicU64::new(0));
+
+        let (client, eventloop) = AsyncClient::new(options, QUEUE_SIZE);
+        let interface: SharedInterface = Arc::new(std::sync::Mutex::new(None));
+
+        let discovery = settings
+            .home_assistant
+            .then(|| discovery_payload(settings, &strips));
+        let announced_paths = match settings.home_assistant {
+            true => strips
+                .iter()
+                .flat_map(|(_, fader)| {
+                    [PathType::Fader, PathType::Mute, PathType::ScribbleName]
+                        .map(|path_type| fader.get_osc_path(path_type))
+                })
+                .collect(),
+            false => Vec::new(),
+        };
+
+        tokio::spawn(run_eventloop(
+            eventloop,
+            client.clone(),
+            settings.topic_prefix.clone(),
+            settings.fader_unit,
+            discovery,
+            topics.clone(),
+            surfaces.clone(),
+            failed_commands.clone(),
+            interface.clone(),
+        ));
+        if settings.status_interval_secs > 0 {
+            tokio::spawn(publish_status(
+                client.clone(),
+                format!("{}/status", settings.topic_prefix),
+                Duration::from_secs(settings.status_interval_secs),
+                link_status.clone(),
+                surfaces,
+                failed_commands,
+                interface.clone(),
+            ));
+        }
+        if !meters.is_empty() {
+            tokio::spawn(publish_meters(
+                client.clone(),
+                meter_topics,
+                meter_peaks.clone(),
+                Duration::from_millis(settings.meter_interval_ms.max(1)),
+            ));
+        }
+
+        info!(
+            host = settings.host,
+            port = settings.port,
+            tls = settings.tls.is_some(),
+            "MQTT client started"
+        );
+
+        Ok(Self {
+            client,
+            prefix: settings.topic_prefix.clone(),
+            paths: settings.paths.clone(),
+            announced_paths,
+            fader_unit: settings.fader_unit,
+            topics,
+            meters,
+            meter_peaks,
+            link_status,
+            interface,
+        })
+    }
+}
+
+/// The TLS connection with the certificates of the settings
+fn tls_transport(tls: &MqttTlsSettings) -> Result<Transport> {
+    let read = |file: &str| {
+        std::fs::read(file).with_context(|| format!("Failed to read MQTT certificate {}", file))
+    };
+
+    let client_auth = match (&tls.cert_file, &tls.key_file) {
+        (Some(cert), Some(key)) => Some((read(cert)?, read(key)?)),
+        (None, None) => None,
+        _ => anyhow::bail!("An MQTT client certificate needs both a cert_file and a key_file"),
+    };
+
+    match &tls.ca_file {
+        Some(ca) => Ok(Transport::tls(read(ca)?, client_auth, None)),
+        None if client_auth.is_some() => {
+            anyhow::bail!("An MQTT client certificate needs the ca_file of the broker")
+        }
+        None => Ok(Transport::tls_with_default_config()),
+    }
+}
+
+impl WriteProvider for Mqtt {
+    fn write(&self, addr: &str, value: Value) -> anyhow::Result<()> {
+        let value = level_to_mqtt(self.fader_unit, addr, value);
+        let mapped = self
+            .topics
+            .iter()
+            .find_map(|mapping| mapping.state(addr, &value).transpose());
+        let (topic, payload) = match mapped {
+            Some(state) => state?,
+            None => (
+                format!("{}/state{}", self.prefix, addr),
+                serde_json::to_string(&value)?,
+            ),
+        };
+        self.client
+            .try_publish(topic, QoS::AtLeastOnce, true, payload)?;
+
+        Ok(())
+    }
+
+    fn write_meter_values(&self, values: Vec<Vec<f32>>) -> anyhow::Result<()> {
+        let mut peaks = self.meter_peaks.lock().unwrap();
+        for (peak, meter) in peaks.iter_mut().zip(values) {
+            let Some(level) = meter.first() else {
+                continue;
+            };
+            let db = (level - 1.0) * METER_RANGE_DB;
+            *peak = Some(peak.map_or(db, |peak| peak.max(db)));
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        "MQTT".to_string()
+    }
+
+    fn write_link_status(&self, status: LinkStatus) -> anyhow::Result<()> {
+        *self.link_status.lock().unwrap() = status;
+        Ok(())
+    }
+
+    fn set_interface(&self, interface: Interface) {
+        let paths = self.paths.clone();
+        let announced_paths = self.announced_paths.clone();
+        let meters = self.meters.clone();
+        let topic_paths: Vec<String> = self.topics.iter().map(TopicMapping::path_pattern).collect();
+
+        self.interface.lock().unwrap().replace(interface.clone());
+
+        tokio::spawn(async move {
+            if !meters.is_empty()
+                && let Err(e) = interface.subscribe_to_meters(meters).await
+            {
+                warn!("Failed to subscribe to meters for MQTT: {:?}", e);
             }
 
-            let result = client
-                .publish(
-                    "xtouchwing/state",
-                    rumqttc::QoS::AtLeastOnce,
-                    false,
-                    r#"{ "main_volume": 50 }"#,
-                )
-                .await;
-
-            if let Err(e) = result {
-                error!("Failed to publish MQTT config: {:?}", e);
+            if !paths.is_empty() {
+                let mut patterns = paths.clone();
+                patterns.extend(announced_paths.iter().cloned());
+                // Custom topics are published whichever paths are set
+                patterns.extend(topic_paths.iter().cloned());
+                interface.subscribe(&patterns).await;
             }
 
-            let result = client
-                .subscribe("xtouchwing/command", rumqttc::QoS::ExactlyOnce)
-                .await;
+            // Parameters without wildcards are published right away, the others once they
+            // change
+            let exact_paths = paths
+                .iter()
+                .chain(&topic_paths)
+                .filter(|path| !path.contains('*'));
+            for path in exact_paths.chain(&announced_paths) {
+                interface.request_value_notification(path, false).await;
+            }
+        });
+    }
+}
+
+/// The mixer strips on the banks and fixed faders of all controllers, with their labels.
+/// Strips that are routed to other consoles are left out.
+pub fn configured_strips(config: &Settings) -> Vec<(String, Fader)> {
+    let banks = config
+        .midi
+        .iter()
+        .flat_map(|midi| midi.assignments.banks.iter())
+        .filter(|bank| bank.console.is_none())
+        .flat_map(|bank| bank.faders.iter());
+    let fixed = config
+        .midi
+        .iter()
+        .flat_map(|midi| midi.assignments.fixed_faders.values());
+
+    let mut seen = HashSet::new();
+    banks
+        .chain(fixed)
+        .filter_map(|label| Some((label.clone(), Fader::new_from_label(label).ok()?)))
+        // Only mixer strips have a name and a mute
+        .filter(|(_, fader)| fader.label().is_none() && fader.console_id().is_none())
+        .filter(|(_, fader)| seen.insert(fader.get_osc_path(PathType::Fader)))
+        .collect()
+}
+
+/// The Home Assistant device with a level, mute and name entity for each strip
+fn discovery_payload(settings: &MqttSettings, strips: &[(String, Fader)]) -> String {
+    let prefix = &settings.topic_prefix;
+    let (min, max, step, unit) = match settings.fader_unit {
+        MqttFaderUnit::Db => (LEVEL_MIN_DB, LEVEL_MAX_DB, 0.1, "dB"),
+        MqttFaderUnit::Percent => (0.0, 100.0, 1.0, "%"),
+    };
+
+    let mut components = serde_json::Map::new();
+    for (label, fader) in strips {
+        let level = fader.get_osc_path(PathType::Fader);
+        let mute = fader.get_osc_path(PathType::Mute);
+        let name = fader.get_osc_path(PathType::ScribbleName);
+
+        components.insert(
+            object_id(&level),
+            json!({
+                "p": "number",
+                "name": format!("{} level", label),
+                "unique_id": format!("{}_{}", prefix, object_id(&level)),
+                "state_topic": format!("{}/state{}", prefix, level),
+                "command_topic": format!("{}/set{}", prefix, level),
+                "min": min,
+                "max": max,
+                "step": step,
+                "unit_of_measurement": unit,
+                "mode": "slider",
+                "icon": "mdi:volume-high",
+            }),
+        );
+        components.insert(
+            object_id(&mute),
+            json!({
+                "p": "switch",
+                "name": format!("{} mute", label),
+                "unique_id": format!("{}_{}", prefix, object_id(&mute)),
+                "state_topic": format!("{}/state{}", prefix, mute),
+                "command_topic": format!("{}/set{}", prefix, mute),
+                // Consoles send mutes as booleans or integers
+                "value_template": "{{ value_json | int }}",
+                "state_on": "1",
+                "state_off": "0",
+                "payload_on": "true",
+                "payload_off": "false",
+                "icon": "mdi:volume-off",
+            }),
+        );
+        components.insert(
+            object_id(&name),
+            json!({
+                "p": "sensor",
+                "name": format!("{} name", label),
+                "unique_id": format!("{}_{}", prefix, object_id(&name)),
+                "state_topic": format!("{}/state{}", prefix, name),
+                "value_template": "{{ value_json }}",
+                "icon": "mdi:label",
+            }),
+        );
+    }
+
+    json!({
+        "dev": {
+            "ids": prefix,
+            "name": "X-Touch Wing",
+            "mf": "kongr45gpen",
+            "mdl": "X-Touch Wing",
+            "sw": env!("CARGO_PKG_VERSION"),
+        },
+        "o": {
+            "name": "xtouch-wing",
+            "sw": env!("CARGO_PKG_VERSION"),
+            "url": "https://github.com/kongr45gpen/xtouch-wing",
+        },
+        "cmps": components,
+        "avty_t": availability_topic(prefix),
+        "qos": 1,
+    })
+    .to_string()
+}
+
+/// The topic that shows whether the bridge is connected to the broker
+fn availability_topic(prefix: &str) -> String {
+    format!("{}/availability", prefix)
+}
+
+/// Announce the entities to Home Assistant
+fn publish_discovery(client: &AsyncClient, prefix: &str, discovery: &str) {
+    let topic = format!("{}/device/{}/config", DISCOVERY_PREFIX, prefix);
+    if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, discovery) {
+        warn!("Failed to publish Home Assistant discovery: {:?}", e);
+    }
+}
+
+/// An identifier of a parameter for Home Assistant, e.g. `ch_1_fdr` for `/ch/1/fdr`
+fn object_id(osc_path: &str) -> String {
+    osc_path
+        .trim_start_matches('/')
+        .replace('/', "_")
+        .replace('$', "")
+}
+
+/// Convert a fader level from the console to the unit used on MQTT
+fn level_to_mqtt(unit: MqttFaderUnit, osc_path: &str, value: Value) -> Value {
+    match (unit, value) {
+        (MqttFaderUnit::Percent, Value::Float(db)) if osc_path.ends_with("/fdr") => {
+            let percent = Fader::db_to_float(db as f64) * 100.0;
+            Value::Float((percent * 10.0).round() as f32 / 10.0)
+        }
+        (_, value) => value,
+    }
+}
+
+/// Convert a fader level from the unit used on MQTT to the console
+fn level_from_mqtt(unit: MqttFaderUnit, osc_path: &str, value: Value) -> Value {
+    let percent = match (unit, &value) {
+        (MqttFaderUnit::Percent, Value::Float(percent)) => *percent as f64,
+        (MqttFaderUnit::Percent, Value::Int(percent)) => *percent as f64,
+        _ => return value,
+    };
+    if !osc_path.ends_with("/fdr") {
+        return value;
+    }
+
+    let db = Fader::float_to_db((percent / 100.0).clamp(0.0, 1.0)) as f32;
+    Value::Float(db.max(LEVEL_MIN_DB))
+}
+
+/// Publish the peak levels of the meters periodically, skipping meters without new levels,
+/// e.g. while the console is disconnected
+async fn publish_meters(
+    client: AsyncClient,
+    topics: Vec<String>,
+    peaks: Arc<std::sync::Mutex<Vec<Option<f32>>>>,
+    interval: Duration,
+) {
+    let mut interval = time::interval(interval);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
 
-            if let Err(e) = result {
-                error!("Failed to subscribe to MQTT command topic: {:?}", e);
+    loop {
+        interval.tick().await;
+
+        let peaks = std::mem::replace(&mut *peaks.lock().unwrap(), vec![None; topics.len()]);
+        for (topic, peak) in topics.iter().zip(peaks) {
+            let Some(db) = peak else {
+                continue;
+            };
+            let db = (db.clamp(-METER_RANGE_DB, 0.0) * 10.0).round() / 10.0;
+            // Old levels are of no use, so they are neither retained nor repeated
+            if let Err(e) = client.try_publish(topic, QoS::AtMostOnce, false, db.to_string()) {
+                debug!(topic, "Failed to publish meter: {:?}", e);
             }
+        }
+    }
+}
+
+/// Publish the status of the bridge periodically
+async fn publish_status(
+    client: AsyncClient,
+    topic: String,
+    interval: Duration,
+    link_status: Arc<std::sync::Mutex<LinkStatus>>,
+    surfaces: Surfaces,
+    failed_commands: Arc<AtomicU64>,
+    interface: SharedInterface,
+) {
+    let mut interval = time::interval(interval);
+
+    loop {
+        interval.tick().await;
+
+        let console = match *link_status.lock().unwrap() {
+            LinkStatus::Online => "online",
+            LinkStatus::Connecting => "connecting",
+            LinkStatus::Offline => "offline",
+        };
+        let mut surface_status: Vec<SurfaceStatus> = Vec::new();
+        for surface in surfaces.iter() {
+            surface_status.push(surface.lock().await.status());
+        }
+        // The metrics are only known once the orchestrator is running
+        let metrics = interface
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|interface| interface.metrics().snapshot());
+
+        let status = json!({
+            "console": console,
+            "round_trip_ms": metrics
+                .as_ref()
+                .and_then(|metrics| metrics.round_trip)
+                .map(|round_trip| round_trip.as_secs_f64() * 1000.0),
+            "surfaces": surface_status,
+            "uptime_secs": metrics.as_ref().map(|metrics| metrics.uptime.as_secs()),
+            "cache_size": metrics.as_ref().map(|metrics| metrics.cache_size),
+            "errors": {
+                "console_timeouts": metrics.as_ref().map(|metrics| metrics.timeouts),
+                "mqtt_commands": failed_commands.load(Ordering::Relaxed),
+            },
+        });
+        if let Err(e) = client.try_publish(&topic, QoS::AtLeastOnce, true, status.to_string()) {
+            warn!("Failed to publish MQTT status: {:?}", e);
+        }
+    }
+}
+
+/// Execute the button function of an action on a surface, the first one unless the topic has
+/// the number of another
+async fn run_action(surfaces: &Surfaces, surface: Option<&str>, payload: &[u8]) -> Result<()> {
+    let label = std::str::from_utf8(payload)
+        .context("Payload is not text")?
+        .trim();
+    if let Some(fader) = identify_fader(label) {
+        return identify(surfaces, surface, fader).await;
+    }
+    let button = InternalButton::new_from_label(label)?;
+
+    let index = surface_index(surface)?;
+    let surface = surfaces
+        .get(index)
+        .with_context(|| format!("There is no surface {}", index + 1))?;
+
+    info!(label, surface = index + 1, "MQTT action received");
+    surface.lock().await.trigger_function(button.function).await
+}
+
+/// The fader label of an identify action
+fn identify_fader(label: &str) -> Option<&str> {
+    let (action, fader) = label.split_once(char::is_whitespace)?;
+    action
+        .eq_ignore_ascii_case("identify")
+        .then(|| fader.trim())
+}
+
+/// Flash the strip of a fader on a surface, or on all surfaces that have it
+async fn identify(surfaces: &Surfaces, surface: Option<&str>, label: &str) -> Result<()> {
+    let fader = Fader::new_from_label(label)
+        .with_context(|| format!("Invalid fader to identify: {}", label))?;
+
+    if surface.is_some() {
+        let index = surface_index(surface)?;
+        let surface = surfaces
+            .get(index)
+            .with_context(|| format!("There is no surface {}", index + 1))?;
+
+        info!(label, surface = index + 1, "MQTT identify received");
+        return surface.lock().await.identify(&fader).await;
+    }
+
+    info!(label, "MQTT identify received");
+    let mut found = false;
+    for surface in surfaces.iter() {
+        match surface.lock().await.identify(&fader).await {
+            Ok(()) => found = true,
+            Err(e) => debug!("Not identifying on controller: {:?}", e),
+        }
+    }
+    if !found {
+        bail!("{} is not on any surface", label);
+    }
+
+    Ok(())
+}
+
+/// The index of the surface with the number of an action topic, the first one without
+fn surface_index(surface: Option<&str>) -> Result<usize> {
+    match surface {
+        Some(number) => number
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| number.checked_sub(1))
+            .with_context(|| format!("Invalid surface number {}", number)),
+        None => Ok(0),
+    }
+}
 
-            loop {
-                debug!("MQTT in your loop");
-                while let Ok(notification) = eventloop.poll().await {
-                    println!("Received = {:?} = {:?}", 1, notification);
+/// Answer a command or action that failed with the error
+fn publish_error(
+    client: &AsyncClient,
+    reply_topic: &str,
+    publish: &Publish,
+    error: &anyhow::Error,
+) {
+    let reply = json!({
+        "topic": publish.topic,
+        "payload": String::from_utf8_lossy(&publish.payload),
+        "error": format!("{:#}", error),
+    });
+    if let Err(e) = client.try_publish(reply_topic, QoS::AtLeastOnce, false, reply.to_string()) {
+        warn!("Failed to publish MQTT error: {:?}", e);
+    }
+}
 
-                    if let rumqttc::Event::Incoming(incoming) = notification {
-                        debug!("Received MQTT message on topic '{}': {:?}", 1, incoming);
+/// Write the value of a command to its parameter. The value is JSON, or else plain text.
+async fn run_command(
+    interface: &SharedInterface,
+    fader_unit: MqttFaderUnit,
+    command: Result<(String, String)>,
+) -> Result<()> {
+    let (path, text) = command?;
+    let value = serde_json::from_str(&text).unwrap_or_else(|_| Value::Str(text.trim().to_string()));
+    let value = level_from_mqtt(fader_unit, &path, value);
 
-                        if let rumqttc::Packet::Publish(publish) = incoming {
-                            let topic = publish.topic;
-                            let payload = String::from_utf8_lossy(&publish.payload);
+    let interface = interface
+        .lock()
+        .unwrap()
+        .clone()
+        .context("Not connected to the console yet")?;
 
-                            debug!("MQTT Publish received on topic '{}': {}", topic, payload);
-                        }
+    debug!(path, ?value, "MQTT command received");
+    // Sent back to MQTT too, so that the state topic follows
+    interface.set_value_checked(&path, value).await
+}
+
+/// Handle the connection to the broker and the messages that it sends
+async fn run_eventloop(
+    mut eventloop: EventLoop,
+    client: AsyncClient,
+    prefix: String,
+    fader_unit: MqttFaderUnit,
+    discovery: Option<String>,
+    topics: Arc<Vec<TopicMapping>>,
+    surfaces: Surfaces,
+    failed_commands: Arc<AtomicU64>,
+    interface: SharedInterface,
+) {
+    let set_prefix = format!("{}/set", prefix);
+    let reply_topic = format!("{}/status/set", prefix);
+    let action_topic = format!("{}/action", prefix);
+    let surface_action_prefix = format!("{}/", action_topic);
+    let action_reply_topic = format!("{}/status/action", prefix);
+    // Home Assistant publishes its birth message here when it starts
+    let home_assistant_status = format!("{}/status", DISCOVERY_PREFIX);
+
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("Connected to MQTT broker");
+
+                // The subscription is gone if the broker did not keep the session. Waiting for
+                // the request to be queued would block the event loop that sends it.
+                if let Err(e) = client.try_subscribe(format!("{}/#", set_prefix), QoS::AtLeastOnce)
+                {
+                    warn!("Failed to subscribe to MQTT command topics: {:?}", e);
+                }
+                for filter in [action_topic.clone(), format!("{}+", surface_action_prefix)] {
+                    if let Err(e) = client.try_subscribe(filter, QoS::AtLeastOnce) {
+                        warn!("Failed to subscribe to MQTT action topics: {:?}", e);
+                    }
+                }
+                for filter in topics.iter().filter_map(TopicMapping::command_filter) {
+                    if let Err(e) = client.try_subscribe(filter, QoS::AtLeastOnce) {
+                        warn!(filter, "Failed to subscribe to MQTT command topic: {:?}", e);
                     }
                 }
+
+                let online =
+                    client.try_publish(availability_topic(&prefix), QoS::AtLeastOnce, true, ONLINE);
+                if let Err(e) = online {
+                    warn!("Failed to publish MQTT availability: {:?}", e);
+                }
+
+                if let Some(discovery) = &discovery {
+                    if let Err(e) = client.try_subscribe(&home_assistant_status, QoS::AtLeastOnce) {
+                        warn!("Failed to subscribe to Home Assistant status: {:?}", e);
+                    }
+                    publish_discovery(&client, &prefix, discovery);
+                }
             }
-        });
+            Ok(Event::Incoming(Packet::Publish(publish)))
+                if publish.topic == home_assistant_status =>
+            {
+                if let Some(discovery) = &discovery
+                    && publish.payload.as_ref() == ONLINE.as_bytes()
+                {
+                    info!("Home Assistant started, announcing the strips again");
+                    publish_discovery(&client, &prefix, discovery);
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish)))
+                if publish.topic == action_topic
+                    || publish.topic.starts_with(&surface_action_prefix) =>
+            {
+                let surface = publish.topic.strip_prefix(&surface_action_prefix);
+
+                if let Err(e) = run_action(&surfaces, surface, &publish.payload).await {
+                    warn!(topic = publish.topic, "MQTT action failed: {:#}", e);
+                    failed_commands.fetch_add(1, Ordering::Relaxed);
+                    publish_error(&client, &action_reply_topic, &publish, &e);
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let command = match publish.topic.strip_prefix(&set_prefix) {
+                    Some(path) => Some(
+                        std::str::from_utf8(&publish.payload)
+                            .context("Payload is not text")
+                            .map(|text| (path.to_string(), text.to_string())),
+                    ),
+                    None => topics
+                        .iter()
+                        .find_map(|mapping| mapping.command(&publish.topic, &publish.payload)),
+                };
+                let Some(command) = command else {
+                    continue;
+                };
 
-        Ok(Self {})
+                if let Err(e) = run_command(&interface, fader_unit, command).await {
+                    warn!(topic = publish.topic, "MQTT command failed: {:#}", e);
+                    failed_commands.fetch_add(1, Ordering::Relaxed);
+                    publish_error(&client, &reply_topic, &publish, &e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("MQTT connection failed: {}", e);
+                time::sleep(RECONNECT_DELAY).await;
+            }
+        }
     }
 }
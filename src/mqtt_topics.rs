@@ -0,0 +1,220 @@
+//! Custom MQTT topics and payloads of parameters, to fit existing automations instead of the
+//! `<prefix>/state/<OSC path>` and `<prefix>/set/<OSC path>` topics
+//!
+//! Each `*` segment of the path pattern of a mapping is available as `{1}`, `{2}` and so on
+//! in its topics, and `{value}` in the payload is the JSON value. For example, the mapping of
+//! `/ch/*/fdr` to `studio/channel/{1}/level` with the payload `{"level": {value}}` publishes
+//! `{"level": -10.5}` to `studio/channel/3/level` when the level of channel 3 changes.
+
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+
+use crate::orchestrator::Value;
+use crate::settings::MqttTopicMapping;
+
+/// Placeholder of the value in a payload template
+const VALUE_PLACEHOLDER: &str = "{value}";
+
+pub struct TopicMapping {
+    /// Segments of the path pattern, `*` for the captured ones
+    path: Vec<String>,
+    topic: String,
+    /// The command topic filter to subscribe to, and the pattern that captures the segments
+    /// from the topics
+    command_filter: Option<String>,
+    command: Option<Regex>,
+    /// The payload template, and the pattern that captures the value from commands
+    payload: Option<String>,
+    payload_pattern: Option<Regex>,
+}
+
+impl TopicMapping {
+    pub fn new(settings: &MqttTopicMapping) -> Result<Self> {
+        let path: Vec<String> = settings.path.split('/').map(str::to_string).collect();
+        if path
+            .iter()
+            .any(|segment| segment != "*" && segment.contains('*'))
+        {
+            bail!(
+                "Topic mapping of {} can only use * as whole path segments",
+                settings.path
+            );
+        }
+        let captures = path.iter().filter(|segment| *segment == "*").count();
+
+        let (command_filter, command) = match &settings.command_topic {
+            Some(command_topic) => {
+                let (filter, pattern, used) = command_pattern(command_topic)?;
+                let unknown = used.iter().any(|index| *index == 0 || *index > captures);
+                if unknown || (1..=captures).any(|index| !used.contains(&index)) {
+                    bail!(
+                        "Command topic {} must contain all {} segments of {}",
+                        command_topic,
+                        captures,
+                        settings.path
+                    );
+                }
+                (Some(filter), Some(pattern))
+            }
+            None => (None, None),
+        };
+
+        let payload_pattern = match &settings.payload {
+            Some(payload) => {
+                let Some((before, after)) = payload.split_once(VALUE_PLACEHOLDER) else {
+                    bail!(
+                        "Payload of {} must contain {}",
+                        settings.path,
+                        VALUE_PLACEHOLDER
+                    );
+                };
+                let pattern = format!(
+                    r"(?s)^{}(.*?){}$",
+                    regex::escape(before),
+                    regex::escape(after)
+                );
+                Some(Regex::new(&pattern)?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            path,
+            topic: settings.topic.clone(),
+            command_filter,
+            command,
+            payload: settings.payload.clone(),
+            payload_pattern,
+        })
+    }
+
+    /// The path pattern of the parameters of this mapping
+    pub fn path_pattern(&self) -> String {
+        self.path.join("/")
+    }
+
+    /// The topic filter of the commands of this mapping, if it has any
+    pub fn command_filter(&self) -> Option<&str> {
+        self.command_filter.as_deref()
+    }
+
+    /// The topic and payload of a value of a parameter, if the mapping is for it
+    pub fn state(&self, osc_path: &str, value: &Value) -> Result<Option<(String, String)>> {
+        let Some(captures) = self.captures(osc_path) else {
+            return Ok(None);
+        };
+
+        let mut topic = self.topic.clone();
+        for (index, capture) in captures.iter().enumerate() {
+            topic = topic.replace(&format!("{{{}}}", index + 1), capture);
+        }
+
+        let value = serde_json::to_string(value)?;
+        let payload = match &self.payload {
+            Some(payload) => payload.replace(VALUE_PLACEHOLDER, &value),
+            None => value,
+        };
+
+        Ok(Some((topic, payload)))
+    }
+
+    /// The parameter of a command topic and the text of the value in its payload. None if the
+    /// topic is not a command topic of this mapping.
+    pub fn command(&self, topic: &str, payload: &[u8]) -> Option<Result<(String, String)>> {
+        let captures = self.command.as_ref()?.captures(topic)?;
+
+        // The segments are numbered by their position in the path pattern
+        let mut index = 0;
+        let path = self
+            .path
+            .iter()
+            .map(|segment| match segment.as_str() {
+                "*" => {
+                    index += 1;
+                    captures[format!("s{}", index).as_str()].to_string()
+                }
+                segment => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        Some(self.command_value(payload).map(|value| (path, value)))
+    }
+
+    /// The value text of a command payload, without the text around it in the template
+    fn command_value(&self, payload: &[u8]) -> Result<String> {
+        let payload = std::str::from_utf8(payload).context("Payload is not text")?;
+
+        let Some(pattern) = &self.payload_pattern else {
+            return Ok(payload.to_string());
+        };
+        let captures = pattern
+            .captures(payload)
+            .with_context(|| format!("Payload does not match {}", pattern.as_str()))?;
+        Ok(captures[1].to_string())
+    }
+
+    /// The segments of a path that the `*` of the pattern stand for, if it matches
+    fn captures<'a>(&self, osc_path: &'a str) -> Option<Vec<&'a str>> {
+        let segments: Vec<&str> = osc_path.split('/').collect();
+        if segments.len() != self.path.len() {
+            return None;
+        }
+
+        let mut captures = Vec::new();
+        for (pattern, segment) in self.path.iter().zip(segments) {
+            match pattern.as_str() {
+                "*" => captures.push(segment),
+                pattern if pattern == segment => {}
+                _ => return None,
+            }
+        }
+
+        Some(captures)
+    }
+}
+
+/// The MQTT topic filter of a command topic template, the pattern that captures each `{n}` of
+/// the template as `sn`, and the numbers that the template uses
+fn command_pattern(template: &str) -> Result<(String, Regex, Vec<usize>)> {
+    let placeholder = Regex::new(r"\{(\d+)\}").unwrap();
+
+    let mut filter = String::new();
+    let mut pattern = String::from("^");
+    let mut used = Vec::new();
+    let mut last = 0;
+    for captures in placeholder.captures_iter(template) {
+        let whole = captures.get(0).unwrap();
+        let index: usize = captures[1].parse()?;
+        // MQTT wildcards only stand for whole topic levels
+        let starts_level = whole.start() == 0 || template[..whole.start()].ends_with('/');
+        let ends_level = whole.end() == template.len() || template[whole.end()..].starts_with('/');
+        if !starts_level || !ends_level {
+            bail!(
+                "{{{}}} must be a whole level of command topic {}",
+                index,
+                template
+            );
+        }
+        if used.contains(&index) {
+            bail!(
+                "Command topic {} uses {{{}}} more than once",
+                template,
+                index
+            );
+        }
+        used.push(index);
+
+        let text = &template[last..whole.start()];
+        filter.push_str(text);
+        filter.push('+');
+        pattern.push_str(&regex::escape(text));
+        pattern.push_str(&format!("(?P<s{}>[^/]+)", index));
+        last = whole.end();
+    }
+    filter.push_str(&template[last..]);
+    pattern.push_str(&regex::escape(&template[last..]));
+    pattern.push('$');
+
+    Ok((filter, Regex::new(&pattern)?, used))
+}
@@ -1,142 +1,1202 @@
 //! The orchestrator module is responsible for synchronising values across various providers
+//!
+//! The orchestrator is a task that owns the consoles, the providers and the parameter cache.
+//! Providers and consoles talk to it through cloneable [`Interface`]s, which send it commands
+//! that it handles one after the other. Values written by users go ahead of all other
+//! commands, so that faders do not lag behind background work such as bank hydration.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::ControlFlow;
 use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{Context, Ok, Result, anyhow};
-use figment::providers;
+use anyhow::{Context, Ok, Result};
 use libwing::Meter;
-use tracing::{debug, error, info, warn};
-use tokio::sync::{Notify, RwLock};
-use tokio::time::timeout;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{Instant, timeout};
+use tracing::{debug, error, info, trace, warn};
 
-use crate::console::Console;
+use crate::access::{Access, SafeMode};
+use crate::console::{ConsoleBackend, RequestBatch};
+use crate::history::History;
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::settings::TimeoutSettings;
+use crate::subscriptions::{PathPattern, Subscriptions};
+use crate::transforms::Transforms;
 
-const OSC_TIMEOUT: Duration = Duration::from_millis(100);
+/// How long to wait at least for all values of a batch request
+const OSC_BATCH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often unanswered requests are checked
+const RETRY_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+/// Delay before the first retry of an unanswered request, doubled on each retry
+const RETRY_INITIAL_DELAY: Duration = Duration::from_millis(200);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(2);
+/// Requests are given up after this many attempts
+const RETRY_MAX_ATTEMPTS: u32 = 5;
+
+/// How long the console echo of a value written by a provider is expected for
+const ECHO_TIMEOUT: Duration = Duration::from_millis(500);
+/// Largest difference of the console echo of a float from the written value, as the console
+/// rounds some values to its steps
+const ECHO_TOLERANCE: f32 = 0.1;
+
+/// How often a summary of the metrics is logged
+const METRICS_LOG_INTERVAL: Duration = Duration::from_secs(60);
+/// How often the history of changes is stored
+const HISTORY_SAVE_INTERVAL: Duration = Duration::from_secs(10);
 
 /// Value types stored in the parameter cache (replaces Fader)
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(untagged)]
 pub enum Value {
     Int(i32),
     Float(f32),
     Str(String),
+    /// An on/off switch, such as a mute
+    Bool(bool),
+}
+
+impl Value {
+    /// The state of an on/off value. Integers are on when not zero, as consoles that do not
+    /// describe their parameters send switches as integers.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(on) => Some(*on),
+            Value::Int(i) => Some(*i != 0),
+            _ => None,
+        }
+    }
+
+    /// Whether a value from the console is the echo of this written value, allowing for the
+    /// rounding of numbers
+    fn is_echoed_by(&self, echo: &Value) -> bool {
+        match (self, echo) {
+            (Value::Float(a), Value::Float(b)) => (a - b).abs() <= ECHO_TOLERANCE,
+            (Value::Float(a), Value::Int(b)) | (Value::Int(b), Value::Float(a)) => {
+                (a - *b as f32).abs() <= ECHO_TOLERANCE
+            }
+            (Value::Bool(_), _) | (_, Value::Bool(_)) => {
+                self.as_bool().is_some() && self.as_bool() == echo.as_bool()
+            }
+            _ => self == echo,
+        }
+    }
+}
+
+/// State of the connection to the console
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkStatus {
+    /// The console is responding
+    Online,
+    /// A connection is being established
+    Connecting,
+    /// The console is not responding
+    Offline,
+}
+
+/// Health of the link to the first console
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkHealth {
+    pub status: LinkStatus,
+    /// Round-trip time of the last answered heartbeat, if the console sends them
+    pub round_trip: Option<Duration>,
+}
+
+/// The kinds of requests that wait for the console to answer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OscOperation {
+    /// Identifying the console after connecting
+    Identify,
+    /// Requesting the values of a bank when it is shown
+    BankHydration,
+    /// Values that a control needs right away
+    Interactive,
+}
+
+/// How long to wait for the console to answer each kind of request
+#[derive(Debug, Clone, Copy)]
+pub struct OscTimeouts {
+    pub identify: Duration,
+    pub bank_hydration: Duration,
+    pub interactive: Duration,
+}
+
+impl OscTimeouts {
+    pub fn get(&self, operation: OscOperation) -> Duration {
+        match operation {
+            OscOperation::Identify => self.identify,
+            OscOperation::BankHydration => self.bank_hydration,
+            OscOperation::Interactive => self.interactive,
+        }
+    }
+}
+
+impl From<&TimeoutSettings> for OscTimeouts {
+    fn from(settings: &TimeoutSettings) -> Self {
+        let timeout = |ms: Option<u64>| Duration::from_millis(ms.unwrap_or(settings.osc_ms));
+
+        Self {
+            identify: timeout(settings.identify_ms),
+            bank_hydration: timeout(settings.bank_ms),
+            interactive: timeout(settings.interactive_ms),
+        }
+    }
 }
 
-pub trait WriteProvider {
+/// A request to a console that has not been answered yet
+struct PendingRequest {
+    attempts: u32,
+    retry_at: Instant,
+}
+
+impl PendingRequest {
+    fn new() -> Self {
+        Self {
+            attempts: 1,
+            retry_at: Instant::now() + RETRY_INITIAL_DELAY,
+        }
+    }
+}
+
+/// The meters that each interface is subscribed to
+#[derive(Default)]
+struct MeterSubscriptions {
+    by_interface: HashMap<usize, Vec<Meter>>,
+    /// All subscribed meters without duplicates, as requested from the first console, in the
+    /// order of its meter values
+    requested: Vec<Meter>,
+}
+
+/// Parameters that follow each other, such as the levels of two language feeds
+#[derive(Debug, Clone)]
+pub struct ParameterLink {
+    /// The parameters, with the offset of each from the others' values
+    pub members: Vec<(String, f32)>,
+}
+
+impl ParameterLink {
+    fn offset_of(&self, osc_addr: &str) -> Option<f32> {
+        self.members
+            .iter()
+            .find(|(path, _)| path == osc_addr)
+            .map(|(_, offset)| *offset)
+    }
+}
+
+/// A value moved by an offset. Values that are not numbers are the same for all parameters.
+fn offset_value(value: &Value, offset: f32) -> Value {
+    match value {
+        Value::Float(f) => Value::Float(f + offset),
+        Value::Int(i) => Value::Int(i + offset.round() as i32),
+        other => other.clone(),
+    }
+}
+
+/// Whether two values are the same, ignoring rounding by the console
+fn values_close(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Float(a), Value::Float(b)) => (a - b).abs() < 0.01,
+        _ => a == b,
+    }
+}
+
+pub trait WriteProvider: Send {
+    /// A short description of the provider, e.g. to show who changed a value
+    fn name(&self) -> String;
     fn write(&self, addr: &str, value: Value) -> anyhow::Result<()>;
     fn write_meter_values(&self, values: Vec<Vec<f32>>) -> anyhow::Result<()>;
+    fn write_link_status(&self, status: LinkStatus) -> anyhow::Result<()>;
     fn set_interface(&self, interface: Interface);
 }
 
+/// A request to the orchestrator task. Requests with a reply are answered once handled, or
+/// once the value they wait for has arrived.
+enum Command {
+    /// A value from an interface, which is sent to all others, or also back to the interface
+    /// itself when broadcast
+    SetValue {
+        id: usize,
+        osc_addr: String,
+        value: Value,
+        broadcast: bool,
+    },
+    /// Check a value from an interface against the definition of its parameter, answering
+    /// with the value that would be written
+    ValidateValue {
+        id: usize,
+        osc_addr: String,
+        value: Value,
+        reply: oneshot::Sender<Result<Value>>,
+    },
+    /// Values from an interface, which are sent to all interfaces
+    SetValues {
+        id: usize,
+        values: Vec<(String, Value)>,
+    },
+    EnsureValue {
+        osc_addr: String,
+        force_refresh: bool,
+    },
+    GetValue {
+        id: usize,
+        osc_addr: String,
+        force_refresh: bool,
+        reply: oneshot::Sender<Value>,
+    },
+    /// Values from the cache, converted for an interface if one is given
+    GetCachedValues {
+        id: Option<usize>,
+        osc_addrs: Vec<String>,
+        reply: oneshot::Sender<Vec<Option<Value>>>,
+    },
+    RequestValues {
+        osc_addrs: Vec<String>,
+        force_refresh: bool,
+        reply: oneshot::Sender<Result<PendingValues>>,
+    },
+    /// Send a value to an interface, answering once it was sent if a reply is given
+    RequestNotification {
+        id: usize,
+        osc_addr: String,
+        force_refresh: bool,
+        reply: Option<oneshot::Sender<Value>>,
+    },
+    ExportCache {
+        reply: oneshot::Sender<HashMap<String, Value>>,
+    },
+    /// The cached values of the parameters that match a pattern, converted for an interface
+    QueryCache {
+        id: usize,
+        pattern: PathPattern,
+        reply: oneshot::Sender<BTreeMap<String, Value>>,
+    },
+    ClearCache {
+        console_prefix: String,
+    },
+    SetTransforms {
+        id: usize,
+        transforms: Transforms,
+    },
+    Subscribe {
+        id: usize,
+        patterns: Vec<PathPattern>,
+    },
+    SubscribeToMeters {
+        id: usize,
+        meters: Vec<Meter>,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    SetMeters {
+        values: Vec<Vec<f32>>,
+    },
+    LinkStatus {
+        status: LinkStatus,
+    },
+    /// Stop the orchestrator, answering once it has cleaned up
+    Shutdown {
+        reply: oneshot::Sender<()>,
+    },
+}
+
+impl Command {
+    /// Whether the command writes values of a user, which go ahead of all other commands.
+    /// Values from the consoles are not, as they keep their order with other console traffic.
+    fn is_user_write(&self) -> bool {
+        match self {
+            Command::SetValue { id, .. } | Command::SetValues { id, .. } => *id != 0,
+            _ => false,
+        }
+    }
+}
+
+/// Values requested from the consoles, which arrive later
+#[derive(Default)]
+struct PendingValues {
+    /// Requests to consoles that report when they have answered them
+    batches: Vec<RequestBatch>,
+    /// Values from consoles that do not report that, which are awaited one by one
+    values: Vec<oneshot::Receiver<Value>>,
+}
+
+/// State of the orchestrator that interfaces use without a command
+struct Shared {
+    /// Mute parameters of the strips that are muted together by the Mute All function
+    mute_all_paths: Vec<String>,
+    /// Health of the link to the first console, for anyone interested in its changes
+    link_health: watch::Sender<LinkHealth>,
+    metrics: Arc<Metrics>,
+    /// Recent changes of parameter values and who made them
+    history: History,
+    timeouts: OscTimeouts,
+    safe_mode: SafeMode,
+}
+
+/// A handle to the orchestrator task
+#[derive(Clone)]
 pub struct Orchestrator {
-    // TODO: Switch to tokio synchronisation structs
-    console: Arc<RwLock<Console>>,
-
-    providers: Vec<Arc<Box<dyn WriteProvider>>>,
-
-    cache: Arc<RwLock<HashMap<String, Value>>>,
-    /// A tokio Notify that is signaled whenever the cache is updated
-    cache_notifier: Notify,
-    /// A (provider id, osc addr)-keyed map showing whether an OSC set notification for a
-    /// parameter should be suppressed.
-    /// TODO: Not used
-    suppressed_notifications: Arc<RwLock<HashMap<(usize, String), usize>>>,
+    /// Values written by users, which are handled before all other commands
+    user_commands: mpsc::UnboundedSender<Command>,
+    commands: mpsc::UnboundedSender<Command>,
+    shared: Arc<Shared>,
 }
 
 impl Orchestrator {
-    pub async fn new(console: Console, providers: Vec<Arc<Box<dyn WriteProvider>>>) -> Arc<Self> {
-        let mut orchestra = Arc::new(Self {
-            console: Arc::new(RwLock::new(console)),
-            providers: providers,
-            cache: Arc::new(RwLock::new(HashMap::new())),
-            cache_notifier: Notify::new(),
-            suppressed_notifications: Arc::new(RwLock::new(HashMap::new())),
-        });
+    /// Create an orchestrator for consoles with their IDs and providers with their access,
+    /// and start its task. The first console is the default.
+    pub async fn new(
+        consoles: Vec<(String, ConsoleBackend)>,
+        providers: Vec<(Box<dyn WriteProvider>, Access)>,
+        mute_all_paths: Vec<String>,
+        links: Vec<ParameterLink>,
+        history: History,
+        timeouts: OscTimeouts,
+        safe_mode: SafeMode,
+    ) -> Self {
+        let (user_commands, user_receiver) = mpsc::unbounded_channel();
+        let (commands, receiver) = mpsc::unbounded_channel();
 
-        {
-            orchestra
-                .console
-                .write()
-                .await
-                .set_interface(Interface::new(0, orchestra.clone()))
-                .await;
+        let orchestrator = Self {
+            user_commands,
+            commands,
+            shared: Arc::new(Shared {
+                mute_all_paths,
+                link_health: watch::Sender::new(LinkHealth {
+                    status: LinkStatus::Offline,
+                    round_trip: None,
+                }),
+                metrics: Arc::new(Metrics::new()),
+                history,
+                timeouts,
+                safe_mode,
+            }),
+        };
+
+        let console_ids = consoles
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(index, (id, _))| (id.clone(), index))
+            .collect();
+
+        let mut console_backends = Vec::with_capacity(consoles.len());
+        for (index, (id, mut console)) in consoles.into_iter().enumerate() {
+            let mut interface = Interface::new(0, orchestrator.clone());
+            if index > 0 {
+                interface.console_prefix = format!("@{}", id);
+            }
+            console.set_interface(interface).await;
+            console_backends.push(console);
         }
 
-        for (id, provider) in orchestra.providers.iter().enumerate() {
-            let interface = Interface::new(id + 1, orchestra.clone());
+        let provider_names = providers
+            .iter()
+            .enumerate()
+            .map(|(index, (provider, _))| format!("{} {}", provider.name(), index + 1))
+            .collect();
+
+        let (providers, access): (Vec<_>, Vec<_>) = providers
+            .into_iter()
+            .map(|(provider, access)| (provider, Arc::new(access)))
+            .unzip();
+
+        for (id, provider) in providers.iter().enumerate() {
+            let mut interface = Interface::new(id + 1, orchestrator.clone());
+            interface.access = access[id].clone();
             provider.set_interface(interface);
         }
 
-        orchestra
+        let state = State {
+            consoles: console_backends,
+            console_ids,
+            providers,
+            provider_names,
+            access,
+            cache: HashMap::new(),
+            waiters: HashMap::new(),
+            pending_requests: HashMap::new(),
+            meter_subscriptions: MeterSubscriptions::default(),
+            subscriptions: Subscriptions::default(),
+            transforms: HashMap::new(),
+            links,
+            linked_writes: HashMap::new(),
+            suppressed_notifications: HashMap::new(),
+            shared: orchestrator.shared.clone(),
+        };
+        tokio::spawn(state.run(user_receiver, receiver));
+
+        orchestrator
     }
 
-    pub async fn value_exists_in_cache(&self, osc_addr: &str) -> bool {
-        let cache = self.cache.read().await;
-        cache.contains_key(osc_addr)
+    /// An interface for callers that are not providers, such as the command line.
+    /// Notifications are never sent to it.
+    pub fn external_interface(&self) -> Interface {
+        Interface::new(usize::MAX, self.clone())
     }
 
-    /// Get a value from the OSC cache, or None if it is not cached currently.
-    pub async fn get_cached_value(&self, osc_addr: &str) -> Option<Value> {
-        let cache = self.cache.read().await;
-        cache.get(osc_addr).cloned()
+    fn send(&self, command: Command) {
+        let channel = if command.is_user_write() {
+            &self.user_commands
+        } else {
+            &self.commands
+        };
+
+        if channel.send(command).is_err() {
+            error!("Orchestrator is not running");
+        }
+    }
+
+    /// Stop the orchestrator before exiting, releasing the meters of the console, storing the
+    /// history and closing the console connections
+    pub async fn shut_down(&self) {
+        if let Err(e) = self.query(|reply| Command::Shutdown { reply }).await {
+            warn!("Orchestrator did not shut down: {:?}", e);
+        }
+    }
+
+    /// Send a command and wait for its reply
+    async fn query<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> Command) -> Result<T> {
+        let (reply, answer) = oneshot::channel();
+        self.send(command(reply));
+        answer.await.context("Orchestrator did not answer")
+    }
+}
+
+impl Debug for Orchestrator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Orchestrator")
+            .field("link_health", &*self.shared.link_health.borrow())
+            .finish()
+    }
+}
+
+/// The state of the orchestrator, owned by its task
+struct State {
+    /// The consoles, the first one being the default for unprefixed paths
+    consoles: Vec<ConsoleBackend>,
+    /// Index of each additional console by its ID, used in `@<id>/...` paths
+    console_ids: HashMap<String, usize>,
+
+    providers: Vec<Box<dyn WriteProvider>>,
+    /// Names of the providers in the history, in the order of their IDs
+    provider_names: Vec<String>,
+    /// The parameters that each provider may read and write, in the order of their IDs
+    access: Vec<Arc<Access>>,
+
+    cache: HashMap<String, Value>,
+    /// Interfaces waiting for parameters to arrive, by parameter
+    waiters: HashMap<String, Vec<(usize, oneshot::Sender<Value>)>>,
+    /// Requests that the consoles have not answered yet, which are retried
+    pending_requests: HashMap<String, PendingRequest>,
+    meter_subscriptions: MeterSubscriptions,
+    /// The parameters that each provider is notified of
+    subscriptions: Subscriptions,
+    /// Conversions of values between the console and each provider
+    transforms: HashMap<usize, Transforms>,
+    links: Vec<ParameterLink>,
+    /// Parameters set by a link, and until when their console echo is expected
+    linked_writes: HashMap<String, Instant>,
+    /// A (provider id, osc addr)-keyed map of the values written by a provider whose console
+    /// echoes are not sent back to it, oldest first, and until when they are expected.
+    suppressed_notifications: HashMap<(usize, String), (VecDeque<Value>, Instant)>,
+
+    shared: Arc<Shared>,
+}
+
+impl State {
+    /// Handle commands until all interfaces are gone, with values of users first. Unanswered
+    /// requests are retried, and the metrics and history kept up to date in between.
+    async fn run(
+        mut self,
+        mut user_commands: mpsc::UnboundedReceiver<Command>,
+        mut commands: mpsc::UnboundedReceiver<Command>,
+    ) {
+        let mut retry_interval = tokio::time::interval(RETRY_CHECK_INTERVAL);
+        let mut history_interval = tokio::time::interval(HISTORY_SAVE_INTERVAL);
+        let mut metrics_interval = tokio::time::interval(METRICS_LOG_INTERVAL);
+        let mut previous_metrics: Option<MetricsSnapshot> = None;
+
+        loop {
+            tokio::select! {
+                biased;
+
+                Some(command) = user_commands.recv() => {
+                    if self.handle(command).await.is_break() {
+                        return;
+                    }
+                }
+                Some(command) = commands.recv() => {
+                    if self.handle(command).await.is_break() {
+                        return;
+                    }
+                }
+                _ = retry_interval.tick() => self.retry_requests().await,
+                _ = history_interval.tick() => {
+                    // Writing the file would hold up the commands meanwhile
+                    tokio::task::spawn_blocking(self.save_history());
+                }
+                _ = metrics_interval.tick() => {
+                    let snapshot = self.shared.metrics.snapshot();
+                    if let Some(previous) = &previous_metrics {
+                        let rates = snapshot.rates_since(previous);
+                        debug!(?snapshot, ?rates, "Orchestrator metrics");
+                    }
+                    previous_metrics = Some(snapshot);
+                }
+            }
+
+            if user_commands.is_closed() && commands.is_closed() {
+                debug!("All interfaces are gone, stopping the orchestrator");
+                return;
+            }
+        }
+    }
+
+    /// Handle a command, breaking when the orchestrator is to stop
+    async fn handle(&mut self, command: Command) -> ControlFlow<()> {
+        match command {
+            Command::SetValue {
+                id,
+                osc_addr,
+                value,
+                broadcast,
+            } => {
+                let value = self.to_console(id, &osc_addr, value);
+                let origin = if broadcast { None } else { Some(id) };
+                self.set_value(&osc_addr, value, origin).await;
+            }
+            Command::ValidateValue {
+                id,
+                osc_addr,
+                value,
+                reply,
+            } => {
+                let value = self.to_console(id, &osc_addr, value);
+                let _ = reply.send(self.validate(&osc_addr, value));
+            }
+            Command::SetValues { id, values } => {
+                let values = values
+                    .into_iter()
+                    .map(|(osc_addr, value)| {
+                        let value = self.to_console(id, &osc_addr, value);
+                        (osc_addr, value)
+                    })
+                    .collect();
+                self.set_values(values, None).await;
+            }
+            Command::EnsureValue {
+                osc_addr,
+                force_refresh,
+            } => {
+                if force_refresh || !self.cache.contains_key(&osc_addr) {
+                    self.request_value_from_console(&osc_addr).await;
+                }
+            }
+            Command::GetValue {
+                id,
+                osc_addr,
+                force_refresh,
+                reply,
+            } => {
+                let cached = self.cache.get(&osc_addr).filter(|_| !force_refresh).cloned();
+                match cached {
+                    Some(value) => {
+                        let _ = reply.send(self.to_provider(id, &osc_addr, value));
+                    }
+                    None => {
+                        self.waiters.entry(osc_addr.clone()).or_default().push((id, reply));
+                        self.request_value_from_console(&osc_addr).await;
+                    }
+                }
+            }
+            Command::GetCachedValues {
+                id,
+                osc_addrs,
+                reply,
+            } => {
+                let values = osc_addrs
+                    .iter()
+                    .map(|osc_addr| {
+                        let value = self.cache.get(osc_addr).cloned()?;
+                        Some(match id {
+                            Some(id) => self.to_provider(id, osc_addr, value),
+                            None => value,
+                        })
+                    })
+                    .collect();
+                let _ = reply.send(values);
+            }
+            Command::RequestValues {
+                osc_addrs,
+                force_refresh,
+                reply,
+            } => {
+                let missing: Vec<String> = osc_addrs
+                    .into_iter()
+                    .filter(|osc_addr| force_refresh || !self.cache.contains_key(osc_addr))
+                    .collect();
+
+                let pending = if missing.is_empty() {
+                    Ok(PendingValues::default())
+                } else {
+                    self.request_values_from_console(&missing).await
+                };
+                let _ = reply.send(pending);
+            }
+            Command::RequestNotification {
+                id,
+                osc_addr,
+                force_refresh,
+                reply,
+            } => {
+                let cached = self.cache.get(&osc_addr).filter(|_| !force_refresh).cloned();
+                match cached {
+                    // If the value is in the cache, send an explicit notification
+                    Some(value) => {
+                        self.notify_provider_by_id(id, &osc_addr, &value).await;
+                        if let Some(reply) = reply {
+                            let _ = reply.send(value);
+                        }
+                    }
+                    // Requesting the value from the console will generate a notification
+                    None => {
+                        if let Some(reply) = reply {
+                            self.waiters.entry(osc_addr.clone()).or_default().push((id, reply));
+                        }
+                        self.request_value_from_console(&osc_addr).await;
+                    }
+                }
+            }
+            Command::ExportCache { reply } => {
+                let _ = reply.send(self.cache.clone());
+            }
+            Command::QueryCache { id, pattern, reply } => {
+                let values = self
+                    .cache
+                    .iter()
+                    .filter(|(osc_addr, _)| pattern.matches(osc_addr))
+                    .map(|(osc_addr, value)| {
+                        (osc_addr.clone(), self.to_provider(id, osc_addr, value.clone()))
+                    })
+                    .collect();
+                let _ = reply.send(values);
+            }
+            Command::ClearCache { console_prefix } => self.clear_cache(&console_prefix),
+            Command::SetTransforms { id, transforms } => {
+                self.transforms.insert(id, transforms);
+            }
+            Command::Subscribe { id, patterns } => self.subscriptions.set(id, patterns),
+            Command::SubscribeToMeters { id, meters, reply } => {
+                let _ = reply.send(self.subscribe_to_meters(id, meters).await);
+            }
+            Command::SetMeters { values } => self.send_meters(values),
+            Command::LinkStatus { status } => {
+                for provider in &self.providers {
+                    if let Err(e) = provider.write_link_status(status) {
+                        error!("Provider failed to write link status: {:?}", e);
+                    }
+                }
+            }
+            Command::Shutdown { reply } => {
+                self.shut_down().await;
+                let _ = reply.send(());
+                return ControlFlow::Break(());
+            }
+        }
+
+        ControlFlow::Continue(())
+    }
+
+    /// Release the meters of the console, store the history and close the console connections,
+    /// so that they can be opened again once the shutdown is answered
+    async fn shut_down(&mut self) {
+        if !self.meter_subscriptions.requested.is_empty() {
+            if let Err(e) = self.consoles[0].set_meters(Vec::new()).await {
+                warn!("Failed to release meters: {:?}", e);
+            }
+        }
+
+        if let Err(e) = tokio::task::spawn_blocking(self.save_history()).await {
+            warn!("Failed to save history: {:?}", e);
+        }
+
+        for console in self.consoles.drain(..) {
+            console.close().await;
+        }
+
+        info!("Orchestrator stopped");
+    }
+
+    /// A function that stores the history in its file, to be run where it may block
+    fn save_history(&self) -> impl FnOnce() + Send + 'static {
+        let shared = self.shared.clone();
+
+        move || {
+            if let Err(e) = shared.history.save() {
+                warn!("Failed to save history: {:?}", e);
+            }
+        }
+    }
+
+    /// Request unanswered values again, waiting longer after each attempt, and give up on
+    /// them after a few attempts. Interfaces that stopped waiting for values are forgotten.
+    async fn retry_requests(&mut self) {
+        self.waiters.retain(|_, waiters| {
+            waiters.retain(|(_, reply)| !reply.is_closed());
+            !waiters.is_empty()
+        });
+
+        // Requests are not answered without a console, and are made again on reconnection
+        if self.shared.link_health.borrow().status != LinkStatus::Online {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut retries = Vec::new();
+        let metrics = &self.shared.metrics;
+
+        self.pending_requests.retain(|osc_addr, request| {
+            if request.retry_at > now {
+                return true;
+            }
+
+            if request.attempts >= RETRY_MAX_ATTEMPTS {
+                metrics.record_timeout();
+                warn!(
+                    osc_addr = osc_addr.as_str(),
+                    attempts = request.attempts,
+                    "Console did not answer request, giving up"
+                );
+                return false;
+            }
+
+            let delay = RETRY_INITIAL_DELAY * 2u32.pow(request.attempts);
+            request.attempts += 1;
+            request.retry_at = now + delay.min(RETRY_MAX_DELAY);
+            retries.push(osc_addr.clone());
+            true
+        });
+
+        for osc_addr in retries {
+            debug!(osc_addr = osc_addr.as_str(), "Retrying unanswered request");
+            if let Err(e) = self.send_request(&osc_addr).await {
+                error!("Failed to request value {}: {:?}", osc_addr, e);
+            }
+        }
+    }
+
+    /// Remember that values were requested, so that they are requested again if they are not
+    /// answered
+    fn track_requests<'a>(&mut self, osc_addrs: impl IntoIterator<Item = &'a str>) {
+        let mut count = 0;
+        for osc_addr in osc_addrs {
+            self.pending_requests
+                .entry(osc_addr.to_string())
+                .or_insert_with(PendingRequest::new);
+            count += 1;
+        }
+        self.shared.metrics.record_requests(count);
+    }
+
+    /// Add a change of a value to the history. Values that did not change, and the first
+    /// values received from the console, are left out.
+    fn record_change(
+        &self,
+        osc_addr: &str,
+        old: Option<Value>,
+        new: &Value,
+        origin: Option<usize>,
+    ) {
+        if old.as_ref() == Some(new) || (origin == Some(0) && old.is_none()) {
+            return;
+        }
+
+        let origin = match origin {
+            Some(0) => "console",
+            Some(usize::MAX) => "external",
+            Some(id) => self.provider_names.get(id - 1).map_or("provider", String::as_str),
+            None => "automation",
+        };
+        self.shared.history.record(origin, osc_addr, old, new.clone());
+    }
+
+    /// Forget all cached values of a console, e.g. when they may have changed while it was
+    /// away
+    fn clear_cache(&mut self, console_prefix: &str) {
+        self.cache.retain(|osc_addr, _| {
+            if console_prefix.is_empty() {
+                osc_addr.starts_with('@')
+            } else {
+                !osc_addr
+                    .strip_prefix(console_prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+            }
+        });
+        self.shared.metrics.set_cache_size(self.cache.len());
+    }
+
+    /// The index of the console that a parameter belongs to, and the path of the parameter
+    /// on it
+    fn route_index<'a>(&self, osc_addr: &'a str) -> Result<(usize, &'a str)> {
+        let Some(rest) = osc_addr.strip_prefix('@') else {
+            return Ok((0, osc_addr));
+        };
+
+        let (id, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let index = self
+            .console_ids
+            .get(id)
+            .with_context(|| format!("Unknown console '{}' in {}", id, osc_addr))?;
+
+        Ok((*index, path))
+    }
+
+    /// Check a value against the definition of its parameter before it is written, converting
+    /// it if needed
+    fn validate(&self, osc_addr: &str, value: Value) -> Result<Value> {
+        let (index, path) = self.route_index(osc_addr)?;
+        self.consoles[index].validate(path, value)
+    }
+
+    /// Write a value to the console that it belongs to
+    async fn write_to_console(&mut self, osc_addr: &str, value: Value) {
+        let result = match self.route_index(osc_addr) {
+            std::result::Result::Ok((index, path)) => {
+                self.consoles[index].set_value(path, value).await
+            }
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            error!("Console failed to write {}: {:?}", osc_addr, e);
+        }
+    }
+
+    /// Write several values to their consoles. Only the last value of a parameter that
+    /// appears more than once is written.
+    async fn write_values_to_console(&mut self, values: &[(String, Value)]) {
+        let last_positions: HashMap<&str, usize> = values
+            .iter()
+            .enumerate()
+            .map(|(position, (osc_addr, _))| (osc_addr.as_str(), position))
+            .collect();
+
+        for (position, (osc_addr, value)) in values.iter().enumerate() {
+            if last_positions[osc_addr.as_str()] != position {
+                continue;
+            }
+
+            self.write_to_console(osc_addr, value.clone()).await;
+        }
     }
 
     /// Request a value for future retrieval. The result is not returned. There is no
     /// guarantee that a result will be returned.
-    async fn request_value_from_console(&self, osc_addr: &str) {
-        let mut console = self.console.write().await;
-        if let Err(e) = console.request_value(osc_addr).await {
+    async fn request_value_from_console(&mut self, osc_addr: &str) {
+        self.track_requests([osc_addr]);
+
+        if let Err(e) = self.send_request(osc_addr).await {
             error!("Failed to request value {}: {:?}", osc_addr, e);
         }
     }
 
-    /// Request a value. If it is available in the cache, it will be returned immediately.
-    /// Otherwise, a request will be made and the value awaited.
-    /// Note that this may never return if a value is not found. Define your own timeout
-    /// when needed.
-    async fn wait_for_value(&self, osc_addr: &str, force_refresh: bool) -> Value {
-        if !force_refresh {
-            let cache = self.cache.read().await;
-            if let Some(value) = cache.get(osc_addr) {
-                return value.clone();
+    /// Send a request for a value to the console it is on
+    async fn send_request(&mut self, osc_addr: &str) -> Result<()> {
+        let (index, path) = self.route_index(osc_addr)?;
+        self.consoles[index].request_value(path).await
+    }
+
+    /// Request several values at once. The values of consoles that do not report when they
+    /// have answered are awaited one by one.
+    async fn request_values_from_console(&mut self, osc_addrs: &[String]) -> Result<PendingValues> {
+        let mut console_paths = vec![Vec::new(); self.consoles.len()];
+        for osc_addr in osc_addrs {
+            let (index, path) = self.route_index(osc_addr)?;
+            console_paths[index].push((osc_addr.as_str(), path));
+        }
+
+        let mut pending = PendingValues::default();
+
+        for (index, paths) in console_paths.iter().enumerate() {
+            if paths.is_empty() {
+                continue;
+            }
+
+            self.track_requests(paths.iter().map(|(osc_addr, _)| *osc_addr));
+
+            let paths_on_console: Vec<&str> = paths.iter().map(|(_, path)| *path).collect();
+            match self.consoles[index].request_values(&paths_on_console).await? {
+                Some(batch) => pending.batches.push(batch),
+                None => {
+                    for (osc_addr, _) in paths {
+                        let (reply, value) = oneshot::channel();
+                        self.waiters
+                            .entry(osc_addr.to_string())
+                            .or_default()
+                            .push((usize::MAX, reply));
+                        pending.values.push(value);
+                    }
+                }
             }
         }
 
-        self.request_value_from_console(osc_addr).await;
+        Ok(pending)
+    }
 
-        loop {
-            self.cache_notifier.notified().await;
+    /// Update the cache with a new value and notify all providers except its origin. The
+    /// parameters linked to it follow.
+    async fn set_value(&mut self, osc_addr: &str, value: Value, origin: Option<usize>) {
+        self.store_value(osc_addr, value.clone(), origin).await;
+        self.apply_links(osc_addr, &value, origin).await;
+    }
+
+    /// Update the cache with several values at once, and write them to the console and all
+    /// providers except their origin. The parameters linked to them follow.
+    async fn set_values(&mut self, values: Vec<(String, Value)>, origin: Option<usize>) {
+        self.store_values(values.clone(), origin).await;
+
+        for (osc_addr, value) in &values {
+            self.apply_links(osc_addr, value, origin).await;
+        }
+    }
+
+    /// Set the other parameters of the links that a parameter is a member of. Changes of the
+    /// linked parameters do not move the others again, and neither do their console echoes.
+    async fn apply_links(&mut self, osc_addr: &str, value: &Value, origin: Option<usize>) {
+        if self.links.is_empty() {
+            return;
+        }
+
+        if origin == Some(0)
+            && self
+                .linked_writes
+                .remove(osc_addr)
+                .is_some_and(|until| until > Instant::now())
+        {
+            return;
+        }
 
-            let cache = self.cache.read().await;
-            if let Some(value) = cache.get(osc_addr) {
-                return value.clone();
+        let mut linked = Vec::new();
+        for link in &self.links {
+            let Some(source_offset) = link.offset_of(osc_addr) else {
+                continue;
+            };
+
+            for (path, offset) in &link.members {
+                if path == osc_addr {
+                    continue;
+                }
+
+                let target = offset_value(value, offset - source_offset);
+                let current = self.cache.get(path);
+                if current.is_some_and(|current| values_close(current, &target)) {
+                    continue;
+                }
+
+                linked.push((path.clone(), target));
             }
         }
+
+        if linked.is_empty() {
+            return;
+        }
+
+        debug!(osc_addr, linked = linked.len(), "Following linked parameters");
+
+        let until = Instant::now() + ECHO_TIMEOUT;
+        for (path, _) in &linked {
+            self.linked_writes.insert(path.clone(), until);
+        }
+
+        self.store_values(linked, None).await;
     }
 
-    /// Notify a provider for a value update
-    async fn notify_provider_by_id(&self, provider_id: usize, osc_addr: &str, value: &Value) {
-        if provider_id == 0 {
-            // Console
-            let mut console = self.console.write().await;
-            if let Err(e) = console.set_value(osc_addr, value.clone()).await {
-                error!("Console failed to write {}: {:?}", osc_addr, e);
+    /// Put a value in the cache, and hand it to the interfaces waiting for it
+    fn cache_value(&mut self, osc_addr: &str, value: &Value, origin: Option<usize>) {
+        let old = self.cache.insert(osc_addr.to_string(), value.clone());
+        self.record_change(osc_addr, old, value, origin);
+
+        if let Some(waiters) = self.waiters.remove(osc_addr) {
+            for (id, reply) in waiters {
+                let _ = reply.send(self.to_provider(id, osc_addr, value.clone()));
             }
+        }
+    }
+
+    /// Update the cache with a new value and notify all providers except its origin.
+    async fn store_value(&mut self, osc_addr: &str, value: Value, origin: Option<usize>) {
+        // Values from the console are valid by definition
+        let value = if origin == Some(0) {
+            self.pending_requests.remove(osc_addr);
+            value
         } else {
-            let provider = match self.providers.get(provider_id - 1) {
-                Some(p) => p,
-                None => {
-                    error!(
-                        "Tried to notify unknown provider {} for OSC update",
-                        provider_id
-                    );
+            match self.validate(osc_addr, value) {
+                std::result::Result::Ok(value) => value,
+                Err(e) => {
+                    error!("Rejected value for {}: {:?}", osc_addr, e);
                     return;
                 }
+            }
+        };
+
+        self.cache_value(osc_addr, &value, origin);
+        self.shared.metrics.set_cache_size(self.cache.len());
+
+        if origin != Some(0) {
+            // Write to console which is not part of the provider list
+            self.write_to_console(osc_addr, value.clone()).await;
+
+            // Only providers are notified of values
+            if let Some(id) = origin.filter(|id| *id <= self.providers.len()) {
+                self.suppress_echo(id, osc_addr, &value);
+            }
+        }
+
+        for id in 1..=self.providers.len() {
+            // Do not write to the origin, or to providers that are not interested
+            if Some(id) == origin || !self.notifies(id, osc_addr) {
+                continue;
+            }
+
+            // Nor back to a provider that wrote this value itself
+            if origin == Some(0) && self.take_echo(id, osc_addr, &value) {
+                continue;
+            }
+
+            let value = self.to_provider(id, osc_addr, value.clone());
+            if let Err(e) = self.providers[id - 1].write(osc_addr, value) {
+                error!("Provider {} failed to write {}: {:?}", id - 1, osc_addr, e);
+            }
+        }
+    }
+
+    /// Update the cache with several values at once, and write them to the console and all
+    /// providers except their origin.
+    async fn store_values(&mut self, values: Vec<(String, Value)>, origin: Option<usize>) {
+        let mut valid_values = Vec::with_capacity(values.len());
+        for (osc_addr, value) in values {
+            if origin == Some(0) {
+                self.pending_requests.remove(&osc_addr);
+                valid_values.push((osc_addr, value));
+                continue;
+            }
+
+            match self.validate(&osc_addr, value) {
+                std::result::Result::Ok(value) => valid_values.push((osc_addr, value)),
+                Err(e) => error!("Rejected value for {}: {:?}", osc_addr, e),
+            }
+        }
+        let values = valid_values;
+
+        for (osc_addr, value) in &values {
+            self.cache_value(osc_addr, value, origin);
+        }
+        self.shared.metrics.set_cache_size(self.cache.len());
+
+        if origin != Some(0) {
+            self.write_values_to_console(&values).await;
+        }
+
+        for (index, provider) in self.providers.iter().enumerate() {
+            let id = index + 1;
+            if Some(id) == origin {
+                continue;
+            }
+
+            for (osc_addr, value) in &values {
+                if !self.notifies(id, osc_addr) {
+                    continue;
+                }
+
+                let value = self.to_provider(id, osc_addr, value.clone());
+                if let Err(e) = provider.write(osc_addr, value) {
+                    error!("Provider {} failed to write {}: {:?}", index, osc_addr, e);
+                }
+            }
+        }
+    }
+
+    /// Whether a provider is sent the values of a parameter, because it subscribed to it and
+    /// may read it
+    fn notifies(&self, provider_id: usize, osc_addr: &str) -> bool {
+        self.subscriptions.wants(provider_id, osc_addr)
+            && self.access[provider_id - 1].may_read(osc_addr)
+    }
+
+    /// Convert a value from the console for a provider, with the transforms it set
+    fn to_provider(&self, provider_id: usize, osc_addr: &str, value: Value) -> Value {
+        match self.transforms.get(&provider_id) {
+            Some(transforms) => transforms.to_provider(osc_addr, value),
+            None => value,
+        }
+    }
+
+    /// Convert a value from a provider for the console, with the transforms it set
+    fn to_console(&self, provider_id: usize, osc_addr: &str, value: Value) -> Value {
+        match self.transforms.get(&provider_id) {
+            Some(transforms) => transforms.to_console(osc_addr, value),
+            None => value,
+        }
+    }
+
+    /// Expect the console to echo a value written by a provider, so that the echo is not sent
+    /// back to it. Moving faders would otherwise jump back to positions they just passed.
+    fn suppress_echo(&mut self, provider_id: usize, osc_addr: &str, value: &Value) {
+        let entry = self
+            .suppressed_notifications
+            .entry((provider_id, osc_addr.to_string()))
+            .or_insert((VecDeque::new(), Instant::now()));
+
+        entry.0.push_back(value.clone());
+        entry.1 = Instant::now() + ECHO_TIMEOUT;
+    }
+
+    /// Whether a value from the console is the echo of a value written by a provider, which is
+    /// then no longer expected, nor are the values written before it. Values that differ, e.g.
+    /// because the parameter was changed on the console meanwhile, are sent to the provider.
+    /// Echoes that did not arrive in time are forgotten.
+    fn take_echo(&mut self, provider_id: usize, osc_addr: &str, value: &Value) -> bool {
+        let key = (provider_id, osc_addr.to_string());
+
+        let Some((written, expires)) = self.suppressed_notifications.get_mut(&key) else {
+            return false;
+        };
+
+        if *expires <= Instant::now() {
+            self.suppressed_notifications.remove(&key);
+            return false;
+        }
+        let Some(position) = written.iter().position(|w| w.is_echoed_by(value)) else {
+            return false;
+        };
+
+        written.drain(..=position);
+        if written.is_empty() {
+            self.suppressed_notifications.remove(&key);
+        }
+
+        trace!(provider_id, osc_addr, "Suppressing console echo");
+        true
+    }
+
+    /// Notify a provider for a value update
+    async fn notify_provider_by_id(&mut self, provider_id: usize, osc_addr: &str, value: &Value) {
+        if provider_id == 0 {
+            // Console
+            self.write_to_console(osc_addr, value.clone()).await;
+        } else {
+            let Some(provider) = self.providers.get(provider_id - 1) else {
+                error!(
+                    "Tried to notify unknown provider {} for OSC update",
+                    provider_id
+                );
+                return;
             };
 
-            if let Err(e) = provider.write(osc_addr, value.clone()) {
+            let value = self.to_provider(provider_id, osc_addr, value.clone());
+            if let Err(e) = provider.write(osc_addr, value) {
                 error!(
                     "Provider {} failed to write {}: {:?}",
                     provider_id - 1,
@@ -146,14 +1206,66 @@ impl Orchestrator {
             }
         }
     }
-}
 
-impl Debug for Orchestrator {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Orchestrator")
-            .field("console", &"console::Console")
-            .field("providers", &self.providers.len())
-            .finish()
+    /// Replace the meters of an interface, and ask the console for the meters of all
+    /// interfaces if they changed
+    async fn subscribe_to_meters(&mut self, id: usize, meters: Vec<Meter>) -> Result<()> {
+        let subscriptions = &mut self.meter_subscriptions;
+
+        if meters.is_empty() {
+            subscriptions.by_interface.remove(&id);
+        } else {
+            subscriptions.by_interface.insert(id, meters);
+        }
+
+        let mut ids: Vec<usize> = subscriptions.by_interface.keys().copied().collect();
+        ids.sort();
+
+        let mut requested: Vec<Meter> = Vec::new();
+        for meter in ids.iter().flat_map(|id| &subscriptions.by_interface[id]) {
+            if !requested.contains(meter) {
+                requested.push(meter.clone());
+            }
+        }
+
+        if requested == subscriptions.requested {
+            return Ok(());
+        }
+        subscriptions.requested = requested.clone();
+
+        // Meters are only received from the first console
+        debug!(meter_count = requested.len(), "Requesting meters from the console");
+        self.consoles[0].set_meters(requested).await
+    }
+
+    /// Send meter values, in the order of the requested meters, to the providers. Each
+    /// provider receives the values of the meters it is subscribed to, in the order it
+    /// subscribed to them.
+    fn send_meters(&self, values: Vec<Vec<f32>>) {
+        let subscriptions = &self.meter_subscriptions;
+
+        for (id, provider) in self.providers.iter().enumerate() {
+            let Some(meters) = subscriptions.by_interface.get(&(id + 1)) else {
+                continue;
+            };
+
+            let provider_values: Vec<Vec<f32>> = meters
+                .iter()
+                .filter_map(|meter| {
+                    let index = subscriptions.requested.iter().position(|m| m == meter)?;
+                    values.get(index).cloned()
+                })
+                .collect();
+
+            // Values decoded before the console took the latest subscription
+            if provider_values.len() != meters.len() {
+                continue;
+            }
+
+            if let Err(e) = provider.write_meter_values(provider_values) {
+                error!("Provider failed to write meter values: {:?}", e);
+            }
+        }
     }
 }
 
@@ -161,53 +1273,186 @@ impl Debug for Orchestrator {
 pub struct Interface {
     /// Console is always 0. The rest is the index in providers + 1
     id: usize,
-    orchestrator: Arc<Orchestrator>,
+    orchestrator: Orchestrator,
+    /// For consoles other than the first one, the `@<id>` prefix added to the paths of the
+    /// values they set and request
+    console_prefix: String,
+    /// The parameters that this interface may read and write
+    access: Arc<Access>,
 }
 
-// TODO: Is this necessary and safe?
-// We only access the orchestrator through safe methods
-unsafe impl Send for Orchestrator {}
-unsafe impl Sync for Orchestrator {}
-
 impl Interface {
-    pub fn new(id: usize, orchestrator: Arc<Orchestrator>) -> Self {
-        Self { id, orchestrator }
+    pub fn new(id: usize, orchestrator: Orchestrator) -> Self {
+        Self {
+            id,
+            orchestrator,
+            console_prefix: String::new(),
+            access: Arc::new(Access::default()),
+        }
+    }
+
+    /// Whether this interface may write a parameter, warning if it may not. Values from the
+    /// consoles are always allowed, as they have already changed.
+    pub fn may_write(&self, osc_addr: &str) -> bool {
+        if self.id == 0 {
+            return true;
+        }
+
+        if !self.access.may_write(osc_addr) {
+            warn!(interface_id = self.id, osc_addr, "Interface may not write parameter");
+            return false;
+        }
+
+        if !self.orchestrator.shared.safe_mode.allows(osc_addr) {
+            warn!(interface_id = self.id, osc_addr, "Parameter not changed in safe mode");
+            return false;
+        }
+
+        true
+    }
+
+    /// Fail for the parameters that this interface may not read
+    fn check_readable(&self, osc_addr: &str) -> Result<()> {
+        if !self.access.may_read(osc_addr) {
+            anyhow::bail!("Interface {} may not read {}", self.id, osc_addr);
+        }
+        Ok(())
     }
 
     /// Ensure that the value is available, requesting it if necessary.
     /// This may generate a notification that will be sent to the caller.
     pub async fn ensure_value(&self, osc_addr: &str, force_refresh: bool) {
-        if !force_refresh && self.orchestrator.value_exists_in_cache(osc_addr).await {
-            return;
-        }
+        self.orchestrator.send(Command::EnsureValue {
+            osc_addr: osc_addr.to_string(),
+            force_refresh,
+        });
+    }
+
+    /// Get an OSC value from the cache, without making any requests to the console.
+    pub async fn get_cached_value(&self, osc_addr: &str) -> Option<Value> {
+        self.check_readable(osc_addr).ok()?;
 
-        self.orchestrator.request_value_from_console(osc_addr).await;
+        let values = self
+            .orchestrator
+            .query(|reply| Command::GetCachedValues {
+                id: Some(self.id),
+                osc_addrs: vec![osc_addr.to_string()],
+                reply,
+            })
+            .await
+            .ok()?;
+        values.into_iter().next().flatten()
     }
 
     /// Get an OSC value, requesting it from the console if necessary.
     /// This may generate a notification that will be sent to the caller.
     /// Results to an error in case of a timeout.
     pub async fn get_value(&self, osc_addr: &str, force_refresh: bool) -> Result<Value> {
-        let future = self.orchestrator.wait_for_value(osc_addr, force_refresh);
+        self.get_value_for(OscOperation::Interactive, osc_addr, force_refresh)
+            .await
+    }
+
+    /// Like `get_value`, with the timeout of a kind of request
+    pub async fn get_value_for(
+        &self,
+        operation: OscOperation,
+        osc_addr: &str,
+        force_refresh: bool,
+    ) -> Result<Value> {
+        self.check_readable(osc_addr)?;
+
+        let osc_addr = format!("{}{}", self.console_prefix, osc_addr);
+        let future = self.orchestrator.query(|reply| Command::GetValue {
+            id: self.id,
+            osc_addr: osc_addr.clone(),
+            force_refresh,
+            reply,
+        });
 
-        timeout(OSC_TIMEOUT, future)
+        timeout(self.timeout(operation), future)
             .await
-            .with_context(|| format!("Timed out waiting for value {}", osc_addr))
+            .with_context(|| format!("Timed out waiting for value {}", osc_addr))?
+    }
+
+    /// Request several values at once, and wait until the console has sent all of them. Like
+    /// with `ensure_value`, the values are notified when they arrive, and values in the cache
+    /// are not requested again unless forced.
+    pub async fn request_values(&self, osc_addrs: &[String], force_refresh: bool) -> Result<()> {
+        let pending = self
+            .orchestrator
+            .query(|reply| Command::RequestValues {
+                osc_addrs: osc_addrs.to_vec(),
+                force_refresh,
+                reply,
+            })
+            .await??;
+
+        let all_arrived = async {
+            for batch in &pending.batches {
+                batch.wait().await;
+            }
+            for value in pending.values {
+                let _ = value.await;
+            }
+        };
+
+        let batch_timeout = OSC_BATCH_TIMEOUT.max(self.timeout(OscOperation::BankHydration));
+        timeout(batch_timeout, all_arrived)
+            .await
+            .with_context(|| format!("Timed out waiting for {} values", osc_addrs.len()))
+    }
+
+    /// Get several OSC values in the order of their paths, requesting those that are not in
+    /// the cache from the console at once. Results to an error if any of them does not arrive
+    /// in time.
+    pub async fn get_values(
+        &self,
+        osc_addrs: &[String],
+        force_refresh: bool,
+    ) -> Result<Vec<Value>> {
+        for osc_addr in osc_addrs {
+            self.check_readable(osc_addr)?;
+        }
+
+        let osc_addrs: Vec<String> = osc_addrs
+            .iter()
+            .map(|osc_addr| format!("{}{}", self.console_prefix, osc_addr))
+            .collect();
+
+        self.request_values(&osc_addrs, force_refresh).await?;
+
+        let values = self
+            .orchestrator
+            .query(|reply| Command::GetCachedValues {
+                id: Some(self.id),
+                osc_addrs: osc_addrs.clone(),
+                reply,
+            })
+            .await?;
+
+        osc_addrs
+            .iter()
+            .zip(values)
+            .map(|(osc_addr, value)| {
+                value.with_context(|| format!("Value {} not available", osc_addr))
+            })
+            .collect()
     }
 
     /// Request a value notification that contains a value.
     /// A notification is not guaranteed in case of error.
     pub async fn request_value_notification(&self, osc_addr: &str, force_refresh: bool) {
-        if force_refresh || !self.orchestrator.value_exists_in_cache(osc_addr).await {
-            // Requesting the value from the console will generate a notification
-            self.orchestrator.request_value_from_console(osc_addr).await;
-        } else {
-            // If the value is in the cache, send an explicit notification
-            let value = self.orchestrator.get_cached_value(osc_addr).await.unwrap();
-            self.orchestrator
-                .notify_provider_by_id(self.id, osc_addr, &value)
-                .await;
+        if let Err(e) = self.check_readable(osc_addr) {
+            debug!("Not requesting notification: {:?}", e);
+            return;
         }
+
+        self.orchestrator.send(Command::RequestNotification {
+            id: self.id,
+            osc_addr: osc_addr.to_string(),
+            force_refresh,
+            reply: None,
+        });
     }
 
     /// Request a value notification that contains an OSC value.
@@ -217,74 +1462,292 @@ impl Interface {
         osc_addr: &str,
         force_refresh: bool,
     ) -> Result<()> {
-        if force_refresh || !self.orchestrator.value_exists_in_cache(osc_addr).await {
-            // Requesting the value from the console will generate a notification
-            let future = self.orchestrator.wait_for_value(osc_addr, force_refresh);
-
-            timeout(OSC_TIMEOUT, future)
-                .await
-                .with_context(|| format!("Timed out waiting for value {}", osc_addr))?;
-            Ok(())
-        } else {
-            // If the value is in the cache, send an explicit notification
-            let value = self.orchestrator.get_cached_value(osc_addr).await.unwrap();
-            self.orchestrator
-                .notify_provider_by_id(self.id, osc_addr, &value)
-                .await;
-            Ok(())
-        }
+        self.check_readable(osc_addr)?;
+
+        let future = self.orchestrator.query(|reply| Command::RequestNotification {
+            id: self.id,
+            osc_addr: osc_addr.to_string(),
+            force_refresh,
+            reply: Some(reply),
+        });
+
+        timeout(self.timeout(OscOperation::BankHydration), future)
+            .await
+            .with_context(|| format!("Timed out waiting for value {}", osc_addr))??;
+        Ok(())
+    }
+
+    /// How long to wait for the console to answer a kind of request
+    pub fn timeout(&self, operation: OscOperation) -> Duration {
+        self.orchestrator.shared.timeouts.get(operation)
     }
 
     /// Set an OSC value, notifying all other providers/interfaces except self.
-    /// 
+    ///
     /// For example, a console can set_value, which will notify everyone else.
     pub async fn set_value(&self, osc_addr: &str, value: Value) {
-        // Update cache
+        if !self.may_write(osc_addr) {
+            return;
+        }
+
+        self.orchestrator.shared.metrics.record_values(self.id, 1);
+        self.orchestrator.send(Command::SetValue {
+            id: self.id,
+            osc_addr: format!("{}{}", self.console_prefix, osc_addr),
+            value,
+            broadcast: false,
+        });
+    }
+
+    /// Convert the values of this interface, e.g. from a percentage to a level in dB, with the
+    /// first transform whose pattern matches a parameter. Values notified to this interface
+    /// are converted the other way round.
+    pub async fn set_transforms(&self, transforms: Transforms) {
+        if transforms.is_empty() {
+            return;
+        }
+
+        debug!(interface_id = self.id, "Interface transforms set");
+        self.orchestrator.send(Command::SetTransforms {
+            id: self.id,
+            transforms,
+        });
+    }
+
+    /// Set an OSC value, notifying all providers/interfaces including self.
+    ///
+    /// This is meant for values that do not come from the provider's own inputs, such as
+    /// macro steps, which the provider also needs to display.
+    pub async fn set_value_broadcast(&self, osc_addr: &str, value: Value) {
+        if !self.may_write(osc_addr) {
+            return;
+        }
+
+        self.orchestrator.shared.metrics.record_values(self.id, 1);
+        self.orchestrator.send(Command::SetValue {
+            id: self.id,
+            osc_addr: osc_addr.to_string(),
+            value,
+            broadcast: true,
+        });
+    }
+
+    /// Set an OSC value like `set_value_broadcast`, failing if this interface may not write
+    /// the parameter or the value does not fit its definition
+    pub async fn set_value_checked(&self, osc_addr: &str, value: Value) -> Result<()> {
+        if !self.may_write(osc_addr) {
+            anyhow::bail!("Interface {} may not write {}", self.id, osc_addr);
+        }
+
         self.orchestrator
-            .cache
-            .write()
-            .await
-            .insert(osc_addr.to_string(), value.clone());
-        self.orchestrator.cache_notifier.notify_waiters();
+            .query(|reply| Command::ValidateValue {
+                id: self.id,
+                osc_addr: osc_addr.to_string(),
+                value: value.clone(),
+                reply,
+            })
+            .await??;
 
-        if self.id != 0 {
-            // Write to console which is not part of the provider list
-            let mut console = self.orchestrator.console.write().await;
-            if let Err(e) = console.set_value(osc_addr, value.clone()).await {
-                error!("Console failed to write {}: {:?}", osc_addr, e);
-            }
+        self.set_value_broadcast(osc_addr, value).await;
+        Ok(())
+    }
+
+    /// Set several OSC values at once, notifying all providers/interfaces including self.
+    pub async fn set_values(&self, mut values: Vec<(String, Value)>) {
+        values.retain(|(osc_addr, _)| self.may_write(osc_addr));
+        if values.is_empty() {
+            return;
+        }
+
+        self.orchestrator.shared.metrics.record_values(self.id, values.len());
+        self.orchestrator.send(Command::SetValues {
+            id: self.id,
+            values,
+        });
+    }
+
+    /// Get the values of several OSC parameters, requesting them from the console at once if
+    /// necessary. Parameters that are not available are left out.
+    pub async fn capture_values(&self, osc_addrs: &[String]) -> HashMap<String, Value> {
+        if let Err(e) = self.request_values(osc_addrs, false).await {
+            warn!("Not all values captured: {:?}", e);
         }
 
-        for (id, provider) in self.orchestrator.providers.iter().enumerate() {
-            // Do not write to self!
-            if id + 1 != self.id {
-                if let Err(e) = provider.write(osc_addr, value.clone()) {
-                    error!("Provider {} failed to write {}: {:?}", id, osc_addr, e);
+        let cached = self
+            .orchestrator
+            .query(|reply| Command::GetCachedValues {
+                id: None,
+                osc_addrs: osc_addrs.to_vec(),
+                reply,
+            })
+            .await
+            .unwrap_or_default();
+
+        let mut values = HashMap::new();
+        for (osc_addr, value) in osc_addrs.iter().zip(cached) {
+            match value {
+                Some(value) => {
+                    values.insert(osc_addr.clone(), value);
                 }
+                None => warn!("Value of {} not captured", osc_addr),
             }
         }
+
+        values
+    }
+
+    /// A copy of all cached OSC values, e.g. to store them in a snapshot.
+    pub async fn export_cache(&self) -> HashMap<String, Value> {
+        self.orchestrator
+            .query(|reply| Command::ExportCache { reply })
+            .await
+            .unwrap_or_default()
+    }
+
+    /// The cached values of all parameters that match a pattern, sorted by path, e.g.
+    /// `/ch/*/mute` for all channel mutes or `/ch/1/**` for everything under `/ch/1`. See
+    /// [`PathPattern`] for the syntax. No requests are made to the console.
+    pub async fn cached_values(&self, pattern: &str) -> BTreeMap<String, Value> {
+        let mut values = self
+            .orchestrator
+            .query(|reply| Command::QueryCache {
+                id: self.id,
+                pattern: PathPattern::new(pattern),
+                reply,
+            })
+            .await
+            .unwrap_or_default();
+
+        values.retain(|osc_addr, _| self.access.may_read(osc_addr));
+        values
+    }
+
+    /// Mute or unmute all strips of the Mute All function at once.
+    pub async fn mute_all(&self, mute: bool) {
+        info!(interface_id = self.id, mute, "Mute all");
+
+        let values = self
+            .mute_all_paths()
+            .iter()
+            .map(|path| (path.clone(), Value::Bool(mute)))
+            .collect();
+
+        self.set_values(values).await;
+    }
+
+    /// The mute parameters of the strips of the Mute All function
+    pub fn mute_all_paths(&self) -> &[String] {
+        &self.orchestrator.shared.mute_all_paths
+    }
+
+    /// Only be notified of the parameters that match these patterns, replacing any previous
+    /// patterns. Interfaces that never subscribe are notified of all parameters. See
+    /// [`PathPattern`] for the syntax.
+    pub async fn subscribe(&self, patterns: &[String]) {
+        debug!(
+            interface_id = self.id,
+            patterns = patterns.len(),
+            "Interface subscribed to parameters"
+        );
+
+        self.orchestrator.send(Command::Subscribe {
+            id: self.id,
+            patterns: patterns.iter().map(|pattern| PathPattern::new(pattern)).collect(),
+        });
     }
 
-    /// Subscribe to specific meter updates from the console.
-    /// 
-    /// NOTE: This will override any previous subscriptions.
-    /// TODO: Make it not override any previous subscriptions.
+    /// Subscribe to specific meter updates from the console, replacing the previous meters of
+    /// this interface. Subscribing to no meters releases them. The console is only asked for
+    /// the meters that any interface is subscribed to.
     pub async fn subscribe_to_meters(&self, meters: Vec<Meter>) -> Result<()> {
-        let mut console = self.orchestrator.console.write().await;
-        debug!(interface_id = self.id, meter_count = meters.len(), "Interface subscribed to meters");
-        console.set_meters(meters).await
+        debug!(
+            interface_id = self.id,
+            meter_count = meters.len(),
+            "Interface subscribed to meters"
+        );
+
+        self.orchestrator
+            .query(|reply| Command::SubscribeToMeters {
+                id: self.id,
+                meters,
+                reply,
+            })
+            .await?
     }
 
-    /// Broadcast meter values.
-    /// 
+    /// Broadcast meter values, in the order of the requested meters. Each provider receives
+    /// the values of the meters it is subscribed to, in the order it subscribed to them.
+    ///
     /// These values are not cached, but instead are sent immediatelly to subscribers.
-    /// 
-    /// TODO: Use slice instead of vector
     pub(crate) async fn set_meters(&self, values: Vec<Vec<f32>>) {
-        for provider in self.orchestrator.providers.iter() {
-            if let Err(e) = provider.write_meter_values(values.clone()) {
-                error!("Provider failed to write meter values: {:?}", e);
+        self.orchestrator.send(Command::SetMeters { values });
+    }
+
+    /// Forget all cached values, so that they are requested from the console again.
+    pub(crate) async fn clear_cache(&self) {
+        debug!(console = self.console_prefix.as_str(), "Clearing parameter cache");
+        self.orchestrator.send(Command::ClearCache {
+            console_prefix: self.console_prefix.clone(),
+        });
+    }
+
+    /// Whether only the parameters allowed in safe mode may be changed
+    pub fn safe_mode(&self) -> bool {
+        self.orchestrator.shared.safe_mode.is_enabled()
+    }
+
+    /// Switch safe mode for all interfaces
+    pub fn set_safe_mode(&self, enabled: bool) {
+        self.orchestrator.shared.safe_mode.set_enabled(enabled);
+    }
+
+    /// Safe mode, which can be watched for changes
+    pub fn watch_safe_mode(&self) -> watch::Receiver<bool> {
+        self.orchestrator.shared.safe_mode.watch()
+    }
+
+    /// The metrics registry of the orchestrator, shared by all interfaces
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.orchestrator.shared.metrics.clone()
+    }
+
+    /// The health of the link to the first console, which can be watched for changes
+    pub fn link_health(&self) -> watch::Receiver<LinkHealth> {
+        self.orchestrator.shared.link_health.subscribe()
+    }
+
+    /// Record the round-trip time of an answered heartbeat
+    pub(crate) fn set_round_trip(&self, round_trip: Duration) {
+        if self.console_prefix.is_empty() {
+            self.orchestrator.shared.metrics.set_round_trip(round_trip);
+            self.orchestrator
+                .shared
+                .link_health
+                .send_modify(|health| health.round_trip = Some(round_trip));
+        }
+    }
+
+    /// Broadcast a change of the console connection state to all providers. Only the link
+    /// of the first console is shown.
+    pub(crate) async fn set_link_status(&self, status: LinkStatus) {
+        if !self.console_prefix.is_empty() {
+            info!(?status, console = self.console_prefix.as_str(), "Console link status changed");
+            return;
+        }
+
+        let changed = self.orchestrator.shared.link_health.send_if_modified(|health| {
+            let changed = health.status != status;
+            health.status = status;
+            if status != LinkStatus::Online {
+                health.round_trip = None;
             }
+            changed
+        });
+        if !changed {
+            return;
         }
+
+        info!(?status, "Console link status changed");
+        self.orchestrator.send(Command::LinkStatus { status });
     }
 }
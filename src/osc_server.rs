@@ -0,0 +1,325 @@
+//! A UDP port for OSC clients such as TouchOSC, which set the parameters of the console by
+//! their paths and are sent the changes of the parameters
+//!
+//! Clients are registered when they send their first message, and are then sent the changes
+//! from the console, the surfaces and the other clients. A message without arguments asks for
+//! the value of a parameter, which is only sent back to the client that asked.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rosc::{OscMessage, OscPacket, OscType, decoder, encoder};
+use tokio::net::UdpSocket;
+use tracing::{debug, info, trace, warn};
+
+use crate::orchestrator::{Interface, LinkStatus, Value, WriteProvider};
+use crate::settings::OscServerSettings;
+use crate::transforms::Transforms;
+
+type SharedInterface = Arc<std::sync::Mutex<Option<Interface>>>;
+type SharedClients = Arc<std::sync::Mutex<Clients>>;
+
+/// Most clients that are registered at once. The one that was heard from least recently is
+/// forgotten first.
+const MAX_CLIENTS: usize = 16;
+
+/// The addresses that changes are sent to
+struct Clients {
+    /// Clients from the settings, which are never forgotten
+    fixed: Vec<SocketAddr>,
+    /// Clients that sent a message, the most recent last
+    registered: Vec<SocketAddr>,
+}
+
+impl Clients {
+    /// Remember a client that sent a message, returning whether it is new
+    fn register(&mut self, client: SocketAddr) -> bool {
+        if self.fixed.contains(&client) {
+            return false;
+        }
+
+        let known = match self.registered.iter().position(|c| *c == client) {
+            Some(index) => {
+                self.registered.remove(index);
+                true
+            }
+            None => false,
+        };
+        if self.registered.len() >= MAX_CLIENTS {
+            let forgotten = self.registered.remove(0);
+            debug!(%forgotten, "Too many OSC clients, forgetting the oldest");
+        }
+        self.registered.push(client);
+
+        !known
+    }
+
+    fn all(&self) -> impl Iterator<Item = &SocketAddr> {
+        self.fixed.iter().chain(&self.registered)
+    }
+}
+
+pub struct OscServer {
+    socket: Arc<UdpSocket>,
+    clients: SharedClients,
+    paths: Vec<String>,
+    transforms: Transforms,
+    interface: SharedInterface,
+}
+
+impl OscServer {
+    /// Listen on the port of the settings, handling the messages of the clients in the
+    /// background
+    pub async fn new(settings: &OscServerSettings) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", settings.port))
+            .await
+            .with_context(|| format!("Failed to bind OSC server UDP port {}", settings.port))?;
+        let socket = Arc::new(socket);
+
+        let fixed = settings
+            .clients
+            .iter()
+            .map(|client| {
+                client
+                    .parse()
+                    .with_context(|| format!("Invalid OSC client address {}", client))
+            })
+            .collect::<Result<Vec<SocketAddr>>>()?;
+        let clients = Arc::new(std::sync::Mutex::new(Clients {
+            fixed,
+            registered: Vec::new(),
+        }));
+
+        let transforms = Transforms::new(&settings.transforms)?;
+        let interface: SharedInterface = Arc::new(std::sync::Mutex::new(None));
+
+        tokio::spawn(run_server(
+            socket.clone(),
+            clients.clone(),
+            settings.reply_port,
+            settings.paths.clone(),
+            interface.clone(),
+        ));
+
+        info!(
+            port = settings.port,
+            clients = settings.clients.len(),
+            "OSC server listening"
+        );
+
+        Ok(Self {
+            socket,
+            clients,
+            paths: settings.paths.clone(),
+            transforms,
+            interface,
+        })
+    }
+}
+
+impl WriteProvider for OscServer {
+    fn write(&self, addr: &str, value: Value) -> anyhow::Result<()> {
+        let clients: Vec<SocketAddr> = self.clients.lock().unwrap().all().copied().collect();
+        send_value(&self.socket, addr, value, &clients)
+    }
+
+    fn write_meter_values(&self, _values: Vec<Vec<f32>>) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        "OSC".to_string()
+    }
+
+    fn write_link_status(&self, _status: LinkStatus) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn set_interface(&self, interface: Interface) {
+        let paths = self.paths.clone();
+        let transforms = self.transforms.clone();
+
+        self.interface.lock().unwrap().replace(interface.clone());
+
+        tokio::spawn(async move {
+            interface.set_transforms(transforms).await;
+
+            if !paths.is_empty() {
+                interface.subscribe(&paths).await;
+            }
+
+            // The clients from the settings are sent the current values
+            for path in paths.iter().filter(|path| !path.contains('*')) {
+                interface.request_value_notification(path, false).await;
+            }
+        });
+    }
+}
+
+/// Send a value to some clients. Clients that cannot be reached are skipped.
+fn send_value(socket: &UdpSocket, addr: &str, value: Value, clients: &[SocketAddr]) -> Result<()> {
+    if clients.is_empty() {
+        return Ok(());
+    }
+
+    let arg = match value {
+        Value::Float(f) => OscType::Float(f),
+        Value::Int(i) => OscType::Int(i),
+        Value::Str(s) => OscType::String(s),
+        Value::Bool(on) => OscType::Int(i32::from(on)),
+    };
+    let packet = OscPacket::Message(OscMessage {
+        addr: addr.to_string(),
+        args: vec![arg],
+    });
+    let buf = encoder::encode(&packet)?;
+
+    for client in clients {
+        if let Err(e) = socket.try_send_to(&buf, *client) {
+            debug!(%client, "Failed to send OSC message {}: {}", addr, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Receive the messages of the clients, registering every client that sends one
+async fn run_server(
+    socket: Arc<UdpSocket>,
+    clients: SharedClients,
+    reply_port: Option<u16>,
+    paths: Vec<String>,
+    interface: SharedInterface,
+) {
+    let mut buf = vec![0u8; decoder::MTU];
+
+    loop {
+        let (len, source) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                warn!("Error during OSC server reception: {:?}", e);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                continue;
+            }
+        };
+
+        let packet = match decoder::decode_udp(&buf[..len]) {
+            Ok((_, packet)) => packet,
+            Err(e) => {
+                warn!(%source, "Failed to decode OSC packet: {:?}", e);
+                continue;
+            }
+        };
+
+        let Some(iface) = interface.lock().unwrap().clone() else {
+            debug!(%source, "OSC message received before the interface was set");
+            continue;
+        };
+
+        let client = match reply_port {
+            Some(port) => SocketAddr::new(source.ip(), port),
+            None => source,
+        };
+        if clients.lock().unwrap().register(client) {
+            info!(%client, "OSC client registered");
+            tokio::spawn(send_current_values(
+                socket.clone(),
+                client,
+                paths.clone(),
+                iface.clone(),
+            ));
+        }
+
+        let mut packets = vec![packet];
+        while let Some(packet) = packets.pop() {
+            match packet {
+                OscPacket::Bundle(bundle) => packets.extend(bundle.content),
+                OscPacket::Message(message) => {
+                    process_message(&socket, &clients, client, &iface, message).await
+                }
+            }
+        }
+    }
+}
+
+async fn process_message(
+    socket: &Arc<UdpSocket>,
+    clients: &SharedClients,
+    client: SocketAddr,
+    interface: &Interface,
+    message: OscMessage,
+) {
+    trace!(?message, %client, "Received OSC client message");
+
+    let value = match message.args.into_iter().last() {
+        // A query, answered once the value is known
+        None => {
+            let socket = socket.clone();
+            let interface = interface.clone();
+            tokio::spawn(async move {
+                match interface.get_value(&message.addr, false).await {
+                    Ok(value) => {
+                        if let Err(e) = send_value(&socket, &message.addr, value, &[client]) {
+                            warn!("Failed to answer OSC query: {:?}", e);
+                        }
+                    }
+                    Err(e) => debug!(%client, "Failed to answer OSC query: {:#}", e),
+                }
+            });
+            return;
+        }
+        Some(OscType::Float(f)) => Value::Float(f),
+        Some(OscType::Double(d)) => Value::Float(d as f32),
+        Some(OscType::Int(i)) => Value::Int(i),
+        Some(OscType::Long(l)) => Value::Int(l as i32),
+        Some(OscType::Bool(on)) => Value::Bool(on),
+        Some(OscType::String(s)) => Value::Str(s),
+        other => {
+            warn!(
+                osc_addr = message.addr.as_str(),
+                ?other,
+                "Unexpected OSC client value"
+            );
+            return;
+        }
+    };
+
+    if !interface.may_write(&message.addr) {
+        return;
+    }
+
+    debug!(osc_addr = message.addr.as_str(), ?value, %client, "Received OSC client value");
+    interface.set_value(&message.addr, value.clone()).await;
+
+    // Values are not sent back to the interface that set them, so the other clients are sent
+    // them here
+    let others: Vec<SocketAddr> = clients
+        .lock()
+        .unwrap()
+        .all()
+        .filter(|other| **other != client)
+        .copied()
+        .collect();
+    if let Err(e) = send_value(socket, &message.addr, value, &others) {
+        warn!("Failed to send OSC value to the other clients: {:?}", e);
+    }
+}
+
+/// Send the values of the parameters without wildcards to a new client
+async fn send_current_values(
+    socket: Arc<UdpSocket>,
+    client: SocketAddr,
+    paths: Vec<String>,
+    interface: Interface,
+) {
+    for path in paths.iter().filter(|path| !path.contains('*')) {
+        let Some(value) = interface.get_cached_value(path).await else {
+            continue;
+        };
+        if let Err(e) = send_value(&socket, path, value, &[client]) {
+            warn!("Failed to send OSC value to a new client: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! MIDI definitions of the supported controllers, selected by name with the `profile` of a
+//! controller
+//!
+//! The profiles are built in, and can be replaced or extended with YAML files in a `profiles`
+//! directory next to the configuration file, e.g. `profiles/x-touch.yml`.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use tracing::debug;
+
+use crate::settings::MidiDefinition;
+
+/// The profile of controllers that do not name one
+pub const DEFAULT_PROFILE: &str = "x-touch";
+
+/// Directory with additional profiles, next to the configuration file
+pub const PROFILE_DIRECTORY: &str = "profiles";
+
+const BUILT_IN: [(&str, &str); 4] = [
+    ("x-touch", include_str!("../profiles/x-touch.yml")),
+    ("x-touch-extender", include_str!("../profiles/x-touch-extender.yml")),
+    ("x-touch-one", include_str!("../profiles/x-touch-one.yml")),
+    ("icon-mcu", include_str!("../profiles/icon-mcu.yml")),
+];
+
+/// Load a profile by its name, from the profile directory if it has one with this name, or
+/// from the built-in ones otherwise
+pub fn load(name: &str, directory: &Path) -> Result<MidiDefinition> {
+    let file = directory.join(format!("{}.yml", name));
+    if file.is_file() {
+        debug!(file = %file.display(), "Loading MIDI profile");
+        let contents = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read MIDI profile {}", file.display()))?;
+        return serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse MIDI profile {}", file.display()));
+    }
+
+    let Some((_, contents)) = BUILT_IN.iter().find(|(built_in, _)| *built_in == name) else {
+        let names: Vec<&str> = BUILT_IN.iter().map(|(name, _)| *name).collect();
+        anyhow::bail!(
+            "Unknown MIDI profile '{}', use one of {} or add {}",
+            name,
+            names.join(", "),
+            file.display()
+        );
+    };
+
+    serde_yaml::from_str(contents)
+        .with_context(|| format!("Failed to parse built-in MIDI profile '{}'", name))
+}
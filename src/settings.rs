@@ -3,61 +3,378 @@
 //! Settings can be provided via external YAML file or environment variables
 
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
+use anyhow::Context;
 use figment::Figment;
 use figment::providers::Format;
 use tracing::{Level, debug, event};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use serde_with::serde_as;
+use serde_with::{OneOrMany, formats::PreferOne, serde_as};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::data::Fader;
+use crate::midi::STRIP_COUNT;
+use crate::orchestrator::Value;
+use crate::profiles::{self, DEFAULT_PROFILE, PROFILE_DIRECTORY};
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 struct FaderAssignment {
     osc: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 struct ButtonAssignment {
     osc: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct ConsoleSettings {
-    pub ip: String,
+    /// Identifier of the console, for banks and faders routed to it, e.g. `@stream Channel 1`.
+    /// Faders use the first console unless they are routed to another one.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// The kind of console, and the protocol it speaks
+    #[serde(rename = "type", default)]
+    pub console_type: ConsoleType,
+    /// Address of the console. If not set, a WING is discovered on the network.
+    #[serde(default)]
+    pub ip: Option<String>,
+    /// Port of the console, 10023 for an X32
     pub port: u16,
+    /// Name of the console to connect to, when several are discovered
+    #[serde(default)]
+    pub name: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConsoleType {
+    #[default]
+    Wing,
+    /// An X32 or M32, over its OSC protocol
+    X32,
+    /// A WING over plain OSC instead of its native protocol, usually on port 2223. Meters
+    /// are not available.
+    WingOsc,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct FaderBank {
     pub name: Option<String>,
+    /// Fader labels such as "Channel 1", ranges such as "Bus 1-8", or raw OSC directories of
+    /// nodes with the parameters of a strip, such as "/ch/7"
     pub faders: Vec<String>,
+    /// What the meter bridge shows for the strips of this bank
+    #[serde(default)]
+    pub meter: MeterSource,
+    /// Level offsets of faders on the surface, by fader label, in dB. A trim of -6 shows
+    /// and controls a fader 6 dB lower than its level on the console.
+    #[serde(default)]
+    pub trim_db: HashMap<String, f32>,
+    /// Identifier of the console that the faders of this bank are on, if not the first one
+    #[serde(default)]
+    pub console: Option<String>,
+    /// Show only the faders that match, updated as the console changes
+    #[serde(default)]
+    pub filter: Option<BankFilter>,
 }
 
-#[serde_as]
-#[derive(Debug, Serialize, Deserialize)]
+/// Conditions on the strips of a bank, which must all be met for a strip to be shown
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BankFilter {
+    /// Strips assigned to this DCA
+    #[serde(default)]
+    pub dca: Option<u8>,
+    /// Strips that are not muted
+    #[serde(default)]
+    pub unmuted: bool,
+}
+
+/// The banks of a controller
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum Banks {
+    /// Built at startup from the strips that are named on the console
+    Auto(AutoBanks),
+    List(Vec<FaderBank>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AutoBanks {
+    Auto,
+}
+
+impl Banks {
+    pub fn is_auto(&self) -> bool {
+        matches!(self, Banks::Auto(_))
+    }
+}
+
+/// The banks as a list, which is empty until automatic banks are built
+impl std::ops::Deref for Banks {
+    type Target = [FaderBank];
+
+    fn deref(&self) -> &[FaderBank] {
+        match self {
+            Banks::Auto(_) => &[],
+            Banks::List(banks) => banks,
+        }
+    }
+}
+
+impl std::ops::DerefMut for Banks {
+    fn deref_mut(&mut self) -> &mut [FaderBank] {
+        match self {
+            Banks::Auto(_) => &mut [],
+            Banks::List(banks) => banks,
+        }
+    }
+}
+
+/// What the channel strip encoders control
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EncoderMode {
+    /// The encoders do nothing
+    #[default]
+    Off,
+    /// Preamp gain of the visible strips
+    Gain,
+    /// Levels of the first eight sends of the selected strip
+    Sends,
+    /// Gain, pan and low cut of the selected strip, whichever bank is shown
+    Focus,
+}
+
+/// Fields that are not set keep their default values
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct EncoderSettings {
+    /// Mode of the encoders on startup
+    pub mode: EncoderMode,
+    /// Gain change per encoder click, in dB
+    pub gain_step_db: f32,
+    /// Gain will not be raised above this value with the encoders, in dB
+    pub max_gain_db: f32,
+    /// Send level change per encoder click, as a fraction of the fader travel
+    pub send_step: f32,
+    /// Panning change per encoder click in focus mode
+    pub pan_step: f32,
+    /// Ratio of the low cut frequency change per encoder click in focus mode, so that each
+    /// click sounds alike
+    pub low_cut_ratio: f32,
+    /// Make fast turns move further than slow ones
+    #[serde(default)]
+    pub acceleration: Option<EncoderAcceleration>,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self {
+            mode: EncoderMode::Off,
+            gain_step_db: 1.0,
+            max_gain_db: 45.0,
+            send_step: 0.01,
+            pan_step: 2.0,
+            low_cut_ratio: 1.05,
+            acceleration: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
+pub(crate) struct EncoderAcceleration {
+    /// Clicks closer together than this are accelerated, more so the closer they are
+    pub window_ms: u64,
+    /// The largest number of steps a single click can move
+    pub max_multiplier: f32,
+}
+
+/// The part of the console's meter data shown on the meter bridge
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MeterSource {
+    /// Signal level
+    #[default]
+    Level,
+    /// Compressor/dynamics gain reduction
+    GainReduction,
+    /// Gate attenuation
+    Gate,
+}
+
+/// Fields that are not set are taken from the X-Touch assignments
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
 pub(crate) struct ControllerAssignments {
-    pub banks: Vec<FaderBank>,
+    /// The banks of strips, or "auto" to build them from the strips in use on the console
+    pub banks: Banks,
+    /// Strip buttons that toggle the parameter of the same name of their strip, by their
+    /// description in the MIDI definition: "Mute" or "Solo"
     pub fader_buttons: Vec<String>,
 
+    /// Faders that do not change with the bank, such as the master fader, by MIDI channel
+    #[serde_as(as = "Vec<(_, _)>")]
     pub fixed_faders: HashMap<u32, String>,
     #[serde_as(as = "Vec<(_, _)>")]
     pub fixed_buttons: HashMap<u32, String>,
+    /// Alternate functions, triggered when a button is held down
+    #[serde_as(as = "Vec<(_, _)>")]
+    pub long_press_buttons: HashMap<u32, String>,
+    /// How long a button must be held to trigger its long-press function
+    pub long_press_ms: u64,
+
+    /// Fader label controlled by the expression pedal, if any
+    pub expression_pedal: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct ControllerSettings {
     pub input: String,
     pub output: String,
 
+    /// Name of the MIDI definition of the controller, such as "x-touch", "x-touch-extender",
+    /// "x-touch-one" or "icon-mcu" ("x-touch" if not set)
+    #[serde(default)]
+    pub profile: Option<String>,
+
+    #[serde(default)]
     pub assignments: ControllerAssignments,
+
+    /// Scroll names that do not fit on a scribble strip
+    #[serde(default)]
+    pub scribble_marquee: bool,
+
+    /// Use the Rec LEDs to show when a strip's level is above this threshold, in dB
+    #[serde(default)]
+    pub signal_present_db: Option<f32>,
+
+    /// Put the surface to sleep after a period of inactivity
+    #[serde(default)]
+    pub sleep: Option<SleepSettings>,
+
+    /// How WING scribble colours are shown on the X-Touch
+    #[serde(default)]
+    pub colours: ColourSettings,
+
+    /// Maximum number of MIDI messages sent to the controller per second, for slow links
+    #[serde(default)]
+    pub max_messages_per_sec: Option<u32>,
+
+    /// What the channel strip encoders control
+    #[serde(default)]
+    pub encoders: EncoderSettings,
+
+    /// Show the name of the active scene on the main display, instead of the bank name
+    #[serde(default)]
+    pub scene_on_main_display: bool,
+
+    /// The parameters that the controller may read and write, all if not set
+    #[serde(default)]
+    pub access: Option<AccessSettings>,
+
+    /// Move all faders to the bottom when the bridge exits
+    #[serde(default)]
+    pub park_faders_on_exit: bool,
+
+    /// Control the monitor level with the master fader, instead of its fixed fader
+    #[serde(default)]
+    pub monitor_on_master: bool,
+
+    /// Strip buttons that toggle the main bus assignment of their strip when pressed
+    /// together, such as holding Select and pressing Mute
+    #[serde(default)]
+    pub main_assign_combo: Option<ButtonCombo>,
+
+    /// Level change of the nudge functions, in dB (0.5 dB if not set)
+    #[serde(default)]
+    pub nudge_step_db: Option<f32>,
+
+    /// Ignore console fader updates that are very close to the fader's position, so that
+    /// the motors do not twitch
+    #[serde(default)]
+    pub fader_deadband: Option<FaderDeadband>,
+
+    /// Switch banks when the layer selection changes on the console itself
+    #[serde(default)]
+    pub follow_layer: Option<LayerFollowSettings>,
+
+    /// Keep the selected strip in sync with the channel selected on the console
+    #[serde(default)]
+    pub follow_selection: bool,
+}
+
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LayerFollowSettings {
+    /// OSC parameter with the index of the selected layer on the console
+    pub osc: String,
+    /// The name of the bank shown for each layer index
+    #[serde_as(as = "Vec<(_, _)>")]
+    pub banks: HashMap<i32, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FaderDeadband {
+    /// Updates closer than this to the fader's level are ignored, in dB
+    pub db: f32,
+    /// Updates closer than this to the fader's position are ignored, in 14-bit MIDI counts
+    pub counts: u16,
+}
+
+/// Two buttons of the same strip, by their description in the MIDI definition
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ButtonCombo {
+    /// The button that is held down
+    pub hold: String,
+    /// The button that is pressed while the other one is held
+    pub press: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SleepSettings {
+    /// Seconds without any MIDI or OSC activity before the surface sleeps
+    pub timeout_secs: u64,
+    /// Move all faders to the bottom while sleeping
+    #[serde(default)]
+    pub park_faders: bool,
+}
+
+/// X-Touch scribble colours are 0 (off), 1 (red), 2 (green), 3 (yellow), 4 (blue),
+/// 5 (magenta), 6 (cyan) and 7 (white)
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ColourSettings {
+    /// The X-Touch colour for each WING colour index
+    pub table: Vec<u8>,
+    /// The X-Touch colour for WING colours missing from the table
+    pub fallback: u8,
+}
+
+impl Default for ColourSettings {
+    fn default() -> Self {
+        Self {
+            table: vec![0, 7, 6, 4, 7, 2, 2, 3, 3, 1, 1, 5, 5],
+            fallback: 7,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct MidiButton {
     pub channel: u8,
@@ -65,7 +382,7 @@ pub(crate) struct MidiButton {
     pub description: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct MidiFader {
     pub channel: u8,
@@ -73,452 +390,555 @@ pub(crate) struct MidiFader {
     pub description: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct MidiDefinition {
     pub faders: Vec<MidiFader>,
     pub buttons: Vec<MidiButton>,
+    /// Control Change number sent by the expression pedal input
+    pub expression_pedal: Option<u8>,
+    /// Relative encoders of the channel strips, in strip order
+    #[serde(default)]
+    pub encoders: Vec<MidiEncoder>,
+}
+
+impl MidiDefinition {
+    /// Number of channel strips, which are the faders before those on the channels after the
+    /// strips, like the master fader
+    pub fn strip_count(&self) -> usize {
+        self.faders
+            .iter()
+            .take_while(|fader| (fader.channel as usize) < STRIP_COUNT)
+            .count()
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MidiEncoder {
+    /// Control Change sent when the encoder is turned
+    pub cc: u8,
+    /// Control Change that sets the LED ring around the encoder
+    pub ring_cc: u8,
+}
+
+/// Connection to an MQTT broker, where parameter values are published and can be changed
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct MqttSettings {
+    #[serde(default)]
+    pub enabled: bool,
     pub host: String,
     pub port: u16,
+    /// Identifier of the bridge on the broker, which must be unique
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Password of the user, which can also be set with the `WING_MQTT_PASSWORD` environment
+    /// variable
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Connect with TLS
+    #[serde(default)]
+    pub tls: Option<MqttTlsSettings>,
+    /// First segment of all topics, e.g. `xtouchwing/state/ch/1/fdr`
+    pub topic_prefix: String,
+    /// Path patterns of the parameters that are published, e.g. `/ch/*/fdr`, all if empty
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// The parameters that MQTT clients may read and write, all if not set
+    #[serde(default)]
+    pub access: Option<AccessSettings>,
+    /// Announce the strips of the banks to Home Assistant with MQTT discovery
+    #[serde(default)]
+    pub home_assistant: bool,
+    /// Unit of the fader levels on MQTT
+    #[serde(default)]
+    pub fader_unit: MqttFaderUnit,
+    /// Custom topics of parameters, which are then not published to the default topics. The
+    /// first mapping whose path matches is used.
+    #[serde(default)]
+    pub topics: Vec<MqttTopicMapping>,
+    /// Fader labels of the strips whose meter levels are published in dB, e.g. `Main 1`
+    #[serde(default)]
+    pub meters: Vec<String>,
+    /// How often the meters are published, with the peak level since the last time
+    pub meter_interval_ms: u64,
+    /// How often the status of the bridge is published to `<prefix>/status`, or 0 to not
+    /// publish it
+    pub status_interval_secs: u64,
+}
+
+/// Topics of the parameters that match a path pattern, such as `/ch/*/fdr`. Each `*` segment
+/// is available as `{1}`, `{2}` and so on in the topics.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MqttTopicMapping {
+    pub path: String,
+    /// Topic that the values are published to, e.g. `studio/channel/{1}/level`
+    pub topic: String,
+    /// Topic that values are written from, none if not set
+    #[serde(default)]
+    pub command_topic: Option<String>,
+    /// Payload with `{value}` in place of the JSON value, e.g. `{"level": {value}}`, in both
+    /// directions. Just the value if not set.
+    #[serde(default)]
+    pub payload: Option<String>,
+}
+
+/// Certificates for a TLS connection to the broker, as PEM files
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MqttTlsSettings {
+    /// Certificate authority of the broker, the ones trusted by the system if not set
+    #[serde(default)]
+    pub ca_file: Option<String>,
+    /// Certificate and private key of the client, for brokers that authenticate clients by
+    /// their certificate
+    #[serde(default)]
+    pub cert_file: Option<String>,
+    #[serde(default)]
+    pub key_file: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MqttFaderUnit {
+    /// The level in dB, as on the console
+    #[default]
+    Db,
+    /// The fader position in percent of its travel
+    Percent,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: "xtouch-wing".to_string(),
+            username: None,
+            password: None,
+            tls: None,
+            topic_prefix: "xtouchwing".to_string(),
+            paths: Vec::new(),
+            access: None,
+            home_assistant: false,
+            fader_unit: MqttFaderUnit::Db,
+            topics: Vec::new(),
+            meters: Vec::new(),
+            meter_interval_ms: 1000,
+            status_interval_secs: 30,
+        }
+    }
+}
+
+/// A secondary, general-purpose MIDI controller with freely mapped controls
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GenericMidiSettings {
+    pub input: String,
+    /// Output port for value feedback, e.g. to motorised faders or LED rings
+    #[serde(default)]
+    pub output: Option<String>,
+    pub mappings: Vec<GenericMidiMapping>,
+    /// Conversions of the values of parameters, before they are scaled to MIDI
+    #[serde(default)]
+    pub transforms: Vec<ValueTransform>,
+    /// The parameters that the controller may read and write, all if not set
+    #[serde(default)]
+    pub access: Option<AccessSettings>,
+}
+
+/// A UDP port for OSC clients such as TouchOSC, which set the parameters of the console by
+/// their paths and are sent their changes
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct OscServerSettings {
+    /// Port that the clients send to
+    pub port: u16,
+    /// Port that the clients receive on, if it is not the one they send from
+    #[serde(default)]
+    pub reply_port: Option<u16>,
+    /// Clients that are sent the changes from the start, e.g. `192.168.1.20:9000`. Other
+    /// clients are sent them once they send a message.
+    #[serde(default)]
+    pub clients: Vec<String>,
+    /// Path patterns of the parameters that are sent to the clients, all if empty
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Conversions of the values of parameters, e.g. of fader levels to the 0..1 range of
+    /// TouchOSC faders
+    #[serde(default)]
+    pub transforms: Vec<ValueTransform>,
+    /// The parameters that the clients may read and write, all if not set
+    #[serde(default)]
+    pub access: Option<AccessSettings>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Path patterns of the parameters that a provider may read and write, e.g. `/bus/*/fdr`.
+/// Leaving out a list allows all parameters, an empty list allows none.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AccessSettings {
+    #[serde(default)]
+    pub read: Option<Vec<String>>,
+    #[serde(default)]
+    pub write: Option<Vec<String>>,
+}
+
+/// Converts the values of the parameters that match a path pattern between the console and a
+/// provider. Values from the console are scaled, offset, clamped to `min`..`max`, shaped by
+/// the curve and inverted, in this order. Values to the console are converted back.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ValueTransform {
+    /// Parameter path, where `*` matches within a segment and a last `**` segment matches any
+    /// remaining segments, e.g. `/main/*/fdr`
+    pub path: String,
+    /// Factor the console value is multiplied with, 1 if not set
+    #[serde(default)]
+    pub scale: Option<f32>,
+    #[serde(default)]
+    pub offset: f32,
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+    /// Exponent of the position within `min`..`max`, 1 (linear) if not set
+    #[serde(default)]
+    pub curve: Option<f32>,
+    /// Reverse the `min`..`max` range
+    #[serde(default)]
+    pub invert: bool,
+}
+
+/// The kind of MIDI message a generic mapping listens to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GenericMidiMessage {
+    ControlChange,
+    Note,
+    PitchBend,
+}
+
+/// Binds a MIDI message to an OSC parameter, scaling the MIDI range to `min`..`max`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GenericMidiMapping {
+    pub message: GenericMidiMessage,
+    /// MIDI channel, 0-15
+    pub channel: u8,
+    /// Controller or note number, ignored for pitch bend
+    #[serde(default)]
+    pub number: u8,
+    pub osc: String,
+    #[serde(default)]
+    pub min: f32,
+    pub max: f32,
+    /// Send integer values, e.g. for on/off parameters
+    #[serde(default)]
+    pub integer: bool,
+}
+
+/// A single step of a user-defined macro
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MacroStep {
+    pub osc: String,
+    pub value: Value,
+    /// Time to wait before executing this step
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct Settings {
     pub faders: [FaderAssignment; 8],
     pub master: FaderAssignment,
-    pub console: ConsoleSettings,
-    pub midi: ControllerSettings,
-    pub midi_definition: MidiDefinition,
+    /// One console, or a list of consoles that banks can be routed to
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    pub console: Vec<ConsoleSettings>,
+    /// One controller, or a list of controllers that each have their own banks
+    #[serde_as(as = "OneOrMany<_, PreferOne>")]
+    pub midi: Vec<ControllerSettings>,
+    /// A MIDI definition for all controllers, instead of the ones of their profiles
+    #[serde(default)]
+    pub midi_definition: Option<MidiDefinition>,
+    /// The MIDI definition of each controller, once loaded from its profile
+    #[serde(skip)]
+    pub midi_definitions: Vec<MidiDefinition>,
     pub mqtt: MqttSettings,
+    /// Named macros that can be bound to buttons
+    pub macros: HashMap<String, Vec<MacroStep>>,
+    /// Additional MIDI controllers with generic mappings
+    pub generic_midi: Vec<GenericMidiSettings>,
+    /// A port for OSC clients such as TouchOSC
+    #[serde(default)]
+    pub osc_server: Option<OscServerSettings>,
+    /// Fader labels of the strips muted by the Mute All function, e.g. as a panic control
+    pub mute_all: Vec<String>,
+    /// The cue list, stepped through with the GO and BACK functions
+    pub cues: Vec<CueSettings>,
+    pub snapshots: SnapshotSettings,
+    /// Parameters that are kept at the same value
+    #[serde(default)]
+    pub links: Vec<LinkSettings>,
+    #[serde(default)]
+    pub history: HistorySettings,
+    /// How long to wait for the console to answer
+    #[serde(default)]
+    pub timeouts: TimeoutSettings,
+    #[serde(default)]
+    pub safe_mode: SafeModeSettings,
+    /// File where the bank, encoder mode and lock of each surface are kept, so that they are
+    /// restored after a restart
+    #[serde(default)]
+    pub state_file: Option<String>,
+    /// Other configuration files merged before this one, in order, e.g. a shared base and
+    /// the banks of a show. Settings of later files replace those of earlier ones.
+    #[serde(default)]
+    pub include: Vec<String>,
+}
+
+/// The includes of a configuration file, read before the whole configuration is merged
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Includes {
+    include: Vec<String>,
+}
+
+/// Safe mode only allows a few parameters to be changed, e.g. to protect the main mix while
+/// volunteers use the surface. It can be switched with a button at runtime.
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SafeModeSettings {
+    /// Start in safe mode
+    #[serde(default)]
+    pub enabled: bool,
+    /// Path patterns of the parameters that may be changed in safe mode, e.g. `/bus/*/fdr`
+    #[serde(default)]
+    pub paths: Vec<String>,
+}
+
+/// Timeouts of requests to the console in milliseconds. Operations without a timeout of their
+/// own use the OSC timeout.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TimeoutSettings {
+    pub osc_ms: u64,
+    /// Identifying the console after connecting
+    #[serde(default)]
+    pub identify_ms: Option<u64>,
+    /// Requesting the values of a bank when it is shown
+    #[serde(default)]
+    pub bank_ms: Option<u64>,
+    /// Values that a control needs right away, e.g. to adjust a parameter with a knob
+    #[serde(default)]
+    pub interactive_ms: Option<u64>,
+}
+
+impl Default for TimeoutSettings {
+    fn default() -> Self {
+        Self {
+            osc_ms: 100,
+            identify_ms: None,
+            bank_ms: None,
+            interactive_ms: None,
+        }
+    }
+}
+
+/// Fields that are not set keep their default values
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct HistorySettings {
+    /// Number of changes kept, the oldest are dropped first. 0 disables the history.
+    pub size: usize,
+    /// File where the history is stored, so that it can be read after the bridge is stopped.
+    /// Null keeps the history only in memory.
+    pub file: Option<String>,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            size: 5000,
+            file: Some("history.yml".to_string()),
+        }
+    }
+}
+
+/// Parameters that follow each other, e.g. the levels of two language feeds
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LinkSettings {
+    pub paths: Vec<String>,
+    /// Offset of each parameter in the order of `paths`, e.g. in dB for levels. None if empty.
+    #[serde(default)]
+    pub offsets: Vec<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SnapshotSettings {
+    /// Directory where snapshots are stored
+    pub directory: String,
+    /// OSC parameters stored in snapshots. If empty, all known parameters are stored.
+    /// Patterns such as `/ch/*/mute` store the known parameters that match them.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// OSC parameters switched by the A/B compare function
+    #[serde(default)]
+    pub ab_compare: Vec<String>,
+}
+
+impl Default for SnapshotSettings {
+    fn default() -> Self {
+        Self {
+            directory: "snapshots".to_string(),
+            paths: Vec::new(),
+            ab_compare: Vec::new(),
+        }
+    }
+}
+
+/// A step of the cue list. The scene is recalled first, then the parameters are set and
+/// the fades started.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CueSettings {
+    pub name: String,
+    /// Scene to recall, by its index in the show
+    #[serde(default)]
+    pub scene: Option<i32>,
+    #[serde(default)]
+    pub set: Vec<CueSet>,
+    #[serde(default)]
+    pub fades: Vec<CueFade>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CueSet {
+    pub osc: String,
+    pub value: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CueFade {
+    /// Fader label, e.g. "Main 1"
+    pub fader: String,
+    /// Level at the end of the fade, in dB
+    pub level_db: f32,
+    pub time_secs: f32,
+}
+
+impl Default for ControllerAssignments {
+    fn default() -> Self {
+        Self::x_touch_full()
+    }
+}
+
+impl FaderBank {
+    /// A bank with a name and the faders of a label or range, e.g. "Channel 1-8"
+    pub fn new(name: &str, faders: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            faders: vec![faders.to_string()],
+            meter: MeterSource::Level,
+            trim_db: HashMap::new(),
+            console: None,
+            filter: None,
+        }
+    }
+
+    /// The banks of this bank's faders once ranges such as "Bus 1..16" are expanded. Banks
+    /// with ranges are split into banks of at most `size` faders, while banks that list their
+    /// faders one by one are kept whole.
+    pub fn expand(self, size: usize) -> anyhow::Result<Vec<FaderBank>> {
+        let mut faders = Vec::new();
+        let mut has_range = false;
+        for label in &self.faders {
+            match Fader::expand_label_range(label)? {
+                Some(labels) => {
+                    faders.extend(labels);
+                    has_range = true;
+                }
+                None => faders.push(label.clone()),
+            }
+        }
+
+        let bank = FaderBank { faders, ..self };
+        if has_range {
+            Ok(bank.split(size))
+        } else {
+            Ok(vec![bank])
+        }
+    }
+
+    /// This bank as banks of at most `size` faders. Only the first bank keeps the name as it
+    /// is, and filtered banks are not split as they usually show fewer faders.
+    pub fn split(self, size: usize) -> Vec<FaderBank> {
+        if self.faders.len() <= size || self.filter.is_some() {
+            return vec![self];
+        }
+
+        self.faders
+            .chunks(size)
+            .enumerate()
+            .map(|(part, faders)| FaderBank {
+                name: match (&self.name, part) {
+                    (name, 0) => name.clone(),
+                    (name, part) => name.as_ref().map(|name| format!("{} ({})", name, part + 1)),
+                },
+                faders: faders.to_vec(),
+                meter: self.meter,
+                trim_db: self.trim_db.clone(),
+                console: self.console.clone(),
+                filter: None,
+            })
+            .collect()
+    }
 }
 
 impl ControllerAssignments {
     /// Example MIDI assignments for Behringer X-Touch
     fn x_touch_full() -> Self {
         ControllerAssignments {
-            banks: vec![
-                FaderBank {
-                    name: Some("CH 1-8".to_string()),
-                    faders: (1..=8).map(|i| format!("Channel {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("CH 9-16".to_string()),
-                    faders: (9..=16).map(|i| format!("Channel {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("CH 17-24".to_string()),
-                    faders: (17..=24).map(|i| format!("Channel {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("CH 25-32".to_string()),
-                    faders: (25..=32).map(|i| format!("Channel {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("CH 33-40".to_string()),
-                    faders: (33..=40).map(|i| format!("Channel {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("AUX 1-8".to_string()),
-                    faders: (1..=8).map(|i| format!("Aux {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("BUS 1-8".to_string()),
-                    faders: (1..=8).map(|i| format!("Bus {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("BUS 9-16".to_string()),
-                    faders: (9..=16).map(|i| format!("Bus {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("MAIN".to_string()),
-                    faders: (1..=4).map(|i| format!("Main {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("MATRIX".to_string()),
-                    faders: (1..=8).map(|i| format!("Matrix {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("DCA 1-8".to_string()),
-                    faders: (1..=8).map(|i| format!("DCA {}", i)).collect(),
-                },
-                FaderBank {
-                    name: Some("DCA 9-16".to_string()),
-                    faders: (9..=16).map(|i| format!("DCA {}", i)).collect(),
-                },
-            ],
-            fader_buttons: vec!["Rec".to_string(), "Solo".to_string(), "Mute".to_string()],
-            fixed_faders: HashMap::new(),
+            banks: Banks::List(vec![
+                FaderBank::new("CH 1-8", "Channel 1-8"),
+                FaderBank::new("CH 9-16", "Channel 9-16"),
+                FaderBank::new("CH 17-24", "Channel 17-24"),
+                FaderBank::new("CH 25-32", "Channel 25-32"),
+                FaderBank::new("CH 33-40", "Channel 33-40"),
+                FaderBank::new("AUX 1-8", "Aux 1-8"),
+                FaderBank::new("BUS 1-8", "Bus 1-8"),
+                FaderBank::new("BUS 9-16", "Bus 9-16"),
+                FaderBank::new("MAIN", "Main 1-4"),
+                FaderBank::new("MATRIX", "Matrix 1-8"),
+                FaderBank::new("DCA 1-8", "DCA 1-8"),
+                FaderBank::new("DCA 9-16", "DCA 9-16"),
+            ]),
+            fader_buttons: vec!["Solo".to_string(), "Mute".to_string()],
+            fixed_faders: HashMap::from([(8, "Main 1".to_string())]),
             fixed_buttons: HashMap::from([
                 (46, "Previous Bank".to_string()),
                 (47, "Next Bank".to_string()),
+                (48, "Previous Channel".to_string()),
+                (49, "Next Channel".to_string()),
             ]),
+            long_press_buttons: HashMap::new(),
+            long_press_ms: 600,
+            expression_pedal: None,
         }
     }
 }
 
-impl MidiDefinition {
-    /// Example MIDI definition for Behringer X-Touch
-    fn x_touch_full() -> Self {
-        // TODO: Add touch 104...112
-        let channel_buttons = ["Rec", "Solo", "Mute", "Select", "Encoder Push"];
-
-        let mut faders: Vec<MidiFader> = (0..8)
-            .map(|ch| MidiFader {
-                channel: ch,
-                buttons: channel_buttons
-                    .iter()
-                    .enumerate()
-                    .map(|(btn, &name)| MidiButton {
-                        channel: ch,
-                        key: ch + btn as u8 * 8,
-                        description: Some(name.to_string()),
-                    })
-                    .collect(),
-                description: Some(format!("Channel {}", ch + 1)),
-            })
-            .collect();
-
-        faders.push(MidiFader {
-            channel: 8.into(),
-            buttons: vec![],
-            description: Some("Master Fader".to_string()),
-        });
-
-        let buttons = vec![
-            // Encoder Assign
-            MidiButton {
-                channel: 0,
-                key: 40,
-                description: Some("Track".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 42,
-                description: Some("Pan".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 44,
-                description: Some("EQ".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 41,
-                description: Some("Send".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 43,
-                description: Some("Plug-In".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 45,
-                description: Some("Inst".to_string()),
-            },
-            // LCD Display
-            MidiButton {
-                channel: 0,
-                key: 52,
-                description: Some("Name/Value".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 53,
-                description: Some("SMTPE".to_string()),
-            },
-            // Fader Assign
-            MidiButton {
-                channel: 0,
-                key: 51,
-                description: Some("Global View".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 62,
-                description: Some("MIDI Tracks".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 63,
-                description: Some("Inputs".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 64,
-                description: Some("Audio Tracks".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 65,
-                description: Some("Audio Inst".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 66,
-                description: Some("Aux".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 67,
-                description: Some("Buses".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 68,
-                description: Some("Outputs".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 69,
-                description: Some("User".to_string()),
-            },
-            // Main Fader
-            MidiButton {
-                channel: 0,
-                key: 50,
-                description: Some("Flip".to_string()),
-            },
-            // Function
-            MidiButton {
-                channel: 0,
-                key: 54,
-                description: Some("F1".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 55,
-                description: Some("F2".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 56,
-                description: Some("F3".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 57,
-                description: Some("F4".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 58,
-                description: Some("F5".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 59,
-                description: Some("F6".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 60,
-                description: Some("F7".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 61,
-                description: Some("F8".to_string()),
-            },
-            // Modify
-            MidiButton {
-                channel: 0,
-                key: 70,
-                description: Some("Shift".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 71,
-                description: Some("Option".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 72,
-                description: Some("Ctrl".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 73,
-                description: Some("Alt".to_string()),
-            },
-            // Automation
-            MidiButton {
-                channel: 0,
-                key: 74,
-                description: Some("Read/Off".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 75,
-                description: Some("Write".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 76,
-                description: Some("Trim".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 77,
-                description: Some("Touch".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 78,
-                description: Some("Latch".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 79,
-                description: Some("Group".to_string()),
-            },
-            // Utility
-            MidiButton {
-                channel: 0,
-                key: 80,
-                description: Some("Save".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 81,
-                description: Some("Undo".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 82,
-                description: Some("Cancel".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 83,
-                description: Some("Enter".to_string()),
-            },
-            // Transport
-            MidiButton {
-                channel: 0,
-                key: 84,
-                description: Some("Marker".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 85,
-                description: Some("Nudge".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 86,
-                description: Some("Cycle".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 87,
-                description: Some("Drop".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 88,
-                description: Some("Replace".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 89,
-                description: Some("Click".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 90,
-                description: Some("Solo".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 91,
-                description: Some("Rewind".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 92,
-                description: Some("Forward".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 93,
-                description: Some("Stop".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 94,
-                description: Some("Play".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 95,
-                description: Some("Record".to_string()),
-            },
-            // Fader Bank
-            MidiButton {
-                channel: 0,
-                key: 46,
-                description: Some("Bank Left".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 47,
-                description: Some("Bank Right".to_string()),
-            },
-            // Channel
-            MidiButton {
-                channel: 0,
-                key: 48,
-                description: Some("Channel Left".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 49,
-                description: Some("Channel Right".to_string()),
-            },
-            // Navigation
-            MidiButton {
-                channel: 0,
-                key: 96,
-                description: Some("Up".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 97,
-                description: Some("Down".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 98,
-                description: Some("Left".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 99,
-                description: Some("Right".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 100,
-                description: Some("Enter".to_string()),
-            },
-            MidiButton {
-                channel: 0,
-                key: 101,
-                description: Some("Scrub".to_string()),
-            },
-        ];
-
-        MidiDefinition { faders, buttons }
-    }
-}
-
 impl Default for Settings {
     fn default() -> Self {
         Settings {
@@ -551,36 +971,200 @@ impl Default for Settings {
             master: FaderAssignment {
                 osc: "dca.1.fdr".to_string(),
             },
-            console: ConsoleSettings {
-                ip: "127.0.0.1".to_string(),
+            console: vec![ConsoleSettings {
+                id: None,
+                console_type: ConsoleType::Wing,
+                ip: None,
                 port: 2223,
-            },
-            midi: ControllerSettings {
+                name: None,
+            }],
+            midi: vec![ControllerSettings {
                 input: "X-Touch".to_string(),
                 output: "X-Touch".to_string(),
+                profile: None,
                 assignments: ControllerAssignments::x_touch_full(),
-            },
-            midi_definition: MidiDefinition::x_touch_full(),
-            mqtt: MqttSettings {
-                host: "localhost".to_string(),
-                port: 1883,
-            },
+                scribble_marquee: false,
+                signal_present_db: None,
+                sleep: None,
+                colours: ColourSettings::default(),
+                max_messages_per_sec: None,
+                encoders: EncoderSettings::default(),
+                scene_on_main_display: false,
+                access: None,
+                park_faders_on_exit: false,
+                monitor_on_master: false,
+                main_assign_combo: None,
+                nudge_step_db: None,
+                fader_deadband: None,
+                follow_layer: None,
+                follow_selection: false,
+            }],
+            midi_definition: None,
+            midi_definitions: Vec::new(),
+            mqtt: MqttSettings::default(),
+            macros: HashMap::new(),
+            generic_midi: Vec::new(),
+            osc_server: None,
+            mute_all: (1..=4).map(|i| format!("Main {}", i)).collect(),
+            cues: Vec::new(),
+            snapshots: SnapshotSettings::default(),
+            links: Vec::new(),
+            history: HistorySettings::default(),
+            timeouts: TimeoutSettings::default(),
+            safe_mode: SafeModeSettings::default(),
+            state_file: None,
+            include: Vec::new(),
         }
     }
 }
 
+/// A JSON Schema of the configuration file, for editors to complete and validate it
+pub fn json_schema() -> anyhow::Result<String> {
+    let mut schema = serde_json::to_value(schemars::schema_for!(Settings))?;
+    // Settings that are not in the file are filled in from the defaults, at any depth
+    remove_required(&mut schema);
+    Ok(serde_json::to_string_pretty(&schema)?)
+}
+
+/// Remove the lists of required properties from a schema and all schemas within it
+fn remove_required(schema: &mut serde_json::Value) {
+    match schema {
+        serde_json::Value::Object(object) => {
+            object.remove("required");
+            object.values_mut().for_each(remove_required);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(remove_required),
+        _ => {}
+    }
+}
+
+/// Name of the configuration file in each of the searched directories
+const CONFIG_FILE_NAME: &str = "config.yml";
+/// Directory of the configuration file within the user and system configuration directories
+const CONFIG_DIRECTORY: &str = "xtouch-wing";
+
+/// The configuration file to load: the given one, or the first one that exists of
+/// `config.yml` in the working directory, `$XDG_CONFIG_HOME/xtouch-wing/config.yml` (by
+/// default in `~/.config`) and `/etc/xtouch-wing/config.yml`
+pub fn find_config(path: Option<&Path>) -> anyhow::Result<PathBuf> {
+    if let Some(path) = path {
+        if !path.is_file() {
+            anyhow::bail!("Configuration file {} not found", path.display());
+        }
+        return Ok(path.to_path_buf());
+    }
+
+    let user_config = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    let mut candidates = vec![PathBuf::from(CONFIG_FILE_NAME)];
+    if let Some(dir) = user_config {
+        candidates.push(dir.join(CONFIG_DIRECTORY).join(CONFIG_FILE_NAME));
+    }
+    candidates.push(Path::new("/etc").join(CONFIG_DIRECTORY).join(CONFIG_FILE_NAME));
+
+    if let Some(found) = candidates.iter().find(|candidate| candidate.is_file()) {
+        return Ok(found.clone());
+    }
+
+    let searched: Vec<String> = candidates
+        .iter()
+        .map(|candidate| format!("  {}", candidate.display()))
+        .collect();
+    anyhow::bail!(
+        "No configuration file found, pass one with --config or create one of:\n{}",
+        searched.join("\n")
+    )
+}
+
 impl Settings {
-    pub fn new() -> Result<Self, figment::Error> {
+    pub fn new(path: &Path) -> anyhow::Result<Self> {
         // println!("{}", serde_yaml::to_string(&Settings::default()).unwrap());
 
-        let settings: Settings = Figment::new()
-            .merge(figment::providers::Serialized::defaults(Settings::default()))
-            .merge(figment::providers::Yaml::file("config.yml"))
+        let mut files = Vec::new();
+        Self::collect_includes(path, &mut Vec::new(), &mut files)?;
+
+        let mut figment =
+            Figment::new().merge(figment::providers::Serialized::defaults(Settings::default()));
+        for file in &files {
+            figment = figment.merge(figment::providers::Yaml::file_exact(file));
+        }
+        let mut settings: Settings = figment
             .merge(figment::providers::Env::prefixed("WING_").split("_"))
             .extract()?;
+        settings.expand_fader_ranges()?;
+        let directory = path.parent().unwrap_or(Path::new("")).join(PROFILE_DIRECTORY);
+        settings.load_profiles(&directory)?;
 
-        event!(Level::INFO, settings = ?settings, "Loaded settings");
+        event!(Level::INFO, file = %path.display(), settings = ?settings, "Loaded settings");
 
         Ok(settings)
     }
+
+    /// The files of a configuration in the order they are merged: the files it includes, each
+    /// after its own includes, and then the file itself
+    fn collect_includes(
+        path: &Path,
+        including: &mut Vec<PathBuf>,
+        files: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<()> {
+        if including.iter().any(|file| file == path) {
+            anyhow::bail!("Configuration file {} includes itself", path.display());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read configuration file {}", path.display()))?;
+        // Empty files have no includes, nor anything else
+        let includes = serde_yaml::from_str::<Option<Includes>>(&contents)
+            .with_context(|| format!("Failed to parse configuration file {}", path.display()))?
+            .map(|includes| includes.include)
+            .unwrap_or_default();
+
+        including.push(path.to_path_buf());
+        for include in includes {
+            // Included files are relative to the file that includes them
+            let include = path.parent().unwrap_or(Path::new("")).join(include);
+            Self::collect_includes(&include, including, files)?;
+        }
+        including.pop();
+
+        files.push(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Load the MIDI definition of each controller from its profile, unless the configuration
+    /// has its own definition
+    pub fn load_profiles(&mut self, directory: &Path) -> anyhow::Result<()> {
+        self.midi_definitions = self
+            .midi
+            .iter()
+            .map(|midi| match &self.midi_definition {
+                Some(definition) => Ok(definition.clone()),
+                None => {
+                    let name = midi.profile.as_deref().unwrap_or(DEFAULT_PROFILE);
+                    profiles::load(name, directory)
+                }
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(())
+    }
+
+    /// Expand the fader ranges in the banks, splitting the banks whose ranges make them longer
+    /// than the surface
+    pub fn expand_fader_ranges(&mut self) -> anyhow::Result<()> {
+        for midi in &mut self.midi {
+            // Banks that are built from the console are expanded once they are built
+            let Banks::List(list) = &mut midi.assignments.banks else {
+                continue;
+            };
+            let mut banks = Vec::new();
+            for bank in std::mem::take(list) {
+                banks.extend(bank.expand(STRIP_COUNT)?);
+            }
+            *list = banks;
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,342 @@
+//! A terminal emulation of the X-Touch over a pair of virtual MIDI ports, so that the
+//! bridge can be used without any hardware.
+//!
+//! The surface is redrawn whenever it changes. Input is read from stdin, one command per
+//! line:
+//! - `f <strip> <0-100>`: move a fader (strip 9 is the master fader)
+//! - `p <note>`: press and release a button
+//! - `d <note>` / `u <note>`: press or release a button
+//! - `t <strip>`: touch or release a fader
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
+use midly::live::LiveEvent;
+use midly::{MidiMessage, PitchBend};
+use tracing::{debug, info, warn};
+
+use crate::midi::ASCII_TO_7SEGMENT;
+
+/// Name of the virtual ports, as shown to other MIDI clients
+const PORT_NAME: &str = "X-Touch Wing Simulator";
+
+/// How often the terminal is redrawn, if anything has changed
+const RENDER_INTERVAL: Duration = Duration::from_millis(100);
+
+const STRIP_COUNT: usize = 8;
+const SCRIBBLE_ROW_LEN: usize = 7;
+
+/// Single-letter names of the X-Touch scribble colours
+const COLOUR_NAMES: [char; 8] = ['-', 'R', 'G', 'Y', 'B', 'M', 'C', 'W'];
+
+/// Notes of the per-strip buttons, with the letter shown when their LED is lit
+const STRIP_BUTTONS: [(u8, char); 4] = [(0, 'R'), (8, 'S'), (16, 'M'), (24, 'X')];
+
+/// Everything the bridge has sent to the surface
+struct SurfaceState {
+    /// Fader positions, 0 to 1, including the master fader
+    faders: [f64; STRIP_COUNT + 1],
+    touched: [bool; STRIP_COUNT + 1],
+    lit_buttons: BTreeSet<u8>,
+    /// Both rows of all scribble strips
+    scribbles: [u8; STRIP_COUNT * SCRIBBLE_ROW_LEN * 2],
+    colours: [u8; STRIP_COUNT],
+    meters: [u8; STRIP_COUNT],
+    /// 7-segment digits, indexed by CC - 64
+    segments: [char; 12],
+    dirty: bool,
+}
+
+pub struct Simulator {
+    _input: MidiInputConnection<Arc<Mutex<SurfaceState>>>,
+    _output: Arc<Mutex<MidiOutputConnection>>,
+}
+
+impl Simulator {
+    /// Create the virtual ports and start drawing the surface. Returns the simulator and
+    /// the names of the input and output ports that the bridge should connect to.
+    #[cfg(unix)]
+    pub fn start() -> Result<(Self, String, String)> {
+        use midir::os::unix::{VirtualInput, VirtualOutput};
+
+        let state = Arc::new(Mutex::new(SurfaceState {
+            faders: [0.0; _],
+            touched: [false; _],
+            lit_buttons: BTreeSet::new(),
+            scribbles: [b' '; _],
+            colours: [0; _],
+            meters: [0; _],
+            segments: [' '; _],
+            dirty: true,
+        }));
+
+        // The bridge's output is the simulator's input, and vice versa
+        let input = MidiInput::new(PORT_NAME)?
+            .create_virtual(PORT_NAME, process_surface_midi, state.clone())
+            .map_err(|e| anyhow!("Failed to create virtual MIDI input: {}", e))?;
+        let output = MidiOutput::new(PORT_NAME)?
+            .create_virtual(PORT_NAME)
+            .map_err(|e| anyhow!("Failed to create virtual MIDI output: {}", e))?;
+        let output = Arc::new(Mutex::new(output));
+
+        let bridge_input = find_port_name(&MidiInput::new("X-Touch Wing Scan")?)?;
+        let bridge_output = find_port_name(&MidiOutput::new("X-Touch Wing Scan")?)?;
+
+        info!(input = bridge_input, output = bridge_output, "Simulated surface started");
+
+        let render_state = state.clone();
+        std::thread::spawn(move || render_loop(render_state));
+
+        let command_output = output.clone();
+        std::thread::spawn(move || command_loop(state, command_output));
+
+        Ok((
+            Self {
+                _input: input,
+                _output: output,
+            },
+            bridge_input,
+            bridge_output,
+        ))
+    }
+
+    #[cfg(not(unix))]
+    pub fn start() -> Result<(Self, String, String)> {
+        bail!("The surface simulator needs virtual MIDI ports, which are not available on this platform")
+    }
+}
+
+/// Find the full name of the simulator's virtual port, as seen by other clients
+fn find_port_name<T: midir::MidiIO>(io: &T) -> Result<String> {
+    io.ports()
+        .iter()
+        .filter_map(|p| io.port_name(p).ok())
+        .find(|name| name.contains(PORT_NAME))
+        .ok_or_else(|| anyhow!("Virtual MIDI port '{}' not found", PORT_NAME))
+}
+
+/// Update the surface state with a message from the bridge
+fn process_surface_midi(_timestamp_us: u64, bytes: &[u8], state: &mut Arc<Mutex<SurfaceState>>) {
+    let mut state = state.lock().unwrap();
+
+    match bytes {
+        // Scribble strip text
+        [0xF0, 0x00, 0x00, 0x66, 0x14, 0x12, offset, text @ .., 0xF7] => {
+            for (i, c) in text.iter().enumerate() {
+                if let Some(cell) = state.scribbles.get_mut(*offset as usize + i) {
+                    *cell = *c;
+                }
+            }
+        }
+        // Scribble strip colours
+        [0xF0, 0x00, 0x00, 0x66, 0x14, 0x72, colours @ .., 0xF7] => {
+            for (cell, c) in state.colours.iter_mut().zip(colours) {
+                *cell = *c;
+            }
+        }
+        _ => match LiveEvent::parse(bytes) {
+            Ok(LiveEvent::Midi { channel, message }) => match message {
+                MidiMessage::NoteOn { key, vel } => {
+                    if vel.as_int() > 0 {
+                        state.lit_buttons.insert(key.as_int());
+                    } else {
+                        state.lit_buttons.remove(&key.as_int());
+                    }
+                }
+                MidiMessage::PitchBend { bend } => {
+                    if let Some(fader) = state.faders.get_mut(channel.as_int() as usize) {
+                        *fader = (bend.as_f64() + 1.0) / 2.0;
+                    }
+                }
+                MidiMessage::ChannelAftertouch { vel } => {
+                    let strip = (vel.as_int() >> 4) as usize;
+                    if let Some(meter) = state.meters.get_mut(strip) {
+                        *meter = vel.as_int() & 0x0F;
+                    }
+                }
+                MidiMessage::Controller { controller, value } => {
+                    let index = controller.as_int().wrapping_sub(64) as usize;
+                    if let Some(digit) = state.segments.get_mut(index) {
+                        // The segment codes ignore the decimal point bit
+                        let code = value.as_int() & 0x3F;
+                        *digit = ASCII_TO_7SEGMENT
+                            .iter()
+                            .position(|v| *v == Some(code))
+                            .map_or('?', |c| (c as u8 as char).to_ascii_uppercase());
+                    }
+                }
+                _ => {
+                    debug!(?message, "Unhandled simulator MIDI input");
+                    return;
+                }
+            },
+            _ => {
+                debug!(bytes, "Unhandled simulator MIDI input");
+                return;
+            }
+        },
+    }
+
+    state.dirty = true;
+}
+
+/// Redraw the surface in the terminal whenever it changes
+fn render_loop(state: Arc<Mutex<SurfaceState>>) {
+    loop {
+        std::thread::sleep(RENDER_INTERVAL);
+
+        let frame = {
+            let mut state = state.lock().unwrap();
+            if !state.dirty {
+                continue;
+            }
+            state.dirty = false;
+            render(&state)
+        };
+
+        let mut stdout = std::io::stdout().lock();
+        // Clear the screen and move the cursor home
+        let _ = write!(stdout, "\x1b[2J\x1b[H{}", frame);
+        let _ = stdout.flush();
+    }
+}
+
+fn render(state: &SurfaceState) -> String {
+    let mut out = String::new();
+
+    // The main display digits are CCs 73 to 64 and the Assignment display CCs 75 and 74
+    let main: String = (0..10).rev().map(|i| state.segments[i]).collect();
+    let assignment: String = [state.segments[11], state.segments[10]].iter().collect();
+    out += &format!("[{}]  [{}]\n\n", assignment, main);
+
+    for row in 0..2 {
+        for strip in 0..STRIP_COUNT {
+            let start = (row * STRIP_COUNT + strip) * SCRIBBLE_ROW_LEN;
+            let text = &state.scribbles[start..start + SCRIBBLE_ROW_LEN];
+            out += &format!("|{}", String::from_utf8_lossy(text));
+        }
+        out += "|\n";
+    }
+
+    for strip in 0..STRIP_COUNT {
+        let colour = state.colours[strip];
+        let name = COLOUR_NAMES[(colour & 0x07) as usize];
+        // Inverted colours are shown in lowercase
+        let name = if colour & 0x40 != 0 { name.to_ascii_lowercase() } else { name };
+        out += &format!("|   {}   ", name);
+    }
+    out += "|\n";
+
+    for strip in 0..STRIP_COUNT {
+        let buttons: String = STRIP_BUTTONS
+            .iter()
+            .map(|(base, c)| {
+                if state.lit_buttons.contains(&(base + strip as u8)) { *c } else { '.' }
+            })
+            .collect();
+        out += &format!("| {}  ", buttons);
+    }
+    out += "|\n";
+
+    for strip in 0..STRIP_COUNT {
+        let level = state.meters[strip].min(8) as usize;
+        out += &format!("|{:<7}", "#".repeat(level.min(SCRIBBLE_ROW_LEN)));
+    }
+    out += "|\n";
+
+    for strip in 0..=STRIP_COUNT {
+        let touched = if state.touched[strip] { '*' } else { ' ' };
+        out += &format!("|{:>5.1}%{}", state.faders[strip] * 100.0, touched);
+    }
+    out += "|\n\n";
+
+    let other_buttons = state
+        .lit_buttons
+        .iter()
+        .filter(|note| **note >= 32)
+        .map(|note| note.to_string())
+        .collect::<Vec<_>>();
+    out += &format!("Lit buttons: {}\n", other_buttons.join(" "));
+    out += "Commands: f <strip> <0-100>, p/d/u <note>, t <strip>\n";
+
+    out
+}
+
+/// Read commands from stdin and send the corresponding MIDI messages to the bridge
+fn command_loop(state: Arc<Mutex<SurfaceState>>, output: Arc<Mutex<MidiOutputConnection>>) {
+    let send = |message: MidiMessage, channel: u8| {
+        let ev = LiveEvent::Midi {
+            channel: channel.into(),
+            message,
+        };
+        let mut buf = Vec::with_capacity(3);
+        ev.write(&mut buf).unwrap();
+        if let Err(e) = output.lock().unwrap().send(&buf) {
+            warn!("Failed to send simulated input: {}", e);
+        }
+    };
+    let note = |key: u8, pressed: bool| MidiMessage::NoteOn {
+        key: key.into(),
+        vel: if pressed { 127 } else { 0 }.into(),
+    };
+
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let result: Result<()> = (|| {
+            match args.as_slice() {
+                ["f", strip, position] => {
+                    let channel = parse_strip(strip)?;
+                    let position: f64 = position.parse::<f64>()?.clamp(0.0, 100.0) / 100.0;
+                    state.lock().unwrap().faders[channel as usize] = position;
+                    send(
+                        MidiMessage::PitchBend {
+                            bend: PitchBend::from_f64(position * 2.0 - 1.0),
+                        },
+                        channel,
+                    );
+                }
+                ["p", key] => {
+                    let key = key.parse()?;
+                    send(note(key, true), 0);
+                    send(note(key, false), 0);
+                }
+                ["d", key] => send(note(key.parse()?, true), 0),
+                ["u", key] => send(note(key.parse()?, false), 0),
+                ["t", strip] => {
+                    let channel = parse_strip(strip)? as usize;
+                    let touched = {
+                        let mut state = state.lock().unwrap();
+                        state.touched[channel] = !state.touched[channel];
+                        state.dirty = true;
+                        state.touched[channel]
+                    };
+                    // Touch notes are 104 to 111 for the strips and 112 for the master fader
+                    send(note(104 + channel as u8, touched), 0);
+                }
+                [] => {}
+                _ => bail!("Unknown command '{}'", line),
+            }
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            warn!("{}", e);
+        }
+    }
+}
+
+/// Parse a 1-based strip number into a 0-based MIDI channel
+fn parse_strip(strip: &str) -> Result<u8> {
+    match strip.parse::<u8>()? {
+        strip @ 1..=9 => Ok(strip - 1),
+        strip => bail!("Invalid strip {}", strip),
+    }
+}
@@ -0,0 +1,170 @@
+//! Named snapshots of console parameters, stored as files independently of WING scenes
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, bail};
+use tracing::info;
+
+use crate::orchestrator::{Interface, Value};
+use crate::settings::SnapshotSettings;
+
+/// State of the A/B comparison
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AbState {
+    /// Nothing is stored
+    Off,
+    /// The stored values (A) are live
+    ShowingA,
+    /// The values changed since arming (B) are live
+    ShowingB,
+}
+
+pub struct SnapshotStore {
+    directory: PathBuf,
+    /// Parameters stored in snapshots, or everything in the cache if empty
+    paths: Vec<String>,
+    /// Parameters of the A/B comparison, and the stored A and B values
+    ab_paths: Vec<String>,
+    ab: std::sync::Mutex<(AbState, HashMap<String, Value>, HashMap<String, Value>)>,
+}
+
+impl SnapshotStore {
+    pub fn new(settings: &SnapshotSettings) -> Self {
+        Self {
+            directory: PathBuf::from(&settings.directory),
+            paths: settings.paths.clone(),
+            ab_paths: settings.ab_compare.clone(),
+            ab: std::sync::Mutex::new((AbState::Off, HashMap::new(), HashMap::new())),
+        }
+    }
+
+    pub fn ab_state(&self) -> AbState {
+        self.ab.lock().unwrap().0
+    }
+
+    /// Store the A/B parameters as A on the first use, and then switch between A and the
+    /// values they were changed to afterwards (B)
+    pub async fn ab_toggle(&self, interface: &Interface) -> Result<AbState> {
+        if self.ab_paths.is_empty() {
+            bail!("No parameters are configured for A/B comparison");
+        }
+
+        let state = self.ab_state();
+        let live = interface.capture_values(&self.ab_paths).await;
+
+        let (new_state, recall) = {
+            let mut ab = self.ab.lock().unwrap();
+            let (ab_state, a, b) = &mut *ab;
+
+            let recall = match state {
+                AbState::Off => {
+                    *a = live;
+                    None
+                }
+                AbState::ShowingB => {
+                    // Changes made since arming become B
+                    *b = live;
+                    Some(a.clone())
+                }
+                AbState::ShowingA => {
+                    // Changes made while listening to A are kept in A
+                    *a = live;
+                    Some(b.clone())
+                }
+            };
+
+            *ab_state = match state {
+                AbState::Off | AbState::ShowingA => AbState::ShowingB,
+                AbState::ShowingB => AbState::ShowingA,
+            };
+
+            (*ab_state, recall)
+        };
+
+        if let Some(values) = recall {
+            interface.set_values(values.into_iter().collect()).await;
+        }
+
+        info!(state = ?new_state, "A/B comparison");
+
+        Ok(new_state)
+    }
+
+    /// Forget the stored A/B values, keeping the live ones
+    pub fn ab_reset(&self) {
+        *self.ab.lock().unwrap() = (AbState::Off, HashMap::new(), HashMap::new());
+        info!("A/B comparison reset");
+    }
+
+    fn file_path(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            bail!("Invalid snapshot name '{}'", name);
+        }
+
+        Ok(self.directory.join(format!("{}.yml", name)))
+    }
+
+    /// Whether parameters are a part of snapshots. Console state and actions, such as scene
+    /// recalls, are never stored.
+    fn is_stored(osc_path: &str) -> bool {
+        !osc_path.starts_with("/$")
+    }
+
+    /// Store the current values of the snapshot parameters under a name, returning the
+    /// number of parameters stored
+    pub async fn capture(&self, name: &str, interface: &Interface) -> Result<usize> {
+        let file_path = self.file_path(name)?;
+
+        let values: BTreeMap<String, Value> = if self.paths.is_empty() {
+            interface.export_cache().await.into_iter().collect()
+        } else {
+            let (patterns, paths): (Vec<String>, Vec<String>) =
+                self.paths.iter().cloned().partition(|path| path.contains('*'));
+
+            let mut values: BTreeMap<String, Value> =
+                interface.capture_values(&paths).await.into_iter().collect();
+            for pattern in &patterns {
+                values.extend(interface.cached_values(pattern).await);
+            }
+            values
+        };
+
+        let values: BTreeMap<String, Value> =
+            values.into_iter().filter(|(path, _)| Self::is_stored(path)).collect();
+
+        std::fs::create_dir_all(&self.directory).with_context(|| {
+            format!("Failed to create snapshot directory {}", self.directory.display())
+        })?;
+        let yaml = serde_yaml::to_string(&values)?;
+        std::fs::write(&file_path, yaml)
+            .with_context(|| format!("Failed to write snapshot {}", file_path.display()))?;
+
+        info!(name, parameters = values.len(), "Snapshot captured");
+
+        Ok(values.len())
+    }
+
+    /// Set all parameters of a stored snapshot at once, returning the number of parameters
+    pub async fn restore(&self, name: &str, interface: &Interface) -> Result<usize> {
+        let values = self.load(name)?;
+        let count = values.len();
+
+        info!(name, parameters = count, "Restoring snapshot");
+        interface.set_values(values.into_iter().collect()).await;
+
+        Ok(count)
+    }
+
+    /// Read the parameters of a stored snapshot
+    pub fn load(&self, name: &str) -> Result<BTreeMap<String, Value>> {
+        let file_path = self.file_path(name)?;
+
+        let yaml = std::fs::read_to_string(&file_path)
+            .with_context(|| format!("Failed to read snapshot {}", file_path.display()))?;
+        let values: BTreeMap<String, Value> = serde_yaml::from_str(&yaml)
+            .with_context(|| format!("Snapshot {} is invalid", file_path.display()))?;
+
+        Ok(values.into_iter().filter(|(path, _)| Self::is_stored(path)).collect())
+    }
+}
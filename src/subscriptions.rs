@@ -0,0 +1,82 @@
+//! Registry of the parameters that each provider is interested in, so that values are only
+//! sent to the providers that show them
+
+use std::collections::HashMap;
+
+/// A pattern of parameter paths. `*` matches any text within a path segment, and `**` as the
+/// last segment matches any number of remaining segments, e.g. `/ch/*/mute` or `/ch/**`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathPattern {
+    segments: Vec<String>,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            segments: pattern.split('/').map(str::to_string).collect(),
+        }
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let mut segments = path.split('/');
+
+        for (index, pattern) in self.segments.iter().enumerate() {
+            if pattern == "**" && index == self.segments.len() - 1 {
+                return true;
+            }
+
+            match segments.next() {
+                Some(segment) if segment_matches(pattern, segment) => {}
+                _ => return false,
+            }
+        }
+
+        segments.next().is_none()
+    }
+}
+
+/// Whether a path segment matches a pattern segment, where `*` matches any text
+fn segment_matches(pattern: &str, segment: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+
+    let Some(mut rest) = segment.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard in the pattern
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+/// The path patterns that each provider subscribed to, by provider ID
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    by_provider: HashMap<usize, Vec<PathPattern>>,
+}
+
+impl Subscriptions {
+    /// Replace the patterns of a provider
+    pub fn set(&mut self, provider_id: usize, patterns: Vec<PathPattern>) {
+        self.by_provider.insert(provider_id, patterns);
+    }
+
+    /// Whether a provider is interested in a parameter. Providers that never subscribed
+    /// receive all values.
+    pub fn wants(&self, provider_id: usize, path: &str) -> bool {
+        self.by_provider
+            .get(&provider_id)
+            .is_none_or(|patterns| patterns.iter().any(|pattern| pattern.matches(path)))
+    }
+}
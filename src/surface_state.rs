@@ -0,0 +1,93 @@
+//! The state of the surfaces that the operator changes at runtime, such as the visible bank,
+//! kept in a file so that a restart brings the surfaces back to where they were
+//!
+//! Controllers record their state when it changes, and the file is written periodically
+//! and when the bridge exits.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::settings::EncoderMode;
+
+/// The state of one surface
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SurfaceState {
+    /// The visible bank, and the first visible strip within it
+    pub bank: usize,
+    pub offset: usize,
+    pub encoder_mode: EncoderMode,
+    pub locked: bool,
+}
+
+pub struct SurfaceStateStore {
+    file: Option<PathBuf>,
+    /// The state of each surface, by the name of its MIDI input
+    states: Mutex<BTreeMap<String, SurfaceState>>,
+    /// Whether there are changes that are not stored in the file yet
+    unsaved: AtomicBool,
+}
+
+impl SurfaceStateStore {
+    /// Create the store, with the states in the file if there is one
+    pub fn new(file: Option<&str>) -> Self {
+        let file = file.map(PathBuf::from);
+
+        let states = match &file {
+            Some(file) => Self::read(file).unwrap_or_else(|e| {
+                debug!("Starting without a stored surface state: {:?}", e);
+                BTreeMap::new()
+            }),
+            None => BTreeMap::new(),
+        };
+
+        Self {
+            file,
+            states: Mutex::new(states),
+            unsaved: AtomicBool::new(false),
+        }
+    }
+
+    fn read(file: &Path) -> Result<BTreeMap<String, SurfaceState>> {
+        let contents = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read state file {}", file.display()))?;
+        serde_yaml::from_str(&contents)
+            .with_context(|| format!("Failed to parse state file {}", file.display()))
+    }
+
+    /// The last state of a surface, if it was stored
+    pub fn get(&self, surface: &str) -> Option<SurfaceState> {
+        self.states.lock().unwrap().get(surface).cloned()
+    }
+
+    pub fn update(&self, surface: &str, state: SurfaceState) {
+        let mut states = self.states.lock().unwrap();
+        if states.get(surface) != Some(&state) {
+            states.insert(surface.to_string(), state);
+            self.unsaved.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Store the states in the file, if there is one and anything changed
+    pub fn save(&self) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if !self.unsaved.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let contents = serde_yaml::to_string(&*self.states.lock().unwrap())?;
+        std::fs::write(file, contents)
+            .with_context(|| format!("Failed to write state file {}", file.display()))?;
+
+        debug!(file = %file.display(), "Surface state saved");
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+//! Conversion of values between the console and a provider, e.g. to show a fader level in dB
+//! as a percentage, or to invert a level for a lighting desk
+
+use anyhow::{Result, bail};
+
+use crate::orchestrator::Value;
+use crate::settings::ValueTransform;
+use crate::subscriptions::PathPattern;
+
+#[derive(Debug, Clone)]
+struct Transform {
+    pattern: PathPattern,
+    scale: f32,
+    offset: f32,
+    range: Option<(f32, f32)>,
+    curve: f32,
+    invert: bool,
+}
+
+impl Transform {
+    fn new(settings: &ValueTransform) -> Result<Self> {
+        let scale = settings.scale.unwrap_or(1.0);
+        let curve = settings.curve.unwrap_or(1.0);
+
+        let range = match (settings.min, settings.max) {
+            (Some(min), Some(max)) if min < max => Some((min, max)),
+            (None, None) => None,
+            _ => bail!("Transform of {} needs both a min and a larger max", settings.path),
+        };
+
+        if scale == 0.0 || !scale.is_finite() {
+            bail!("Transform of {} has an invalid scale", settings.path);
+        }
+        if curve <= 0.0 || !curve.is_finite() {
+            bail!("Transform of {} needs a positive curve", settings.path);
+        }
+        if range.is_none() && (settings.invert || curve != 1.0) {
+            bail!("Transform of {} needs a min and max to invert or curve values", settings.path);
+        }
+
+        Ok(Self {
+            pattern: PathPattern::new(&settings.path),
+            scale,
+            offset: settings.offset,
+            range,
+            curve,
+            invert: settings.invert,
+        })
+    }
+
+    /// Convert a value from the console for the provider
+    fn to_provider(&self, value: f32) -> f32 {
+        let value = value * self.scale + self.offset;
+
+        let Some((min, max)) = self.range else {
+            return value;
+        };
+
+        let mut position = ((value - min) / (max - min)).clamp(0.0, 1.0).powf(self.curve);
+        if self.invert {
+            position = 1.0 - position;
+        }
+
+        min + position * (max - min)
+    }
+
+    /// Convert a value from the provider for the console, undoing `to_provider`
+    fn to_console(&self, value: f32) -> f32 {
+        let value = match self.range {
+            Some((min, max)) => {
+                let mut position = ((value - min) / (max - min)).clamp(0.0, 1.0);
+                if self.invert {
+                    position = 1.0 - position;
+                }
+
+                min + position.powf(1.0 / self.curve) * (max - min)
+            }
+            None => value,
+        };
+
+        (value - self.offset) / self.scale
+    }
+}
+
+/// The transforms of a provider. The first one whose pattern matches a parameter applies to
+/// it, and parameters without one are passed through.
+#[derive(Debug, Clone, Default)]
+pub struct Transforms(Vec<Transform>);
+
+impl Transforms {
+    pub fn new(settings: &[ValueTransform]) -> Result<Self> {
+        Ok(Self(settings.iter().map(Transform::new).collect::<Result<_>>()?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn to_provider(&self, osc_addr: &str, value: Value) -> Value {
+        match self.find(osc_addr) {
+            Some(transform) => map_number(value, |v| transform.to_provider(v)),
+            None => value,
+        }
+    }
+
+    pub fn to_console(&self, osc_addr: &str, value: Value) -> Value {
+        match self.find(osc_addr) {
+            Some(transform) => map_number(value, |v| transform.to_console(v)),
+            None => value,
+        }
+    }
+
+    fn find(&self, osc_addr: &str) -> Option<&Transform> {
+        self.0.iter().find(|transform| transform.pattern.matches(osc_addr))
+    }
+}
+
+/// Apply a function to a numeric value, keeping its type. Other values are left as they are.
+fn map_number(value: Value, f: impl Fn(f32) -> f32) -> Value {
+    match value {
+        Value::Float(v) => Value::Float(f(v)),
+        Value::Int(i) => Value::Int(f(i as f32).round() as i32),
+        other => other,
+    }
+}
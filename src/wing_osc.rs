@@ -0,0 +1,217 @@
+//! WING connection over plain OSC on UDP, as an alternative to the native protocol of libwing
+//!
+//! This uses the same parameter paths as the native protocol, and also works against OSC
+//! simulators. Meters are not available over OSC.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rosc::{OscMessage, OscPacket, OscType, decoder, encoder};
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::console::ConsoleTasks;
+use crate::orchestrator::{Interface, LinkStatus, Value};
+
+/// The console stops sending parameter changes 10 seconds after subscribing
+const SUBSCRIPTION_RENEW_INTERVAL: Duration = Duration::from_secs(8);
+/// Without any traffic for this long, the console is considered offline
+const LINK_TIMEOUT: Duration = Duration::from_secs(20);
+/// Subscribes to parameter changes
+const SUBSCRIBE_ADDRESS: &str = "/*s";
+/// Asks the console to identify itself
+const IDENTIFY_ADDRESS: &str = "/?";
+
+/// WING connection over OSC
+pub struct WingOscConsole {
+    socket: Arc<UdpSocket>,
+
+    interface: Arc<Mutex<Option<Interface>>>,
+
+    meters_warned: bool,
+    pub(crate) tasks: ConsoleTasks,
+}
+
+impl WingOscConsole {
+    /// Create a new OSC connection. As the protocol is connectionless, this succeeds even if
+    /// the console is not there.
+    pub async fn new(remote_addr: &str, port: u16, local_port: u16) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", local_port))
+            .await
+            .with_context(|| format!("Failed to bind local UDP port {}", local_port))?;
+        socket.connect((remote_addr, port)).await.with_context(|| {
+            format!("Failed to connect to Wing console at {}:{}", remote_addr, port)
+        })?;
+
+        let console = Self {
+            socket: Arc::new(socket),
+            interface: Arc::new(Mutex::new(None)),
+            meters_warned: false,
+            tasks: ConsoleTasks::default(),
+        };
+
+        console.spawn_recv_task();
+        console.spawn_subscription_task();
+
+        info!(addr = remote_addr, port, "Wing OSC connection opened");
+
+        Ok(console)
+    }
+
+    async fn send(socket: &UdpSocket, osc_addr: &str, args: Vec<OscType>) -> Result<()> {
+        let packet = OscPacket::Message(OscMessage {
+            addr: osc_addr.to_string(),
+            args,
+        });
+        let buf = encoder::encode(&packet)?;
+
+        socket
+            .send(&buf)
+            .await
+            .with_context(|| format!("Failed to send OSC message {}", osc_addr))?;
+
+        Ok(())
+    }
+
+    /// Spawn a background task that keeps the subscription to parameter changes alive, and
+    /// asks the console to identify itself so that its absence is noticed
+    fn spawn_subscription_task(&self) {
+        let socket = self.socket.clone();
+
+        self.tasks.spawn(async move {
+            let mut interval = tokio::time::interval(SUBSCRIPTION_RENEW_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                for osc_addr in [SUBSCRIBE_ADDRESS, IDENTIFY_ADDRESS] {
+                    if let Err(e) = Self::send(&socket, osc_addr, vec![]).await {
+                        warn!("Failed to renew OSC subscription: {:?}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Spawn a background task that listens for incoming OSC packets
+    fn spawn_recv_task(&self) {
+        let socket = self.socket.clone();
+        let interface = self.interface.clone();
+
+        self.tasks.spawn(async move {
+            let mut buf = vec![0u8; decoder::MTU];
+            let mut online = false;
+
+            loop {
+                let len = match timeout(LINK_TIMEOUT, socket.recv(&mut buf)).await {
+                    Ok(Ok(len)) => len,
+                    Ok(Err(e)) => {
+                        warn!("Error during OSC reception: {:?}", e);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        continue;
+                    }
+                    Err(_) => {
+                        if online {
+                            online = false;
+                            if let Some(iface) = interface.lock().await.as_ref() {
+                                iface.set_link_status(LinkStatus::Offline).await;
+                            }
+                        }
+                        continue;
+                    }
+                };
+
+                let iface = interface.lock().await.clone();
+                let Some(iface) = iface else {
+                    warn!("No interface set to handle OSC message");
+                    continue;
+                };
+
+                if !online {
+                    online = true;
+                    iface.set_link_status(LinkStatus::Online).await;
+                }
+
+                let packet = match decoder::decode_udp(&buf[..len]) {
+                    Ok((_, packet)) => packet,
+                    Err(e) => {
+                        warn!("Failed to decode OSC packet: {:?}", e);
+                        continue;
+                    }
+                };
+
+                let mut packets = vec![packet];
+                while let Some(packet) = packets.pop() {
+                    match packet {
+                        OscPacket::Bundle(bundle) => packets.extend(bundle.content),
+                        OscPacket::Message(message) => Self::process_message(&iface, message).await,
+                    }
+                }
+            }
+        });
+    }
+
+    async fn process_message(interface: &Interface, message: OscMessage) {
+        trace!(?message, "Received OSC message");
+
+        if message.addr == IDENTIFY_ADDRESS {
+            debug!(?message.args, "Console identified as");
+            return;
+        }
+
+        // Numeric parameters are sent as their text, their normalised value and their value
+        let value = match message.args.into_iter().last() {
+            Some(OscType::Float(f)) => Value::Float(f),
+            Some(OscType::Int(i)) => Value::Int(i),
+            Some(OscType::String(s)) => Value::Str(s),
+            other => {
+                warn!(osc_addr = message.addr.as_str(), ?other, "Unexpected value received");
+                return;
+            }
+        };
+
+        debug!(osc_addr = message.addr.as_str(), ?value, "Received OSC value");
+        interface.set_value(&message.addr, value).await;
+    }
+
+    /// Performs a request for an OSC value, without returning it.
+    pub async fn request_value(&mut self, osc_addr: &str) -> Result<()> {
+        debug!(osc_addr, "Requesting OSC value");
+        Self::send(&self.socket, osc_addr, vec![]).await
+    }
+
+    /// Set an OSC value
+    pub async fn set_value(&mut self, osc_addr: &str, value: Value) -> Result<()> {
+        debug!(osc_addr, ?value, "Setting OSC value");
+
+        let arg = match value {
+            Value::Float(f) => OscType::Float(f),
+            Value::Int(i) => OscType::Int(i),
+            Value::Str(s) => OscType::String(s),
+            Value::Bool(on) => OscType::Int(i32::from(on)),
+        };
+
+        Self::send(&self.socket, osc_addr, vec![arg]).await
+    }
+
+    pub async fn set_interface(&mut self, interface: Interface) {
+        interface.set_link_status(LinkStatus::Connecting).await;
+        self.interface.lock().await.replace(interface);
+
+        if let Err(e) = Self::send(&self.socket, IDENTIFY_ADDRESS, vec![]).await {
+            error!("Failed to identify console: {:?}", e);
+        }
+    }
+
+    pub async fn set_meters(&mut self, _meters: Vec<libwing::Meter>) -> Result<()> {
+        if !self.meters_warned {
+            warn!("Meters are not available over OSC, use the native Wing connection for them");
+            self.meters_warned = true;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+//! Guided creation of a configuration file, for the `config init` command
+//!
+//! The wizard starts from the default configuration, and only asks for the few settings that
+//! differ between setups: the MIDI ports, the console, the channel banks and the master fader.
+
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use midir::{MidiIO, MidiInput, MidiOutput};
+
+use crate::data::Fader;
+use crate::settings::{Banks, FaderBank, MeterSource, Settings};
+use crate::profiles::PROFILE_DIRECTORY;
+use crate::{check, discovery};
+
+/// Number of strips on the surface, and so of faders in each bank
+const BANK_SIZE: usize = 8;
+/// Channels on a WING
+const MAX_CHANNELS: usize = 40;
+/// MIDI channel of the fader after the strips
+const MASTER_CHANNEL: u32 = 8;
+
+/// Ask for the settings of this setup, and write a configuration file with them
+pub async fn run(path: &Path) -> Result<()> {
+    let overwrite = format!("{} already exists, overwrite it?", path.display());
+    if path.exists() && !confirm(&overwrite, false)? {
+        return Ok(());
+    }
+
+    let mut config = Settings::default();
+
+    let input = MidiInput::new("X-Touch Wing setup")?;
+    let output = MidiOutput::new("X-Touch Wing setup")?;
+    let midi = &mut config.midi[0];
+    midi.input = choose_port("MIDI input", &input, &midi.input)?;
+    midi.output = choose_port("MIDI output", &output, &midi.output)?;
+
+    choose_console(&mut config).await?;
+
+    let banks = ask_number(
+        &format!("How many banks of {} channels?", BANK_SIZE),
+        MAX_CHANNELS / BANK_SIZE,
+        MAX_CHANNELS / BANK_SIZE,
+    )?;
+    let assignments = &mut config.midi[0].assignments;
+    let Banks::List(default_banks) = &mut assignments.banks else {
+        unreachable!("The default banks are a list");
+    };
+    // The default banks start with all channels, replace those with the chosen ones
+    default_banks.retain(|bank| !bank.faders.iter().all(|f| f.starts_with("Channel")));
+    let channel_banks = (0..banks).map(|bank| {
+        let first = bank * BANK_SIZE + 1;
+        let last = first + BANK_SIZE - 1;
+        FaderBank {
+            name: Some(format!("CH {}-{}", first, last)),
+            faders: vec![format!("Channel {}-{}", first, last)],
+            meter: MeterSource::Level,
+            trim_db: Default::default(),
+            console: None,
+            filter: None,
+        }
+    });
+    default_banks.splice(0..0, channel_banks);
+
+    let master = ask_fader("Which fader should the master fader control?", "Main 1")?;
+    assignments.fixed_faders.insert(MASTER_CHANNEL, master);
+
+    let contents = serde_yaml::to_string(&config)?;
+    std::fs::write(path, contents)
+        .with_context(|| format!("Failed to write configuration file {}", path.display()))?;
+    println!("Configuration written to {}", path.display());
+
+    config.expand_fader_ranges()?;
+    let profiles = path.parent().unwrap_or(Path::new("")).join(PROFILE_DIRECTORY);
+    config.load_profiles(&profiles)?;
+    for problem in check::problems(&config) {
+        println!("Warning: {}", problem);
+    }
+    Ok(())
+}
+
+/// Pick one of the MIDI ports, preferring one that looks like the default
+fn choose_port<T: MidiIO>(kind: &str, io: &T, default: &str) -> Result<String> {
+    let names: Vec<String> = io
+        .ports()
+        .iter()
+        .filter_map(|p| io.port_name(p).ok())
+        .collect();
+    if names.is_empty() {
+        println!(
+            "No {} ports found, connect the controller and edit the configuration later",
+            kind
+        );
+        return Ok(default.to_string());
+    }
+
+    println!("{} ports:", kind);
+    for (index, name) in names.iter().enumerate() {
+        println!("  {}) {}", index + 1, name);
+    }
+
+    let suggested = names
+        .iter()
+        .position(|name| name.contains(default))
+        .unwrap_or_default();
+    let index = ask_number(&format!("Which {} port?", kind), suggested + 1, names.len())?;
+    Ok(names[index - 1].clone())
+}
+
+/// Find the console on the network, or ask for its address
+async fn choose_console(config: &mut Settings) -> Result<()> {
+    println!("Searching for consoles on the network...");
+    let consoles = discovery::discover().await.unwrap_or_else(|e| {
+        println!("Discovery failed: {:#}", e);
+        Vec::new()
+    });
+    let console = &mut config.console[0];
+
+    match consoles.as_slice() {
+        [] => println!("No consoles found"),
+        [found] => {
+            println!("Found '{}' ({}) at {}", found.name, found.model, found.ip);
+            if confirm("Use this console?", true)? {
+                console.ip = Some(found.ip.clone());
+                return Ok(());
+            }
+        }
+        _ => {
+            for (index, found) in consoles.iter().enumerate() {
+                println!(
+                    "  {}) '{}' ({}) at {}",
+                    index + 1,
+                    found.name,
+                    found.model,
+                    found.ip
+                );
+            }
+            let index = ask_number("Which console?", 1, consoles.len())?;
+            console.ip = Some(consoles[index - 1].ip.clone());
+            return Ok(());
+        }
+    }
+
+    loop {
+        let ip = ask("Console IP address, or empty to discover it on startup", "")?;
+        if ip.is_empty() {
+            return Ok(());
+        }
+        match discovery::identify(&ip).await {
+            Ok(found) => println!("Found '{}' ({})", found.name, found.model),
+            Err(e) => {
+                println!("{:#}", e);
+                if !confirm("Use this address anyway?", false)? {
+                    continue;
+                }
+            }
+        }
+        console.ip = Some(ip);
+        return Ok(());
+    }
+}
+
+fn ask_fader(question: &str, default: &str) -> Result<String> {
+    loop {
+        let label = ask(question, default)?;
+        match Fader::new_from_label(&label) {
+            Ok(_) => return Ok(label),
+            Err(e) => println!("{:#}, try e.g. 'Main 1', 'DCA 2' or 'Bus 3'", e),
+        }
+    }
+}
+
+fn ask_number(question: &str, default: usize, max: usize) -> Result<usize> {
+    loop {
+        let answer = ask(&format!("{} (1-{})", question, max), &default.to_string())?;
+        match answer.parse::<usize>() {
+            Ok(number) if (1..=max).contains(&number) => return Ok(number),
+            _ => println!("Enter a number between 1 and {}", max),
+        }
+    }
+}
+
+fn confirm(question: &str, default: bool) -> Result<bool> {
+    let default = if default { "y" } else { "n" };
+    loop {
+        match ask(&format!("{} (y/n)", question), default)?
+            .to_lowercase()
+            .as_str()
+        {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+/// Ask a question on the terminal, with the answer used when nothing is entered
+fn ask(question: &str, default: &str) -> Result<String> {
+    match default {
+        "" => print!("{}: ", question),
+        default => print!("{} [{}]: ", question, default),
+    }
+    std::io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer)? == 0 {
+        anyhow::bail!("Setup cancelled");
+    }
+
+    Ok(match answer.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    })
+}
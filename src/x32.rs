@@ -0,0 +1,444 @@
+//! X32/M32 console connection over its OSC protocol
+//!
+//! The rest of the application uses WING parameter paths and units with either console.
+//! They are translated to their X32 equivalents here, e.g. `/ch/1/fdr` in dB becomes
+//! `/ch/01/mix/fader` as a fader position. Parameters without an X32 equivalent are ignored.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rosc::{OscMessage, OscPacket, OscType, decoder, encoder};
+use tokio::net::UdpSocket;
+use tokio::sync::Mutex;
+use tokio::time::timeout;
+use tracing::{debug, error, info, trace, warn};
+
+use crate::console::ConsoleTasks;
+use crate::data::FADER_MIN_DB;
+use crate::orchestrator::{Interface, LinkStatus, Value};
+
+/// The console forgets `/xremote` and meter subscriptions after 10 seconds
+const SUBSCRIPTION_RENEW_INTERVAL: Duration = Duration::from_secs(8);
+/// Without any traffic for this long, the console is considered offline
+const LINK_TIMEOUT: Duration = Duration::from_secs(20);
+/// Meter bank with the levels of all channels, aux inputs, FX returns, buses and matrices
+const METER_BANK: &str = "/meters/1";
+/// Range of the meter scale used for WING meters, in dB
+const METER_RANGE_DB: f32 = 128.0;
+
+/// How a value is converted between its WING and X32 representation
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Conversion {
+    /// Passed as is
+    None,
+    /// dB on the WING, fader position between 0 and 1 on the X32
+    Level,
+    /// Mute on the WING, "on" on the X32
+    Inverted,
+    /// -100 to 100 on the WING, 0 to 1 on the X32
+    Pan,
+}
+
+impl Conversion {
+    fn to_x32(self, value: Value) -> Option<OscType> {
+        Some(match (self, value) {
+            (Self::Level, Value::Float(db)) => OscType::Float(db_to_x32_level(db)),
+            (Self::Inverted, Value::Int(muted)) => OscType::Int((muted == 0) as i32),
+            (Self::Inverted, Value::Bool(muted)) => OscType::Int(i32::from(!muted)),
+            (Self::Pan, Value::Float(pan)) => OscType::Float(((pan + 100.0) / 200.0).clamp(0.0, 1.0)),
+            (Self::None, Value::Float(f)) => OscType::Float(f),
+            (Self::None, Value::Int(i)) => OscType::Int(i),
+            (Self::None, Value::Str(s)) => OscType::String(s),
+            (Self::None, Value::Bool(on)) => OscType::Int(i32::from(on)),
+            _ => return None,
+        })
+    }
+
+    fn from_x32(self, arg: OscType) -> Option<Value> {
+        Some(match (self, arg) {
+            (Self::Level, OscType::Float(level)) => Value::Float(x32_level_to_db(level)),
+            (Self::Inverted, OscType::Int(on)) => Value::Bool(on == 0),
+            (Self::Pan, OscType::Float(pan)) => Value::Float(pan * 200.0 - 100.0),
+            (Self::None, OscType::Float(f)) => Value::Float(f),
+            (Self::None, OscType::Int(i)) => Value::Int(i),
+            (Self::None, OscType::String(s)) => Value::Str(s),
+            _ => return None,
+        })
+    }
+}
+
+/// Parameters of strips, as WING and X32 paths relative to the strip
+const STRIP_PARAMETERS: &[(&str, &str, Conversion)] = &[
+    ("fdr", "mix/fader", Conversion::Level),
+    ("mute", "mix/on", Conversion::Inverted),
+    ("pan", "mix/pan", Conversion::Pan),
+    ("$name", "config/name", Conversion::None),
+    ("main/1/on", "mix/st", Conversion::None),
+];
+
+/// Parameters of DCAs, which are laid out differently
+const DCA_PARAMETERS: &[(&str, &str, Conversion)] = &[
+    ("fdr", "fader", Conversion::Level),
+    ("mute", "on", Conversion::Inverted),
+    ("$name", "config/name", Conversion::None),
+];
+
+fn strip_parameters(wing_type: &str) -> &'static [(&'static str, &'static str, Conversion)] {
+    if wing_type == "dca" { DCA_PARAMETERS } else { STRIP_PARAMETERS }
+}
+
+/// Translate a WING parameter path to its X32 equivalent
+fn path_to_x32(osc_path: &str) -> Option<(String, Conversion)> {
+    let mut parts = osc_path.strip_prefix('/')?.splitn(3, '/');
+    let (wing_type, index, parameter) = (parts.next()?, parts.next()?, parts.next()?);
+    let index: u32 = index.parse().ok()?;
+
+    let directory = match (wing_type, index) {
+        ("ch", 1..=32) => format!("/ch/{:02}", index),
+        ("aux", 1..=8) => format!("/auxin/{:02}", index),
+        ("bus", 1..=16) => format!("/bus/{:02}", index),
+        ("mtx", 1..=6) => format!("/mtx/{:02}", index),
+        ("main", 1) => "/main/st".to_string(),
+        ("main", 2) => "/main/m".to_string(),
+        ("dca", 1..=8) => format!("/dca/{}", index),
+        _ => return None,
+    };
+
+    if let Some(bus) = parameter
+        .strip_prefix("send/")
+        .and_then(|p| p.strip_suffix("/lvl"))
+        .and_then(|bus| bus.parse::<u32>().ok())
+    {
+        return (1..=16).contains(&bus).then(|| {
+            (format!("{}/mix/{:02}/level", directory, bus), Conversion::Level)
+        });
+    }
+
+    strip_parameters(wing_type)
+        .iter()
+        .find(|(wing, _, _)| *wing == parameter)
+        .map(|(_, x32, conversion)| (format!("{}/{}", directory, x32), *conversion))
+}
+
+/// Translate an X32 parameter path to its WING equivalent
+fn path_from_x32(osc_path: &str) -> Option<(String, Conversion)> {
+    let mut parts = osc_path.strip_prefix('/')?.splitn(3, '/');
+    let (x32_type, index, parameter) = (parts.next()?, parts.next()?, parts.next()?);
+
+    let (wing_type, index) = match (x32_type, index) {
+        ("main", "st") => ("main", 1),
+        ("main", "m") => ("main", 2),
+        ("ch", _) => ("ch", index.parse::<u32>().ok()?),
+        ("auxin", _) => ("aux", index.parse().ok()?),
+        ("bus", _) => ("bus", index.parse().ok()?),
+        ("mtx", _) => ("mtx", index.parse().ok()?),
+        ("dca", _) => ("dca", index.parse().ok()?),
+        _ => return None,
+    };
+    let directory = format!("/{}/{}", wing_type, index);
+
+    if let Some(bus) = parameter
+        .strip_prefix("mix/")
+        .and_then(|p| p.strip_suffix("/level"))
+        .and_then(|bus| bus.parse::<u32>().ok())
+    {
+        return Some((format!("{}/send/{}/lvl", directory, bus), Conversion::Level));
+    }
+
+    strip_parameters(wing_type)
+        .iter()
+        .find(|(_, x32, _)| *x32 == parameter)
+        .map(|(wing, _, conversion)| (format!("{}/{}", directory, wing), *conversion))
+}
+
+/// Convert a level in dB to an X32 fader position, following the console's fader law
+fn db_to_x32_level(db: f32) -> f32 {
+    let level = if db < -60.0 {
+        (db + 90.0) / 480.0
+    } else if db < -30.0 {
+        (db + 70.0) / 160.0
+    } else if db < -10.0 {
+        (db + 50.0) / 80.0
+    } else {
+        (db + 30.0) / 40.0
+    };
+
+    level.clamp(0.0, 1.0)
+}
+
+/// Convert an X32 fader position to a level in dB
+fn x32_level_to_db(level: f32) -> f32 {
+    if level <= 0.0 {
+        FADER_MIN_DB
+    } else if level < 0.0625 {
+        level * 480.0 - 90.0
+    } else if level < 0.25 {
+        level * 160.0 - 70.0
+    } else if level < 0.5 {
+        level * 80.0 - 50.0
+    } else {
+        level * 40.0 - 30.0
+    }
+}
+
+/// Position of the level of a meter in the meter bank
+fn meter_index(meter: &libwing::Meter) -> Option<usize> {
+    use libwing::Meter;
+
+    match meter {
+        Meter::Channel(n) | Meter::Channel2(n) => Some(*n as usize),
+        Meter::Aux(n) | Meter::Aux2(n) => Some(32 + *n as usize),
+        Meter::Bus(n) | Meter::Bus2(n) => Some(56 + *n as usize),
+        Meter::Matrix(n) | Meter::Matrix2(n) => Some(72 + *n as usize),
+        _ => None,
+    }
+}
+
+/// X32 connection
+pub struct X32Console {
+    socket: Arc<UdpSocket>,
+
+    interface: Arc<Mutex<Option<Interface>>>,
+
+    meters: Arc<Mutex<Vec<libwing::Meter>>>,
+    pub(crate) tasks: ConsoleTasks,
+}
+
+impl X32Console {
+    /// Create a new X32 connection. As the protocol is connectionless, this succeeds even
+    /// if the console is not there.
+    pub async fn new(remote_addr: &str, port: u16, local_port: u16) -> Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", local_port))
+            .await
+            .with_context(|| format!("Failed to bind local UDP port {}", local_port))?;
+        socket.connect((remote_addr, port)).await.with_context(|| {
+            format!("Failed to connect to X32 console at {}:{}", remote_addr, port)
+        })?;
+
+        let console = Self {
+            socket: Arc::new(socket),
+            interface: Arc::new(Mutex::new(None)),
+            meters: Arc::new(Mutex::new(vec![])),
+            tasks: ConsoleTasks::default(),
+        };
+
+        console.spawn_recv_task();
+        console.spawn_subscription_task();
+
+        info!(addr = remote_addr, port, "X32 console connection opened");
+
+        Ok(console)
+    }
+
+    async fn send(socket: &UdpSocket, osc_addr: &str, args: Vec<OscType>) -> Result<()> {
+        let packet = OscPacket::Message(OscMessage {
+            addr: osc_addr.to_string(),
+            args,
+        });
+        let buf = encoder::encode(&packet)?;
+
+        socket
+            .send(&buf)
+            .await
+            .with_context(|| format!("Failed to send OSC message {}", osc_addr))?;
+
+        Ok(())
+    }
+
+    /// Spawn a background task that keeps the parameter and meter subscriptions alive, and
+    /// asks the console to identify itself so that its absence is noticed
+    fn spawn_subscription_task(&self) {
+        let socket = self.socket.clone();
+        let meters = self.meters.clone();
+
+        self.tasks.spawn(async move {
+            let mut interval = tokio::time::interval(SUBSCRIPTION_RENEW_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let mut messages = vec![("/xremote", vec![]), ("/info", vec![])];
+                if !meters.lock().await.is_empty() {
+                    messages.push(("/meters", vec![OscType::String(METER_BANK.to_string())]));
+                }
+
+                for (osc_addr, args) in messages {
+                    if let Err(e) = Self::send(&socket, osc_addr, args).await {
+                        warn!("Failed to renew X32 subscription: {:?}", e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Spawn a background task that listens for incoming OSC packets
+    fn spawn_recv_task(&self) {
+        let socket = self.socket.clone();
+        let interface = self.interface.clone();
+        let meters = self.meters.clone();
+
+        self.tasks.spawn(async move {
+            let mut buf = vec![0u8; decoder::MTU];
+            let mut online = false;
+
+            loop {
+                let len = match timeout(LINK_TIMEOUT, socket.recv(&mut buf)).await {
+                    Ok(Ok(len)) => len,
+                    Ok(Err(e)) => {
+                        warn!("Error during OSC reception: {:?}", e);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        continue;
+                    }
+                    Err(_) => {
+                        if online {
+                            online = false;
+                            if let Some(iface) = interface.lock().await.as_ref() {
+                                iface.set_link_status(LinkStatus::Offline).await;
+                            }
+                        }
+                        continue;
+                    }
+                };
+
+                let iface = interface.lock().await.clone();
+                let Some(iface) = iface else {
+                    warn!("No interface set to handle OSC message");
+                    continue;
+                };
+
+                if !online {
+                    online = true;
+                    iface.set_link_status(LinkStatus::Online).await;
+                }
+
+                let packet = match decoder::decode_udp(&buf[..len]) {
+                    Ok((_, packet)) => packet,
+                    Err(e) => {
+                        warn!("Failed to decode OSC packet: {:?}", e);
+                        continue;
+                    }
+                };
+
+                let mut packets = vec![packet];
+                while let Some(packet) = packets.pop() {
+                    match packet {
+                        OscPacket::Bundle(bundle) => packets.extend(bundle.content),
+                        OscPacket::Message(message) => {
+                            Self::process_message(&iface, &meters, message).await
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    async fn process_message(
+        interface: &Interface,
+        meters: &Mutex<Vec<libwing::Meter>>,
+        message: OscMessage,
+    ) {
+        trace!(?message, "Received X32 OSC message");
+
+        match message.addr.as_str() {
+            METER_BANK => {
+                if let Some(OscType::Blob(blob)) = message.args.into_iter().next() {
+                    let values = Self::process_meter_data(&meters.lock().await, &blob);
+                    interface.set_meters(values).await;
+                }
+            }
+            "/info" => {
+                let info: Vec<String> = message
+                    .args
+                    .into_iter()
+                    .filter_map(|arg| arg.string())
+                    .collect();
+                debug!(?info, "X32 console identified as");
+            }
+            osc_addr => {
+                let Some((wing_path, conversion)) = path_from_x32(osc_addr) else {
+                    trace!(osc_addr, "Ignoring X32 parameter without a WING equivalent");
+                    return;
+                };
+                let Some(value) = message
+                    .args
+                    .into_iter()
+                    .next()
+                    .and_then(|arg| conversion.from_x32(arg))
+                else {
+                    warn!(osc_addr, "Unexpected value received for X32 parameter");
+                    return;
+                };
+
+                debug!(osc_addr, wing_path, ?value, "Received X32 value");
+                interface.set_value(&wing_path, value).await;
+            }
+        }
+    }
+
+    /// Decode a meter blob of little-endian floats, preceded by their count, into the values
+    /// of the subscribed meters on the WING meter scale
+    fn process_meter_data(meters: &[libwing::Meter], blob: &[u8]) -> Vec<Vec<f32>> {
+        let levels: Vec<f32> = blob
+            .get(4..)
+            .unwrap_or_default()
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect();
+
+        meters
+            .iter()
+            .map(|meter| {
+                let level = meter_index(meter)
+                    .and_then(|index| levels.get(index))
+                    .copied()
+                    .unwrap_or(0.0);
+                let db = 20.0 * level.max(f32::MIN_POSITIVE).log10();
+
+                vec![(1.0 + db / METER_RANGE_DB).clamp(0.0, 1.0)]
+            })
+            .collect()
+    }
+
+    /// Performs a request for an OSC value, without returning it.
+    pub async fn request_value(&mut self, osc_addr: &str) -> Result<()> {
+        let Some((x32_path, _)) = path_to_x32(osc_addr) else {
+            debug!(osc_addr, "Not requesting parameter without an X32 equivalent");
+            return Ok(());
+        };
+
+        debug!(osc_addr, x32_path, "Requesting OSC value");
+        Self::send(&self.socket, &x32_path, vec![]).await
+    }
+
+    /// Set an OSC value
+    pub async fn set_value(&mut self, osc_addr: &str, value: Value) -> Result<()> {
+        let Some((x32_path, conversion)) = path_to_x32(osc_addr) else {
+            debug!(osc_addr, "Not setting parameter without an X32 equivalent");
+            return Ok(());
+        };
+        let Some(arg) = conversion.to_x32(value.clone()) else {
+            anyhow::bail!("Value {:?} cannot be set on X32 parameter {}", value, x32_path);
+        };
+
+        debug!(osc_addr, x32_path, ?arg, "Setting OSC value");
+        Self::send(&self.socket, &x32_path, vec![arg]).await
+    }
+
+    pub async fn set_interface(&mut self, interface: Interface) {
+        interface.set_link_status(LinkStatus::Connecting).await;
+        self.interface.lock().await.replace(interface);
+
+        if let Err(e) = Self::send(&self.socket, "/info", vec![]).await {
+            error!("Failed to identify X32 console: {:?}", e);
+        }
+    }
+
+    pub async fn set_meters(&mut self, meters: Vec<libwing::Meter>) -> Result<()> {
+        *self.meters.lock().await = meters;
+
+        Self::send(&self.socket, "/meters", vec![OscType::String(METER_BANK.to_string())])
+            .await
+            .with_context(|| "Failed to request meters")
+    }
+}